@@ -0,0 +1,170 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Authentication of Flight clients. The scheduler and the executors check the same user
+//! credentials, and the scheduler and executors authenticate as the first of those users
+//! when they fetch partitions from other executors.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use base64::Engine;
+
+use crate::error::{BallistaError, Result};
+
+/// Passwords of the users which clients authenticate as, keyed by the user. Without
+/// configured users, the only user is `admin` with the password `password`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct UserCredentials(HashMap<String, String>);
+
+impl UserCredentials {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn with_user(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.0.insert(user.into(), password.into());
+        self
+    }
+
+    /// Whether the password is the one of the user
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        self.0
+            .get(user)
+            .is_some_and(|expected| expected == password)
+    }
+
+    /// Check the credentials of a `Basic` authorization header. Returns the user if the
+    /// credentials are valid, `None` if they are not, and an error if the header is
+    /// malformed.
+    pub fn authenticate_basic(&self, authorization: &str) -> Result<Option<String>> {
+        let credentials = authorization.strip_prefix("Basic ").ok_or_else(|| {
+            BallistaError::General(format!("Auth type not implemented: {authorization}"))
+        })?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(credentials)
+            .map_err(|_| BallistaError::General("authorization not parsable".to_owned()))?;
+        let credentials = String::from_utf8(bytes)
+            .map_err(|_| BallistaError::General("authorization not parsable".to_owned()))?;
+        let (user, pass) = credentials
+            .split_once(':')
+            .ok_or_else(|| BallistaError::General("Invalid authorization header".to_owned()))?;
+
+        Ok(self.verify(user, pass).then(|| user.to_owned()))
+    }
+
+    /// The first user by name, which the cluster authenticates its own requests as
+    pub fn cluster_user(&self) -> Option<&str> {
+        self.0.keys().min().map(String::as_str)
+    }
+
+    /// `Basic` authorization header of the cluster user
+    fn basic_authorization(&self) -> Option<String> {
+        let user = self.cluster_user()?;
+        let password = &self.0[user];
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+        Some(format!("Basic {credentials}"))
+    }
+}
+
+impl Default for UserCredentials {
+    fn default() -> Self {
+        Self::new().with_user("admin", "password")
+    }
+}
+
+impl fmt::Debug for UserCredentials {
+    // Only the users, passwords must not end up in logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+impl FromStr for UserCredentials {
+    type Err = String;
+
+    /// Parse a list of users like `alice:secret1,bob:secret2`
+    fn from_str(users: &str) -> std::result::Result<Self, Self::Err> {
+        let mut credentials = UserCredentials::new();
+        for user in users.split(',').map(str::trim).filter(|u| !u.is_empty()) {
+            let (user, password) = user
+                .split_once(':')
+                .ok_or_else(|| "Expected users like user:password".to_owned())?;
+            if user.is_empty() || password.is_empty() {
+                return Err("Users and passwords must not be empty".to_owned());
+            }
+            credentials = credentials.with_user(user, password);
+        }
+        if credentials.0.is_empty() {
+            return Err("No users given".to_owned());
+        }
+        Ok(credentials)
+    }
+}
+
+static CLIENT_AUTHORIZATION: OnceLock<String> = OnceLock::new();
+
+/// Authenticate the partition fetches of this process as the first of the users, which
+/// has to be called once when the scheduler or executor starts
+pub fn set_client_credentials(users: &UserCredentials) {
+    if let Some(authorization) = users.basic_authorization() {
+        let _ = CLIENT_AUTHORIZATION.set(authorization);
+    }
+}
+
+/// Authorization header of the partition fetches of this process, if it has credentials
+pub fn client_authorization() -> Option<&'static str> {
+    CLIENT_AUTHORIZATION.get().map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_user_credentials() {
+        let users: UserCredentials = "alice:secret1, bob:secret:2".parse().unwrap();
+        assert!(users.verify("alice", "secret1"));
+        assert!(users.verify("bob", "secret:2"));
+        assert!(!users.verify("alice", "secret:2"));
+        assert!(!users.verify("admin", "password"));
+        assert!(!format!("{users:?}").contains("secret"));
+
+        assert!(UserCredentials::default().verify("admin", "password"));
+        assert!("alice".parse::<UserCredentials>().is_err());
+        assert!(":secret".parse::<UserCredentials>().is_err());
+        assert!("".parse::<UserCredentials>().is_err());
+    }
+
+    #[test]
+    fn authenticate_basic_authorization() {
+        let users: UserCredentials = "bob:secret2,alice:secret1".parse().unwrap();
+        assert_eq!(users.cluster_user(), Some("alice"));
+        let authorization = users.basic_authorization().unwrap();
+        assert_eq!(
+            users.authenticate_basic(&authorization).unwrap(),
+            Some("alice".to_owned())
+        );
+
+        let other = UserCredentials::default().basic_authorization().unwrap();
+        assert_eq!(users.authenticate_basic(&other).unwrap(), None);
+        assert!(users.authenticate_basic("Bearer token").is_err());
+    }
+}
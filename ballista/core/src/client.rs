@@ -39,18 +39,24 @@ use datafusion::arrow::{
 };
 use datafusion::error::DataFusionError;
 
+use crate::auth::client_authorization;
+use crate::config::ShuffleCompression;
 use crate::serde::protobuf;
+use crate::telemetry::inject_trace_metadata;
 use crate::utils::create_grpc_client_connection;
 use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
 use futures::{Stream, StreamExt};
 use log::{debug, warn};
 use prost::Message;
 use tonic::{Code, Streaming};
+use tracing::{info_span, Instrument, Span};
 
 /// Client for interacting with Ballista executors.
 #[derive(Clone)]
 pub struct BallistaClient {
     flight_client: FlightServiceClient<tonic::transport::channel::Channel>,
+    /// The codec partitions are requested to be compressed with for the transfer
+    compression: ShuffleCompression,
 }
 
 //TODO make this configurable
@@ -73,7 +79,16 @@ impl BallistaClient {
         let flight_client = FlightServiceClient::new(connection);
         debug!("BallistaClient connected OK");
 
-        Ok(Self { flight_client })
+        Ok(Self {
+            flight_client,
+            compression: ShuffleCompression::default(),
+        })
+    }
+
+    /// Request fetched partitions to be compressed with the given codec
+    pub fn with_compression(mut self, compression: ShuffleCompression) -> Self {
+        self.compression = compression;
+        self
     }
 
     /// Fetch a partition from an executor
@@ -92,8 +107,17 @@ impl BallistaClient {
             path: path.to_owned(),
             host: host.to_owned(),
             port,
+            compression: self.compression,
         };
+        let span = info_span!(
+            "fetch_partition",
+            executor_id,
+            job_id = partition_id.job_id.as_str(),
+            stage_id = partition_id.stage_id,
+            partition_id = partition_id.partition_id,
+        );
         self.execute_action(&action)
+            .instrument(span)
             .await
             .map_err(|error| match error {
                 // map grpc connection error to partition fetch error.
@@ -126,9 +150,18 @@ impl BallistaClient {
                 tokio::time::sleep(std::time::Duration::from_millis(IO_RETRY_WAIT_TIME_MS)).await;
             }
 
-            let request = tonic::Request::new(Ticket {
+            let mut request = tonic::Request::new(Ticket {
                 ticket: buf.clone().into(),
             });
+            inject_trace_metadata(&Span::current(), request.metadata_mut());
+            if let Some(authorization) = client_authorization() {
+                let authorization = authorization
+                    .parse()
+                    .map_err(|_| BallistaError::General("Invalid client credentials".to_owned()))?;
+                request
+                    .metadata_mut()
+                    .insert("authorization", authorization);
+            }
             let result = self.flight_client.do_get(request).await;
 
             let res = match result {
@@ -19,11 +19,17 @@
 //! Ballista configuration
 
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::result;
+use std::str::FromStr;
+use std::time::Duration;
 
 use crate::error::{BallistaError, Result};
 
 use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::ipc::writer::IpcWriteOptions;
+use datafusion::arrow::ipc::CompressionType;
 
 pub const BALLISTA_DEFAULT_SHUFFLE_PARTITIONS: &str = "ballista.shuffle.partitions";
 pub const BALLISTA_HASH_JOIN_SINGLE_PARTITION_THRESHOLD: &str =
@@ -37,6 +43,44 @@ pub const BALLISTA_COLLECT_STATISTICS: &str = "ballista.collect_statistics";
 
 pub const BALLISTA_WITH_INFORMATION_SCHEMA: &str = "ballista.with_information_schema";
 
+/// The tenant that jobs submitted within the session are accounted to. The scheduler sets
+/// it to the authenticated user of the session, overriding any value sent by the client.
+pub const BALLISTA_TENANT: &str = "ballista.tenant";
+
+/// Whether Flight SQL clients fetch job results straight from the executors
+pub const BALLISTA_FLIGHT_SQL_DIRECT_RESULTS: &str = "ballista.flight_sql.direct_results";
+/// How long a Flight SQL request waits for its job before the job is cancelled
+pub const BALLISTA_FLIGHT_SQL_JOB_TIMEOUT_SECONDS: &str = "ballista.flight_sql.job_timeout_seconds";
+
+/// Whether shuffle output is written to a single data file and index per map task
+pub const BALLISTA_SHUFFLE_SORT_BASED: &str = "ballista.shuffle.sort_based";
+
+/// The codec shuffle output is compressed with, see [`ShuffleCompression`]
+pub const BALLISTA_SHUFFLE_COMPRESSION: &str = "ballista.shuffle.compression";
+
+/// The maximum number of shuffle partitions a task fetches at the same time
+pub const BALLISTA_SHUFFLE_FETCH_MAX_REQUESTS: &str = "ballista.shuffle.fetch.max_requests";
+/// The maximum size of the shuffle partitions a task fetches at the same time
+pub const BALLISTA_SHUFFLE_FETCH_MAX_BYTES_IN_FLIGHT: &str =
+    "ballista.shuffle.fetch.max_bytes_in_flight";
+/// How often a failed fetch of a shuffle partition is retried
+pub const BALLISTA_SHUFFLE_FETCH_MAX_RETRIES: &str = "ballista.shuffle.fetch.max_retries";
+/// The wait before the first retry of a fetch, which doubles with every further retry
+pub const BALLISTA_SHUFFLE_FETCH_RETRY_BACKOFF_MS: &str = "ballista.shuffle.fetch.retry_backoff_ms";
+
+/// Whether global sorts are executed by range partitioning the input across tasks
+pub const BALLISTA_RANGE_PARTITIONED_SORT: &str = "ballista.sort.range_partitioned";
+
+/// Prefix of the settings of the object stores which table data is read from
+pub const BALLISTA_OBJECT_STORE_PREFIX: &str = "ballista.object_store.";
+pub const BALLISTA_S3_ENDPOINT: &str = "ballista.object_store.s3.endpoint";
+pub const BALLISTA_S3_REGION: &str = "ballista.object_store.s3.region";
+pub const BALLISTA_S3_ACCESS_KEY_ID: &str = "ballista.object_store.s3.access_key_id";
+/// Not a setting: sessions are persisted and their settings are sent along with every
+/// task, so the secret access key is only read from `AWS_SECRET_ACCESS_KEY` of each process
+pub const BALLISTA_S3_SECRET_ACCESS_KEY: &str = "ballista.object_store.s3.secret_access_key";
+pub const BALLISTA_S3_ALLOW_HTTP: &str = "ballista.object_store.s3.allow_http";
+
 pub type ParseResult<T> = result::Result<T, String>;
 
 /// Configuration option meta-data
@@ -127,6 +171,20 @@ impl BallistaConfig {
             }
         }
 
+        if let Some(v) = settings.get(BALLISTA_SHUFFLE_COMPRESSION) {
+            v.parse::<ShuffleCompression>().map_err(|e| {
+                BallistaError::General(format!(
+                    "Failed to parse user-supplied value '{BALLISTA_SHUFFLE_COMPRESSION}' for configuration setting '{v}': {e}"
+                ))
+            })?;
+        }
+
+        if settings.contains_key(BALLISTA_S3_SECRET_ACCESS_KEY) {
+            return Err(BallistaError::General(format!(
+                "'{BALLISTA_S3_SECRET_ACCESS_KEY}' can't be set per session, set AWS_SECRET_ACCESS_KEY for the scheduler and executors instead"
+            )));
+        }
+
         Ok(Self { settings })
     }
 
@@ -194,6 +252,48 @@ impl BallistaConfig {
                 "Configuration for collecting statistics during scan".to_string(),
                 DataType::Boolean, Some("false".to_string())
             ),
+            ConfigEntry::new(BALLISTA_TENANT.to_string(),
+                "The tenant that jobs of the session are accounted to for scheduler quotas, which the scheduler sets to the authenticated user".to_string(),
+                DataType::Utf8, None),
+            ConfigEntry::new(BALLISTA_FLIGHT_SQL_DIRECT_RESULTS.to_string(),
+                "Sets whether Flight SQL results are fetched from the executors instead of through the scheduler".to_string(),
+                DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(BALLISTA_FLIGHT_SQL_JOB_TIMEOUT_SECONDS.to_string(),
+                "Sets how many seconds a Flight SQL request waits for its job to finish before the job is cancelled".to_string(),
+                DataType::UInt64, Some("3600".to_string())),
+            ConfigEntry::new(BALLISTA_SHUFFLE_SORT_BASED.to_string(),
+                "Sets whether each map task writes its shuffle output to a single data file sorted by partition, with an index of the partition offsets".to_string(),
+                DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(BALLISTA_SHUFFLE_COMPRESSION.to_string(),
+                "Sets the codec shuffle files and shuffle partitions fetched over Flight are compressed with: none, lz4, zstd or zstd:<level>".to_string(),
+                DataType::Utf8, Some(ShuffleCompression::default().to_string())),
+            ConfigEntry::new(BALLISTA_SHUFFLE_FETCH_MAX_REQUESTS.to_string(),
+                "Sets the maximum number of shuffle partitions a task fetches from other executors at the same time".to_string(),
+                DataType::UInt64, Some("50".to_string())),
+            ConfigEntry::new(BALLISTA_SHUFFLE_FETCH_MAX_BYTES_IN_FLIGHT.to_string(),
+                "Sets the maximum total size in bytes of the shuffle partitions a task fetches at the same time".to_string(),
+                DataType::UInt64, Some((48 * 1024 * 1024).to_string())),
+            ConfigEntry::new(BALLISTA_SHUFFLE_FETCH_MAX_RETRIES.to_string(),
+                "Sets how often a failed fetch of a shuffle partition is retried before the task fails".to_string(),
+                DataType::UInt64, Some("3".to_string())),
+            ConfigEntry::new(BALLISTA_SHUFFLE_FETCH_RETRY_BACKOFF_MS.to_string(),
+                "Sets the wait in milliseconds before retrying a failed fetch, which doubles with every further retry".to_string(),
+                DataType::UInt64, Some("100".to_string())),
+            ConfigEntry::new(BALLISTA_RANGE_PARTITIONED_SORT.to_string(),
+                "Sets whether an ORDER BY sorts range partitions of its input in parallel, with split points sampled from the sort keys, instead of merging all partitions in a single task".to_string(),
+                DataType::Boolean, Some("false".to_string())),
+            ConfigEntry::new(BALLISTA_S3_ENDPOINT.to_string(),
+                "Sets the endpoint of the S3 compatible store, e.g. of a MinIO server".to_string(),
+                DataType::Utf8, None),
+            ConfigEntry::new(BALLISTA_S3_REGION.to_string(),
+                "Sets the region of the S3 compatible store".to_string(),
+                DataType::Utf8, None),
+            ConfigEntry::new(BALLISTA_S3_ACCESS_KEY_ID.to_string(),
+                "Sets the access key id to the S3 compatible store, whose secret access key is taken from AWS_SECRET_ACCESS_KEY".to_string(),
+                DataType::Utf8, None),
+            ConfigEntry::new(BALLISTA_S3_ALLOW_HTTP.to_string(),
+                "Sets whether the S3 compatible store may be accessed over plain HTTP".to_string(),
+                DataType::Boolean, Some("false".to_string())),
         ];
         entries
             .iter()
@@ -241,6 +341,55 @@ impl BallistaConfig {
         self.get_bool_setting(BALLISTA_WITH_INFORMATION_SCHEMA)
     }
 
+    pub fn flight_sql_direct_results(&self) -> bool {
+        self.get_bool_setting(BALLISTA_FLIGHT_SQL_DIRECT_RESULTS)
+    }
+
+    pub fn flight_sql_job_timeout(&self) -> Duration {
+        Duration::from_secs(self.get_usize_setting(BALLISTA_FLIGHT_SQL_JOB_TIMEOUT_SECONDS) as u64)
+    }
+
+    pub fn shuffle_sort_based(&self) -> bool {
+        self.get_bool_setting(BALLISTA_SHUFFLE_SORT_BASED)
+    }
+
+    pub fn shuffle_fetch_max_requests(&self) -> usize {
+        self.get_usize_setting(BALLISTA_SHUFFLE_FETCH_MAX_REQUESTS)
+    }
+
+    pub fn shuffle_fetch_max_bytes_in_flight(&self) -> usize {
+        self.get_usize_setting(BALLISTA_SHUFFLE_FETCH_MAX_BYTES_IN_FLIGHT)
+    }
+
+    pub fn shuffle_fetch_max_retries(&self) -> usize {
+        self.get_usize_setting(BALLISTA_SHUFFLE_FETCH_MAX_RETRIES)
+    }
+
+    pub fn shuffle_fetch_retry_backoff(&self) -> Duration {
+        Duration::from_millis(
+            self.get_usize_setting(BALLISTA_SHUFFLE_FETCH_RETRY_BACKOFF_MS) as u64,
+        )
+    }
+
+    pub fn range_partitioned_sort(&self) -> bool {
+        self.get_bool_setting(BALLISTA_RANGE_PARTITIONED_SORT)
+    }
+
+    pub fn shuffle_compression(&self) -> ShuffleCompression {
+        self.settings
+            .get(BALLISTA_SHUFFLE_COMPRESSION)
+            // infallible because we validate all configs in the constructor
+            .map(|v| v.parse().unwrap())
+            .unwrap_or_default()
+    }
+
+    /// The tenant of the session, if any is set
+    pub fn tenant(&self) -> Option<&str> {
+        self.settings
+            .get(BALLISTA_TENANT)
+            .map(|tenant| tenant.as_str())
+    }
+
     fn get_usize_setting(&self, key: &str) -> usize {
         if let Some(v) = self.settings.get(key) {
             // infallible because we validate all configs in the constructor
@@ -266,6 +415,89 @@ impl BallistaConfig {
     }
 }
 
+/// The level shuffle output is compressed at with zstd if no level is given
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// The codec shuffle output is compressed with, both in shuffle files and when partitions
+/// are fetched over Flight. Arrow compresses zstd buffers only at its default level, so
+/// shuffle files are compressed with zstd at the given level as a whole, see
+/// [`crate::shuffle_compression`], while Flight transfers use the default level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ShuffleCompression {
+    None,
+    #[default]
+    Lz4,
+    Zstd(i32),
+}
+
+impl ShuffleCompression {
+    /// The codec of the buffers of Arrow IPC streams sent over Flight
+    pub fn compression_type(&self) -> Option<CompressionType> {
+        match self {
+            ShuffleCompression::None => None,
+            ShuffleCompression::Lz4 => Some(CompressionType::LZ4_FRAME),
+            ShuffleCompression::Zstd(_) => Some(CompressionType::ZSTD),
+        }
+    }
+
+    /// Options for writing Arrow IPC streams sent over Flight
+    pub fn write_options(&self) -> result::Result<IpcWriteOptions, ArrowError> {
+        IpcWriteOptions::default().try_with_compression(self.compression_type())
+    }
+
+    /// Options for writing the Arrow IPC streams of shuffle files, whose buffers are not
+    /// compressed with zstd since the whole stream is
+    pub fn file_write_options(&self) -> result::Result<IpcWriteOptions, ArrowError> {
+        match self {
+            ShuffleCompression::Zstd(_) => Ok(IpcWriteOptions::default()),
+            _ => self.write_options(),
+        }
+    }
+}
+
+impl FromStr for ShuffleCompression {
+    type Err = String;
+
+    /// Parse a codec like `lz4`, `zstd` or `zstd:9` for zstd at level 9
+    fn from_str(s: &str) -> ParseResult<Self> {
+        let lowercase = s.to_ascii_lowercase();
+        let (codec, level) = match lowercase.split_once(':') {
+            Some((codec, level)) => (codec, Some(level)),
+            None => (lowercase.as_str(), None),
+        };
+        match (codec, level) {
+            ("none", None) => Ok(ShuffleCompression::None),
+            ("lz4", None) => Ok(ShuffleCompression::Lz4),
+            ("zstd", None) => Ok(ShuffleCompression::Zstd(DEFAULT_ZSTD_LEVEL)),
+            ("zstd", Some(level)) => {
+                let levels = zstd::compression_level_range();
+                match level.parse::<i32>() {
+                    Ok(level) if levels.contains(&level) => Ok(ShuffleCompression::Zstd(level)),
+                    _ => Err(format!(
+                        "Invalid zstd level in '{s}', expected a level from {} to {}",
+                        levels.start(),
+                        levels.end()
+                    )),
+                }
+            }
+            _ => Err(format!(
+                "Unknown shuffle compression codec '{s}', expected none, lz4, zstd or zstd:<level>"
+            )),
+        }
+    }
+}
+
+impl Display for ShuffleCompression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShuffleCompression::None => write!(f, "none"),
+            ShuffleCompression::Lz4 => write!(f, "lz4"),
+            ShuffleCompression::Zstd(DEFAULT_ZSTD_LEVEL) => write!(f, "zstd"),
+            ShuffleCompression::Zstd(level) => write!(f, "zstd:{level}"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -275,6 +507,13 @@ mod tests {
         let config = BallistaConfig::new()?;
         assert_eq!(16, config.default_shuffle_partitions());
         assert!(!config.default_with_information_schema());
+        assert!(!config.range_partitioned_sort());
+        assert_eq!(3, config.shuffle_fetch_max_retries());
+        assert_eq!(
+            Duration::from_millis(100),
+            config.shuffle_fetch_retry_backoff()
+        );
+        assert_eq!(None, config.tenant());
         Ok(())
     }
 
@@ -283,9 +522,49 @@ mod tests {
         let config = BallistaConfig::builder()
             .set(BALLISTA_DEFAULT_SHUFFLE_PARTITIONS, "123")
             .set(BALLISTA_WITH_INFORMATION_SCHEMA, "true")
+            .set(BALLISTA_TENANT, "analytics")
             .build()?;
         assert_eq!(123, config.default_shuffle_partitions());
         assert!(config.default_with_information_schema());
+        assert_eq!(Some("analytics"), config.tenant());
+        Ok(())
+    }
+
+    #[test]
+    fn shuffle_compression_config() -> Result<()> {
+        let config = BallistaConfig::new()?;
+        assert_eq!(ShuffleCompression::Lz4, config.shuffle_compression());
+
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_SHUFFLE_COMPRESSION, "ZSTD")
+            .build()?;
+        assert_eq!(
+            ShuffleCompression::Zstd(DEFAULT_ZSTD_LEVEL),
+            config.shuffle_compression()
+        );
+        assert_eq!(
+            Some(CompressionType::ZSTD),
+            config.shuffle_compression().compression_type()
+        );
+        assert_eq!(None, ShuffleCompression::None.compression_type());
+
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_SHUFFLE_COMPRESSION, "zstd:9")
+            .build()?;
+        assert_eq!(ShuffleCompression::Zstd(9), config.shuffle_compression());
+        assert_eq!("zstd:9", config.shuffle_compression().to_string());
+        assert_eq!(
+            "zstd",
+            ShuffleCompression::Zstd(DEFAULT_ZSTD_LEVEL).to_string()
+        );
+        assert!(BallistaConfig::builder()
+            .set(BALLISTA_SHUFFLE_COMPRESSION, "zstd:99")
+            .build()
+            .is_err());
+        assert!(BallistaConfig::builder()
+            .set(BALLISTA_SHUFFLE_COMPRESSION, "lz4:1")
+            .build()
+            .is_err());
         Ok(())
     }
 
@@ -302,6 +581,11 @@ mod tests {
             .build();
         assert!(config.is_err());
         assert_eq!("General(\"Failed to parse user-supplied value 'ballista.with_information_schema' for configuration setting '123': ParseBoolError\")", format!("{:?}", config.unwrap_err()));
+
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_SHUFFLE_COMPRESSION, "gzip")
+            .build();
+        assert!(config.is_err());
         Ok(())
     }
 }
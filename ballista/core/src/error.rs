@@ -133,6 +133,12 @@ impl From<datafusion_proto::logical_plan::to_proto::Error> for BallistaError {
     }
 }
 
+impl From<object_store::Error> for BallistaError {
+    fn from(e: object_store::Error) -> Self {
+        BallistaError::DataFusionError(DataFusionError::ObjectStore(e))
+    }
+}
+
 impl From<futures::future::Aborted> for BallistaError {
     fn from(_: Aborted) -> Self {
         BallistaError::Cancelled
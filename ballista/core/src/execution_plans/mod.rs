@@ -18,10 +18,13 @@
 //! This module contains execution plans that are needed to distribute DataFusion's execution plans into
 //! several Ballista executors.
 
+mod range_partition;
 mod shuffle_reader;
 mod shuffle_writer;
+pub mod sort_shuffle;
 mod unresolved_shuffle;
 
+pub use range_partition::{RangePartitioning, RangeRepartitionExec, RangeSampleExec};
 pub use shuffle_reader::ShuffleReaderExec;
 pub use shuffle_writer::ShuffleWriterExec;
 pub use unresolved_shuffle::UnresolvedShuffleExec;
@@ -0,0 +1,467 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Range partitioning of shuffle output, which lets a global sort be executed by sorting
+//! each output partition independently. The split points between the partitions are
+//! computed from samples of the sort keys, which are taken by a preceding query stage.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, UInt32Array};
+use datafusion::arrow::compute::take;
+use datafusion::arrow::datatypes::{Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::row::{OwnedRow, RowConverter, SortField};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::TaskContext;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::metrics;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, SendableRecordBatchStream,
+    Statistics,
+};
+use futures::{StreamExt, TryStreamExt};
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Range partitioning of the output of a [`ShuffleWriterExec`](super::ShuffleWriterExec)
+#[derive(Debug, Clone)]
+pub struct RangePartitioning {
+    /// The sort order whose key ranges the partitions cover, in partition order
+    pub sort_exprs: Vec<PhysicalSortExpr>,
+    pub partition_count: usize,
+    /// Samples of the sort keys of the input, as taken by [`RangeSampleExec`]
+    pub samples: Arc<dyn ExecutionPlan>,
+}
+
+/// Exchange of the input for its range partitioning by the given sort order. It marks
+/// where the scheduler splits a global sort into query stages and is not executable.
+#[derive(Debug)]
+pub struct RangeRepartitionExec {
+    input: Arc<dyn ExecutionPlan>,
+    sort_exprs: Vec<PhysicalSortExpr>,
+    partition_count: usize,
+}
+
+impl RangeRepartitionExec {
+    pub fn new(
+        input: Arc<dyn ExecutionPlan>,
+        sort_exprs: Vec<PhysicalSortExpr>,
+        partition_count: usize,
+    ) -> Self {
+        Self {
+            input,
+            sort_exprs,
+            partition_count,
+        }
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn sort_exprs(&self) -> &[PhysicalSortExpr] {
+        &self.sort_exprs
+    }
+
+    pub fn partition_count(&self) -> usize {
+        self.partition_count
+    }
+}
+
+impl DisplayAs for RangeRepartitionExec {
+    fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match t {
+            DisplayFormatType::Default | DisplayFormatType::Verbose => {
+                write!(
+                    f,
+                    "RangeRepartitionExec: [{}], partitions={}",
+                    self.sort_exprs.iter().join(", "),
+                    self.partition_count
+                )
+            }
+        }
+    }
+}
+
+impl ExecutionPlan for RangeRepartitionExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.partition_count)
+    }
+
+    fn output_ordering(&self) -> Option<&[PhysicalSortExpr]> {
+        None
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(RangeRepartitionExec::new(
+            children[0].clone(),
+            self.sort_exprs.clone(),
+            self.partition_count,
+        )))
+    }
+
+    fn execute(
+        &self,
+        _partition: usize,
+        _context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        Err(DataFusionError::Plan(
+            "Ballista RangeRepartitionExec does not support execution".to_owned(),
+        ))
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        self.input.statistics()
+    }
+}
+
+/// Takes a uniform random sample of the sort keys of each input partition. Its output has
+/// a column per sort expression and a single batch per partition.
+#[derive(Debug)]
+pub struct RangeSampleExec {
+    input: Arc<dyn ExecutionPlan>,
+    sort_exprs: Vec<PhysicalSortExpr>,
+    /// The maximum number of rows sampled from each input partition
+    sample_size: usize,
+    schema: SchemaRef,
+}
+
+impl RangeSampleExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        sort_exprs: Vec<PhysicalSortExpr>,
+        sample_size: usize,
+    ) -> Result<Self> {
+        let input_schema = input.schema();
+        let fields = sort_exprs
+            .iter()
+            .enumerate()
+            .map(|(i, sort_expr)| {
+                Ok(Field::new(
+                    format!("sort_key_{i}"),
+                    sort_expr.expr.data_type(&input_schema)?,
+                    sort_expr.expr.nullable(&input_schema)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            input,
+            sort_exprs,
+            sample_size,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    pub fn sort_exprs(&self) -> &[PhysicalSortExpr] {
+        &self.sort_exprs
+    }
+
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+}
+
+impl DisplayAs for RangeSampleExec {
+    fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match t {
+            DisplayFormatType::Default | DisplayFormatType::Verbose => {
+                write!(
+                    f,
+                    "RangeSampleExec: [{}], sample_size={}",
+                    self.sort_exprs.iter().join(", "),
+                    self.sample_size
+                )
+            }
+        }
+    }
+}
+
+impl ExecutionPlan for RangeSampleExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.input.output_partitioning().partition_count())
+    }
+
+    fn output_ordering(&self) -> Option<&[PhysicalSortExpr]> {
+        None
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(RangeSampleExec::try_new(
+            children[0].clone(),
+            self.sort_exprs.clone(),
+            self.sample_size,
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let mut input = self.input.execute(partition, context)?;
+        let sort_exprs = self.sort_exprs.clone();
+        let sample_size = self.sample_size;
+        let schema = self.schema.clone();
+        let converter = sort_key_converter(&sort_exprs, &self.input.schema())?;
+
+        let sample = async move {
+            // Seeded by the partition, so that a retried task takes the same sample
+            let mut rng = StdRng::seed_from_u64(partition as u64);
+            let mut reservoir: Vec<OwnedRow> = Vec::with_capacity(sample_size);
+            let mut num_rows = 0;
+            while let Some(batch) = input.next().await {
+                let rows = converter.convert_columns(&sort_keys(&sort_exprs, &batch?)?)?;
+                for row in rows.iter() {
+                    if reservoir.len() < sample_size {
+                        reservoir.push(row.owned());
+                    } else {
+                        let i = rng.gen_range(0..=num_rows);
+                        if i < sample_size {
+                            reservoir[i] = row.owned();
+                        }
+                    }
+                    num_rows += 1;
+                }
+            }
+            let columns = converter.convert_rows(reservoir.iter().map(|row| row.row()))?;
+            Ok::<_, DataFusionError>(RecordBatch::try_new(schema, columns)?)
+        };
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            futures::stream::once(sample),
+        )))
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        Ok(Statistics::new_unknown(&self.schema()))
+    }
+}
+
+fn sort_key_converter(sort_exprs: &[PhysicalSortExpr], schema: &Schema) -> Result<RowConverter> {
+    let fields = sort_exprs
+        .iter()
+        .map(|sort_expr| {
+            Ok(SortField::new_with_options(
+                sort_expr.expr.data_type(schema)?,
+                sort_expr.options,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RowConverter::new(fields)?)
+}
+
+fn sort_keys(sort_exprs: &[PhysicalSortExpr], batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+    sort_exprs
+        .iter()
+        .map(|sort_expr| sort_expr.expr.evaluate(batch)?.into_array(batch.num_rows()))
+        .collect()
+}
+
+/// Splits batches into range partitions, by split points chosen at even intervals of the
+/// sorted samples
+pub(crate) struct RangePartitioner {
+    sort_exprs: Vec<PhysicalSortExpr>,
+    converter: RowConverter,
+    /// The lowest sort key of every partition but the first
+    bounds: Vec<OwnedRow>,
+    partition_count: usize,
+    timer: metrics::Time,
+}
+
+impl RangePartitioner {
+    /// Read all samples of the sort keys and compute the split points from them
+    pub(crate) async fn try_new(
+        partitioning: &RangePartitioning,
+        input_schema: &Schema,
+        context: Arc<TaskContext>,
+        timer: metrics::Time,
+    ) -> Result<Self> {
+        let converter = sort_key_converter(&partitioning.sort_exprs, input_schema)?;
+        let mut samples = vec![];
+        for partition in 0..partitioning.samples.output_partitioning().partition_count() {
+            let batches: Vec<RecordBatch> = partitioning
+                .samples
+                .execute(partition, context.clone())?
+                .try_collect()
+                .await?;
+            for batch in batches {
+                let rows = converter.convert_columns(batch.columns())?;
+                samples.extend(rows.iter().map(|row| row.owned()));
+            }
+        }
+
+        let _timer = timer.timer();
+        samples.sort();
+        let bounds = (1..partitioning.partition_count)
+            .filter_map(|partition| {
+                samples
+                    .get(partition * samples.len() / partitioning.partition_count)
+                    .cloned()
+            })
+            .collect();
+        Ok(Self {
+            sort_exprs: partitioning.sort_exprs.clone(),
+            converter,
+            bounds,
+            partition_count: partitioning.partition_count,
+            timer: timer.clone(),
+        })
+    }
+
+    /// Split a batch into the parts which belong to each partition and pass them to `f`,
+    /// the same way [`BatchPartitioner`] does
+    ///
+    /// [`BatchPartitioner`]: datafusion::physical_plan::repartition::BatchPartitioner
+    pub(crate) fn partition<F>(&mut self, batch: RecordBatch, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, RecordBatch) -> Result<()>,
+    {
+        let timer = self.timer.timer();
+        let rows = self
+            .converter
+            .convert_columns(&sort_keys(&self.sort_exprs, &batch)?)?;
+        let mut indices = vec![vec![]; self.partition_count];
+        for (i, row) in rows.iter().enumerate() {
+            let partition = self.bounds.partition_point(|bound| bound.row() <= row);
+            indices[partition].push(i as u32);
+        }
+        let parts = indices
+            .into_iter()
+            .enumerate()
+            .filter(|(_, indices)| !indices.is_empty())
+            .map(|(partition, indices)| {
+                let indices = UInt32Array::from(indices);
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .map(|column| take(column.as_ref(), &indices, None))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok((partition, RecordBatch::try_new(batch.schema(), columns)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        timer.done();
+
+        for (partition, part) in parts {
+            f(partition, part)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int32Array;
+    use datafusion::arrow::compute::SortOptions;
+    use datafusion::arrow::datatypes::DataType;
+    use datafusion::physical_plan::expressions::Column;
+    use datafusion::physical_plan::memory::MemoryExec;
+    use datafusion::prelude::SessionContext;
+
+    fn batch(schema: &SchemaRef, values: Vec<i32>) -> RecordBatch {
+        RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn partition_by_sampled_ranges() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let partitions = vec![
+            vec![batch(&schema, (0..50).rev().collect())],
+            vec![batch(&schema, (50..100).collect())],
+        ];
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: SortOptions {
+                descending: true,
+                nulls_first: false,
+            },
+        }];
+        // Sampling every row makes the split points exact
+        let samples = Arc::new(RangeSampleExec::try_new(
+            input.clone(),
+            sort_exprs.clone(),
+            100,
+        )?);
+        let task_ctx = SessionContext::new().task_ctx();
+        let sample: Vec<RecordBatch> = samples.execute(0, task_ctx.clone())?.try_collect().await?;
+        assert_eq!(sample[0].num_rows(), 50);
+
+        let partitioning = RangePartitioning {
+            sort_exprs,
+            partition_count: 4,
+            samples,
+        };
+        let mut partitioner =
+            RangePartitioner::try_new(&partitioning, &schema, task_ctx, metrics::Time::new())
+                .await?;
+
+        let mut parts: Vec<Vec<i32>> = vec![vec![]; 4];
+        for partition in partitions.into_iter().flatten() {
+            partitioner.partition(partition, |partition, part| {
+                let values = part
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                parts[partition].extend(values.values().iter());
+                Ok(())
+            })?;
+        }
+        // Descending order puts the highest values into the first partition
+        for (partition, values) in parts.iter_mut().enumerate() {
+            values.sort();
+            let end = 100 - 25 * partition as i32;
+            assert_eq!(values, &(end - 25..end).collect::<Vec<_>>());
+        }
+        Ok(())
+    }
+}
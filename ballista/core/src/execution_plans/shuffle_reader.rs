@@ -21,34 +21,42 @@ use std::any::Any;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::Read;
 use std::pin::Pin;
 use std::result;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use crate::client::BallistaClient;
+use crate::config::{BallistaConfig, ShuffleCompression};
+use crate::execution_plans::sort_shuffle::{is_sort_shuffle_path, read_partition};
 use crate::serde::scheduler::{PartitionLocation, PartitionStats};
+use crate::shuffle_compression::{read_shuffle_stream, ShuffleFileReader};
+use crate::shuffle_storage::{is_object_store_path, read_shuffle_file};
 
 use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::arrow::error::ArrowError;
 use datafusion::arrow::record_batch::RecordBatch;
 
-use datafusion::error::Result;
+use datafusion::error::{DataFusionError, Result};
 use datafusion::physical_plan::expressions::PhysicalSortExpr;
-use datafusion::physical_plan::metrics::{ExecutionPlanMetricsSet, MetricsSet};
+use datafusion::physical_plan::metrics::{
+    self, ExecutionPlanMetricsSet, MetricBuilder, MetricsSet,
+};
 use datafusion::physical_plan::{
     ColumnStatistics, DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, RecordBatchStream,
     SendableRecordBatchStream, Statistics,
 };
-use futures::{Stream, StreamExt, TryStreamExt};
+use futures::{Stream, StreamExt};
 
 use crate::error::BallistaError;
 use datafusion::execution::context::TaskContext;
+use datafusion::execution::runtime_env::RuntimeEnv;
 use datafusion::physical_plan::common::AbortOnDropMany;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use itertools::Itertools;
-use log::{error, info};
+use log::{error, info, warn};
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
 use tokio::sync::{mpsc, Semaphore};
@@ -132,8 +140,6 @@ impl ExecutionPlan for ShuffleReaderExec {
         let task_id = context.task_id().unwrap_or_else(|| partition.to_string());
         info!("ShuffleReaderExec::execute({})", task_id);
 
-        // TODO make the maximum size configurable, or make it depends on global memory control
-        let max_request_num = 50usize;
         let mut partition_locations = HashMap::new();
         for p in &self.partition[partition] {
             partition_locations
@@ -151,11 +157,22 @@ impl ExecutionPlan for ShuffleReaderExec {
         // Shuffle partitions for evenly send fetching partition requests to avoid hot executors within multiple tasks
         partition_locations.shuffle(&mut thread_rng());
 
-        let response_receiver = send_fetch_partitions(partition_locations, max_request_num);
+        let options = match context.session_config().get_extension::<BallistaConfig>() {
+            Some(config) => FetchOptions::new(&config),
+            None => FetchOptions::new(&BallistaConfig::new().map_err(|e| {
+                DataFusionError::ArrowError(ArrowError::ExternalError(Box::new(e)))
+            })?),
+        };
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            options,
+            context.runtime_env(),
+            FetchMetrics::new(partition, &self.metrics),
+        );
 
         let result = RecordBatchStreamAdapter::new(
             Arc::new(self.schema.as_ref().clone()),
-            response_receiver.try_flatten(),
+            response_receiver,
         );
         Ok(Box::pin(result))
     }
@@ -199,17 +216,17 @@ fn stats_for_partitions(
     }
 }
 
-struct LocalShuffleStream {
-    reader: StreamReader<BufReader<File>>,
+struct LocalShuffleStream<R: Read> {
+    reader: StreamReader<R>,
 }
 
-impl LocalShuffleStream {
-    pub fn new(reader: StreamReader<BufReader<File>>) -> Self {
+impl<R: Read> LocalShuffleStream<R> {
+    pub fn new(reader: StreamReader<R>) -> Self {
         LocalShuffleStream { reader }
     }
 }
 
-impl Stream for LocalShuffleStream {
+impl<R: Read + Unpin> Stream for LocalShuffleStream<R> {
     type Item = Result<RecordBatch>;
 
     fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -220,15 +237,75 @@ impl Stream for LocalShuffleStream {
     }
 }
 
-impl RecordBatchStream for LocalShuffleStream {
+impl<R: Read + Unpin> RecordBatchStream for LocalShuffleStream<R> {
     fn schema(&self) -> SchemaRef {
         self.reader.schema()
     }
 }
 
-/// Adapter for a tokio ReceiverStream that implements the SendableRecordBatchStream interface
+/// Limits on the fetches of the shuffle partitions a task reads, and how failed fetches
+/// are retried
+#[derive(Debug, Clone)]
+struct FetchOptions {
+    /// The maximum number of partitions fetched from other executors at the same time,
+    /// which is also the number of batches buffered ahead of the reader
+    max_requests: usize,
+    /// The maximum total size of the partitions fetched at the same time
+    max_bytes_in_flight: usize,
+    max_retries: usize,
+    /// The wait before the first retry, which doubles with every further retry
+    retry_backoff: Duration,
+    compression: ShuffleCompression,
+}
+
+impl FetchOptions {
+    fn new(config: &BallistaConfig) -> Self {
+        Self {
+            max_requests: config.shuffle_fetch_max_requests().max(1),
+            // the size of a fetch is counted in semaphore permits, which are acquired as u32
+            max_bytes_in_flight: config
+                .shuffle_fetch_max_bytes_in_flight()
+                .clamp(1, u32::MAX as usize),
+            max_retries: config.shuffle_fetch_max_retries(),
+            retry_backoff: config.shuffle_fetch_retry_backoff(),
+            compression: config.shuffle_compression(),
+        }
+    }
+
+    /// The wait before retrying a fetch which failed `attempt` times
+    fn backoff(&self, attempt: usize) -> Duration {
+        self.retry_backoff
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1) as u32))
+            .unwrap_or(MAX_FETCH_RETRY_BACKOFF)
+            .min(MAX_FETCH_RETRY_BACKOFF)
+    }
+}
+
+const MAX_FETCH_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+struct FetchMetrics {
+    /// Number of fetches retried after they failed
+    retries: metrics::Count,
+    /// Time spent waiting for fetched batches
+    wait_time: metrics::Time,
+}
+
+impl FetchMetrics {
+    fn new(partition: usize, metrics: &ExecutionPlanMetricsSet) -> Self {
+        let retries = MetricBuilder::new(metrics).counter("fetch_retries", partition);
+        let wait_time = MetricBuilder::new(metrics).subset_time("fetch_wait_time", partition);
+        Self { retries, wait_time }
+    }
+}
+
+/// Adapter for a tokio ReceiverStream of fetched batches that implements the
+/// SendableRecordBatchStream interface
 struct AbortableReceiverStream {
-    inner: ReceiverStream<result::Result<SendableRecordBatchStream, BallistaError>>,
+    inner: ReceiverStream<result::Result<RecordBatch, BallistaError>>,
+    wait_time: metrics::Time,
+    /// When the reader started to wait for the next batch
+    wait_start: Option<Instant>,
 
     #[allow(dead_code)]
     drop_helper: AbortOnDropMany<()>,
@@ -237,33 +314,54 @@ struct AbortableReceiverStream {
 impl AbortableReceiverStream {
     /// Construct a new SendableRecordBatchReceiverStream which will send batches of the specified schema from inner
     pub fn create(
-        rx: tokio::sync::mpsc::Receiver<result::Result<SendableRecordBatchStream, BallistaError>>,
+        rx: tokio::sync::mpsc::Receiver<result::Result<RecordBatch, BallistaError>>,
         join_handles: Vec<JoinHandle<()>>,
+        wait_time: metrics::Time,
     ) -> AbortableReceiverStream {
         let inner = ReceiverStream::new(rx);
         Self {
             inner,
+            wait_time,
+            wait_start: None,
             drop_helper: AbortOnDropMany(join_handles),
         }
     }
 }
 
 impl Stream for AbortableReceiverStream {
-    type Item = result::Result<SendableRecordBatchStream, ArrowError>;
+    type Item = Result<RecordBatch>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.inner
-            .poll_next_unpin(cx)
-            .map_err(|e| ArrowError::ExternalError(Box::new(e)))
+        match self.inner.poll_next_unpin(cx) {
+            Poll::Pending => {
+                self.wait_start.get_or_insert_with(Instant::now);
+                Poll::Pending
+            }
+            Poll::Ready(batch) => {
+                if let Some(wait_start) = self.wait_start.take() {
+                    self.wait_time.add_elapsed(wait_start);
+                }
+                Poll::Ready(batch.map(|batch| {
+                    batch.map_err(|e| {
+                        DataFusionError::ArrowError(ArrowError::ExternalError(Box::new(e)))
+                    })
+                }))
+            }
+        }
     }
 }
 
+/// Fetch the partitions and pre-fetch their batches into a buffer of bounded size, from
+/// which the returned stream reads them
 fn send_fetch_partitions(
     partition_locations: Vec<PartitionLocation>,
-    max_request_num: usize,
+    options: FetchOptions,
+    runtime: Arc<RuntimeEnv>,
+    metrics: FetchMetrics,
 ) -> AbortableReceiverStream {
-    let (response_sender, response_receiver) = mpsc::channel(max_request_num);
-    let semaphore = Arc::new(Semaphore::new(max_request_num));
+    let (response_sender, response_receiver) = mpsc::channel(options.max_requests);
+    let requests = Arc::new(Semaphore::new(options.max_requests));
+    let bytes_in_flight = Arc::new(Semaphore::new(options.max_bytes_in_flight));
     let mut join_handles = vec![];
     let (local_locations, remote_locations): (Vec<_>, Vec<_>) = partition_locations
         .into_iter()
@@ -279,32 +377,127 @@ fn send_fetch_partitions(
     let response_sender_c = response_sender.clone();
     let join_handle = tokio::spawn(async move {
         for p in local_locations {
-            let r = fetch_partition_local(&p).await;
-            if let Err(e) = response_sender_c.send(r).await {
-                error!("Fail to send response event to the channel due to {}", e);
+            let sent = match fetch_partition_local(&p).await {
+                Ok(stream) => send_batches(stream, &p, &response_sender_c).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                if let Err(e) = response_sender_c.send(Err(e)).await {
+                    error!("Fail to send response event to the channel due to {}", e);
+                }
             }
         }
     });
     join_handles.push(join_handle);
 
     for p in remote_locations.into_iter() {
-        let semaphore = semaphore.clone();
+        let requests = requests.clone();
+        let bytes_in_flight = bytes_in_flight.clone();
         let response_sender = response_sender.clone();
+        let runtime = runtime.clone();
+        let options = options.clone();
+        let metrics = metrics.clone();
         let join_handle = tokio::spawn(async move {
-            // Block if exceeds max request number
-            let permit = semaphore.acquire_owned().await.unwrap();
-            let r = fetch_partition_remote(&p).await;
-            // Block if the channel buffer is ful
-            if let Err(e) = response_sender.send(r).await {
-                error!("Fail to send response event to the channel due to {}", e);
+            // Block if exceeds max request number or size
+            let num_bytes = p
+                .partition_stats
+                .num_bytes
+                .unwrap_or_default()
+                .clamp(1, options.max_bytes_in_flight as u64);
+            let request_permit = requests.acquire_owned().await.unwrap();
+            let bytes_permit = bytes_in_flight
+                .acquire_many_owned(num_bytes as u32)
+                .await
+                .unwrap();
+            if let Err(e) =
+                fetch_partition_with_retries(&p, &options, &runtime, &metrics, &response_sender)
+                    .await
+            {
+                if let Err(e) = response_sender.send(Err(e)).await {
+                    error!("Fail to send response event to the channel due to {}", e);
+                }
             }
             // Increase semaphore by dropping existing permits.
-            drop(permit);
+            drop(bytes_permit);
+            drop(request_permit);
         });
         join_handles.push(join_handle);
     }
 
-    AbortableReceiverStream::create(response_receiver, join_handles)
+    AbortableReceiverStream::create(response_receiver, join_handles, metrics.wait_time)
+}
+
+/// Fetch a partition from another executor or an object store and send its batches to
+/// the buffer. A fetch is retried with exponential backoff if it fails before any batch
+/// was sent, as retrying it later would send those batches twice.
+async fn fetch_partition_with_retries(
+    location: &PartitionLocation,
+    options: &FetchOptions,
+    runtime: &RuntimeEnv,
+    metrics: &FetchMetrics,
+    response_sender: &mpsc::Sender<result::Result<RecordBatch, BallistaError>>,
+) -> result::Result<(), BallistaError> {
+    let mut attempt = 0;
+    loop {
+        // Partitions in an object store are read directly rather than from the
+        // executor which wrote them, which may be gone by now
+        let stream = if is_object_store_path(&location.path) {
+            fetch_partition_object_store(location, runtime).await
+        } else {
+            fetch_partition_remote(location, options.compression).await
+        };
+        let error = match stream {
+            Ok(mut stream) => match stream.next().await {
+                Some(Ok(batch)) => {
+                    if response_sender.send(Ok(batch)).await.is_err() {
+                        // the reader is gone
+                        return Ok(());
+                    }
+                    return send_batches(stream, location, response_sender).await;
+                }
+                Some(Err(e)) => fetch_failed(location, e.to_string()),
+                None => return Ok(()),
+            },
+            Err(e) => e,
+        };
+
+        attempt += 1;
+        if attempt > options.max_retries {
+            return Err(error);
+        }
+        let backoff = options.backoff(attempt);
+        warn!(
+            "Failed to fetch shuffle partition {:?} from {}, retrying in {:?} ({}/{}): {}",
+            location.partition_id, location.path, backoff, attempt, options.max_retries, error
+        );
+        metrics.retries.add(1);
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+/// Send the batches of a fetched partition to the buffer, blocking while it is full
+async fn send_batches(
+    mut stream: SendableRecordBatchStream,
+    location: &PartitionLocation,
+    response_sender: &mpsc::Sender<result::Result<RecordBatch, BallistaError>>,
+) -> result::Result<(), BallistaError> {
+    while let Some(batch) = stream.next().await {
+        let batch = batch.map_err(|e| fetch_failed(location, e.to_string()))?;
+        if response_sender.send(Ok(batch)).await.is_err() {
+            // the reader is gone
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn fetch_failed(location: &PartitionLocation, error: String) -> BallistaError {
+    BallistaError::FetchFailed(
+        location.executor_meta.id.clone(),
+        location.partition_id.stage_id,
+        location.partition_id.partition_id,
+        error,
+    )
 }
 
 fn check_is_local_location(location: &PartitionLocation) -> bool {
@@ -313,6 +506,7 @@ fn check_is_local_location(location: &PartitionLocation) -> bool {
 
 async fn fetch_partition_remote(
     location: &PartitionLocation,
+    compression: ShuffleCompression,
 ) -> result::Result<SendableRecordBatchStream, BallistaError> {
     let metadata = &location.executor_meta;
     let partition_id = &location.partition_id;
@@ -320,51 +514,56 @@ async fn fetch_partition_remote(
     // And we should also avoid to keep alive too many connections for long time.
     let host = metadata.host.as_str();
     let port = metadata.port;
-    let mut ballista_client =
-        BallistaClient::try_new(host, port)
-            .await
-            .map_err(|error| match error {
-                // map grpc connection error to partition fetch error.
-                BallistaError::GrpcConnectionError(msg) => BallistaError::FetchFailed(
-                    metadata.id.clone(),
-                    partition_id.stage_id,
-                    partition_id.partition_id,
-                    msg,
-                ),
-                other => other,
-            })?;
+    let mut ballista_client = BallistaClient::try_new(host, port)
+        .await
+        .map_err(|error| match error {
+            // map grpc connection error to partition fetch error.
+            BallistaError::GrpcConnectionError(msg) => BallistaError::FetchFailed(
+                metadata.id.clone(),
+                partition_id.stage_id,
+                partition_id.partition_id,
+                msg,
+            ),
+            other => other,
+        })?
+        .with_compression(compression);
 
     ballista_client
         .fetch_partition(&metadata.id, partition_id, &location.path, host, port)
         .await
 }
 
+async fn fetch_partition_object_store(
+    location: &PartitionLocation,
+    runtime: &RuntimeEnv,
+) -> result::Result<SendableRecordBatchStream, BallistaError> {
+    read_shuffle_file(runtime, &location.path, location.partition_id.partition_id)
+        .await
+        .map_err(|e| fetch_failed(location, e.to_string()))
+}
+
 async fn fetch_partition_local(
     location: &PartitionLocation,
 ) -> result::Result<SendableRecordBatchStream, BallistaError> {
     let path = &location.path;
-    let metadata = &location.executor_meta;
-    let partition_id = &location.partition_id;
 
-    let reader = fetch_partition_local_inner(path).map_err(|e| {
-        // return BallistaError::FetchFailed may let scheduler retry this task.
-        BallistaError::FetchFailed(
-            metadata.id.clone(),
-            partition_id.stage_id,
-            partition_id.partition_id,
-            e.to_string(),
-        )
-    })?;
+    // return BallistaError::FetchFailed may let scheduler retry this task.
+    // A sort-based shuffle data file holds all partitions of the map task
+    if is_sort_shuffle_path(path) {
+        let reader = read_partition(path, location.partition_id.partition_id)
+            .map_err(|e| fetch_failed(location, e.to_string()))?;
+        return Ok(Box::pin(LocalShuffleStream::new(reader)));
+    }
+    let reader =
+        fetch_partition_local_inner(path).map_err(|e| fetch_failed(location, e.to_string()))?;
     Ok(Box::pin(LocalShuffleStream::new(reader)))
 }
 
-fn fetch_partition_local_inner(
-    path: &str,
-) -> result::Result<StreamReader<BufReader<File>>, BallistaError> {
+fn fetch_partition_local_inner(path: &str) -> result::Result<ShuffleFileReader, BallistaError> {
     let file = File::open(path).map_err(|e| {
         BallistaError::General(format!("Failed to open partition file at {path}: {e:?}"))
     })?;
-    let reader = StreamReader::try_new(file, None).map_err(|e| {
+    let reader = read_shuffle_stream(file).map_err(|e| {
         BallistaError::General(format!("Failed to new arrow FileReader at {path}: {e:?}"))
     })?;
     Ok(reader)
@@ -373,9 +572,15 @@ fn fetch_partition_local_inner(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{
+        BallistaConfig, BALLISTA_SHUFFLE_FETCH_MAX_REQUESTS, BALLISTA_SHUFFLE_FETCH_MAX_RETRIES,
+        BALLISTA_SHUFFLE_FETCH_RETRY_BACKOFF_MS, BALLISTA_SHUFFLE_SORT_BASED,
+    };
     use crate::execution_plans::ShuffleWriterExec;
     use crate::serde::scheduler::{ExecutorMetadata, ExecutorSpecification, PartitionId};
     use crate::utils;
+    use async_trait::async_trait;
+    use bytes::Bytes;
     use datafusion::arrow::array::{Int32Array, StringArray, UInt32Array};
     use datafusion::arrow::datatypes::{DataType, Field, Schema};
     use datafusion::arrow::ipc::writer::StreamWriter;
@@ -384,8 +589,19 @@ mod tests {
     use datafusion::physical_expr::expressions::Column;
     use datafusion::physical_plan::common;
     use datafusion::physical_plan::memory::MemoryExec;
-    use datafusion::prelude::SessionContext;
+    use datafusion::prelude::{SessionConfig, SessionContext};
+    use futures::stream::BoxStream;
+    use object_store::memory::InMemory;
+    use object_store::path::Path as ObjectPath;
+    use object_store::{
+        GetOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, PutOptions,
+        PutResult,
+    };
+    use std::fmt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use tempfile::{tempdir, TempDir};
+    use tokio::io::AsyncWrite;
+    use url::Url;
 
     #[tokio::test]
     async fn test_stats_for_partitions_empty() {
@@ -555,6 +771,41 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_read_sort_based_shuffle() {
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_SHUFFLE_SORT_BASED, "true")
+            .build()
+            .unwrap();
+        let session_ctx =
+            SessionContext::new_with_config(SessionConfig::new().with_extension(Arc::new(config)));
+        let task_ctx = session_ctx.task_ctx();
+        let work_dir = TempDir::new().unwrap();
+        let input = ShuffleWriterExec::new(
+            "sort_based".to_owned(),
+            1,
+            create_test_data_plan().unwrap(),
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 1)),
+        );
+
+        let mut stream = input.execute(0, task_ctx).unwrap();
+        let batches = utils::collect_stream(&mut stream)
+            .await
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))
+            .unwrap();
+        let path = batches[0].columns()[1]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(path.value(0).ends_with("shuffle-0.data"));
+
+        let location = get_test_partition_locations(1, path.value(0).to_owned()).remove(0);
+        let stream = fetch_partition_local(&location).await.unwrap();
+        let result = common::collect(stream).await.unwrap();
+        assert_eq!(result, vec![create_test_batch(), create_test_batch()]);
+    }
+
     async fn test_send_fetch_partitions(max_request_num: usize, partition_num: usize) {
         let schema = get_test_partition_schema();
         let data_array = Int32Array::from(vec![1]);
@@ -570,15 +821,170 @@ mod tests {
         let partition_locations =
             get_test_partition_locations(partition_num, file_path.to_str().unwrap().to_string());
 
-        let response_receiver = send_fetch_partitions(partition_locations, max_request_num);
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            test_fetch_options(max_request_num, 0),
+            Arc::new(RuntimeEnv::default()),
+            FetchMetrics::new(0, &ExecutionPlanMetricsSet::new()),
+        );
 
-        let stream =
-            RecordBatchStreamAdapter::new(Arc::new(schema), response_receiver.try_flatten());
+        let stream = RecordBatchStreamAdapter::new(Arc::new(schema), response_receiver);
 
         let result = common::collect(Box::pin(stream)).await.unwrap();
         assert_eq!(partition_num, result.len());
     }
 
+    #[tokio::test]
+    async fn test_read_object_store_shuffle() {
+        let schema = get_test_partition_schema();
+        let data_array = Int32Array::from(vec![1]);
+        let batch =
+            RecordBatch::try_new(Arc::new(schema.clone()), vec![Arc::new(data_array)]).unwrap();
+        let tmp_dir = tempdir().unwrap();
+        let file_path = tmp_dir.path().join("shuffle_data");
+        let file = File::create(&file_path).unwrap();
+        let mut writer = StreamWriter::try_new(file, &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+
+        // The executors which wrote the partitions are not needed to read them
+        let url = format!("file://{}", file_path.to_str().unwrap());
+        let partition_locations = get_test_partition_locations(3, url);
+
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            test_fetch_options(2, 0),
+            Arc::new(RuntimeEnv::default()),
+            FetchMetrics::new(0, &ExecutionPlanMetricsSet::new()),
+        );
+        let stream = RecordBatchStreamAdapter::new(Arc::new(schema), response_receiver);
+
+        let result = common::collect(Box::pin(stream)).await.unwrap();
+        assert_eq!(result, vec![batch.clone(), batch.clone(), batch]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_fetches() {
+        let schema = get_test_partition_schema();
+        // Neither the object store nor the file exist, so that every attempt fails
+        let url = "file:///ballista/missing/shuffle_data".to_owned();
+        let partition_locations = get_test_partition_locations(2, url);
+
+        let metrics_set = ExecutionPlanMetricsSet::new();
+        let metrics = FetchMetrics::new(0, &metrics_set);
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            test_fetch_options(1, 2),
+            Arc::new(RuntimeEnv::default()),
+            metrics.clone(),
+        );
+        let stream = RecordBatchStreamAdapter::new(Arc::new(schema), response_receiver);
+
+        let error = utils::collect_stream(&mut (Box::pin(stream) as SendableRecordBatchStream))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, BallistaError::FetchFailed(_, _, _, _)));
+        // The first failed location fails the read, after it was retried twice
+        assert!(metrics.retries.value() >= 2);
+        assert!(metrics.wait_time.value() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_fetch_succeeds() -> Result<()> {
+        let schema = Arc::new(get_test_partition_schema());
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+        )?;
+        let store = TestObjectStore::with_failures(2);
+        store.put_batch("job/1/0/data.arrow", &batch).await?;
+        let runtime = Arc::new(RuntimeEnv::default());
+        let store_url = Url::parse("test://bucket").unwrap();
+        runtime.register_object_store(&store_url, Arc::new(store.clone()));
+
+        let partition_locations =
+            get_test_partition_locations(1, "test://bucket/job/1/0/data.arrow".to_owned());
+        let metrics = FetchMetrics::new(0, &ExecutionPlanMetricsSet::new());
+        let response_receiver = send_fetch_partitions(
+            partition_locations,
+            test_fetch_options(1, 3),
+            runtime,
+            metrics.clone(),
+        );
+        let stream = RecordBatchStreamAdapter::new(schema, response_receiver);
+
+        // The batches of the failed attempts are not sent again once a retry succeeds
+        let result = common::collect(Box::pin(stream)).await?;
+        assert_eq!(result, vec![batch]);
+        assert_eq!(metrics.retries.value(), 2);
+        assert_eq!(store.fetches(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_limit_bytes_in_flight() -> Result<()> {
+        async fn max_concurrent_fetches(max_bytes_in_flight: usize) -> Result<usize> {
+            let schema = Arc::new(get_test_partition_schema());
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+            )?;
+            let store = TestObjectStore::with_failures(0);
+            store.put_batch("job/1/0/data.arrow", &batch).await?;
+            let runtime = Arc::new(RuntimeEnv::default());
+            let store_url = Url::parse("test://bucket").unwrap();
+            runtime.register_object_store(&store_url, Arc::new(store.clone()));
+
+            let mut partition_locations =
+                get_test_partition_locations(4, "test://bucket/job/1/0/data.arrow".to_owned());
+            for location in &mut partition_locations {
+                location.partition_stats = PartitionStats::new(None, None, Some(100));
+            }
+            let options = FetchOptions {
+                max_bytes_in_flight,
+                ..test_fetch_options(4, 0)
+            };
+            let response_receiver = send_fetch_partitions(
+                partition_locations,
+                options,
+                runtime,
+                FetchMetrics::new(0, &ExecutionPlanMetricsSet::new()),
+            );
+            let stream = RecordBatchStreamAdapter::new(schema, response_receiver);
+
+            let result = common::collect(Box::pin(stream)).await?;
+            assert_eq!(result.len(), 4);
+            Ok(store.max_concurrent_fetches())
+        }
+
+        // Only one partition at a time fits into the bytes in flight
+        assert_eq!(max_concurrent_fetches(150).await?, 1);
+        assert!(max_concurrent_fetches(400).await? > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_retry_backoff() {
+        let options = test_fetch_options(1, 10);
+        assert_eq!(Duration::from_millis(1), options.backoff(1));
+        assert_eq!(Duration::from_millis(2), options.backoff(2));
+        assert_eq!(Duration::from_millis(8), options.backoff(4));
+        assert_eq!(MAX_FETCH_RETRY_BACKOFF, options.backoff(64));
+    }
+
+    fn test_fetch_options(max_requests: usize, max_retries: usize) -> FetchOptions {
+        let config = BallistaConfig::builder()
+            .set(
+                BALLISTA_SHUFFLE_FETCH_MAX_REQUESTS,
+                &max_requests.to_string(),
+            )
+            .set(BALLISTA_SHUFFLE_FETCH_MAX_RETRIES, &max_retries.to_string())
+            .set(BALLISTA_SHUFFLE_FETCH_RETRY_BACKOFF_MS, "1")
+            .build()
+            .unwrap();
+        FetchOptions::new(&config)
+    }
+
     fn get_test_partition_locations(n: usize, path: String) -> Vec<PartitionLocation> {
         (0..n)
             .map(|partition_id| PartitionLocation {
@@ -601,6 +1007,130 @@ mod tests {
             .collect()
     }
 
+    /// An in-memory object store whose first fetches fail, and which records how many
+    /// fetches were running at the same time
+    #[derive(Debug, Clone)]
+    struct TestObjectStore {
+        inner: Arc<InMemory>,
+        failures: usize,
+        fetches: Arc<AtomicUsize>,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    impl TestObjectStore {
+        fn with_failures(failures: usize) -> Self {
+            Self {
+                inner: Arc::new(InMemory::new()),
+                failures,
+                fetches: Default::default(),
+                running: Default::default(),
+                max_running: Default::default(),
+            }
+        }
+
+        async fn put_batch(&self, path: &str, batch: &RecordBatch) -> Result<()> {
+            let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema())?;
+            writer.write(batch)?;
+            writer.finish()?;
+            self.inner
+                .put(&ObjectPath::from(path), writer.into_inner()?.into())
+                .await?;
+            Ok(())
+        }
+
+        fn fetches(&self) -> usize {
+            self.fetches.load(Ordering::SeqCst)
+        }
+
+        fn max_concurrent_fetches(&self) -> usize {
+            self.max_running.load(Ordering::SeqCst)
+        }
+    }
+
+    impl fmt::Display for TestObjectStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "TestObjectStore")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for TestObjectStore {
+        async fn put_opts(
+            &self,
+            location: &ObjectPath,
+            bytes: Bytes,
+            opts: PutOptions,
+        ) -> object_store::Result<PutResult> {
+            self.inner.put_opts(location, bytes, opts).await
+        }
+
+        async fn put_multipart(
+            &self,
+            location: &ObjectPath,
+        ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+            self.inner.put_multipart(location).await
+        }
+
+        async fn abort_multipart(
+            &self,
+            location: &ObjectPath,
+            multipart_id: &MultipartId,
+        ) -> object_store::Result<()> {
+            self.inner.abort_multipart(location, multipart_id).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &ObjectPath,
+            options: GetOptions,
+        ) -> object_store::Result<GetResult> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            // Give other fetches the chance to start while this one is running
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            if self.fetches.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(object_store::Error::Generic {
+                    store: "TestObjectStore",
+                    source: "fetch failed".into(),
+                });
+            }
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &ObjectPath) -> object_store::Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(
+            &self,
+            prefix: Option<&ObjectPath>,
+        ) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&ObjectPath>,
+        ) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &ObjectPath, to: &ObjectPath) -> object_store::Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(
+            &self,
+            from: &ObjectPath,
+            to: &ObjectPath,
+        ) -> object_store::Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
     fn get_test_partition_schema() -> Schema {
         Schema::new(vec![Field::new("id", DataType::Int32, false)])
     }
@@ -20,20 +20,24 @@
 //! partition is re-partitioned and streamed to disk in Arrow IPC format. Future stages of the query
 //! will use the ShuffleReaderExec to read these results.
 
-use datafusion::arrow::ipc::writer::IpcWriteOptions;
-use datafusion::arrow::ipc::CompressionType;
 use datafusion::physical_plan::expressions::PhysicalSortExpr;
 
 use datafusion::arrow::ipc::writer::StreamWriter;
 use std::any::Any;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::future::Future;
 use std::iter::Iterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
+use crate::config::BallistaConfig;
+use crate::execution_plans::range_partition::{RangePartitioner, RangePartitioning};
+use crate::execution_plans::sort_shuffle::{data_file_name, SortShuffleWriter};
+use crate::shuffle_compression::ShuffleFileWriter;
+use crate::shuffle_storage::upload_shuffle_file;
 use crate::utils;
 
 use crate::serde::protobuf::ShuffleWritePartition;
@@ -61,6 +65,7 @@ use datafusion::execution::context::TaskContext;
 use datafusion::physical_plan::repartition::BatchPartitioner;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use log::{debug, info};
+use tracing::{info_span, Instrument};
 
 /// ShuffleWriterExec represents a section of a query plan that has consistent partitioning and
 /// can be executed as one unit with each partition being executed in parallel. The output of each
@@ -76,17 +81,53 @@ pub struct ShuffleWriterExec {
     plan: Arc<dyn ExecutionPlan>,
     /// Path to write output streams to
     work_dir: String,
+    /// Object store URL prefix which the output streams are uploaded to once written, so
+    /// that they outlive the executor
+    remote_dir: Option<String>,
     /// Optional shuffle output partitioning.
     /// If it's none, it means there's no need to do repartitioning.
     shuffle_output_partitioning: Option<Partitioning>,
+    /// Range partitioning of the output, in place of the shuffle output partitioning
+    range_partitioning: Option<RangePartitioning>,
     /// Execution metrics
     metrics: ExecutionPlanMetricsSet,
 }
 
+/// Splits the input batches by the shuffle output partitioning
+enum ShufflePartitioner {
+    Batch(BatchPartitioner),
+    Range(RangePartitioner),
+    /// Sends each batch to the next output partition in turn
+    RoundRobin {
+        next_partition: usize,
+        num_partitions: usize,
+    },
+}
+
+impl ShufflePartitioner {
+    fn partition<F>(&mut self, batch: RecordBatch, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, RecordBatch) -> Result<()>,
+    {
+        match self {
+            ShufflePartitioner::Batch(partitioner) => partitioner.partition(batch, f),
+            ShufflePartitioner::Range(partitioner) => partitioner.partition(batch, f),
+            ShufflePartitioner::RoundRobin {
+                next_partition,
+                num_partitions,
+            } => {
+                let partition = *next_partition;
+                *next_partition = (partition + 1) % *num_partitions;
+                f(partition, batch)
+            }
+        }
+    }
+}
+
 pub struct WriteTracker {
     pub num_batches: usize,
     pub num_rows: usize,
-    pub writer: StreamWriter<File>,
+    pub writer: StreamWriter<ShuffleFileWriter<File>>,
     pub path: PathBuf,
 }
 
@@ -97,6 +138,11 @@ struct ShuffleWriteMetrics {
     repart_time: metrics::Time,
     input_rows: metrics::Count,
     output_rows: metrics::Count,
+    /// In-memory size of the batches written to shuffle files
+    uncompressed_bytes: metrics::Count,
+    /// Size of the shuffle files written. The compression ratio is the ratio of the
+    /// uncompressed bytes to it, which unlike a ratio can be summed across tasks.
+    output_bytes: metrics::Count,
 }
 
 impl ShuffleWriteMetrics {
@@ -108,11 +154,17 @@ impl ShuffleWriteMetrics {
 
         let output_rows = MetricBuilder::new(metrics).output_rows(partition);
 
+        let uncompressed_bytes =
+            MetricBuilder::new(metrics).counter("uncompressed_bytes", partition);
+        let output_bytes = MetricBuilder::new(metrics).counter("output_bytes", partition);
+
         Self {
             write_time,
             repart_time,
             input_rows,
             output_rows,
+            uncompressed_bytes,
+            output_bytes,
         }
     }
 }
@@ -131,11 +183,43 @@ impl ShuffleWriterExec {
             stage_id,
             plan,
             work_dir,
+            remote_dir: None,
             shuffle_output_partitioning,
+            range_partitioning: None,
+            metrics: ExecutionPlanMetricsSet::new(),
+        }
+    }
+
+    /// Range partition the output by the given sort order. The samples of the sort keys
+    /// become the second child of this plan.
+    pub fn with_range_partitioning(mut self, range_partitioning: RangePartitioning) -> Self {
+        self.shuffle_output_partitioning = Some(Partitioning::UnknownPartitioning(
+            range_partitioning.partition_count,
+        ));
+        self.range_partitioning = Some(range_partitioning);
+        self
+    }
+
+    /// Write the output streams to a different path, keeping everything else
+    pub fn with_work_dir(&self, work_dir: String) -> Self {
+        Self {
+            work_dir,
             metrics: ExecutionPlanMetricsSet::new(),
+            ..self.clone()
         }
     }
 
+    /// Upload the output streams to an object store under the given URL prefix
+    pub fn with_remote_dir(mut self, remote_dir: impl Into<String>) -> Self {
+        self.remote_dir = Some(remote_dir.into());
+        self
+    }
+
+    /// Get the object store URL prefix the output streams are uploaded to, if any
+    pub fn remote_dir(&self) -> Option<&str> {
+        self.remote_dir.as_deref()
+    }
+
     /// Get the Job ID for this query stage
     pub fn job_id(&self) -> &str {
         &self.job_id
@@ -156,6 +240,11 @@ impl ShuffleWriterExec {
         self.shuffle_output_partitioning.as_ref()
     }
 
+    /// Get the range partitioning of the output, if any
+    pub fn range_partitioning(&self) -> Option<&RangePartitioning> {
+        self.range_partitioning.as_ref()
+    }
+
     pub fn execute_shuffle_write(
         &self,
         input_partition: usize,
@@ -167,13 +256,31 @@ impl ShuffleWriterExec {
 
         let write_metrics = ShuffleWriteMetrics::new(input_partition, &self.metrics);
         let output_partitioning = self.shuffle_output_partitioning.clone();
+        let range_partitioning = self.range_partitioning.clone();
         let plan = self.plan.clone();
+        let work_dir = self.work_dir.clone();
+        let remote_dir = self.remote_dir.clone();
+        let runtime = context.runtime_env();
+        let config = context.session_config().get_extension::<BallistaConfig>();
+        let sort_based = config
+            .as_ref()
+            .map(|config| config.shuffle_sort_based())
+            .unwrap_or(false);
+        let compression = config
+            .map(|config| config.shuffle_compression())
+            .unwrap_or_default();
+        let span = info_span!(
+            "shuffle_write",
+            job_id = self.job_id.as_str(),
+            stage_id = self.stage_id,
+            input_partition,
+        );
 
         async move {
             let now = Instant::now();
-            let mut stream = plan.execute(input_partition, context)?;
+            let mut stream = plan.execute(input_partition, context.clone())?;
 
-            match output_partitioning {
+            let part_locs: Result<Vec<_>> = match output_partitioning {
                 None => {
                     let timer = write_metrics.write_time.timer();
                     path.push(&format!("{input_partition}"));
@@ -183,10 +290,20 @@ impl ShuffleWriterExec {
                     debug!("Writing results to {}", path);
 
                     // stream results to disk
-                    let stats =
-                        utils::write_stream_to_disk(&mut stream, path, &write_metrics.write_time)
-                            .await
-                            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+                    let stats = utils::write_stream_to_disk(
+                        &mut stream,
+                        path,
+                        &write_metrics.write_time,
+                        compression,
+                    )
+                    .await
+                    .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+                    write_metrics
+                        .uncompressed_bytes
+                        .add(stats.num_bytes.unwrap_or(0) as usize);
+                    write_metrics
+                        .output_bytes
+                        .add(fs::metadata(path)?.len() as usize);
 
                     write_metrics
                         .input_rows
@@ -212,101 +329,205 @@ impl ShuffleWriterExec {
                     }])
                 }
 
-                Some(Partitioning::Hash(exprs, num_output_partitions)) => {
-                    // we won't necessary produce output for every possible partition, so we
-                    // create writers on demand
-                    let mut writers: Vec<Option<WriteTracker>> = vec![];
-                    for _ in 0..num_output_partitions {
-                        writers.push(None);
-                    }
+                Some(partitioning) => {
+                    let num_output_partitions = partitioning.partition_count();
+                    let mut partitioner = match (&range_partitioning, partitioning) {
+                        (Some(range_partitioning), _) => {
+                            ShufflePartitioner::Range(
+                                RangePartitioner::try_new(
+                                    range_partitioning,
+                                    &plan.schema(),
+                                    context,
+                                    write_metrics.repart_time.clone(),
+                                )
+                                .await?,
+                            )
+                        }
+                        (None, partitioning @ Partitioning::Hash(_, _)) => {
+                            ShufflePartitioner::Batch(BatchPartitioner::try_new(
+                                partitioning,
+                                write_metrics.repart_time.clone(),
+                            )?)
+                        }
+                        (None, Partitioning::RoundRobinBatch(num_partitions)) => {
+                            // each map task starts at a different output partition, so that
+                            // map tasks with few batches don't all write to the first ones
+                            ShufflePartitioner::RoundRobin {
+                                next_partition: input_partition % num_partitions,
+                                num_partitions,
+                            }
+                        }
+                        (None, _) => {
+                            return Err(DataFusionError::Execution(
+                                "Invalid shuffle partitioning scheme".to_owned(),
+                            ))
+                        }
+                    };
+
+                    if sort_based {
+                        let mut writer = SortShuffleWriter::new(
+                            num_output_partitions,
+                            stream.schema(),
+                            input_partition,
+                            runtime.clone(),
+                            compression,
+                        );
+
+                        while let Some(result) = stream.next().await {
+                            let input_batch = result?;
+
+                            write_metrics.input_rows.add(input_batch.num_rows());
+
+                            partitioner.partition(input_batch, |output_partition, output_batch| {
+                                let timer = write_metrics.write_time.timer();
+                                write_metrics.output_rows.add(output_batch.num_rows());
+                                write_metrics
+                                    .uncompressed_bytes
+                                    .add(output_batch.get_array_memory_size());
+                                writer.write(output_partition, output_batch)?;
+                                timer.done();
+                                Ok(())
+                            })?;
+                        }
 
-                    let mut partitioner = BatchPartitioner::try_new(
-                        Partitioning::Hash(exprs, num_output_partitions),
-                        write_metrics.repart_time.clone(),
-                    )?;
+                        let timer = write_metrics.write_time.timer();
+                        std::fs::create_dir_all(&path)?;
+                        path.push(data_file_name(input_partition));
+                        debug!("Writing results to {:?}", path);
+                        let part_locs = writer.finish(&path)?;
+                        write_metrics.output_bytes.add(
+                            part_locs
+                                .iter()
+                                .map(|part_loc| part_loc.num_bytes as usize)
+                                .sum(),
+                        );
+                        timer.done();
+                        Ok(part_locs)
+                    } else {
+                        // we won't necessary produce output for every possible partition, so we
+                        // create writers on demand
+                        let mut writers: Vec<Option<WriteTracker>> = vec![];
+                        for _ in 0..num_output_partitions {
+                            writers.push(None);
+                        }
 
-                    while let Some(result) = stream.next().await {
-                        let input_batch = result?;
+                        while let Some(result) = stream.next().await {
+                            let input_batch = result?;
+
+                            write_metrics.input_rows.add(input_batch.num_rows());
+
+                            partitioner.partition(input_batch, |output_partition, output_batch| {
+                                // partition func in datafusion make sure not write empty output_batch.
+                                let timer = write_metrics.write_time.timer();
+                                write_metrics
+                                    .uncompressed_bytes
+                                    .add(output_batch.get_array_memory_size());
+                                match &mut writers[output_partition] {
+                                    Some(w) => {
+                                        w.num_batches += 1;
+                                        w.num_rows += output_batch.num_rows();
+                                        w.writer.write(&output_batch)?;
+                                    }
+                                    None => {
+                                        let mut path = path.clone();
+                                        path.push(&format!("{output_partition}"));
+                                        std::fs::create_dir_all(&path)?;
+
+                                        path.push(format!("data-{input_partition}.arrow"));
+                                        debug!("Writing results to {:?}", path);
+
+                                        let options = compression.file_write_options()?;
+
+                                        let file = ShuffleFileWriter::try_new(
+                                            File::create(path.clone())?,
+                                            compression,
+                                        )?;
+                                        let mut writer = StreamWriter::try_new_with_options(
+                                            file,
+                                            stream.schema().as_ref(),
+                                            options,
+                                        )?;
+
+                                        writer.write(&output_batch)?;
+                                        writers[output_partition] = Some(WriteTracker {
+                                            num_batches: 1,
+                                            num_rows: output_batch.num_rows(),
+                                            writer,
+                                            path,
+                                        });
+                                    }
+                                }
+                                write_metrics.output_rows.add(output_batch.num_rows());
+                                timer.done();
+                                Ok(())
+                            })?;
+                        }
 
-                        write_metrics.input_rows.add(input_batch.num_rows());
+                        let mut part_locs = vec![];
 
-                        partitioner.partition(input_batch, |output_partition, output_batch| {
-                            // partition func in datafusion make sure not write empty output_batch.
-                            let timer = write_metrics.write_time.timer();
-                            match &mut writers[output_partition] {
+                        for (i, w) in writers.into_iter().enumerate() {
+                            match w {
                                 Some(w) => {
-                                    w.num_batches += 1;
-                                    w.num_rows += output_batch.num_rows();
-                                    w.writer.write(&output_batch)?;
-                                }
-                                None => {
-                                    let mut path = path.clone();
-                                    path.push(&format!("{output_partition}"));
-                                    std::fs::create_dir_all(&path)?;
-
-                                    path.push(format!("data-{input_partition}.arrow"));
-                                    debug!("Writing results to {:?}", path);
-
-                                    let options = IpcWriteOptions::default()
-                                        .try_with_compression(Some(CompressionType::LZ4_FRAME))?;
-
-                                    let file = File::create(path.clone())?;
-                                    let mut writer = StreamWriter::try_new_with_options(
-                                        file,
-                                        stream.schema().as_ref(),
-                                        options,
-                                    )?;
-
-                                    writer.write(&output_batch)?;
-                                    writers[output_partition] = Some(WriteTracker {
-                                        num_batches: 1,
-                                        num_rows: output_batch.num_rows(),
-                                        writer,
-                                        path,
+                                    w.writer.into_inner()?.finish()?;
+                                    let num_bytes = fs::metadata(&w.path)?.len();
+                                    write_metrics.output_bytes.add(num_bytes as usize);
+                                    debug!(
+                                        "Finished writing shuffle partition {} at {:?}. Batches: {}. Rows: {}. Bytes: {}.",
+                                        i,
+                                        w.path,
+                                        w.num_batches,
+                                        w.num_rows,
+                                        num_bytes
+                                    );
+
+                                    part_locs.push(ShuffleWritePartition {
+                                        partition_id: i as u64,
+                                        path: w.path.to_string_lossy().to_string(),
+                                        num_batches: w.num_batches as u64,
+                                        num_rows: w.num_rows as u64,
+                                        num_bytes,
                                     });
                                 }
+                                None => {}
                             }
-                            write_metrics.output_rows.add(output_batch.num_rows());
-                            timer.done();
-                            Ok(())
-                        })?;
-                    }
-
-                    let mut part_locs = vec![];
-
-                    for (i, w) in writers.iter_mut().enumerate() {
-                        match w {
-                            Some(w) => {
-                                let num_bytes = fs::metadata(&w.path)?.len();
-                                w.writer.finish()?;
-                                debug!(
-                                    "Finished writing shuffle partition {} at {:?}. Batches: {}. Rows: {}. Bytes: {}.",
-                                    i,
-                                    w.path,
-                                    w.num_batches,
-                                    w.num_rows,
-                                    num_bytes
-                                );
-
-                                part_locs.push(ShuffleWritePartition {
-                                    partition_id: i as u64,
-                                    path: w.path.to_string_lossy().to_string(),
-                                    num_batches: w.num_batches as u64,
-                                    num_rows: w.num_rows as u64,
-                                    num_bytes,
-                                });
-                            }
-                            None => {}
                         }
+                        Ok(part_locs)
                     }
-                    Ok(part_locs)
                 }
-
-                _ => Err(DataFusionError::Execution(
-                    "Invalid shuffle partitioning scheme".to_owned(),
-                )),
+            };
+            let mut part_locs = part_locs?;
+            let compression_ratio = write_metrics.uncompressed_bytes.value() as f64
+                / write_metrics.output_bytes.value().max(1) as f64;
+            debug!(
+                "Shuffle output of partition {} compressed with {} at a ratio of {:.2}",
+                input_partition,
+                compression, compression_ratio
+            );
+
+            if let Some(remote_dir) = &remote_dir {
+                let timer = write_metrics.write_time.timer();
+                // The partitions of a sort-based shuffle share a single data file
+                let mut uploaded: HashMap<String, String> = HashMap::new();
+                for part_loc in &mut part_locs {
+                    if let Some(url) = uploaded.get(&part_loc.path) {
+                        part_loc.path = url.clone();
+                        continue;
+                    }
+                    let url = upload_shuffle_file(
+                        &runtime,
+                        Path::new(&part_loc.path),
+                        &work_dir,
+                        remote_dir,
+                    )
+                    .await
+                    .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+                    uploaded.insert(std::mem::replace(&mut part_loc.path, url.clone()), url);
+                }
+                timer.done();
             }
+            Ok(part_locs)
         }
+        .instrument(span)
     }
 }
 
@@ -314,11 +535,24 @@ impl DisplayAs for ShuffleWriterExec {
     fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match t {
             DisplayFormatType::Default | DisplayFormatType::Verbose => {
-                write!(
-                    f,
-                    "ShuffleWriterExec: {:?}",
-                    self.shuffle_output_partitioning
-                )
+                match &self.range_partitioning {
+                    Some(range_partitioning) => write!(
+                        f,
+                        "ShuffleWriterExec: Range([{}], {})",
+                        range_partitioning
+                            .sort_exprs
+                            .iter()
+                            .map(|sort_expr| sort_expr.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        range_partitioning.partition_count
+                    ),
+                    None => write!(
+                        f,
+                        "ShuffleWriterExec: {:?}",
+                        self.shuffle_output_partitioning
+                    ),
+                }
             }
         }
     }
@@ -346,20 +580,31 @@ impl ExecutionPlan for ShuffleWriterExec {
     }
 
     fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
-        vec![self.plan.clone()]
+        match &self.range_partitioning {
+            Some(range_partitioning) => {
+                vec![self.plan.clone(), range_partitioning.samples.clone()]
+            }
+            None => vec![self.plan.clone()],
+        }
     }
 
     fn with_new_children(
         self: Arc<Self>,
         children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(ShuffleWriterExec::new(
-            self.job_id.clone(),
-            self.stage_id,
-            children[0].clone(),
-            self.work_dir.clone(),
-            self.shuffle_output_partitioning.clone(),
-        )))
+        let range_partitioning =
+            self.range_partitioning
+                .clone()
+                .map(|range_partitioning| RangePartitioning {
+                    samples: children[1].clone(),
+                    ..range_partitioning
+                });
+        Ok(Arc::new(ShuffleWriterExec {
+            plan: children[0].clone(),
+            range_partitioning,
+            metrics: ExecutionPlanMetricsSet::new(),
+            ..self.as_ref().clone()
+        }))
     }
 
     fn execute(
@@ -546,6 +791,141 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_round_robin_partitioned() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let input_plan = create_input_plan()?;
+        let work_dir = TempDir::new()?;
+        let query_stage = ShuffleWriterExec::new(
+            "jobOne".to_owned(),
+            1,
+            input_plan,
+            work_dir.into_path().to_str().unwrap().to_owned(),
+            Some(Partitioning::RoundRobinBatch(3)),
+        );
+        let mut stream = query_stage.execute(1, task_ctx)?;
+        let batches = utils::collect_stream(&mut stream)
+            .await
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        assert_eq!(1, batches.len());
+        let batch = &batches[0];
+        // the two input batches are written to the partitions following the input partition
+        let partitions = batch.columns()[0]
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .unwrap();
+        assert_eq!(vec![1, 2], partitions.values().to_vec());
+        let path = batch.columns()[1]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(path.value(0).ends_with("data-1.arrow"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zstd_level_and_compression_ratio() -> Result<()> {
+        let config = BallistaConfig::builder()
+            .set(crate::config::BALLISTA_SHUFFLE_COMPRESSION, "zstd:9")
+            .build()
+            .unwrap();
+        let session_ctx = SessionContext::new_with_config(
+            datafusion::prelude::SessionConfig::new().with_extension(Arc::new(config)),
+        );
+        let task_ctx = session_ctx.task_ctx();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, true)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(vec![7; 10_000]))],
+        )?;
+        let input_plan = Arc::new(MemoryExec::try_new(&[vec![batch.clone()]], schema, None)?);
+        let work_dir = TempDir::new()?;
+        let query_stage = ShuffleWriterExec::new(
+            "jobOne".to_owned(),
+            1,
+            input_plan,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        );
+        let mut stream = query_stage.execute(0, task_ctx)?;
+        let batches = utils::collect_stream(&mut stream)
+            .await
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        let path = batches[0].columns()[1]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .value(0)
+            .to_owned();
+
+        let written = crate::shuffle_compression::read_shuffle_stream(File::open(path)?)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(written, vec![batch]);
+
+        let ratio = compression_ratio(&query_stage.metrics().unwrap(), None);
+        assert!(ratio > 1.0, "compression ratio of {ratio}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compression_ratio_of_multiple_partitions() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, true)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(vec![7; 10_000]))],
+        )?;
+        let input_plan = Arc::new(MemoryExec::try_new(
+            &[vec![batch.clone()], vec![batch.clone()], vec![batch]],
+            schema,
+            None,
+        )?);
+        let work_dir = TempDir::new()?;
+        let query_stage = ShuffleWriterExec::new(
+            "jobOne".to_owned(),
+            1,
+            input_plan,
+            work_dir.path().to_str().unwrap().to_owned(),
+            Some(Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 2)),
+        );
+        for input_partition in 0..3 {
+            let mut stream = query_stage.execute(input_partition, task_ctx.clone())?;
+            utils::collect_stream(&mut stream)
+                .await
+                .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        }
+
+        // The partitions wrote the same data, so the ratio of the bytes summed across them
+        // is the ratio of each of them
+        let metrics = query_stage.metrics().unwrap();
+        let ratio = compression_ratio(&metrics, None);
+        assert!(ratio > 1.0, "compression ratio of {ratio}");
+        for input_partition in 0..3 {
+            let partition_ratio = compression_ratio(&metrics, Some(input_partition));
+            assert!((ratio - partition_ratio).abs() < 1e-9);
+        }
+        Ok(())
+    }
+
+    /// Ratio of the uncompressed to the written bytes of all or one input partition
+    fn compression_ratio(metrics: &MetricsSet, partition: Option<usize>) -> f64 {
+        let sum = |name: &str| {
+            metrics
+                .iter()
+                .filter(|metric| metric.value().name() == name)
+                .filter(|metric| partition.is_none() || metric.partition() == partition)
+                .map(|metric| metric.value().as_usize())
+                .sum::<usize>() as f64
+        };
+        sum("uncompressed_bytes") / sum("output_bytes")
+    }
+
     fn create_input_plan() -> Result<Arc<dyn ExecutionPlan>> {
         let schema = Arc::new(Schema::new(vec![
             Field::new("a", DataType::UInt32, true),
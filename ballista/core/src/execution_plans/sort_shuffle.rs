@@ -0,0 +1,339 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Sort-based shuffle, which writes all output partitions of a map task to a single data
+//! file in the order of their partition ids, next to an index file with the offsets the
+//! partitions start at. Each partition is a complete Arrow IPC stream within the data file,
+//! so a single partition is read from its byte range.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::disk_manager::RefCountedTempFile;
+use datafusion::execution::memory_pool::{MemoryConsumer, MemoryReservation};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use log::debug;
+
+use crate::config::ShuffleCompression;
+use crate::serde::protobuf::ShuffleWritePartition;
+use crate::shuffle_compression::{read_shuffle_stream, ShuffleFileReader, ShuffleFileWriter};
+
+const DATA_FILE_EXTENSION: &str = ".data";
+const INDEX_FILE_EXTENSION: &str = ".index";
+
+/// Whether a local shuffle file is the data file of a sort-based shuffle, which has an
+/// index file next to it
+pub fn is_sort_shuffle_path(path: &str) -> bool {
+    has_data_file_name(path) && Path::new(&index_path(path)).is_file()
+}
+
+/// Whether a shuffle file is named like the data file of a sort-based shuffle. Only files
+/// which also have an index are data files.
+pub fn has_data_file_name(path: &str) -> bool {
+    path.ends_with(DATA_FILE_EXTENSION)
+}
+
+/// The name of the data file of a map task
+pub fn data_file_name(map_partition: usize) -> String {
+    format!("shuffle-{map_partition}{DATA_FILE_EXTENSION}")
+}
+
+/// The path of the index file belonging to a data file
+pub fn index_path(data_path: &str) -> String {
+    let base = data_path
+        .strip_suffix(DATA_FILE_EXTENSION)
+        .unwrap_or(data_path);
+    format!("{base}{INDEX_FILE_EXTENSION}")
+}
+
+/// The byte range of the offsets of a partition and the next one within an index file
+pub fn index_range(partition: usize) -> Range<usize> {
+    partition * 8..(partition + 2) * 8
+}
+
+/// The byte range of a partition within the data file, from its entry in the index
+pub fn partition_range(index_entry: &[u8]) -> Result<Range<u64>> {
+    if index_entry.len() != 16 {
+        return Err(DataFusionError::Execution(format!(
+            "Invalid shuffle index entry of {} bytes",
+            index_entry.len()
+        )));
+    }
+    let start = u64::from_le_bytes(index_entry[..8].try_into().unwrap());
+    let end = u64::from_le_bytes(index_entry[8..].try_into().unwrap());
+    Ok(start..end)
+}
+
+/// Open a single partition of a local data file for reading
+pub fn read_partition(data_path: &str, partition: usize) -> Result<ShuffleFileReader> {
+    let mut index = File::open(index_path(data_path))?;
+    let range = index_range(partition);
+    let mut index_entry = vec![0; range.len()];
+    index.seek(SeekFrom::Start(range.start as u64))?;
+    index.read_exact(&mut index_entry)?;
+    read_segment(Path::new(data_path), partition_range(&index_entry)?)
+}
+
+fn read_segment(path: &Path, range: Range<u64>) -> Result<ShuffleFileReader> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    read_shuffle_stream(file.take(range.end - range.start))
+}
+
+/// Batches of a partition read from the spills while they are written to the data file
+type SpilledBatches = Box<dyn Iterator<Item = Result<RecordBatch>>>;
+
+/// Partitions written to a temporary file when the buffered batches exceeded the memory
+/// available to the writer, in the same layout as a data file
+struct Spill {
+    file: RefCountedTempFile,
+    offsets: Vec<u64>,
+}
+
+/// Buffers the output batches of a map task by output partition, spilling them to disk
+/// under memory pressure, and writes them to a single data file and index once complete
+pub(crate) struct SortShuffleWriter {
+    schema: SchemaRef,
+    runtime: Arc<RuntimeEnv>,
+    compression: ShuffleCompression,
+    buffered: Vec<Vec<RecordBatch>>,
+    spills: Vec<Spill>,
+    reservation: MemoryReservation,
+    num_batches: Vec<u64>,
+    num_rows: Vec<u64>,
+}
+
+impl SortShuffleWriter {
+    pub(crate) fn new(
+        num_partitions: usize,
+        schema: SchemaRef,
+        map_partition: usize,
+        runtime: Arc<RuntimeEnv>,
+        compression: ShuffleCompression,
+    ) -> Self {
+        let reservation = MemoryConsumer::new(format!("SortShuffleWriter[{map_partition}]"))
+            .with_can_spill(true)
+            .register(&runtime.memory_pool);
+        Self {
+            schema,
+            runtime,
+            compression,
+            buffered: vec![vec![]; num_partitions],
+            spills: vec![],
+            reservation,
+            num_batches: vec![0; num_partitions],
+            num_rows: vec![0; num_partitions],
+        }
+    }
+
+    pub(crate) fn write(&mut self, partition: usize, batch: RecordBatch) -> Result<()> {
+        let size = batch.get_array_memory_size();
+        if self.reservation.try_grow(size).is_err() {
+            self.spill()?;
+            // A batch is buffered even if it exceeds the memory available on its own
+            self.reservation.grow(size);
+        }
+        self.num_batches[partition] += 1;
+        self.num_rows[partition] += batch.num_rows() as u64;
+        self.buffered[partition].push(batch);
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        if self.buffered.iter().all(|batches| batches.is_empty()) {
+            return Ok(());
+        }
+        let file = self
+            .runtime
+            .disk_manager
+            .create_tmp_file("SortShuffleWriter spill")?;
+        let buffered: Vec<_> = self.buffered.iter_mut().map(std::mem::take).collect();
+        let offsets = write_partitions(
+            &self.schema,
+            self.compression,
+            file.path(),
+            buffered,
+            |_| Ok(Box::new(std::iter::empty())),
+        )?;
+        debug!(
+            "Spilled {} bytes of shuffle output to {:?}",
+            self.reservation.size(),
+            file.path()
+        );
+        self.reservation.free();
+        self.spills.push(Spill { file, offsets });
+        Ok(())
+    }
+
+    /// Write the data file to `data_path` and the index next to it. Returns the partitions
+    /// which received any rows.
+    pub(crate) fn finish(mut self, data_path: &Path) -> Result<Vec<ShuffleWritePartition>> {
+        let buffered: Vec<_> = self.buffered.iter_mut().map(std::mem::take).collect();
+        let spills = std::mem::take(&mut self.spills);
+        let offsets = write_partitions(
+            &self.schema,
+            self.compression,
+            data_path,
+            buffered,
+            |partition| {
+                // The segments of the partition are decoded batch by batch from the spill files
+                let mut segments = vec![];
+                for spill in &spills {
+                    let range = spill.offsets[partition]..spill.offsets[partition + 1];
+                    if !range.is_empty() {
+                        segments.push(read_segment(spill.file.path(), range)?);
+                    }
+                }
+                let batches = segments.into_iter().flatten();
+                Ok(
+                    Box::new(batches.map(|batch| batch.map_err(DataFusionError::from)))
+                        as SpilledBatches,
+                )
+            },
+        )?;
+        self.reservation.free();
+
+        let mut index = BufWriter::new(File::create(index_path(&data_path.to_string_lossy()))?);
+        for offset in &offsets {
+            index.write_all(&offset.to_le_bytes())?;
+        }
+        index.flush()?;
+
+        let path = data_path.to_string_lossy().to_string();
+        Ok(offsets
+            .windows(2)
+            .enumerate()
+            .filter(|(partition, _)| self.num_rows[*partition] > 0)
+            .map(|(partition, range)| ShuffleWritePartition {
+                partition_id: partition as u64,
+                path: path.clone(),
+                num_batches: self.num_batches[partition],
+                num_rows: self.num_rows[partition],
+                num_bytes: range[1] - range[0],
+            })
+            .collect())
+    }
+}
+
+/// Write the batches of each partition as an IPC stream to `path`, preceded by the batches
+/// `spilled` returns for the partition. Returns the offsets each partition starts at,
+/// followed by the end of the file.
+fn write_partitions(
+    schema: &SchemaRef,
+    compression: ShuffleCompression,
+    path: &Path,
+    buffered: Vec<Vec<RecordBatch>>,
+    spilled: impl Fn(usize) -> Result<SpilledBatches>,
+) -> Result<Vec<u64>> {
+    let options = compression.file_write_options()?;
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut offsets = vec![0];
+    for (partition, batches) in buffered.into_iter().enumerate() {
+        let mut spilled = spilled(partition)?.peekable();
+        if spilled.peek().is_some() || !batches.is_empty() {
+            // Each partition is compressed on its own so that it can be read from its range
+            let out = ShuffleFileWriter::try_new(&mut file, compression)?;
+            let mut writer =
+                StreamWriter::try_new_with_options(out, schema.as_ref(), options.clone())?;
+            for batch in spilled {
+                writer.write(&batch?)?;
+            }
+            for batch in &batches {
+                writer.write(batch)?;
+            }
+            writer.into_inner()?.finish()?;
+        }
+        offsets.push(file.stream_position()?);
+    }
+    file.flush()?;
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::UInt32Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::execution::runtime_env::RuntimeConfig;
+    use tempfile::TempDir;
+
+    fn batch(schema: &SchemaRef, values: Vec<u32>) -> RecordBatch {
+        RecordBatch::try_new(schema.clone(), vec![Arc::new(UInt32Array::from(values))]).unwrap()
+    }
+
+    fn read_all(data_path: &str, partition: usize) -> Vec<RecordBatch> {
+        read_partition(data_path, partition)
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn write_single_data_file_and_index() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        // Too little memory for any batch, so that buffered batches are spilled on each write
+        let runtime = Arc::new(RuntimeEnv::new(
+            RuntimeConfig::new().with_memory_limit(1, 1.0),
+        )?);
+
+        let mut writer =
+            SortShuffleWriter::new(3, schema.clone(), 7, runtime, ShuffleCompression::Zstd(3));
+        writer.write(2, batch(&schema, vec![1, 2]))?;
+        writer.write(0, batch(&schema, vec![3]))?;
+        writer.write(2, batch(&schema, vec![4, 5, 6]))?;
+        writer.write(0, batch(&schema, vec![7]))?;
+        assert!(!writer.spills.is_empty());
+
+        let data_path = work_dir.path().join(data_file_name(7));
+        let partitions = writer.finish(&data_path)?;
+        let data_path = data_path.to_str().unwrap();
+        assert!(is_sort_shuffle_path(data_path));
+        assert!(Path::new(&index_path(data_path)).exists());
+
+        // Files named like data files are only read as such next to an index
+        let hash_path = work_dir.path().join(data_file_name(8));
+        std::fs::write(&hash_path, [])?;
+        assert!(!is_sort_shuffle_path(hash_path.to_str().unwrap()));
+
+        assert_eq!(
+            partitions
+                .iter()
+                .map(|p| (p.partition_id, p.num_batches, p.num_rows))
+                .collect::<Vec<_>>(),
+            vec![(0, 2, 2), (2, 2, 5)]
+        );
+        assert!(partitions.iter().all(|p| p.path == data_path));
+
+        assert_eq!(
+            read_all(data_path, 0),
+            vec![batch(&schema, vec![3]), batch(&schema, vec![7])]
+        );
+        assert_eq!(
+            read_all(data_path, 2),
+            vec![batch(&schema, vec![1, 2]), batch(&schema, vec![4, 5, 6])]
+        );
+        Ok(())
+    }
+}
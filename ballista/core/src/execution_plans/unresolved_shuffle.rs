@@ -58,7 +58,11 @@ impl DisplayAs for UnresolvedShuffleExec {
     fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match t {
             DisplayFormatType::Default | DisplayFormatType::Verbose => {
-                write!(f, "UnresolvedShuffleExec")
+                write!(
+                    f,
+                    "UnresolvedShuffleExec: stage_id={}, partitions={}",
+                    self.stage_id, self.output_partition_count
+                )
             }
         }
     }
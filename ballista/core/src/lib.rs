@@ -18,12 +18,17 @@
 #![doc = include_str!("../README.md")]
 pub const BALLISTA_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+pub mod auth;
 pub mod client;
 pub mod config;
 pub mod error;
 pub mod event_loop;
 pub mod execution_plans;
+pub mod object_store_registry;
 pub mod utils;
 
 #[macro_use]
 pub mod serde;
+pub mod shuffle_compression;
+pub mod shuffle_storage;
+pub mod telemetry;
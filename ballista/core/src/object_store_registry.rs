@@ -0,0 +1,353 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Object stores for table data in S3 compatible stores and on HTTP servers, next to the
+//! local file system
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::object_store::{DefaultObjectStoreRegistry, ObjectStoreRegistry};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use object_store::aws::AmazonS3Builder;
+use object_store::http::HttpBuilder;
+use object_store::ObjectStore;
+use url::Url;
+
+use crate::config::{
+    BALLISTA_OBJECT_STORE_PREFIX, BALLISTA_S3_ACCESS_KEY_ID, BALLISTA_S3_ALLOW_HTTP,
+    BALLISTA_S3_ENDPOINT, BALLISTA_S3_REGION,
+};
+
+/// Settings of the object stores created for a session. Settings which are not given
+/// are taken from the standard `AWS_*` environment variables, which is also where the
+/// secret access key always comes from, so that it is never part of a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ObjectStoreConfig {
+    pub s3_endpoint: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_access_key_id: Option<String>,
+    pub s3_allow_http: bool,
+}
+
+impl ObjectStoreConfig {
+    /// Read the object store settings from the settings of a session
+    pub fn from_settings(settings: &HashMap<String, String>) -> Self {
+        Self {
+            s3_endpoint: settings.get(BALLISTA_S3_ENDPOINT).cloned(),
+            s3_region: settings.get(BALLISTA_S3_REGION).cloned(),
+            s3_access_key_id: settings.get(BALLISTA_S3_ACCESS_KEY_ID).cloned(),
+            s3_allow_http: settings
+                .get(BALLISTA_S3_ALLOW_HTTP)
+                .and_then(|allow_http| allow_http.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn build_store(&self, url: &Url) -> Result<Arc<dyn ObjectStore>> {
+        match url.scheme() {
+            "s3" => {
+                let bucket = url.host_str().ok_or_else(|| {
+                    DataFusionError::Configuration(format!("No bucket given in {url}"))
+                })?;
+                let mut builder = AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .with_allow_http(self.s3_allow_http);
+                if let Some(endpoint) = &self.s3_endpoint {
+                    builder = builder.with_endpoint(endpoint);
+                }
+                if let Some(region) = &self.s3_region {
+                    builder = builder.with_region(region);
+                }
+                if let Some(access_key_id) = &self.s3_access_key_id {
+                    builder = builder.with_access_key_id(access_key_id);
+                }
+                Ok(Arc::new(builder.build()?))
+            }
+            "http" | "https" => Ok(Arc::new(
+                HttpBuilder::new()
+                    .with_url(url.origin().ascii_serialization())
+                    .build()?,
+            )),
+            scheme => Err(DataFusionError::Configuration(format!(
+                "No object store available for scheme {scheme}"
+            ))),
+        }
+    }
+}
+
+/// The settings of a session which the object stores are created with, to be passed on
+/// to the executors running the tasks of the session
+pub fn object_store_settings(settings: &HashMap<String, String>) -> HashMap<String, String> {
+    settings
+        .iter()
+        .filter(|(key, _)| key.starts_with(BALLISTA_OBJECT_STORE_PREFIX))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// How long a created store is kept after it was last used
+const STORE_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Settings and base URL of a created store
+type StoreKey = (ObjectStoreConfig, String);
+
+struct CreatedStore {
+    store: Arc<dyn ObjectStore>,
+    last_used: Instant,
+}
+
+/// Stores created by any [`BallistaObjectStoreRegistry`] of the process, by their
+/// settings and base URL. This keeps sessions and tasks from creating a new client
+/// for every scan. Stores which were not used for [`STORE_IDLE_TIMEOUT`] are dropped
+/// whenever a store is created.
+fn created_stores() -> &'static Mutex<HashMap<StoreKey, CreatedStore>> {
+    static STORES: OnceLock<Mutex<HashMap<StoreKey, CreatedStore>>> = OnceLock::new();
+    STORES.get_or_init(Default::default)
+}
+
+/// Get the store created for `key`, creating it if there is none
+fn get_or_create_store(
+    key: StoreKey,
+    now: Instant,
+    create: impl FnOnce() -> Result<Arc<dyn ObjectStore>>,
+) -> Result<Arc<dyn ObjectStore>> {
+    let mut stores = created_stores().lock().unwrap();
+    if let Some(created) = stores.get_mut(&key) {
+        created.last_used = now;
+        return Ok(created.store.clone());
+    }
+    stores.retain(|_, created| now.duration_since(created.last_used) < STORE_IDLE_TIMEOUT);
+    let store = create()?;
+    stores.insert(
+        key,
+        CreatedStore {
+            store: store.clone(),
+            last_used: now,
+        },
+    );
+    Ok(store)
+}
+
+/// An [`ObjectStoreRegistry`] creating the stores for `s3://` and `http(s)://` URLs with
+/// the settings of a session when they are first used. Stores for other URLs, like the
+/// local file system, and stores registered explicitly are looked up in an inner registry.
+#[derive(Debug)]
+pub struct BallistaObjectStoreRegistry {
+    inner: Arc<dyn ObjectStoreRegistry>,
+    config: ObjectStoreConfig,
+}
+
+impl BallistaObjectStoreRegistry {
+    pub fn new(config: ObjectStoreConfig) -> Self {
+        Self::with_inner(Arc::new(DefaultObjectStoreRegistry::new()), config)
+    }
+
+    pub fn with_inner(inner: Arc<dyn ObjectStoreRegistry>, config: ObjectStoreConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// A registry looking up the same registered stores, which creates the other stores
+    /// with different settings
+    pub fn with_config(&self, config: ObjectStoreConfig) -> Self {
+        Self::with_inner(self.inner.clone(), config)
+    }
+}
+
+impl ObjectStoreRegistry for BallistaObjectStoreRegistry {
+    fn register_store(
+        &self,
+        url: &Url,
+        store: Arc<dyn ObjectStore>,
+    ) -> Option<Arc<dyn ObjectStore>> {
+        self.inner.register_store(url, store)
+    }
+
+    fn get_store(&self, url: &Url) -> Result<Arc<dyn ObjectStore>> {
+        match self.inner.get_store(url) {
+            Ok(store) => Ok(store),
+            Err(_) if matches!(url.scheme(), "s3" | "http" | "https") => {
+                let key = (
+                    self.config.clone(),
+                    format!("{}://{}", url.scheme(), url.authority()),
+                );
+                get_or_create_store(key, Instant::now(), || self.config.build_store(url))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A runtime sharing the memory pool, disk manager and caches of `runtime`, which creates
+/// object stores with the given settings
+pub fn with_object_stores(runtime: &RuntimeEnv, config: ObjectStoreConfig) -> Arc<RuntimeEnv> {
+    with_object_store_registry(
+        runtime,
+        BallistaObjectStoreRegistry::with_inner(runtime.object_store_registry.clone(), config),
+    )
+}
+
+/// A runtime sharing the memory pool, disk manager and caches of `runtime`, which looks
+/// up object stores in `registry`
+pub fn with_object_store_registry(
+    runtime: &RuntimeEnv,
+    registry: BallistaObjectStoreRegistry,
+) -> Arc<RuntimeEnv> {
+    Arc::new(RuntimeEnv {
+        memory_pool: runtime.memory_pool.clone(),
+        disk_manager: runtime.disk_manager.clone(),
+        cache_manager: runtime.cache_manager.clone(),
+        object_store_registry: Arc::new(registry),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BallistaConfig, BALLISTA_S3_SECRET_ACCESS_KEY};
+    use crate::utils::default_session_builder;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::prelude::{SessionConfig, SessionContext};
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+
+    #[test]
+    fn create_stores_on_first_use() -> Result<()> {
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_S3_ENDPOINT, "http://localhost:9000")
+            .set(BALLISTA_S3_REGION, "us-east-1")
+            .set(BALLISTA_S3_ALLOW_HTTP, "true")
+            .set("ballista.shuffle.partitions", "4")
+            .build()
+            .unwrap();
+        let settings = object_store_settings(config.settings());
+        assert_eq!(settings.len(), 3);
+
+        let registry =
+            BallistaObjectStoreRegistry::new(ObjectStoreConfig::from_settings(&settings));
+        let bucket = registry.get_store(&Url::parse("s3://bucket/path/file.parquet").unwrap())?;
+        let same_bucket = registry.get_store(&Url::parse("s3://bucket/other.parquet").unwrap())?;
+        let other_bucket = registry.get_store(&Url::parse("s3://other/file.parquet").unwrap())?;
+        assert!(Arc::ptr_eq(&bucket, &same_bucket));
+        assert!(!Arc::ptr_eq(&bucket, &other_bucket));
+
+        registry.get_store(&Url::parse("https://example.com/data/file.csv").unwrap())?;
+        registry.get_store(&Url::parse("file:///tmp/file.csv").unwrap())?;
+        assert!(registry
+            .get_store(&Url::parse("gs://bucket/file.csv").unwrap())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sessions_create_stores_with_their_settings() -> Result<()> {
+        let process = BallistaObjectStoreRegistry::new(ObjectStoreConfig {
+            s3_region: Some("us-east-1".to_owned()),
+            ..Default::default()
+        });
+        let registered = Url::parse("s3://registered-bucket").unwrap();
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        process.register_store(&registered, store.clone());
+
+        let session = process.with_config(ObjectStoreConfig {
+            s3_endpoint: Some("http://localhost:9000".to_owned()),
+            s3_region: Some("us-east-1".to_owned()),
+            ..Default::default()
+        });
+        assert!(Arc::ptr_eq(&session.get_store(&registered)?, &store));
+        let bucket = Url::parse("s3://bucket").unwrap();
+        assert!(!Arc::ptr_eq(
+            &session.get_store(&bucket)?,
+            &process.get_store(&bucket)?
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn secret_access_key_is_no_session_setting() {
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_S3_ACCESS_KEY_ID, "minioadmin")
+            .set(BALLISTA_S3_SECRET_ACCESS_KEY, "minioadmin")
+            .build();
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn evict_idle_stores() -> Result<()> {
+        let now = Instant::now();
+        let idle: StoreKey = (ObjectStoreConfig::default(), "s3://idle-bucket".to_owned());
+        let used: StoreKey = (ObjectStoreConfig::default(), "s3://used-bucket".to_owned());
+        let store = get_or_create_store(idle.clone(), now, || Ok(Arc::new(InMemory::new())))?;
+        let same = get_or_create_store(idle.clone(), now, || unreachable!())?;
+        assert!(Arc::ptr_eq(&store, &same));
+
+        let later = now + STORE_IDLE_TIMEOUT;
+        get_or_create_store(used.clone(), later, || Ok(Arc::new(InMemory::new())))?;
+        let stores = created_stores().lock().unwrap();
+        assert!(!stores.contains_key(&idle));
+        assert!(stores.contains_key(&used));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_table_from_stand_in_store() -> Result<()> {
+        // An in-memory store stands in for the S3 compatible store of the bucket
+        let bucket = Url::parse("s3://bucket").unwrap();
+        let store = Arc::new(InMemory::new());
+        store
+            .put(&Path::from("data/part-0.csv"), "a,b\n1,x\n2,y\n".into())
+            .await?;
+        store
+            .put(&Path::from("data/part-1.csv"), "a,b\n3,z\n".into())
+            .await?;
+
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_S3_ENDPOINT, "http://localhost:9000")
+            .build()
+            .unwrap();
+        let state =
+            default_session_builder(SessionConfig::new().with_extension(Arc::new(config.clone())));
+        let ctx = SessionContext::new_with_state(state);
+        ctx.runtime_env().register_object_store(&bucket, store);
+
+        ctx.sql(
+            "CREATE EXTERNAL TABLE t STORED AS CSV WITH HEADER ROW LOCATION 's3://bucket/data/'",
+        )
+        .await?;
+        let batches = ctx.sql("SELECT sum(a) FROM t").await?.collect().await?;
+        let sum = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap()
+            .value(0);
+        assert_eq!(sum, 6);
+
+        // Tasks look up the stores of their session's runtime first
+        let runtime = with_object_stores(
+            &ctx.runtime_env(),
+            ObjectStoreConfig::from_settings(config.settings()),
+        );
+        assert!(Arc::ptr_eq(
+            &runtime.object_store_registry.get_store(&bucket)?,
+            &ctx.runtime_env().object_store_registry.get_store(&bucket)?
+        ));
+        Ok(())
+    }
+}
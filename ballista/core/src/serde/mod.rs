@@ -21,12 +21,17 @@
 use crate::{error::BallistaError, serde::scheduler::Action as BallistaAction};
 
 use arrow_flight::sql::ProstMessageExt;
+use datafusion::arrow::compute::SortOptions;
+use datafusion::arrow::datatypes::Schema;
 use datafusion::common::DataFusionError;
 use datafusion::execution::FunctionRegistry;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
 use datafusion::physical_plan::{ExecutionPlan, Partitioning};
 use datafusion_proto::common::proto_error;
-use datafusion_proto::physical_plan::from_proto::parse_protobuf_hash_partitioning;
-use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
+use datafusion_proto::physical_plan::from_proto::{
+    parse_physical_expr, parse_protobuf_hash_partitioning,
+};
+use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode, PhysicalSortExprNode};
 use datafusion_proto::{
     convert_required,
     logical_plan::{AsLogicalPlan, DefaultLogicalExtensionCodec, LogicalExtensionCodec},
@@ -39,7 +44,9 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 use std::{convert::TryInto, io::Cursor};
 
-use crate::execution_plans::{ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec};
+use crate::execution_plans::{
+    RangePartitioning, RangeSampleExec, ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec,
+};
 use crate::serde::protobuf::ballista_physical_plan_node::PhysicalPlanType;
 use crate::serde::scheduler::PartitionLocation;
 pub use generated::ballista as protobuf;
@@ -68,6 +75,15 @@ pub fn decode_protobuf(bytes: &[u8]) -> Result<BallistaAction, BallistaError> {
         .and_then(|node| node.try_into())
 }
 
+/// Decode the ticket of a Flight `do_get`, which is either an encoded action or an action
+/// wrapped in an `Any`, as handed out by the Flight SQL service of the scheduler
+pub fn decode_ticket(bytes: &[u8]) -> Result<BallistaAction, BallistaError> {
+    match arrow_flight::sql::Any::decode(bytes) {
+        Ok(any) if any.type_url == protobuf::Action::type_url() => decode_protobuf(&any.value),
+        _ => decode_protobuf(bytes),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct BallistaCodec<
     T: 'static + AsLogicalPlan = LogicalPlanNode,
@@ -140,19 +156,44 @@ impl PhysicalExtensionCodec for BallistaPhysicalExtensionCodec {
             PhysicalPlanType::ShuffleWriter(shuffle_writer) => {
                 let input = inputs[0].clone();
 
-                let shuffle_output_partitioning = parse_protobuf_hash_partitioning(
-                    shuffle_writer.output_partitioning.as_ref(),
-                    registry,
-                    input.schema().as_ref(),
-                )?;
+                let shuffle_output_partitioning = match shuffle_writer.round_robin_partition_count {
+                    0 => parse_protobuf_hash_partitioning(
+                        shuffle_writer.output_partitioning.as_ref(),
+                        registry,
+                        input.schema().as_ref(),
+                    )?,
+                    partition_count => {
+                        Some(Partitioning::RoundRobinBatch(partition_count as usize))
+                    }
+                };
 
-                Ok(Arc::new(ShuffleWriterExec::new(
+                let shuffle_writer_exec = ShuffleWriterExec::new(
                     shuffle_writer.job_id.clone(),
                     shuffle_writer.stage_id as usize,
-                    input,
+                    input.clone(),
                     "".to_string(), // this is intentional but hacky - the executor will fill this in
                     shuffle_output_partitioning,
-                )))
+                );
+                Ok(Arc::new(match &shuffle_writer.range_partitioning {
+                    Some(range_partitioning) => {
+                        let samples = inputs.get(1).cloned().ok_or_else(|| {
+                            DataFusionError::Internal(
+                                "Range partitioned ShuffleWriterExec is missing its samples"
+                                    .to_string(),
+                            )
+                        })?;
+                        shuffle_writer_exec.with_range_partitioning(RangePartitioning {
+                            sort_exprs: parse_sort_exprs(
+                                &range_partitioning.sort_expr,
+                                registry,
+                                input.schema().as_ref(),
+                            )?,
+                            partition_count: range_partitioning.partition_count as usize,
+                            samples,
+                        })
+                    }
+                    None => shuffle_writer_exec,
+                }))
             }
             PhysicalPlanType::ShuffleReader(shuffle_reader) => {
                 let stage_id = shuffle_reader.stage_id as usize;
@@ -184,6 +225,16 @@ impl PhysicalExtensionCodec for BallistaPhysicalExtensionCodec {
                     output_partition_count: unresolved_shuffle.output_partition_count as usize,
                 }))
             }
+            PhysicalPlanType::RangeSample(range_sample) => {
+                let input = inputs[0].clone();
+                let sort_exprs =
+                    parse_sort_exprs(&range_sample.sort_expr, registry, input.schema().as_ref())?;
+                Ok(Arc::new(RangeSampleExec::try_new(
+                    input,
+                    sort_exprs,
+                    range_sample.sample_size as usize,
+                )?))
+            }
         }
     }
 
@@ -193,9 +244,26 @@ impl PhysicalExtensionCodec for BallistaPhysicalExtensionCodec {
         buf: &mut Vec<u8>,
     ) -> Result<(), DataFusionError> {
         if let Some(exec) = node.as_any().downcast_ref::<ShuffleWriterExec>() {
+            let range_partitioning = exec
+                .range_partitioning()
+                .map(|range_partitioning| {
+                    Ok::<_, DataFusionError>(protobuf::RangePartitioning {
+                        sort_expr: encode_sort_exprs(&range_partitioning.sort_exprs)?,
+                        partition_count: range_partitioning.partition_count as u64,
+                    })
+                })
+                .transpose()?;
+
             // note that we use shuffle_output_partitioning() rather than output_partitioning()
             // to get the true output partitioning
+            let mut round_robin_partition_count = 0;
             let output_partitioning = match exec.shuffle_output_partitioning() {
+                // range partitioning is encoded separately
+                Some(_) if range_partitioning.is_some() => None,
+                Some(Partitioning::RoundRobinBatch(partition_count)) => {
+                    round_robin_partition_count = *partition_count as u64;
+                    None
+                }
                 Some(Partitioning::Hash(exprs, partition_count)) => {
                     Some(datafusion_proto::protobuf::PhysicalHashRepartition {
                         hash_expr: exprs
@@ -220,6 +288,8 @@ impl PhysicalExtensionCodec for BallistaPhysicalExtensionCodec {
                         stage_id: exec.stage_id() as u32,
                         input: None,
                         output_partitioning,
+                        range_partitioning,
+                        round_robin_partition_count,
                     },
                 )),
             };
@@ -280,6 +350,22 @@ impl PhysicalExtensionCodec for BallistaPhysicalExtensionCodec {
                 ))
             })?;
 
+            Ok(())
+        } else if let Some(exec) = node.as_any().downcast_ref::<RangeSampleExec>() {
+            let proto = protobuf::BallistaPhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::RangeSample(
+                    protobuf::RangeSampleExecNode {
+                        sort_expr: encode_sort_exprs(exec.sort_exprs())?,
+                        sample_size: exec.sample_size() as u64,
+                    },
+                )),
+            };
+            proto.encode(buf).map_err(|e| {
+                DataFusionError::Internal(format!(
+                    "failed to encode range sample execution plan: {e:?}"
+                ))
+            })?;
+
             Ok(())
         } else {
             Err(DataFusionError::Internal(
@@ -288,3 +374,218 @@ impl PhysicalExtensionCodec for BallistaPhysicalExtensionCodec {
         }
     }
 }
+
+fn encode_sort_exprs(
+    sort_exprs: &[PhysicalSortExpr],
+) -> Result<Vec<PhysicalSortExprNode>, DataFusionError> {
+    sort_exprs
+        .iter()
+        .map(|sort_expr| {
+            Ok(PhysicalSortExprNode {
+                expr: Some(Box::new(sort_expr.expr.clone().try_into()?)),
+                asc: !sort_expr.options.descending,
+                nulls_first: sort_expr.options.nulls_first,
+            })
+        })
+        .collect()
+}
+
+fn parse_sort_exprs(
+    sort_exprs: &[PhysicalSortExprNode],
+    registry: &dyn FunctionRegistry,
+    input_schema: &Schema,
+) -> Result<Vec<PhysicalSortExpr>, DataFusionError> {
+    sort_exprs
+        .iter()
+        .map(|sort_expr| {
+            let expr = sort_expr.expr.as_ref().ok_or_else(|| {
+                proto_error("Unexpected empty physical expression in sort expression")
+            })?;
+            Ok(PhysicalSortExpr {
+                expr: parse_physical_expr(expr, registry, input_schema)?,
+                options: SortOptions {
+                    descending: !sort_expr.asc,
+                    nulls_first: sort_expr.nulls_first,
+                },
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ShuffleCompression;
+
+    #[test]
+    fn decode_tickets() {
+        let action = protobuf::Action {
+            action_type: Some(protobuf::action::ActionType::FetchPartition(
+                protobuf::FetchPartition {
+                    job_id: "job".to_owned(),
+                    stage_id: 1,
+                    partition_id: 2,
+                    path: "/tmp/job/1/2/data.arrow".to_owned(),
+                    host: "localhost".to_owned(),
+                    port: 50051,
+                },
+            )),
+            settings: vec![],
+        };
+
+        for ticket in [action.encode_to_vec(), action.as_any().encode_to_vec()] {
+            match decode_ticket(&ticket).unwrap() {
+                BallistaAction::FetchPartition {
+                    job_id,
+                    partition_id,
+                    compression,
+                    ..
+                } => {
+                    assert_eq!(job_id, "job");
+                    assert_eq!(partition_id, 2);
+                    assert_eq!(compression, ShuffleCompression::Lz4);
+                }
+            }
+        }
+
+        let action = BallistaAction::FetchPartition {
+            job_id: "job".to_owned(),
+            stage_id: 1,
+            partition_id: 2,
+            path: "/tmp/job/1/2/data.arrow".to_owned(),
+            host: "localhost".to_owned(),
+            port: 50051,
+            compression: ShuffleCompression::Zstd(9),
+        };
+        let ticket: protobuf::Action = action.try_into().unwrap();
+        match decode_ticket(&ticket.encode_to_vec()).unwrap() {
+            BallistaAction::FetchPartition { compression, .. } => {
+                assert_eq!(compression, ShuffleCompression::Zstd(9));
+            }
+        }
+    }
+
+    #[test]
+    fn s3_locations_roundtrip() {
+        use datafusion::arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::common::Statistics;
+        use datafusion::datasource::listing::PartitionedFile;
+        use datafusion::datasource::object_store::ObjectStoreUrl;
+        use datafusion::datasource::physical_plan::{FileScanConfig, ParquetExec};
+        use datafusion::prelude::SessionContext;
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let config = FileScanConfig {
+            object_store_url: ObjectStoreUrl::parse("s3://bucket").unwrap(),
+            file_schema: schema.clone(),
+            file_groups: vec![vec![PartitionedFile::new("path/data.parquet".to_owned(), 1024)]],
+            statistics: Statistics::new_unknown(&schema),
+            projection: None,
+            limit: None,
+            table_partition_cols: vec![],
+            output_ordering: vec![],
+            infinite_source: false,
+        };
+        let plan: Arc<dyn ExecutionPlan> = Arc::new(ParquetExec::new(config, None, None));
+
+        let codec: BallistaCodec = BallistaCodec::default();
+        let node = PhysicalPlanNode::try_from_physical_plan(plan, codec.physical_extension_codec())
+            .unwrap();
+        let ctx = SessionContext::new();
+        let plan = node
+            .try_into_physical_plan(&ctx, &ctx.runtime_env(), codec.physical_extension_codec())
+            .unwrap();
+
+        let config = plan
+            .as_any()
+            .downcast_ref::<ParquetExec>()
+            .unwrap()
+            .base_config();
+        assert_eq!(config.object_store_url.as_str(), "s3://bucket/");
+        assert_eq!(
+            config.file_groups[0][0].object_meta.location.as_ref(),
+            "path/data.parquet"
+        );
+    }
+
+    #[test]
+    fn range_partitioned_shuffle_writer_roundtrip() {
+        use datafusion::arrow::datatypes::{DataType, Field};
+        use datafusion::physical_plan::expressions::Column;
+        use datafusion::prelude::SessionContext;
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let input = Arc::new(UnresolvedShuffleExec::new(1, schema, 2));
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: SortOptions {
+                descending: true,
+                nulls_first: false,
+            },
+        }];
+        let samples =
+            Arc::new(RangeSampleExec::try_new(input.clone(), sort_exprs.clone(), 20).unwrap());
+        let plan: Arc<dyn ExecutionPlan> = Arc::new(
+            ShuffleWriterExec::new("job".to_owned(), 2, input, "".to_owned(), None)
+                .with_range_partitioning(RangePartitioning {
+                    sort_exprs,
+                    partition_count: 4,
+                    samples,
+                }),
+        );
+
+        let codec: BallistaCodec = BallistaCodec::default();
+        let node = PhysicalPlanNode::try_from_physical_plan(plan, codec.physical_extension_codec())
+            .unwrap();
+        let ctx = SessionContext::new();
+        let plan = node
+            .try_into_physical_plan(&ctx, &ctx.runtime_env(), codec.physical_extension_codec())
+            .unwrap();
+
+        let shuffle_writer = plan.as_any().downcast_ref::<ShuffleWriterExec>().unwrap();
+        assert_eq!(shuffle_writer.output_partitioning().partition_count(), 4);
+        let range_partitioning = shuffle_writer.range_partitioning().unwrap();
+        assert_eq!(range_partitioning.partition_count, 4);
+        assert_eq!(
+            range_partitioning.sort_exprs[0].to_string(),
+            "a@0 DESC NULLS LAST"
+        );
+        let samples = range_partitioning
+            .samples
+            .as_any()
+            .downcast_ref::<RangeSampleExec>()
+            .unwrap();
+        assert_eq!(samples.sample_size(), 20);
+        assert_eq!(samples.schema().field(0).name(), "sort_key_0");
+    }
+
+    #[test]
+    fn round_robin_shuffle_writer_roundtrip() {
+        use datafusion::arrow::datatypes::{DataType, Field};
+        use datafusion::prelude::SessionContext;
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let input = Arc::new(UnresolvedShuffleExec::new(1, schema, 2));
+        let plan: Arc<dyn ExecutionPlan> = Arc::new(ShuffleWriterExec::new(
+            "job".to_owned(),
+            2,
+            input,
+            "".to_owned(),
+            Some(Partitioning::RoundRobinBatch(8)),
+        ));
+
+        let codec: BallistaCodec = BallistaCodec::default();
+        let node = PhysicalPlanNode::try_from_physical_plan(plan, codec.physical_extension_codec())
+            .unwrap();
+        let ctx = SessionContext::new();
+        let plan = node
+            .try_into_physical_plan(&ctx, &ctx.runtime_env(), codec.physical_extension_codec())
+            .unwrap();
+
+        let shuffle_writer = plan.as_any().downcast_ref::<ShuffleWriterExec>().unwrap();
+        assert!(matches!(
+            shuffle_writer.shuffle_output_partitioning(),
+            Some(Partitioning::RoundRobinBatch(8))
+        ));
+    }
+}
@@ -28,6 +28,7 @@ use std::convert::TryInto;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::config::{ShuffleCompression, BALLISTA_SHUFFLE_COMPRESSION};
 use crate::error::BallistaError;
 use crate::serde::scheduler::{
     Action, ExecutorMetadata, ExecutorSpecification, PartitionId, PartitionLocation,
@@ -43,6 +44,16 @@ impl TryInto<Action> for protobuf::Action {
     fn try_into(self) -> Result<Action, Self::Error> {
         match self.action_type {
             Some(protobuf::action::ActionType::FetchPartition(fetch)) => {
+                // Clients which don't choose a codec get the one used before it was
+                // configurable
+                let compression = self
+                    .settings
+                    .iter()
+                    .find(|kv| kv.key == BALLISTA_SHUFFLE_COMPRESSION)
+                    .map(|kv| kv.value.parse::<ShuffleCompression>())
+                    .transpose()
+                    .map_err(BallistaError::General)?
+                    .unwrap_or_default();
                 Ok(Action::FetchPartition {
                     job_id: fetch.job_id,
                     stage_id: fetch.stage_id as usize,
@@ -50,6 +61,7 @@ impl TryInto<Action> for protobuf::Action {
                     path: fetch.path,
                     host: fetch.host,
                     port: fetch.port as u16,
+                    compression,
                 })
             }
             _ => Err(BallistaError::General(
@@ -25,6 +25,7 @@ use datafusion::arrow::datatypes::{DataType, Field};
 use datafusion::physical_plan::ExecutionPlan;
 use serde::Serialize;
 
+use crate::config::ShuffleCompression;
 use crate::error::BallistaError;
 
 pub mod from_proto;
@@ -41,6 +42,8 @@ pub enum Action {
         path: String,
         host: String,
         port: u16,
+        /// The codec the partition is compressed with for the transfer
+        compression: ShuffleCompression,
     },
 }
 
@@ -18,6 +18,7 @@
 use datafusion::physical_plan::metrics::{MetricValue, MetricsSet};
 use std::convert::TryInto;
 
+use crate::config::BALLISTA_SHUFFLE_COMPRESSION;
 use crate::error::BallistaError;
 
 use crate::serde::protobuf;
@@ -39,6 +40,7 @@ impl TryInto<protobuf::Action> for Action {
                 path,
                 host,
                 port,
+                compression,
             } => Ok(protobuf::Action {
                 action_type: Some(ActionType::FetchPartition(protobuf::FetchPartition {
                     job_id,
@@ -48,7 +50,10 @@ impl TryInto<protobuf::Action> for Action {
                     host,
                     port: port as u32,
                 })),
-                settings: vec![],
+                settings: vec![protobuf::KeyValuePair {
+                    key: BALLISTA_SHUFFLE_COMPRESSION.to_owned(),
+                    value: compression.to_string(),
+                }],
             }),
         }
     }
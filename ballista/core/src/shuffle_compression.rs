@@ -0,0 +1,140 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Compression of shuffle files with zstd at a configured level. Arrow only compresses the
+//! buffers of IPC streams with zstd at its default level, so the IPC streams of shuffle
+//! files compressed with zstd are written uncompressed into a zstd frame instead. Readers
+//! recognize the frame by its magic number, so they don't need to know the codec.
+
+use std::io::{self, BufReader, Cursor, Read, Write};
+
+use datafusion::arrow::ipc::reader::StreamReader;
+
+use crate::config::ShuffleCompression;
+
+/// The first bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Reader of the IPC stream of a shuffle file, or of a partition of a sort-based shuffle
+/// data file
+pub type ShuffleFileReader = StreamReader<BufReader<Box<dyn Read + Send>>>;
+
+/// Writer of the IPC stream of a shuffle file, which compresses it into a zstd frame if
+/// the shuffle is compressed with zstd
+pub enum ShuffleFileWriter<W: Write> {
+    Plain(W),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> ShuffleFileWriter<W> {
+    pub fn try_new(inner: W, compression: ShuffleCompression) -> io::Result<Self> {
+        match compression {
+            ShuffleCompression::Zstd(level) => Ok(Self::Zstd(zstd::Encoder::new(inner, level)?)),
+            ShuffleCompression::None | ShuffleCompression::Lz4 => Ok(Self::Plain(inner)),
+        }
+    }
+
+    /// Complete the zstd frame, if any, and return the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(inner) => Ok(inner),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for ShuffleFileWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(inner) => inner.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(inner) => inner.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Read the IPC stream of a shuffle file, decompressing it if it was written into a
+/// zstd frame
+pub fn read_shuffle_stream(
+    inner: impl Read + Send + 'static,
+) -> datafusion::error::Result<ShuffleFileReader> {
+    let mut inner = inner;
+    let mut magic = [0; ZSTD_MAGIC.len()];
+    let mut len = 0;
+    while len < magic.len() {
+        match inner.read(&mut magic[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    let stream = Cursor::new(magic[..len].to_vec()).chain(inner);
+    let stream: Box<dyn Read + Send> = if magic[..len] == ZSTD_MAGIC {
+        Box::new(zstd::Decoder::new(stream)?)
+    } else {
+        Box::new(stream)
+    };
+    Ok(StreamReader::try_new(stream, None)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::UInt32Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::ipc::writer::StreamWriter;
+    use datafusion::arrow::record_batch::RecordBatch;
+    use std::sync::Arc;
+
+    #[test]
+    fn read_shuffle_streams_of_any_compression() -> datafusion::error::Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(vec![7; 1000]))],
+        )?;
+
+        let mut sizes = vec![];
+        for compression in [
+            ShuffleCompression::None,
+            ShuffleCompression::Lz4,
+            ShuffleCompression::Zstd(1),
+            ShuffleCompression::Zstd(19),
+        ] {
+            let out = ShuffleFileWriter::try_new(vec![], compression)?;
+            let mut writer = StreamWriter::try_new_with_options(
+                out,
+                &schema,
+                compression.file_write_options()?,
+            )?;
+            writer.write(&batch)?;
+            let bytes = writer.into_inner()?.finish()?;
+            sizes.push(bytes.len());
+
+            let batches = read_shuffle_stream(Cursor::new(bytes))?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            assert_eq!(batches, vec![batch.clone()], "{compression}");
+        }
+        assert!(sizes[2] < sizes[0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Storage of shuffle files in an object store, where they outlive the executors which
+//! wrote them. The files are written to the local disk first and uploaded once complete,
+//! and their location is the URL they were uploaded to instead of a local path.
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use bytes::Bytes;
+use datafusion::datasource::object_store::ObjectStoreUrl;
+use datafusion::error::DataFusionError;
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectPath;
+use object_store::{GetOptions, ObjectStore};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::Handle;
+use tokio_stream::wrappers::ReceiverStream;
+use url::Url;
+
+use crate::error::{BallistaError, Result};
+use crate::execution_plans::sort_shuffle::{
+    has_data_file_name, index_path, index_range, is_sort_shuffle_path, partition_range,
+};
+use crate::shuffle_compression::read_shuffle_stream;
+
+/// Whether the location of a shuffle file is a URL in an object store rather than a path
+/// on the local disk of an executor
+pub fn is_object_store_path(path: &str) -> bool {
+    // Single letter schemes are drive letters of Windows paths
+    Url::parse(path)
+        .map(|url| url.scheme().len() > 1)
+        .unwrap_or(false)
+}
+
+fn object_store(runtime: &RuntimeEnv, url: &Url) -> Result<(Arc<dyn ObjectStore>, ObjectPath)> {
+    let store_url = ObjectStoreUrl::parse(format!("{}://{}", url.scheme(), url.authority()))?;
+    let store = runtime.object_store(store_url)?;
+    let path = ObjectPath::from_url_path(url.path())
+        .map_err(|e| BallistaError::General(format!("Invalid shuffle file URL {url}: {e}")))?;
+    Ok((store, path))
+}
+
+/// Upload a shuffle file from the local disk to the same path relative to `remote_dir` as
+/// it has relative to `work_dir`, and remove the local file. The index of a sort-based
+/// shuffle data file is uploaded along with it. Returns the URL of the uploaded file.
+pub async fn upload_shuffle_file(
+    runtime: &RuntimeEnv,
+    local_path: &Path,
+    work_dir: &str,
+    remote_dir: &str,
+) -> Result<String> {
+    let url = upload_file(runtime, local_path, work_dir, remote_dir).await?;
+    let local_path = local_path.to_string_lossy();
+    if is_sort_shuffle_path(&local_path) {
+        upload_file(
+            runtime,
+            Path::new(&index_path(&local_path)),
+            work_dir,
+            remote_dir,
+        )
+        .await?;
+    }
+    Ok(url)
+}
+
+async fn upload_file(
+    runtime: &RuntimeEnv,
+    local_path: &Path,
+    work_dir: &str,
+    remote_dir: &str,
+) -> Result<String> {
+    let relative_path = local_path.strip_prefix(work_dir).map_err(|_| {
+        BallistaError::General(format!(
+            "Shuffle file {} is not in the work dir {work_dir}",
+            local_path.display()
+        ))
+    })?;
+    let relative_path: Vec<_> = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    let url = format!(
+        "{}/{}",
+        remote_dir.trim_end_matches('/'),
+        relative_path.join("/")
+    );
+    let parsed_url = Url::parse(&url)
+        .map_err(|e| BallistaError::General(format!("Invalid shuffle file URL {url}: {e}")))?;
+    let (store, path) = object_store(runtime, &parsed_url)?;
+
+    let mut file = tokio::fs::File::open(local_path).await?;
+    let (multipart_id, mut writer) = store.put_multipart(&path).await?;
+    let uploaded = async {
+        tokio::io::copy(&mut file, &mut writer).await?;
+        writer.shutdown().await
+    }
+    .await;
+    if let Err(e) = uploaded {
+        store.abort_multipart(&path, &multipart_id).await?;
+        return Err(BallistaError::General(format!(
+            "Failed to upload shuffle file to {url}: {e}"
+        )));
+    }
+
+    tokio::fs::remove_file(local_path).await?;
+    Ok(url)
+}
+
+/// Remove the shuffle files of a job which were uploaded to `remote_dir`
+pub async fn remove_remote_job_dir(
+    runtime: &RuntimeEnv,
+    remote_dir: &str,
+    job_id: &str,
+) -> Result<()> {
+    // The job ID must not widen the prefix to the files of other jobs
+    if job_id.is_empty() || job_id.contains('/') || job_id == "." || job_id == ".." {
+        return Err(BallistaError::General(format!("Invalid job ID {job_id}")));
+    }
+    let url = format!("{}/{job_id}", remote_dir.trim_end_matches('/'));
+    let parsed_url = Url::parse(&url)
+        .map_err(|e| BallistaError::General(format!("Invalid shuffle dir URL {url}: {e}")))?;
+    let (store, prefix) = object_store(runtime, &parsed_url)?;
+    let files: Vec<_> = store.list(Some(&prefix)).try_collect().await?;
+    for file in files {
+        match store.delete(&file.location).await {
+            // Executors which wrote output of the job remove it at the same time
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Blocking reader over the chunks of an object being downloaded, for the synchronous
+/// IPC reader to decode batches as they arrive. Must be used on a blocking thread.
+struct ChunkReader {
+    chunks: BoxStream<'static, object_store::Result<Bytes>>,
+    chunk: Bytes,
+    handle: Handle,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.handle.block_on(self.chunks.next()) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(e)) => return Err(io::Error::other(e)),
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        self.chunk = self.chunk.slice(len..);
+        Ok(len)
+    }
+}
+
+/// Read a shuffle partition from a file in an object store. Only the byte range of the
+/// partition is fetched from a sort-based shuffle data file. The file is streamed, and
+/// batches are decoded as they are downloaded.
+pub async fn read_shuffle_file(
+    runtime: &RuntimeEnv,
+    url: &str,
+    partition: usize,
+) -> Result<SendableRecordBatchStream> {
+    let parsed_url = Url::parse(url)
+        .map_err(|e| BallistaError::General(format!("Invalid shuffle file URL {url}: {e}")))?;
+    let (store, path) = object_store(runtime, &parsed_url)?;
+    // Only data files of a sort-based shuffle were uploaded along with an index
+    let range = if has_data_file_name(url) {
+        let index_url = Url::parse(&index_path(url)).map_err(|e| {
+            BallistaError::General(format!("Invalid shuffle index URL for {url}: {e}"))
+        })?;
+        let (_, index) = object_store(runtime, &index_url)?;
+        match store.get_range(&index, index_range(partition)).await {
+            Ok(index_entry) => {
+                let range = partition_range(&index_entry)?;
+                Some(range.start as usize..range.end as usize)
+            }
+            // Reading the whole file would return the rows of every partition
+            Err(object_store::Error::NotFound { .. }) => {
+                return Err(BallistaError::General(format!(
+                    "Index of sort-based shuffle data file {url} not found"
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        None
+    };
+    let options = GetOptions {
+        range,
+        ..Default::default()
+    };
+    let chunks = store.get_opts(&path, options).await?.into_stream();
+
+    let chunk_reader = ChunkReader {
+        chunks,
+        chunk: Bytes::new(),
+        handle: Handle::current(),
+    };
+    let reader = tokio::task::spawn_blocking(move || read_shuffle_stream(chunk_reader))
+        .await
+        .map_err(|e| BallistaError::General(format!("Failed to read shuffle file {url}: {e}")))??;
+    let schema = reader.schema();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(2);
+    tokio::task::spawn_blocking(move || {
+        for batch in reader {
+            // The receiver is gone once the partition is no longer read
+            if tx
+                .blocking_send(batch.map_err(DataFusionError::from))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    Ok(Box::pin(RecordBatchStreamAdapter::new(
+        schema,
+        ReceiverStream::new(rx),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ShuffleCompression;
+    use crate::execution_plans::sort_shuffle::{data_file_name, SortShuffleWriter};
+    use datafusion::arrow::array::UInt32Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::ipc::writer::StreamWriter;
+    use datafusion::arrow::record_batch::RecordBatch;
+    use futures::TryStreamExt;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn upload_and_read_shuffle_file() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let work_path = work_dir.path().to_str().unwrap();
+        let remote_url = format!("file://{}", remote_dir.path().to_str().unwrap());
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(vec![1, 2, 3]))],
+        )?;
+        let dir = work_dir.path().join("job").join("1").join("0");
+        std::fs::create_dir_all(&dir)?;
+        let local_path = dir.join("data-2.arrow");
+        let mut writer = StreamWriter::try_new(std::fs::File::create(&local_path)?, &schema)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+
+        let runtime = RuntimeEnv::default();
+        let url = upload_shuffle_file(&runtime, &local_path, work_path, &remote_url).await?;
+        assert_eq!(url, format!("{remote_url}/job/1/0/data-2.arrow"));
+        assert!(is_object_store_path(&url));
+        assert!(!is_object_store_path(local_path.to_str().unwrap()));
+        assert!(!local_path.exists());
+
+        let batches: Vec<_> = read_shuffle_file(&runtime, &url, 2)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(batches, vec![batch]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_and_read_sort_shuffle_partition() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let work_path = work_dir.path().to_str().unwrap();
+        let remote_url = format!("file://{}", remote_dir.path().to_str().unwrap());
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let batch = |values: Vec<u32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(UInt32Array::from(values))])
+        };
+        let runtime = Arc::new(RuntimeEnv::default());
+        let mut writer = SortShuffleWriter::new(
+            3,
+            schema.clone(),
+            0,
+            runtime.clone(),
+            ShuffleCompression::default(),
+        );
+        writer.write(0, batch(vec![1, 2])?)?;
+        writer.write(1, batch(vec![3])?)?;
+        writer.write(2, batch(vec![4, 5, 6])?)?;
+        let dir = work_dir.path().join("job").join("1");
+        std::fs::create_dir_all(&dir)?;
+        let local_path = dir.join(data_file_name(0));
+        writer.finish(&local_path)?;
+
+        let url = upload_shuffle_file(&runtime, &local_path, work_path, &remote_url).await?;
+        assert_eq!(url, format!("{remote_url}/job/1/shuffle-0.data"));
+        assert!(!local_path.exists());
+        assert!(!Path::new(&index_path(local_path.to_str().unwrap())).exists());
+
+        let batches: Vec<_> = read_shuffle_file(&runtime, &url, 1)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(batches, vec![batch(vec![3])?]);
+
+        let remote_path = remote_dir
+            .path()
+            .join("job")
+            .join("1")
+            .join(data_file_name(0));
+        std::fs::remove_file(index_path(remote_path.to_str().unwrap()))?;
+        assert!(read_shuffle_file(&runtime, &url, 1).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_uploaded_files_of_job() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let work_path = work_dir.path().to_str().unwrap();
+        let remote_url = format!("file://{}", remote_dir.path().to_str().unwrap());
+        let runtime = RuntimeEnv::default();
+
+        for job_id in ["job", "job_2"] {
+            for stage_id in ["1", "2"] {
+                let dir = work_dir.path().join(job_id).join(stage_id).join("0");
+                std::fs::create_dir_all(&dir)?;
+                let local_path = dir.join("data-0.arrow");
+                std::fs::write(&local_path, [1, 2, 3])?;
+                upload_shuffle_file(&runtime, &local_path, work_path, &remote_url).await?;
+            }
+        }
+
+        remove_remote_job_dir(&runtime, &remote_url, "job").await?;
+        let remaining = walk(remote_dir.path())?;
+        assert_eq!(
+            remaining,
+            vec![
+                remote_dir.path().join("job_2/1/0/data-0.arrow"),
+                remote_dir.path().join("job_2/2/0/data-0.arrow"),
+            ]
+        );
+        // Removing the files again does nothing
+        remove_remote_job_dir(&runtime, &remote_url, "job").await?;
+        assert!(remove_remote_job_dir(&runtime, &remote_url, "..")
+            .await
+            .is_err());
+        assert!(remove_remote_job_dir(&runtime, &remote_url, "")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    /// The files below the directory, sorted by path
+    fn walk(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                files.extend(walk(&path)?);
+            } else {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
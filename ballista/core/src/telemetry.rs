@@ -0,0 +1,238 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Distributed tracing of jobs across the scheduler and the executors.
+//!
+//! Spans are recorded with `tracing` and exported with OpenTelemetry, either to an OTLP
+//! collector or to a local file. The context of a span travels to other processes in the W3C
+//! trace context format, in the props of task definitions and in the metadata of Flight
+//! requests, so that the spans of one job share a single trace.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::OpenOptions;
+
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self as sdktrace, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::error::{BallistaError, Result};
+use crate::serde::protobuf::KeyValuePair;
+
+/// Keys of the W3C trace context
+const TRACE_CONTEXT_KEYS: [&str; 2] = ["traceparent", "tracestate"];
+
+/// Where the spans of this process are exported to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TraceExporter {
+    /// Spans are not exported
+    #[default]
+    None,
+    /// OTLP collector listening for gRPC, e.g. `http://localhost:4317`
+    Otlp(String),
+    /// File to which spans are appended as JSON, for testing
+    File(String),
+}
+
+impl TraceExporter {
+    /// Read the exporter from the `OTEL_EXPORTER_OTLP_ENDPOINT` and `BALLISTA_TRACE_FILE`
+    /// environment variables, preferring the collector if both are set
+    pub fn from_env() -> Self {
+        if let Ok(endpoint) = env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            TraceExporter::Otlp(endpoint)
+        } else if let Ok(path) = env::var("BALLISTA_TRACE_FILE") {
+            TraceExporter::File(path)
+        } else {
+            TraceExporter::None
+        }
+    }
+}
+
+/// Create the `tracing` layer which exports spans of `service_name` to `exporter`, or `None`
+/// if spans are not exported. Call [`shutdown_tracing`] before exiting to flush pending spans.
+pub fn trace_layer<S>(
+    service_name: &str,
+    exporter: &TraceExporter,
+) -> Result<Option<OpenTelemetryLayer<S, Tracer>>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let config = sdktrace::config().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        service_name.to_owned(),
+    )]));
+    let provider = match exporter {
+        TraceExporter::None => return Ok(None),
+        TraceExporter::Otlp(endpoint) => {
+            let span_exporter = opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint)
+                .build_span_exporter()
+                .map_err(|e| {
+                    BallistaError::General(format!("Failed to create OTLP exporter: {e}"))
+                })?;
+            TracerProvider::builder()
+                .with_batch_exporter(span_exporter, runtime::Tokio)
+                .with_config(config)
+                .build()
+        }
+        TraceExporter::File(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let span_exporter = opentelemetry_stdout::SpanExporter::builder()
+                .with_writer(file)
+                .build();
+            TracerProvider::builder()
+                .with_simple_exporter(span_exporter)
+                .with_config(config)
+                .build()
+        }
+    };
+
+    let tracer = provider.tracer("ballista");
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Export the spans which have not been exported yet
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Whether the key of a task prop belongs to the trace context rather than the session config
+pub fn is_trace_context_key(key: &str) -> bool {
+    TRACE_CONTEXT_KEYS.contains(&key)
+}
+
+/// Add the trace context of `span` to the props of a task definition, replacing the trace
+/// context of any other span
+pub fn inject_trace_props(span: &Span, props: &mut Vec<KeyValuePair>) {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&span.context(), &mut carrier);
+    props.retain(|kv| !is_trace_context_key(&kv.key));
+    props.extend(
+        carrier
+            .into_iter()
+            .map(|(key, value)| KeyValuePair { key, value }),
+    );
+}
+
+/// Make `span` a child of the span whose context was sent in the props of a task definition
+pub fn set_parent_from_props(span: &Span, props: &HashMap<String, String>) {
+    if props.keys().any(|key| is_trace_context_key(key)) {
+        span.set_parent(TraceContextPropagator::new().extract(props));
+    }
+}
+
+/// Add the trace context of `span` to the metadata of a gRPC request
+pub fn inject_trace_metadata(span: &Span, metadata: &mut MetadataMap) {
+    TraceContextPropagator::new().inject_context(&span.context(), &mut MetadataInjector(metadata));
+}
+
+/// Make `span` a child of the span whose context was sent in the metadata of a gRPC request
+pub fn set_parent_from_metadata(span: &Span, metadata: &MetadataMap) {
+    if TRACE_CONTEXT_KEYS
+        .iter()
+        .any(|key| metadata.contains_key(*key))
+    {
+        span.set_parent(TraceContextPropagator::new().extract(&MetadataExtractor(metadata)));
+    }
+}
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        TRACE_CONTEXT_KEYS
+            .iter()
+            .copied()
+            .filter(|key| self.0.contains_key(*key))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn propagate_trace_context() {
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("launch_task");
+            let trace_id = span.context().span().span_context().trace_id();
+
+            let mut props = vec![KeyValuePair {
+                key: "traceparent".to_owned(),
+                value: "stale".to_owned(),
+            }];
+            inject_trace_props(&span, &mut props);
+            let parents: Vec<_> = props
+                .iter()
+                .filter(|kv| kv.key == "traceparent")
+                .collect();
+            assert_eq!(parents.len(), 1);
+            assert_ne!(parents[0].value, "stale");
+            let props: HashMap<String, String> =
+                props.into_iter().map(|kv| (kv.key, kv.value)).collect();
+            let task_span = tracing::info_span!("run_task");
+            set_parent_from_props(&task_span, &props);
+            assert_eq!(
+                task_span.context().span().span_context().trace_id(),
+                trace_id
+            );
+
+            let mut metadata = MetadataMap::new();
+            inject_trace_metadata(&span, &mut metadata);
+            let fetch_span = tracing::info_span!("do_get");
+            set_parent_from_metadata(&fetch_span, &metadata);
+            assert_eq!(
+                fetch_span.context().span().span_context().trace_id(),
+                trace_id
+            );
+        });
+    }
+}
@@ -15,15 +15,16 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use crate::config::{BallistaConfig, ShuffleCompression};
 use crate::error::{BallistaError, Result};
+use crate::object_store_registry::{BallistaObjectStoreRegistry, ObjectStoreConfig};
 use crate::serde::scheduler::PartitionStats;
+use crate::shuffle_compression::ShuffleFileWriter;
 
-use datafusion::arrow::ipc::writer::IpcWriteOptions;
 use datafusion::arrow::ipc::writer::StreamWriter;
-use datafusion::arrow::ipc::CompressionType;
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::execution::context::{SessionConfig, SessionState};
-use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
 use datafusion::physical_plan::metrics::MetricsSet;
 use datafusion::physical_plan::{metrics, ExecutionPlan, RecordBatchStream};
 use futures::StreamExt;
@@ -34,9 +35,19 @@ use std::{fs::File, pin::Pin};
 use tonic::codegen::StdError;
 use tonic::transport::{Channel, Error, Server};
 
-/// Default session builder using the provided configuration
+/// Default session builder using the provided configuration. Object stores for S3 and
+/// HTTP locations are created with the settings of the session.
 pub fn default_session_builder(config: SessionConfig) -> SessionState {
-    SessionState::new_with_config_rt(config, Arc::new(RuntimeEnv::default()))
+    let object_store_config = config
+        .get_extension::<BallistaConfig>()
+        .map(|ballista_config| ObjectStoreConfig::from_settings(ballista_config.settings()))
+        .unwrap_or_default();
+    let runtime_config = RuntimeConfig::new().with_object_store_registry(Arc::new(
+        BallistaObjectStoreRegistry::new(object_store_config),
+    ));
+    // Creating a runtime only fails for a misconfigured disk manager, which the default one is not
+    let runtime = RuntimeEnv::new(runtime_config).unwrap();
+    SessionState::new_with_config_rt(config, Arc::new(runtime))
 }
 
 /// Stream data to disk in Arrow IPC format
@@ -44,6 +55,7 @@ pub async fn write_stream_to_disk(
     stream: &mut Pin<Box<dyn RecordBatchStream + Send>>,
     path: &str,
     disk_write_metric: &metrics::Time,
+    compression: ShuffleCompression,
 ) -> Result<PartitionStats> {
     let file = File::create(path).map_err(|e| {
         error!("Failed to create partition file at {}: {:?}", path, e);
@@ -54,8 +66,8 @@ pub async fn write_stream_to_disk(
     let mut num_batches = 0;
     let mut num_bytes = 0;
 
-    let options =
-        IpcWriteOptions::default().try_with_compression(Some(CompressionType::LZ4_FRAME))?;
+    let options = compression.file_write_options()?;
+    let file = ShuffleFileWriter::try_new(file, compression)?;
 
     let mut writer = StreamWriter::try_new_with_options(file, stream.schema().as_ref(), options)?;
 
@@ -72,7 +84,7 @@ pub async fn write_stream_to_disk(
         timer.done();
     }
     let timer = disk_write_metric.timer();
-    writer.finish()?;
+    writer.into_inner()?.finish()?;
     timer.done();
     Ok(PartitionStats::new(
         Some(num_rows as u64),
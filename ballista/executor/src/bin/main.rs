@@ -17,9 +17,10 @@
 
 //! Ballista Rust executor binary.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::sync::Arc;
 
+use ballista_core::telemetry::TraceExporter;
 use ballista_executor::executor_process::{start_executor_process, ExecutorProcessConfig};
 
 #[tokio::main]
@@ -42,9 +43,31 @@ async fn main() -> Result<()> {
             .unwrap(),
         concurrent_tasks: 0, // 0 defaults to all available cores
         work_dir: None,
+        remote_shuffle_dir: std::env::var("REMOTE_SHUFFLE_DIR").ok(),
+        shuffle_service_dir: std::env::var("SHUFFLE_SERVICE_DIR").ok(),
         grpc_server_max_decoding_message_size: 16777216, // 16MB
         grpc_server_max_encoding_message_size: 16777216, // 16MB
         executor_heartbeat_interval_seconds: 60,
+        job_data_clean_up_interval_seconds: std::env::var("JOB_DATA_CLEAN_UP_INTERVAL_SECONDS")
+            .unwrap_or("600".to_string())
+            .parse::<u64>()
+            .unwrap(),
+        job_data_ttl_seconds: std::env::var("JOB_DATA_TTL_SECONDS")
+            .unwrap_or("0".to_string())
+            .parse::<u64>()
+            .unwrap(),
+        max_job_data_bytes: std::env::var("MAX_JOB_DATA_BYTES")
+            .unwrap_or("0".to_string())
+            .parse::<u64>()
+            .unwrap(),
+        trace_exporter: TraceExporter::from_env(),
+        // The same users as those of the scheduler, e.g. `alice:secret1,bob:secret2`
+        users: match std::env::var("SCHEDULER_USERS") {
+            Ok(users) => users
+                .parse()
+                .map_err(|e| anyhow!("Invalid SCHEDULER_USERS: {e}"))?,
+            Err(_) => Default::default(),
+        },
     };
 
     start_executor_process(Arc::new(config)).await
@@ -0,0 +1,64 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Ballista Rust shuffle service binary.
+
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+
+use ballista_executor::shuffle_service::{start_shuffle_service, ShuffleServiceConfig};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = ShuffleServiceConfig {
+        special_mod_log_level: "INFO,datafusion=INFO".to_string(),
+        bind_host: "0.0.0.0".to_string(),
+        port: std::env::var("BIND_PORT")
+            .unwrap_or("50053".to_string())
+            .parse::<u16>()
+            .unwrap(),
+        grpc_port: std::env::var("BIND_GRPC_PORT")
+            .unwrap_or("50054".to_string())
+            .parse::<u16>()
+            .unwrap(),
+        scheduler_host: "localhost".to_string(),
+        scheduler_port: std::env::var("SCHEDULER_PORT")
+            .unwrap_or("50050".to_string())
+            .parse::<u16>()
+            .unwrap(),
+        // Executors on this host write their shuffle output into this directory
+        work_dir: std::env::var("SHUFFLE_SERVICE_DIR").unwrap_or(
+            std::env::temp_dir()
+                .join("ballista-shuffle-service")
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        heartbeat_interval_seconds: 60,
+        // The same users as those of the scheduler, e.g. `alice:secret1,bob:secret2`
+        users: match std::env::var("SCHEDULER_USERS") {
+            Ok(users) => users
+                .parse()
+                .map_err(|e| anyhow!("Invalid SCHEDULER_USERS: {e}"))?,
+            Err(_) => Default::default(),
+        },
+        // The remote shuffle dir of the executors, whose uploaded files of finished jobs
+        // are removed along with the local ones
+        remote_shuffle_dir: std::env::var("REMOTE_SHUFFLE_DIR").ok(),
+    };
+
+    start_shuffle_service(Arc::new(config)).await
+}
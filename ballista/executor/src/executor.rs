@@ -19,6 +19,9 @@
 
 use ballista_core::error::BallistaError;
 use ballista_core::execution_plans::ShuffleWriterExec;
+use ballista_core::object_store_registry::{
+    with_object_store_registry, with_object_stores, BallistaObjectStoreRegistry, ObjectStoreConfig,
+};
 use ballista_core::serde::protobuf;
 use ballista_core::serde::protobuf::ExecutorRegistration;
 use ballista_core::serde::scheduler::PartitionId;
@@ -33,6 +36,8 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
+use crate::janitor::JanitorMetrics;
+
 pub struct TasksDrainedFuture(pub Arc<Executor>);
 
 impl Future for TasksDrainedFuture {
@@ -58,9 +63,27 @@ pub struct Executor {
     /// Directory for storing partial results
     pub work_dir: String,
 
+    /// Object store URL prefix which shuffle output is uploaded to, if it is not kept
+    /// in the work dir
+    pub remote_shuffle_dir: Option<String>,
+
+    /// Id of the shuffle service whose work dir this executor writes shuffle output into,
+    /// if the output is served by a shuffle service rather than the executor
+    pub shuffle_service_id: Option<String>,
+
+    /// Shuffle data removed from the work dir by the janitor
+    pub janitor_metrics: Arc<JanitorMetrics>,
+
+    /// Users which submitted the jobs whose tasks the executor ran, by the job ID
+    pub job_owners: Arc<DashMap<String, String>>,
+
     /// Runtime environment for Executor
     runtime: Arc<RuntimeEnv>,
 
+    /// Object stores of the runtime, if it creates them with the settings of the process.
+    /// Tasks create them with the settings of their session instead.
+    object_stores: Option<Arc<BallistaObjectStoreRegistry>>,
+
     /// Concurrent tasks can run in executor
     pub concurrent_tasks: usize,
 
@@ -79,7 +102,12 @@ impl Executor {
         Self {
             metadata,
             work_dir: work_dir.to_owned(),
+            remote_shuffle_dir: None,
+            shuffle_service_id: None,
+            janitor_metrics: Default::default(),
+            job_owners: Default::default(),
             runtime,
+            object_stores: None,
             concurrent_tasks,
             abort_handles: Default::default(),
         }
@@ -87,10 +115,41 @@ impl Executor {
 }
 
 impl Executor {
+    /// Upload shuffle output to an object store under the given URL prefix
+    pub fn with_remote_shuffle_dir(mut self, remote_shuffle_dir: impl Into<String>) -> Self {
+        self.remote_shuffle_dir = Some(remote_shuffle_dir.into());
+        self
+    }
+
+    /// Report shuffle output as served by the given shuffle service, whose work dir must be
+    /// the work dir of this executor
+    pub fn with_shuffle_service(mut self, shuffle_service_id: impl Into<String>) -> Self {
+        self.shuffle_service_id = Some(shuffle_service_id.into());
+        self
+    }
+
+    /// Let tasks create the object stores of `object_stores`, which must be the registry
+    /// of the runtime, with the settings of their session
+    pub fn with_object_stores(mut self, object_stores: Arc<BallistaObjectStoreRegistry>) -> Self {
+        self.object_stores = Some(object_stores);
+        self
+    }
+
     pub fn get_runtime(&self) -> Arc<RuntimeEnv> {
         self.runtime.clone()
     }
 
+    /// Runtime of a task, which reads table data with the object store settings of the
+    /// task's session
+    pub fn get_task_runtime(&self, config: ObjectStoreConfig) -> Arc<RuntimeEnv> {
+        match &self.object_stores {
+            Some(object_stores) => {
+                with_object_store_registry(&self.runtime, object_stores.with_config(config))
+            }
+            None => with_object_stores(&self.runtime, config),
+        }
+    }
+
     /// Execute one partition of a query stage and persist the result to disk in IPC format. On
     /// success, return a RecordBatch containing metadata about the results, including path
     /// and statistics.
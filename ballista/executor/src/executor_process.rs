@@ -20,6 +20,7 @@
 use std::net::SocketAddr;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{env, io};
 
 use anyhow::{Context, Result};
@@ -30,13 +31,17 @@ use log::{error, info};
 use tempfile::TempDir;
 use tokio::signal;
 use tokio::task::JoinHandle;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 use uuid::Uuid;
 
 use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
 use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
 
+use ballista_core::auth::{set_client_credentials, UserCredentials};
 use ballista_core::error::BallistaError;
+use ballista_core::object_store_registry::{BallistaObjectStoreRegistry, ObjectStoreConfig};
 use ballista_core::serde::protobuf::executor_resource::Resource;
 use ballista_core::serde::protobuf::executor_status::Status;
 use ballista_core::serde::protobuf::{
@@ -44,6 +49,7 @@ use ballista_core::serde::protobuf::{
     ExecutorSpecification, ExecutorStatus, HeartBeatParams,
 };
 use ballista_core::serde::BallistaCodec;
+use ballista_core::telemetry::{shutdown_tracing, trace_layer, TraceExporter};
 use ballista_core::utils::{create_grpc_client_connection, create_grpc_server};
 use ballista_core::BALLISTA_VERSION;
 
@@ -51,6 +57,8 @@ use crate::executor::{Executor, TasksDrainedFuture};
 use crate::executor_server;
 use crate::executor_server::TERMINATING;
 use crate::flight_service::BallistaFlightService;
+use crate::janitor::{Janitor, JanitorConfig};
+use crate::shuffle_service::read_shuffle_service_id;
 
 pub struct ExecutorProcessConfig {
     pub bind_host: String,
@@ -60,24 +68,47 @@ pub struct ExecutorProcessConfig {
     pub scheduler_port: u16,
     pub concurrent_tasks: usize,
     pub work_dir: Option<String>,
+    /// Object store URL prefix which shuffle output is uploaded to, e.g. `s3://bucket/shuffle`.
+    /// Shuffle output stays in the work dir if this is not set.
+    pub remote_shuffle_dir: Option<String>,
+    /// Work dir of a shuffle service on the same host. Shuffle output is written into it
+    /// instead of the work dir, and served by the shuffle service rather than this executor.
+    pub shuffle_service_dir: Option<String>,
     pub special_mod_log_level: String,
     /// The maximum size of a decoded message at the grpc server side.
     pub grpc_server_max_decoding_message_size: u32,
     /// The maximum size of an encoded message at the grpc server side.
     pub grpc_server_max_encoding_message_size: u32,
     pub executor_heartbeat_interval_seconds: u64,
+    /// How often job data the scheduler has not cleaned up is removed from the work dir,
+    /// 0 means the janitor is disabled
+    pub job_data_clean_up_interval_seconds: u64,
+    /// Job data which has not been written for this long is removed from the work dir,
+    /// 0 means job data never expires
+    pub job_data_ttl_seconds: u64,
+    /// The oldest job data is removed while the work dir holds more than this many bytes,
+    /// 0 means the size of the work dir is not limited
+    pub max_job_data_bytes: u64,
+    /// Where the spans of jobs running on this executor are exported to
+    pub trace_exporter: TraceExporter,
+    /// Users which Flight clients fetching partitions have to authenticate as, the same
+    /// as those of the scheduler
+    pub users: UserCredentials,
 }
 
 pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<()> {
     let rust_log = env::var(EnvFilter::DEFAULT_ENV);
     let log_filter = EnvFilter::new(rust_log.unwrap_or(opt.special_mod_log_level.clone()));
     // Console layer
-    tracing_subscriber::fmt()
+    let console_layer = tracing_subscriber::fmt::layer()
         .with_ansi(false)
         .with_thread_names(true)
         .with_thread_ids(true)
         .with_writer(io::stdout)
-        .with_env_filter(log_filter)
+        .with_filter(log_filter);
+    tracing_subscriber::registry()
+        .with(console_layer)
+        .with(trace_layer("ballista-executor", &opt.trace_exporter)?)
         .init();
 
     let addr = format!("{}:{}", opt.bind_host, opt.port);
@@ -89,13 +120,31 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
     let scheduler_port = opt.scheduler_port;
     let scheduler_url = format!("http://{scheduler_host}:{scheduler_port}");
 
-    let work_dir = opt.work_dir.clone().unwrap_or(
-        TempDir::new()?
-            .into_path()
-            .into_os_string()
-            .into_string()
-            .unwrap(),
-    );
+    let shuffle_service_id = match &opt.shuffle_service_dir {
+        Some(shuffle_service_dir) => Some(
+            read_shuffle_service_id(shuffle_service_dir)
+                .with_context(|| {
+                    format!("Could not read shuffle service id in {shuffle_service_dir}")
+                })?
+                .with_context(|| {
+                    format!("No shuffle service has been started in {shuffle_service_dir}")
+                })?,
+        ),
+        None => None,
+    };
+
+    // Shuffle output served by a shuffle service has to be written into its work dir
+    let work_dir = opt
+        .shuffle_service_dir
+        .clone()
+        .or_else(|| opt.work_dir.clone())
+        .unwrap_or(
+            TempDir::new()?
+                .into_path()
+                .into_os_string()
+                .into_string()
+                .unwrap(),
+        );
 
     let concurrent_tasks = if opt.concurrent_tasks == 0 {
         // use all available cores if no concurrency level is specified
@@ -107,6 +156,12 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
     info!("Running with config:");
     info!("work_dir: {}", work_dir);
     info!("concurrent_tasks: {}", concurrent_tasks);
+    if let Some(remote_shuffle_dir) = &opt.remote_shuffle_dir {
+        info!("remote_shuffle_dir: {}", remote_shuffle_dir);
+    }
+    if let Some(shuffle_service_id) = &shuffle_service_id {
+        info!("shuffle_service: {}", shuffle_service_id);
+    }
 
     // assign this executor an unique ID
     let executor_id = Uuid::new_v4().to_string();
@@ -121,19 +176,29 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
         }),
     };
 
-    let config = RuntimeConfig::new().with_temp_file_path(work_dir.clone());
+    // Shuffle files in a remote shuffle dir are read and written with the object store
+    // settings of the process, table data with those of the session of each task
+    let object_stores = Arc::new(BallistaObjectStoreRegistry::new(
+        ObjectStoreConfig::default(),
+    ));
+    let config = RuntimeConfig::new()
+        .with_temp_file_path(work_dir.clone())
+        .with_object_store_registry(object_stores.clone());
     let runtime = {
         Arc::new(RuntimeEnv::new(config).map_err(|_| {
             BallistaError::Internal("Failed to init Executor RuntimeEnv".to_owned())
         })?)
     };
 
-    let executor = Arc::new(Executor::new(
-        executor_meta,
-        &work_dir,
-        runtime,
-        concurrent_tasks,
-    ));
+    let mut executor = Executor::new(executor_meta, &work_dir, runtime, concurrent_tasks)
+        .with_object_stores(object_stores);
+    if let Some(remote_shuffle_dir) = &opt.remote_shuffle_dir {
+        executor = executor.with_remote_shuffle_dir(remote_shuffle_dir);
+    }
+    if let Some(shuffle_service_id) = shuffle_service_id {
+        executor = executor.with_shuffle_service(shuffle_service_id);
+    }
+    let executor = Arc::new(executor);
 
     let connection = create_grpc_client_connection(scheduler_url)
         .await
@@ -158,7 +223,23 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
         )
         .await?,
     );
-    service_handlers.push(tokio::spawn(flight_server_run(addr)));
+    // Partitions of other executors are fetched as one of the users
+    set_client_credentials(&opt.users);
+    let flight_service = BallistaFlightService::new(&work_dir)
+        .with_runtime(executor.get_runtime())
+        .with_users(opt.users.clone())
+        .with_job_owners(executor.job_owners.clone());
+    service_handlers.push(tokio::spawn(flight_server_run(addr, flight_service)));
+
+    if opt.job_data_clean_up_interval_seconds > 0 {
+        let config = JanitorConfig {
+            interval: Duration::from_secs(opt.job_data_clean_up_interval_seconds),
+            ttl: (opt.job_data_ttl_seconds > 0)
+                .then(|| Duration::from_secs(opt.job_data_ttl_seconds)),
+            max_bytes: (opt.max_job_data_bytes > 0).then_some(opt.max_job_data_bytes),
+        };
+        Janitor::new(&work_dir, config, executor.janitor_metrics.clone()).start(scheduler.clone());
+    }
 
     let tasks_drained = TasksDrainedFuture(executor);
 
@@ -201,6 +282,7 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
                         }],
                     }),
                 }),
+                metrics: tasks_drained.0.janitor_metrics.executor_metrics(),
             })
             .await
         {
@@ -212,12 +294,15 @@ pub async fn start_executor_process(opt: Arc<ExecutorProcessConfig>) -> Result<(
     }
 
     info!("Executor stopped.");
+    shutdown_tracing();
     Ok(())
 }
 
 // Arrow flight service
-async fn flight_server_run(addr: SocketAddr) -> Result<(), BallistaError> {
-    let service = BallistaFlightService::new();
+pub(crate) async fn flight_server_run(
+    addr: SocketAddr,
+    service: BallistaFlightService,
+) -> Result<(), BallistaError> {
     let server = FlightServiceServer::new(service);
     info!(
         "Ballista v{} Rust Executor Flight Server listening on {:?}",
@@ -233,7 +318,7 @@ async fn flight_server_run(addr: SocketAddr) -> Result<(), BallistaError> {
 }
 
 // Check the status of long running services
-async fn check_services(
+pub(crate) async fn check_services(
     service_handlers: &mut FuturesUnordered<JoinHandle<Result<(), BallistaError>>>,
 ) -> Result<(), BallistaError> {
     loop {
@@ -27,9 +27,12 @@ use tokio::sync::mpsc;
 use log::{debug, error, info, warn};
 use tonic::transport::Channel;
 use tonic::{Request, Response, Status};
+use tracing::{info_span, Instrument};
 
+use ballista_core::config::BallistaConfig;
 use ballista_core::error::BallistaError;
 use ballista_core::execution_plans::ShuffleWriterExec;
+use ballista_core::object_store_registry::ObjectStoreConfig;
 use ballista_core::serde::protobuf::{
     executor_grpc_server::{ExecutorGrpc, ExecutorGrpcServer},
     executor_status,
@@ -42,10 +45,13 @@ use ballista_core::serde::scheduler::from_proto::get_task_definition_vec;
 use ballista_core::serde::scheduler::PartitionId;
 use ballista_core::serde::scheduler::TaskDefinition;
 use ballista_core::serde::BallistaCodec;
+use ballista_core::shuffle_storage::remove_remote_job_dir;
+use ballista_core::telemetry::{is_trace_context_key, set_parent_from_props};
 use ballista_core::utils::{create_grpc_client_connection, create_grpc_server};
 use dashmap::DashMap;
 use datafusion::common::DataFusionError;
 use datafusion::config::ConfigOptions;
+use datafusion::execution::runtime_env::RuntimeEnv;
 use datafusion::execution::TaskContext;
 use datafusion::prelude::SessionConfig;
 use datafusion_proto::{logical_plan::AsLogicalPlan, physical_plan::AsExecutionPlan};
@@ -248,6 +254,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> ExecutorServer<T,
                 status: Some(status),
             }),
             metadata: Some(self.executor.metadata.clone()),
+            metrics: self.executor.janitor_metrics.executor_metrics(),
         };
         let mut scheduler = self.scheduler_to_register.clone();
         match scheduler
@@ -303,41 +310,58 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> ExecutorServer<T,
         let plan = task.plan;
 
         let part = PartitionId {
-            job_id: job_id.clone(),
+            job_id,
             stage_id,
             partition_id,
         };
 
         // the query plan created by the scheduler always starts with a ShuffleWriterExec
-        let shuffle_writer =
-            if let Some(shuffle_writer) = plan.as_any().downcast_ref::<ShuffleWriterExec>() {
-                // recreate the shuffle writer with the correct working directory
-                Ok(ShuffleWriterExec::new(
-                    job_id,
-                    stage_id,
-                    plan.children()[0].clone(),
-                    self.executor.work_dir.to_string(),
-                    shuffle_writer.shuffle_output_partitioning().cloned(),
-                ))
-            } else {
-                Err(DataFusionError::Internal(
-                    "Plan is not a ShuffleWriterExec".to_string(),
-                ))
-            }
-            .unwrap();
+        let shuffle_writer = if let Some(shuffle_writer) =
+            plan.as_any().downcast_ref::<ShuffleWriterExec>()
+        {
+            // recreate the shuffle writer with the correct working directory
+            let shuffle_writer = shuffle_writer.with_work_dir(self.executor.work_dir.to_string());
+            Ok(match &self.executor.remote_shuffle_dir {
+                Some(remote_shuffle_dir) => shuffle_writer.with_remote_dir(remote_shuffle_dir),
+                None => shuffle_writer,
+            })
+        } else {
+            Err(DataFusionError::Internal(
+                "Plan is not a ShuffleWriterExec".to_string(),
+            ))
+        }
+        .unwrap();
         let shuffle_writer = Arc::new(shuffle_writer);
 
         let task_context = {
             let task_props = task.props;
             let mut config = ConfigOptions::new();
-            for (k, v) in task_props.iter() {
-                if let Err(e) = config.set(k, v) {
+            let mut ballista_settings = HashMap::new();
+            for (k, v) in task_props.iter().filter(|(k, _)| !is_trace_context_key(k)) {
+                if k.starts_with("ballista.") {
+                    ballista_settings.insert(k.clone(), v.clone());
+                } else if let Err(e) = config.set(k, v) {
                     debug!("Fail to set session config for ({},{}): {:?}", k, v, e);
                 }
             }
-            let session_config = SessionConfig::from(config);
+            let ballista_config =
+                BallistaConfig::with_settings(ballista_settings).unwrap_or_else(|e| {
+                    warn!("Invalid Ballista settings of task {task_identity}: {e:?}");
+                    BallistaConfig::new().unwrap()
+                });
+            // The user of the session may fetch the output of the job
+            if let Some(tenant) = ballista_config.tenant() {
+                self.executor
+                    .job_owners
+                    .insert(part.job_id.clone(), tenant.to_owned());
+            }
 
-            let runtime = self.executor.get_runtime();
+            // Table data is read with the object store settings of the job's session
+            let runtime = self
+                .executor
+                .get_task_runtime(ObjectStoreConfig::from_settings(ballista_config.settings()));
+            let session_config =
+                SessionConfig::from(config).with_extension(Arc::new(ballista_config));
 
             Arc::new(TaskContext::new(
                 Some(task_identity.clone()),
@@ -383,6 +407,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> ExecutorServer<T,
         let task_status = as_task_status(
             execution_result,
             executor_id.clone(),
+            self.executor.shuffle_service_id.clone(),
             task_id,
             part,
             operator_metrics,
@@ -529,10 +554,22 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskRunnerPool<T,
                     );
                     info!("Received task {:?}", &task_identity);
 
+                    let span = info_span!(
+                        "run_task",
+                        task_id = curator_task.task.task_id,
+                        job_id = curator_task.task.job_id.as_str(),
+                        stage_id = curator_task.task.stage_id,
+                        partition_id = curator_task.task.partition_id,
+                    );
+                    set_parent_from_props(&span, &curator_task.task.props);
+
                     let server = executor_server.clone();
-                    dedicated_executor.spawn(async move {
-                        server.run_task(task_identity.clone(), curator_task).await;
-                    });
+                    dedicated_executor.spawn(
+                        async move {
+                            server.run_task(task_identity.clone(), curator_task).await;
+                        }
+                        .instrument(span),
+                    );
                 } else {
                     info!("Channel is closed and will exit the task receive loop");
                     return;
@@ -610,34 +647,67 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> ExecutorGrpc
         request: Request<RemoveJobDataParams>,
     ) -> Result<Response<RemoveJobDataResult>, Status> {
         let job_id = request.into_inner().job_id;
+        remove_job_output(
+            &self.executor.work_dir,
+            self.executor.remote_shuffle_dir.as_deref(),
+            &self.executor.get_runtime(),
+            &job_id,
+        )
+        .await?;
+        self.executor.job_owners.remove(&job_id);
+        Ok(Response::new(RemoveJobDataResult {}))
+    }
+}
 
-        let work_dir = PathBuf::from(&self.executor.work_dir);
-        let mut path = work_dir.clone();
-        path.push(&job_id);
-
-        // Verify it's an existing directory
-        if !path.is_dir() {
-            return if !path.exists() {
-                Ok(Response::new(RemoveJobDataResult {}))
-            } else {
-                Err(Status::invalid_argument(format!(
-                    "Path {path:?} is not for a directory!!!"
-                )))
-            };
-        }
-
-        if !is_subdirectory(path.as_path(), work_dir.as_path()) {
-            return Err(Status::invalid_argument(format!(
-                "Path {path:?} is not a subdirectory of {work_dir:?}!!!"
-            )));
-        }
+/// Remove the shuffle output of a job from the work dir, and from the remote shuffle dir
+/// if it was uploaded to one
+pub(crate) async fn remove_job_output(
+    work_dir: &str,
+    remote_shuffle_dir: Option<&str>,
+    runtime: &RuntimeEnv,
+    job_id: &str,
+) -> Result<(), Status> {
+    remove_job_dir(work_dir, job_id)?;
+    if let Some(remote_shuffle_dir) = remote_shuffle_dir {
+        remove_remote_job_dir(runtime, remote_shuffle_dir, job_id)
+            .await
+            .map_err(|e| {
+                Status::internal(format!(
+                    "Failed to remove the shuffle files of job {job_id} from {remote_shuffle_dir}: {e}"
+                ))
+            })?;
+    }
+    Ok(())
+}
 
-        info!("Remove data for job {:?}", job_id);
+/// Remove the directory holding the shuffle output of a job from the work dir
+fn remove_job_dir(work_dir: &str, job_id: &str) -> Result<(), Status> {
+    let work_dir = PathBuf::from(work_dir);
+    let mut path = work_dir.clone();
+    path.push(job_id);
 
-        std::fs::remove_dir_all(&path)?;
+    // Verify it's an existing directory
+    if !path.is_dir() {
+        return if !path.exists() {
+            Ok(())
+        } else {
+            Err(Status::invalid_argument(format!(
+                "Path {path:?} is not for a directory!!!"
+            )))
+        };
+    }
 
-        Ok(Response::new(RemoveJobDataResult {}))
+    if !is_subdirectory(path.as_path(), work_dir.as_path()) {
+        return Err(Status::invalid_argument(format!(
+            "Path {path:?} is not a subdirectory of {work_dir:?}!!!"
+        )));
     }
+
+    info!("Remove data for job {:?}", job_id);
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
 }
 
 // Check whether the path is the subdirectory of the base directory
@@ -655,7 +725,7 @@ fn is_subdirectory(path: &Path, base_path: &Path) -> bool {
 
 #[cfg(test)]
 mod test {
-    use crate::executor_server::is_subdirectory;
+    use super::*;
     use std::fs;
     use std::path::{Path, PathBuf};
     use tempfile::TempDir;
@@ -686,6 +756,31 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn remove_local_and_uploaded_output_of_job() -> Result<(), BallistaError> {
+        let work_dir = TempDir::new()?;
+        let remote_dir = TempDir::new()?;
+        let remote_url = format!("file://{}", remote_dir.path().display());
+        for dir in [work_dir.path(), remote_dir.path()] {
+            for job_id in ["job_a", "job_b"] {
+                let dir = dir.join(job_id).join("1").join("0");
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join("data-0.arrow"), [1, 2, 3])?;
+            }
+        }
+
+        let work_path = work_dir.path().to_str().unwrap();
+        let runtime = RuntimeEnv::default();
+        remove_job_output(work_path, Some(&remote_url), &runtime, "job_a")
+            .await
+            .unwrap();
+        assert!(!work_dir.path().join("job_a").exists());
+        assert!(!remote_dir.path().join("job_a/1/0/data-0.arrow").exists());
+        assert!(work_dir.path().join("job_b/1/0/data-0.arrow").exists());
+        assert!(remote_dir.path().join("job_b/1/0/data-0.arrow").exists());
+        Ok(())
+    }
+
     fn prepare_testing_job_directory(base_dir: &Path, job_id: &str) -> PathBuf {
         let mut path = base_dir.to_path_buf();
         path.push(job_id);
@@ -20,46 +20,171 @@
 use arrow::ipc::reader::StreamReader;
 use std::convert::TryFrom;
 use std::fs::File;
+use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use arrow::ipc::CompressionType;
 use arrow_flight::encode::FlightDataEncoderBuilder;
 use arrow_flight::error::FlightError;
+use ballista_core::auth::UserCredentials;
+use ballista_core::config::ShuffleCompression;
 use ballista_core::error::BallistaError;
-use ballista_core::serde::decode_protobuf;
+use ballista_core::execution_plans::sort_shuffle::{self, is_sort_shuffle_path};
 use ballista_core::serde::scheduler::Action as BallistaAction;
+use ballista_core::serde::{decode_protobuf, decode_ticket};
+use ballista_core::shuffle_compression::read_shuffle_stream;
+use ballista_core::shuffle_storage::{is_object_store_path, read_shuffle_file};
+use ballista_core::telemetry::set_parent_from_metadata;
 
-use arrow::ipc::writer::IpcWriteOptions;
 use arrow_flight::{
     flight_service_server::FlightService, Action, ActionType, Criteria, Empty, FlightData,
     FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse, PutResult, SchemaResult,
     Ticket,
 };
+use dashmap::DashMap;
 use datafusion::arrow::{error::ArrowError, record_batch::RecordBatch};
+use datafusion::execution::runtime_env::RuntimeEnv;
 use futures::{Stream, StreamExt, TryStreamExt};
 use log::{debug, info};
-use std::io::{Read, Seek};
+use std::io::{BufReader, Read};
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::error::SendError;
 use tokio::{sync::mpsc::Sender, task};
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::metadata::MetadataValue;
+use tonic::metadata::{MetadataMap, MetadataValue};
 use tonic::{Request, Response, Status, Streaming};
-use tracing::warn;
+use tracing::{info_span, warn, Instrument};
+
+/// Tokens handed out by the handshake which have not been used for this long expire
+const TOKEN_TTL: Duration = Duration::from_secs(3600);
 
 /// Service implementing the Apache Arrow Flight Protocol
 #[derive(Clone)]
-pub struct BallistaFlightService {}
+pub struct BallistaFlightService {
+    /// Runtime providing the object stores of partitions which were uploaded to one
+    runtime: Arc<RuntimeEnv>,
+    /// Directory the local partitions are served from, partitions of a job have to be in
+    /// the directory of the job in it
+    work_dir: PathBuf,
+    /// Users which clients have to authenticate as, the same as those of the scheduler
+    users: Arc<UserCredentials>,
+    /// Tokens handed out by the handshake, by the token
+    tokens: Arc<DashMap<String, Token>>,
+    /// Users which submitted the jobs, by the job ID. Other users than the cluster user
+    /// can only fetch the partitions of their own jobs.
+    job_owners: Arc<DashMap<String, String>>,
+}
+
+/// Token handed out to a user by the handshake
+struct Token {
+    user: String,
+    last_used: Instant,
+}
 
 impl BallistaFlightService {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(work_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            runtime: Arc::new(RuntimeEnv::default()),
+            work_dir: work_dir.into(),
+            users: Arc::new(UserCredentials::default()),
+            tokens: Arc::new(DashMap::new()),
+            job_owners: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn with_runtime(mut self, runtime: Arc<RuntimeEnv>) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    pub fn with_users(mut self, users: UserCredentials) -> Self {
+        self.users = Arc::new(users);
+        self
+    }
+
+    /// Share the owners of the jobs whose tasks the executor runs
+    pub fn with_job_owners(mut self, job_owners: Arc<DashMap<String, String>>) -> Self {
+        self.job_owners = job_owners;
+        self
+    }
+
+    /// Check that the request carries the credentials of a user, or a token handed out
+    /// by the handshake, and return the user
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<String, Status> {
+        let authorization = metadata
+            .get("authorization")
+            .ok_or_else(|| Status::unauthenticated("authorization field not present"))?
+            .to_str()
+            .map_err(|_| Status::invalid_argument("authorization not parsable"))?;
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            return match self.tokens.get_mut(token) {
+                Some(mut token) if token.last_used.elapsed() < TOKEN_TTL => {
+                    token.last_used = Instant::now();
+                    Ok(token.user.clone())
+                }
+                _ => Err(Status::unauthenticated("Invalid or expired token")),
+            };
+        }
+        match self.users.authenticate_basic(authorization) {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(Status::unauthenticated("Invalid credentials!")),
+            Err(e) => Err(Status::invalid_argument(e.to_string())),
+        }
+    }
+
+    /// Check that the user may fetch the partitions of the job. The scheduler and the
+    /// executors fetch them as the cluster user, clients only those of their own jobs.
+    fn check_job_owner(&self, user: &str, job_id: &str) -> Result<(), Status> {
+        let owned = self
+            .job_owners
+            .get(job_id)
+            .is_some_and(|owner| owner.as_str() == user);
+        if owned || self.users.cluster_user() == Some(user) {
+            Ok(())
+        } else {
+            Err(Status::permission_denied(format!(
+                "Job {job_id} was not submitted by {user}"
+            )))
+        }
+    }
+
+    /// The canonical path of a local partition file of the job, which has to be in the
+    /// directory of the job in the work dir
+    fn job_file(&self, job_id: &str, path: &str) -> Result<PathBuf, Status> {
+        let denied =
+            || Status::permission_denied(format!("{path} is not a partition file of job {job_id}"));
+        let mut components = Path::new(job_id).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(denied());
+        }
+        let job_dir = self
+            .work_dir
+            .join(job_id)
+            .canonicalize()
+            .map_err(|_| denied())?;
+        let file = Path::new(path).canonicalize().map_err(|e| {
+            Status::not_found(format!("Failed to open partition file at {path}: {e:?}"))
+        })?;
+        if file.starts_with(job_dir) {
+            Ok(file)
+        } else {
+            Err(denied())
+        }
     }
 }
 
-impl Default for BallistaFlightService {
-    fn default() -> Self {
-        Self::new()
+/// Check that a partition uploaded to an object store is one of the job
+fn check_job_url(job_id: &str, url: &str) -> Result<(), Status> {
+    if !job_id.is_empty() && url.split('/').any(|segment| segment == job_id) {
+        Ok(())
+    } else {
+        Err(Status::permission_denied(format!(
+            "{url} is not a partition file of job {job_id}"
+        )))
     }
 }
 
@@ -70,10 +195,20 @@ impl FlightService for BallistaFlightService {
     type HandshakeStream = BoxedFlightStream<HandshakeResponse>;
     async fn handshake(
         &self,
-        _request: Request<Streaming<HandshakeRequest>>,
+        request: Request<Streaming<HandshakeRequest>>,
     ) -> Result<Response<Self::HandshakeStream>, Status> {
+        let user = self.authenticate(request.metadata())?;
         let token = uuid::Uuid::new_v4();
         info!("do_handshake token={}", token);
+        self.tokens
+            .retain(|_, token| token.last_used.elapsed() < TOKEN_TTL);
+        self.tokens.insert(
+            token.to_string(),
+            Token {
+                user,
+                last_used: Instant::now(),
+            },
+        );
 
         let result = HandshakeResponse {
             protocol_version: 0,
@@ -114,43 +249,81 @@ impl FlightService for BallistaFlightService {
         &self,
         request: Request<Ticket>,
     ) -> Result<Response<Self::DoGetStream>, Status> {
+        let span = info_span!("serve_partition", path = tracing::field::Empty);
+        set_parent_from_metadata(&span, request.metadata());
+        let user = self.authenticate(request.metadata())?;
         let ticket = request.into_inner();
 
-        let action = decode_protobuf(&ticket.ticket).map_err(|e| from_ballista_err(&e))?;
+        let action = decode_ticket(&ticket.ticket).map_err(|e| from_ballista_err(&e))?;
+        let BallistaAction::FetchPartition { job_id, .. } = &action;
+        self.check_job_owner(&user, job_id)?;
 
         match &action {
-            BallistaAction::FetchPartition { path, .. } => {
-                debug!("FetchPartition reading {}", path);
-                let file = File::open(path)
-                    .map_err(|e| {
-                        BallistaError::General(format!(
-                            "Failed to open partition file at {path}: {e:?}"
-                        ))
-                    })
+            BallistaAction::FetchPartition {
+                job_id,
+                path,
+                partition_id,
+                compression,
+                ..
+            } if is_object_store_path(path) => {
+                check_job_url(job_id, path)?;
+                debug!("FetchPartition reading {} from object store", path);
+                span.record("path", path.as_str());
+                let stream = read_shuffle_file(&self.runtime, path, *partition_id)
+                    .instrument(span)
+                    .await
                     .map_err(|e| from_ballista_err(&e))?;
-                let reader = StreamReader::try_new(file, None).map_err(|e| from_arrow_err(&e))?;
-
-                let (tx, rx) = channel(2);
-                let schema = reader.schema();
-                task::spawn_blocking(move || {
-                    if let Err(e) = read_partition(reader, tx) {
-                        warn!(error = %e, "error streaming shuffle partition");
-                    }
-                });
-
-                let write_options: IpcWriteOptions = IpcWriteOptions::default()
-                    .try_with_compression(Some(CompressionType::LZ4_FRAME))
+
+                let write_options = compression
+                    .write_options()
                     .map_err(|e| from_arrow_err(&e))?;
                 let flight_data_stream = FlightDataEncoderBuilder::new()
-                    .with_schema(schema)
+                    .with_schema(stream.schema())
                     .with_options(write_options)
-                    .build(ReceiverStream::new(rx))
+                    .build(stream.map_err(|e| FlightError::ExternalError(Box::new(e))))
                     .map_err(|err| Status::from_error(Box::new(err)));
 
                 Ok(Response::new(
                     Box::pin(flight_data_stream) as Self::DoGetStream
                 ))
             }
+            BallistaAction::FetchPartition {
+                job_id,
+                path,
+                partition_id,
+                compression,
+                ..
+            } if is_sort_shuffle_path(path) => {
+                let file = self.job_file(job_id, path)?;
+                debug!(
+                    "FetchPartition reading partition {} of {}",
+                    partition_id, path
+                );
+                span.record("path", path.as_str());
+                let reader = sort_shuffle::read_partition(&file.to_string_lossy(), *partition_id)
+                    .map_err(|e| from_ballista_err(&BallistaError::from(e)))?;
+                serve_partition(reader, *compression, span)
+            }
+            BallistaAction::FetchPartition {
+                job_id,
+                path,
+                compression,
+                ..
+            } => {
+                let file = self.job_file(job_id, path)?;
+                debug!("FetchPartition reading {}", path);
+                span.record("path", path.as_str());
+                let file = File::open(file)
+                    .map_err(|e| {
+                        BallistaError::General(format!(
+                            "Failed to open partition file at {path}: {e:?}"
+                        ))
+                    })
+                    .map_err(|e| from_ballista_err(&e))?;
+                let reader = read_shuffle_stream(file)
+                    .map_err(|e| from_ballista_err(&BallistaError::from(e)))?;
+                serve_partition(reader, *compression, span)
+            }
         }
     }
 
@@ -201,12 +374,42 @@ impl FlightService for BallistaFlightService {
     }
 }
 
+/// Stream a partition read from the local disk to the client
+fn serve_partition<T>(
+    reader: StreamReader<BufReader<T>>,
+    compression: ShuffleCompression,
+    span: tracing::Span,
+) -> Result<Response<BoxedFlightStream<FlightData>>, Status>
+where
+    T: Read + Send + 'static,
+{
+    let (tx, rx) = channel(2);
+    let schema = reader.schema();
+    task::spawn_blocking(move || {
+        let _guard = span.enter();
+        if let Err(e) = read_partition(reader, tx) {
+            warn!(error = %e, "error streaming shuffle partition");
+        }
+    });
+
+    let write_options = compression
+        .write_options()
+        .map_err(|e| from_arrow_err(&e))?;
+    let flight_data_stream = FlightDataEncoderBuilder::new()
+        .with_schema(schema)
+        .with_options(write_options)
+        .build(ReceiverStream::new(rx))
+        .map_err(|err| Status::from_error(Box::new(err)));
+
+    Ok(Response::new(Box::pin(flight_data_stream)))
+}
+
 fn read_partition<T>(
-    reader: StreamReader<std::io::BufReader<T>>,
+    reader: StreamReader<BufReader<T>>,
     tx: Sender<Result<RecordBatch, FlightError>>,
 ) -> Result<(), FlightError>
 where
-    T: Read + Seek,
+    T: Read,
 {
     if tx.is_closed() {
         return Err(FlightError::Tonic(Status::internal(
@@ -234,3 +437,129 @@ fn from_arrow_err(e: &ArrowError) -> Status {
 fn from_ballista_err(e: &ballista_core::error::BallistaError) -> Status {
     Status::internal(format!("Ballista Error: {e:?}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ballista_core::serde::protobuf;
+    use prost::Message;
+    use tempfile::TempDir;
+
+    #[test]
+    fn serve_only_partition_files_of_the_job() -> Result<(), Box<dyn std::error::Error>> {
+        let work_dir = TempDir::new()?;
+        for job_id in ["job_a", "job_b"] {
+            let dir = work_dir.path().join(job_id).join("1").join("0");
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(dir.join("data-0.arrow"), [])?;
+        }
+        std::fs::write(work_dir.path().join("secret"), [])?;
+        let service = BallistaFlightService::new(work_dir.path());
+
+        let path = work_dir.path().join("job_a/1/0/data-0.arrow");
+        let path = path.to_str().unwrap();
+        assert!(service.job_file("job_a", path).is_ok());
+        for (job_id, path) in [
+            ("job_b", path.to_owned()),
+            (
+                "job_a",
+                format!("{}/job_a/../secret", work_dir.path().display()),
+            ),
+            ("..", format!("{}/secret", work_dir.path().display())),
+            ("", path.to_owned()),
+        ] {
+            let err = service.job_file(job_id, &path).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        }
+
+        assert!(check_job_url("job_a", "s3://bucket/shuffle/job_a/1/0/data-0.arrow").is_ok());
+        assert!(check_job_url("job_b", "s3://bucket/shuffle/job_a/1/0/data-0.arrow").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn authenticate_with_the_users_of_the_scheduler() {
+        let service = BallistaFlightService::new(".")
+            .with_users(UserCredentials::new().with_user("alice", "secret"));
+        let request = |authorization: Option<&str>| {
+            let mut metadata = MetadataMap::new();
+            if let Some(authorization) = authorization {
+                metadata.insert("authorization", authorization.parse().unwrap());
+            }
+            metadata
+        };
+
+        // alice:secret
+        let alice = "Basic YWxpY2U6c2VjcmV0";
+        assert_eq!(
+            service.authenticate(&request(Some(alice))).unwrap(),
+            "alice"
+        );
+        // admin:password
+        let admin = "Basic YWRtaW46cGFzc3dvcmQ=";
+        let err = service.authenticate(&request(Some(admin))).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        let err = service.authenticate(&request(None)).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let err = service
+            .authenticate(&request(Some("Bearer token")))
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        service.tokens.insert(
+            "token".to_owned(),
+            Token {
+                user: "alice".to_owned(),
+                last_used: Instant::now(),
+            },
+        );
+        assert_eq!(
+            service
+                .authenticate(&request(Some("Bearer token")))
+                .unwrap(),
+            "alice"
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_partitions_only_to_the_job_owner() -> Result<(), Box<dyn std::error::Error>> {
+        let work_dir = TempDir::new()?;
+        let dir = work_dir.path().join("job_a").join("1").join("0");
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("data-0.arrow"), [])?;
+        let job_owners = Arc::new(DashMap::new());
+        job_owners.insert("job_a".to_owned(), "alice".to_owned());
+        let users = UserCredentials::default()
+            .with_user("alice", "secret")
+            .with_user("bob", "secret");
+        let service = BallistaFlightService::new(work_dir.path())
+            .with_users(users)
+            .with_job_owners(job_owners);
+
+        assert!(service.check_job_owner("alice", "job_a").is_ok());
+        // The scheduler and the other executors fetch partitions as the cluster user
+        assert!(service.check_job_owner("admin", "job_a").is_ok());
+        assert!(service.check_job_owner("alice", "job_b").is_err());
+
+        let action: protobuf::Action = BallistaAction::FetchPartition {
+            job_id: "job_a".to_owned(),
+            stage_id: 1,
+            partition_id: 0,
+            path: dir.join("data-0.arrow").to_string_lossy().into_owned(),
+            host: "localhost".to_owned(),
+            port: 50051,
+            compression: ShuffleCompression::default(),
+        }
+        .try_into()?;
+        let mut request = Request::new(Ticket {
+            ticket: action.encode_to_vec().into(),
+        });
+        // bob:secret
+        request
+            .metadata_mut()
+            .insert("authorization", "Basic Ym9iOnNlY3JldA==".parse()?);
+        let err = service.do_get(request).await.err().unwrap();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        Ok(())
+    }
+}
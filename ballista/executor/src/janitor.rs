@@ -0,0 +1,363 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Removal of shuffle data which the scheduler will never ask the executor to remove, e.g.
+//! because the scheduler crashed or the executor restarted before the job data was
+//! cleaned up. The janitor asks the scheduler which jobs are still active, and removes the
+//! directories of the other jobs from the work dir. It also removes the data of jobs which
+//! has not been written for longer than a TTL, and the oldest job data while the work dir
+//! holds more than a total size.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use tonic::transport::Channel;
+
+use ballista_core::error::Result;
+use ballista_core::serde::protobuf::{
+    executor_metric, scheduler_grpc_client::SchedulerGrpcClient, ExecutorMetric,
+    GetActiveJobsParams,
+};
+
+/// Directories of jobs the scheduler does not know about are only removed once they have
+/// not been written for this long, as their jobs may have been submitted after the
+/// scheduler was asked for the active jobs
+const MIN_UNKNOWN_JOB_DATA_AGE: Duration = Duration::from_secs(60);
+
+/// Prefix of the directories DataFusion spills to, which are in the work dir as well
+const SPILL_DIR_PREFIX: &str = "datafusion-";
+
+pub struct JanitorConfig {
+    /// How often the work dir is cleaned up
+    pub interval: Duration,
+    /// Job data which has not been written for this long is removed, even if the job is
+    /// still active
+    pub ttl: Option<Duration>,
+    /// The oldest job data is removed while the work dir holds more than this many bytes
+    pub max_bytes: Option<u64>,
+}
+
+/// Shuffle data removed by the janitor, which is reported to the scheduler in heartbeats
+#[derive(Debug, Default)]
+pub struct JanitorMetrics {
+    reclaimed_bytes: AtomicU64,
+    removed_job_dirs: AtomicU64,
+}
+
+impl JanitorMetrics {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.reclaimed_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn removed_job_dirs(&self) -> u64 {
+        self.removed_job_dirs.load(Ordering::Relaxed)
+    }
+
+    pub fn executor_metrics(&self) -> Vec<ExecutorMetric> {
+        vec![
+            ExecutorMetric {
+                metric: Some(executor_metric::Metric::ReclaimedShuffleBytes(
+                    self.reclaimed_bytes(),
+                )),
+            },
+            ExecutorMetric {
+                metric: Some(executor_metric::Metric::RemovedJobDirs(
+                    self.removed_job_dirs(),
+                )),
+            },
+        ]
+    }
+
+    fn record_removal(&self, bytes: u64) {
+        self.reclaimed_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.removed_job_dirs.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The data of one job in the work dir
+#[derive(Debug)]
+struct JobDir {
+    job_id: String,
+    path: PathBuf,
+    bytes: u64,
+    /// Last time a file of the job was written
+    modified: SystemTime,
+}
+
+pub(crate) struct Janitor {
+    work_dir: String,
+    config: JanitorConfig,
+    metrics: Arc<JanitorMetrics>,
+}
+
+impl Janitor {
+    pub(crate) fn new(
+        work_dir: impl Into<String>,
+        config: JanitorConfig,
+        metrics: Arc<JanitorMetrics>,
+    ) -> Self {
+        Self {
+            work_dir: work_dir.into(),
+            config,
+            metrics,
+        }
+    }
+
+    /// Clean up the work dir now and then periodically
+    pub(crate) fn start(self, scheduler: SchedulerGrpcClient<Channel>) {
+        let janitor = Arc::new(self);
+        tokio::spawn(async move {
+            info!("Starting janitor to clean up the work dir periodically");
+            loop {
+                if let Err(e) = janitor.run(scheduler.clone()).await {
+                    warn!("Fail to clean up the work dir due to {:?}", e);
+                }
+                tokio::time::sleep(janitor.config.interval).await;
+            }
+        });
+    }
+
+    async fn run(self: &Arc<Self>, mut scheduler: SchedulerGrpcClient<Channel>) -> Result<()> {
+        // Job data is only removed by TTL and size if the active jobs are not known
+        let active_jobs: Option<HashSet<String>> =
+            match scheduler.get_active_jobs(GetActiveJobsParams {}).await {
+                Ok(result) => Some(result.into_inner().job_id.into_iter().collect()),
+                Err(e) => {
+                    warn!("Fail to get active jobs from the scheduler due to {:?}", e);
+                    None
+                }
+            };
+
+        let janitor = self.clone();
+        tokio::task::spawn_blocking(move || {
+            janitor.clean_up(active_jobs.as_ref(), SystemTime::now())
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Remove the directories of jobs which are not active, have expired or do not fit
+    /// into the maximum size, and return the number of bytes removed
+    fn clean_up(&self, active_jobs: Option<&HashSet<String>>, now: SystemTime) -> Result<u64> {
+        let age = |job_dir: &JobDir| now.duration_since(job_dir.modified).unwrap_or_default();
+
+        let mut kept = vec![];
+        let mut reclaimed_bytes = 0;
+        for job_dir in job_dirs(Path::new(&self.work_dir))? {
+            let inactive = active_jobs.is_some_and(|active_jobs| {
+                !active_jobs.contains(&job_dir.job_id) && age(&job_dir) >= MIN_UNKNOWN_JOB_DATA_AGE
+            });
+            let expired = self.config.ttl.is_some_and(|ttl| age(&job_dir) >= ttl);
+            if inactive || expired {
+                info!(
+                    "Remove data for {} job {:?}",
+                    if inactive { "inactive" } else { "expired" },
+                    job_dir.job_id
+                );
+                reclaimed_bytes += self.remove(&job_dir)?;
+            } else {
+                kept.push(job_dir);
+            }
+        }
+
+        if let Some(max_bytes) = self.config.max_bytes {
+            let mut total_bytes: u64 = kept.iter().map(|job_dir| job_dir.bytes).sum();
+            kept.sort_by_key(|job_dir| job_dir.modified);
+            for job_dir in kept {
+                if total_bytes <= max_bytes {
+                    break;
+                }
+                info!(
+                    "Remove data for job {:?} as the work dir holds {} bytes, more than {}",
+                    job_dir.job_id, total_bytes, max_bytes
+                );
+                total_bytes -= job_dir.bytes;
+                reclaimed_bytes += self.remove(&job_dir)?;
+            }
+        }
+
+        Ok(reclaimed_bytes)
+    }
+
+    fn remove(&self, job_dir: &JobDir) -> Result<u64> {
+        match std::fs::remove_dir_all(&job_dir.path) {
+            Ok(()) => {
+                self.metrics.record_removal(job_dir.bytes);
+                Ok(job_dir.bytes)
+            }
+            // Removed by another executor sharing the work dir of a shuffle service
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Skip the current entry of a listing of the work dir if it was removed meanwhile, e.g.
+/// by the clean up of its job or by another executor sharing the work dir
+macro_rules! skip_not_found {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    };
+}
+
+/// List the job directories in the work dir with their sizes and modification times
+fn job_dirs(work_dir: &Path) -> io::Result<Vec<JobDir>> {
+    let mut job_dirs = vec![];
+    for entry in std::fs::read_dir(work_dir)? {
+        let entry = skip_not_found!(entry);
+        let job_id = entry.file_name().to_string_lossy().into_owned();
+        if !skip_not_found!(entry.file_type()).is_dir() || job_id.starts_with(SPILL_DIR_PREFIX) {
+            continue;
+        }
+        let path = entry.path();
+        let mut job_dir = JobDir {
+            job_id,
+            bytes: 0,
+            modified: skip_not_found!(entry.metadata()).modified()?,
+            path: path.clone(),
+        };
+        add_dir_contents(&path, &mut job_dir)?;
+        job_dirs.push(job_dir);
+    }
+    Ok(job_dirs)
+}
+
+fn add_dir_contents(dir: &Path, job_dir: &mut JobDir) -> io::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = skip_not_found!(entry);
+        let metadata = skip_not_found!(entry.metadata());
+        job_dir.modified = job_dir.modified.max(metadata.modified()?);
+        if metadata.is_dir() {
+            add_dir_contents(&entry.path(), job_dir)?;
+        } else {
+            job_dir.bytes += metadata.len();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_job_data(work_dir: &Path, job_id: &str, bytes: usize) {
+        let dir = work_dir.join(job_id).join("1").join("0");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("data-0.arrow"), vec![0u8; bytes]).unwrap();
+    }
+
+    fn janitor(work_dir: &Path, ttl: Option<Duration>, max_bytes: Option<u64>) -> Janitor {
+        Janitor::new(
+            work_dir.to_str().unwrap(),
+            JanitorConfig {
+                interval: Duration::from_secs(60),
+                ttl,
+                max_bytes,
+            },
+            Arc::new(JanitorMetrics::default()),
+        )
+    }
+
+    #[test]
+    fn remove_data_of_inactive_jobs() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        write_job_data(work_dir.path(), "active", 10);
+        write_job_data(work_dir.path(), "finished", 20);
+        std::fs::create_dir(work_dir.path().join("datafusion-spill"))?;
+
+        let janitor = janitor(work_dir.path(), None, None);
+        let active_jobs = HashSet::from(["active".to_owned()]);
+
+        // Data of unknown jobs is kept while it may belong to a newly submitted job
+        assert_eq!(janitor.clean_up(Some(&active_jobs), SystemTime::now())?, 0);
+        assert!(work_dir.path().join("finished").exists());
+
+        let later = SystemTime::now() + MIN_UNKNOWN_JOB_DATA_AGE;
+        // Nothing is removed if the active jobs are not known
+        assert_eq!(janitor.clean_up(None, later)?, 0);
+        assert_eq!(janitor.clean_up(Some(&active_jobs), later)?, 20);
+        assert!(work_dir.path().join("active").exists());
+        assert!(!work_dir.path().join("finished").exists());
+        assert!(work_dir.path().join("datafusion-spill").exists());
+        assert_eq!(janitor.metrics.reclaimed_bytes(), 20);
+        assert_eq!(janitor.metrics.removed_job_dirs(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn skip_job_data_removed_while_listing() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        write_job_data(work_dir.path(), "job", 10);
+
+        let mut job_dir = job_dirs(work_dir.path())?.remove(0);
+        assert_eq!(job_dir.bytes, 10);
+        std::fs::remove_dir_all(&job_dir.path)?;
+        add_dir_contents(&job_dir.path.clone(), &mut job_dir)?;
+        assert_eq!(job_dir.bytes, 10);
+        assert!(job_dirs(work_dir.path())?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn remove_expired_job_data() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        write_job_data(work_dir.path(), "active", 10);
+
+        let ttl = Duration::from_secs(3600);
+        let janitor = janitor(work_dir.path(), Some(ttl), None);
+        let active_jobs = HashSet::from(["active".to_owned()]);
+
+        assert_eq!(janitor.clean_up(Some(&active_jobs), SystemTime::now())?, 0);
+        assert_eq!(
+            janitor.clean_up(Some(&active_jobs), SystemTime::now() + ttl)?,
+            10
+        );
+        assert!(!work_dir.path().join("active").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn remove_oldest_job_data_over_max_size() -> Result<()> {
+        let work_dir = TempDir::new()?;
+        write_job_data(work_dir.path(), "job_a", 100);
+        write_job_data(work_dir.path(), "job_b", 100);
+        write_job_data(work_dir.path(), "job_c", 100);
+
+        let janitor = janitor(work_dir.path(), None, Some(250));
+
+        assert_eq!(janitor.clean_up(None, SystemTime::now())?, 100);
+        let remaining = job_dirs(work_dir.path())?;
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(janitor.metrics.removed_job_dirs(), 1);
+        Ok(())
+    }
+}
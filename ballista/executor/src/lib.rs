@@ -21,6 +21,8 @@ pub mod executor;
 pub mod executor_process;
 pub mod executor_server;
 pub mod flight_service;
+pub mod janitor;
+pub mod shuffle_service;
 
 mod cpu_bound_executor;
 
@@ -41,6 +43,7 @@ pub struct TaskExecutionTimes {
 pub fn as_task_status(
     execution_result: ballista_core::error::Result<Vec<ShuffleWritePartition>>,
     executor_id: String,
+    shuffle_service_id: Option<String>,
     task_id: usize,
     partition_id: PartitionId,
     operator_metrics: Option<Vec<OperatorMetricsSet>>,
@@ -66,6 +69,7 @@ pub fn as_task_status(
                 status: Some(task_status::Status::Successful(SuccessfulTask {
                     executor_id,
                     partitions,
+                    shuffle_service_id: shuffle_service_id.unwrap_or_default(),
                 })),
             }
         }
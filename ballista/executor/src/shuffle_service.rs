@@ -0,0 +1,288 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Standalone shuffle service, which serves the shuffle files that executors on the same
+//! host write into its work dir. The partition locations of those files point at the
+//! shuffle service, so they stay readable after the executors which wrote them are gone.
+//!
+//! The shuffle service registers with the scheduler like an executor without task slots,
+//! so it is never offered tasks and its liveness is tracked through its heartbeats.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::stream::FuturesUnordered;
+use log::{error, info, warn};
+use tokio::signal;
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status};
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
+
+use ballista_core::auth::UserCredentials;
+use ballista_core::error::BallistaError;
+use ballista_core::object_store_registry::{BallistaObjectStoreRegistry, ObjectStoreConfig};
+use ballista_core::serde::protobuf::executor_resource::Resource;
+use ballista_core::serde::protobuf::{
+    executor_grpc_server::{ExecutorGrpc, ExecutorGrpcServer},
+    executor_status,
+    scheduler_grpc_client::SchedulerGrpcClient,
+    CancelTasksParams, CancelTasksResult, ExecutorRegistration, ExecutorResource,
+    ExecutorSpecification, ExecutorStatus, HeartBeatParams, LaunchMultiTaskParams,
+    LaunchMultiTaskResult, RegisterExecutorParams, RemoveJobDataParams, RemoveJobDataResult,
+};
+use ballista_core::utils::{create_grpc_client_connection, create_grpc_server};
+use ballista_core::BALLISTA_VERSION;
+
+use crate::executor_process::{check_services, flight_server_run};
+use crate::executor_server::remove_job_output;
+use crate::flight_service::BallistaFlightService;
+
+/// Name of the file in the work dir of a shuffle service which holds its id. Executors
+/// writing into the work dir read the id from it, and a restarted shuffle service keeps
+/// the id so that the partition locations pointing at it remain valid.
+pub const SHUFFLE_SERVICE_ID_FILE: &str = "shuffle-service.id";
+
+pub struct ShuffleServiceConfig {
+    pub bind_host: String,
+    /// Port of the flight server which serves the shuffle files
+    pub port: u16,
+    /// Port of the grpc server which the scheduler asks to remove job data through
+    pub grpc_port: u16,
+    pub scheduler_host: String,
+    pub scheduler_port: u16,
+    /// Directory which executors write the shuffle files served by this service into
+    pub work_dir: String,
+    pub special_mod_log_level: String,
+    pub heartbeat_interval_seconds: u64,
+    /// Users which Flight clients fetching partitions have to authenticate as, the same
+    /// as those of the scheduler
+    pub users: UserCredentials,
+    /// Object store URL prefix which the executors upload shuffle output to, if they do
+    pub remote_shuffle_dir: Option<String>,
+}
+
+/// Read the id of the shuffle service with the given work dir, if one has been started
+pub fn read_shuffle_service_id(work_dir: &str) -> io::Result<Option<String>> {
+    match std::fs::read_to_string(Path::new(work_dir).join(SHUFFLE_SERVICE_ID_FILE)) {
+        Ok(id) => Ok(Some(id.trim().to_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Return the id of the shuffle service with the given work dir, assigning a new one if
+/// no shuffle service has been started with it yet
+fn shuffle_service_id(work_dir: &str) -> io::Result<String> {
+    if let Some(id) = read_shuffle_service_id(work_dir)? {
+        return Ok(id);
+    }
+    std::fs::create_dir_all(work_dir)?;
+    let id = format!("shuffle-service-{}", Uuid::new_v4());
+    std::fs::write(Path::new(work_dir).join(SHUFFLE_SERVICE_ID_FILE), &id)?;
+    Ok(id)
+}
+
+pub async fn start_shuffle_service(opt: Arc<ShuffleServiceConfig>) -> Result<()> {
+    let rust_log = std::env::var(EnvFilter::DEFAULT_ENV);
+    let log_filter = EnvFilter::new(rust_log.unwrap_or(opt.special_mod_log_level.clone()));
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_thread_names(true)
+        .with_thread_ids(true)
+        .with_writer(io::stdout)
+        .with_env_filter(log_filter)
+        .init();
+
+    let addr = format!("{}:{}", opt.bind_host, opt.port);
+    let addr = addr
+        .parse()
+        .with_context(|| format!("Could not parse address: {addr}"))?;
+    let grpc_addr = format!("{}:{}", opt.bind_host, opt.grpc_port);
+    let grpc_addr = grpc_addr
+        .parse()
+        .with_context(|| format!("Could not parse address: {grpc_addr}"))?;
+
+    let id = shuffle_service_id(&opt.work_dir)
+        .with_context(|| format!("Could not assign shuffle service id in {}", opt.work_dir))?;
+    info!(
+        "Running shuffle service {} with work_dir: {}",
+        id, opt.work_dir
+    );
+
+    let metadata = ExecutorRegistration {
+        id: id.clone(),
+        port: opt.port as u32,
+        grpc_port: opt.grpc_port as u32,
+        specification: Some(ExecutorSpecification {
+            resources: vec![ExecutorResource {
+                resource: Some(Resource::TaskSlots(0)),
+            }],
+        }),
+    };
+
+    let scheduler_url = format!("http://{}:{}", opt.scheduler_host, opt.scheduler_port);
+    let connection = create_grpc_client_connection(scheduler_url)
+        .await
+        .context("Could not connect to scheduler")?;
+    let mut scheduler = SchedulerGrpcClient::new(connection);
+
+    let mut service_handlers: FuturesUnordered<JoinHandle<Result<(), BallistaError>>> =
+        FuturesUnordered::new();
+
+    // Shuffle files in a remote shuffle dir are read and removed with the object store
+    // settings of the process, as the executors wrote them
+    let object_stores = Arc::new(BallistaObjectStoreRegistry::new(
+        ObjectStoreConfig::default(),
+    ));
+    let runtime = RuntimeEnv::new(RuntimeConfig::new().with_object_store_registry(object_stores))
+        .context("Failed to init Shuffle Service RuntimeEnv")?;
+    let runtime = Arc::new(runtime);
+
+    let server = ExecutorGrpcServer::new(ShuffleService {
+        work_dir: opt.work_dir.clone(),
+        remote_shuffle_dir: opt.remote_shuffle_dir.clone(),
+        runtime: runtime.clone(),
+    });
+    info!(
+        "Ballista v{} Rust Shuffle Service Grpc Server listening on {:?}",
+        BALLISTA_VERSION, grpc_addr
+    );
+    service_handlers.push(tokio::spawn(async move {
+        create_grpc_server()
+            .add_service(server)
+            .serve(grpc_addr)
+            .await
+            .map_err(|e| {
+                error!("Tonic error, Could not start Shuffle Service Grpc Server.");
+                BallistaError::TonicError(e)
+            })
+    }));
+    let flight_service = BallistaFlightService::new(&opt.work_dir)
+        .with_runtime(runtime)
+        .with_users(opt.users.clone());
+    service_handlers.push(tokio::spawn(flight_server_run(addr, flight_service)));
+
+    let registered = scheduler
+        .register_executor(RegisterExecutorParams {
+            metadata: Some(metadata.clone()),
+        })
+        .await?;
+    if !registered.into_inner().success {
+        return Err(
+            BallistaError::General("Shuffle service registration failed!!!".to_owned()).into(),
+        );
+    }
+    info!("Shuffle service registration succeed");
+
+    let heartbeat_interval = Duration::from_secs(opt.heartbeat_interval_seconds);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(heartbeat_interval).await;
+            if let Err(e) = scheduler
+                .heart_beat_from_executor(HeartBeatParams {
+                    executor_id: metadata.id.clone(),
+                    status: Some(ExecutorStatus {
+                        status: Some(executor_status::Status::Active(String::default())),
+                    }),
+                    metadata: Some(metadata.clone()),
+                    metrics: vec![],
+                })
+                .await
+            {
+                warn!("Fail to update heartbeat of shuffle service due to {:?}", e);
+            }
+        }
+    });
+
+    tokio::select! {
+        service_val = check_services(&mut service_handlers) => {
+            info!("shuffle service stopped with reason {service_val:?}");
+        },
+        _ = signal::ctrl_c() => {
+            info!("shuffle service received ctrl-c event.");
+        },
+    };
+
+    Ok(())
+}
+
+/// Grpc service of a shuffle service. It runs no tasks, but removes the shuffle output of
+/// finished jobs from its work dir when the scheduler asks it to.
+struct ShuffleService {
+    work_dir: String,
+    remote_shuffle_dir: Option<String>,
+    runtime: Arc<RuntimeEnv>,
+}
+
+#[tonic::async_trait]
+impl ExecutorGrpc for ShuffleService {
+    async fn launch_multi_task(
+        &self,
+        _request: Request<LaunchMultiTaskParams>,
+    ) -> Result<Response<LaunchMultiTaskResult>, Status> {
+        Err(Status::failed_precondition(
+            "A shuffle service has no task slots to launch tasks on",
+        ))
+    }
+
+    async fn cancel_tasks(
+        &self,
+        _request: Request<CancelTasksParams>,
+    ) -> Result<Response<CancelTasksResult>, Status> {
+        Ok(Response::new(CancelTasksResult { cancelled: true }))
+    }
+
+    async fn remove_job_data(
+        &self,
+        request: Request<RemoveJobDataParams>,
+    ) -> Result<Response<RemoveJobDataResult>, Status> {
+        let job_id = request.into_inner().job_id;
+        remove_job_output(
+            &self.work_dir,
+            self.remote_shuffle_dir.as_deref(),
+            &self.runtime,
+            &job_id,
+        )
+        .await?;
+        Ok(Response::new(RemoveJobDataResult {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn shuffle_service_id_is_kept_across_restarts() {
+        let work_dir = TempDir::new().unwrap();
+        let work_dir = work_dir.path().join("shuffle");
+        let work_dir = work_dir.to_str().unwrap();
+
+        assert_eq!(read_shuffle_service_id(work_dir).unwrap(), None);
+        let id = shuffle_service_id(work_dir).unwrap();
+        assert!(id.starts_with("shuffle-service-"));
+        assert_eq!(shuffle_service_id(work_dir).unwrap(), id);
+        assert_eq!(read_shuffle_service_id(work_dir).unwrap(), Some(id));
+    }
+}
@@ -10,17 +10,34 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::api::result::{stream_job_output, ResultFormat};
+use crate::config::DEFAULT_TENANT;
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
 use crate::scheduler_server::SchedulerServer;
-use crate::state::execution_graph::ExecutionStage;
+use crate::state::execution_graph::{ExecutionGraph, ExecutionStage};
+use crate::state::execution_graph_dot::StageGraph;
+use crate::state::job_events::JobEvent;
+use crate::state::job_history::{status_and_reason, JobHistoryFilter};
+use crate::state::session_manager::session_tenant;
+use crate::state::task_manager::JobOverview;
+use ballista_core::config::{BallistaConfig, BALLISTA_TENANT};
 use ballista_core::serde::protobuf::job_status::Status;
+use ballista_core::serde::protobuf::{
+    executor_metric, executor_status, ExecutorHeartbeat, JobStatus,
+};
 use ballista_core::BALLISTA_VERSION;
 use datafusion::physical_plan::metrics::{MetricValue, MetricsSet, Time};
+use datafusion::prelude::SessionContext;
 use datafusion_proto::logical_plan::AsLogicalPlan;
 use datafusion_proto::physical_plan::AsExecutionPlan;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
 
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
-use warp::Rejection;
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
 
 #[derive(Debug, serde::Serialize)]
 struct SchedulerStateResponse {
@@ -36,15 +53,176 @@ pub struct ExecutorMetaResponse {
     pub last_seen: u128,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct ExecutorDetailResponse {
+    pub id: String,
+    pub host: String,
+    pub port: u16,
+    pub grpc_port: u16,
+    pub status: String,
+    pub last_seen: u64,
+    pub total_task_slots: u32,
+    pub available_task_slots: Option<u32>,
+    pub running_tasks: Vec<RunningTaskResponse>,
+    /// Recent heartbeats received by this scheduler, oldest first
+    pub heartbeats: Vec<HeartbeatResponse>,
+    /// Bytes of orphaned or expired shuffle data the executor has removed from its work dir
+    pub reclaimed_shuffle_bytes: u64,
+    /// Job directories the executor has removed from its work dir without being asked to
+    pub removed_job_dirs: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RunningTaskResponse {
+    pub job_id: String,
+    pub stage_id: usize,
+    pub partition_id: usize,
+    pub task_id: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct HeartbeatResponse {
+    pub timestamp: u64,
+    pub status: String,
+}
+
+/// Filtering, sorting and pagination of the job list
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct JobsQuery {
+    /// Status of the job, e.g. `Running` or `Failed`
+    pub status: Option<String>,
+    /// Tenant the job was submitted by
+    pub user: Option<String>,
+    /// One of `job_id`, `queued_at`, `start_time` or `end_time`, defaults to `queued_at`
+    pub sort_by: Option<String>,
+    /// `asc` or `desc`, defaults to `desc`
+    pub order: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct JobResponse {
     pub job_id: String,
     pub job_status: String,
+    pub session_id: String,
+    pub user: String,
+    pub queued_at: u64,
+    pub start_time: u64,
+    pub end_time: u64,
     pub num_stages: usize,
     pub completed_stages: usize,
     pub percent_complete: u8,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct JobDetailResponse {
+    pub job_id: String,
+    pub status: String,
+    pub failure_reason: Option<String>,
+    /// Session and user are unknown while the job is queued for planning
+    pub session_id: Option<String>,
+    pub user: Option<String>,
+    pub sql: Option<String>,
+    pub queued_at: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub num_stages: usize,
+    pub completed_stages: usize,
+    pub output_locations: Vec<OutputLocationResponse>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct OutputLocationResponse {
+    pub partition_id: u32,
+    pub executor_id: String,
+    pub host: String,
+    pub port: u32,
+    pub path: String,
+    pub num_rows: i64,
+    pub num_batches: i64,
+    pub num_bytes: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct StageTasksResponse {
+    pub stage_id: usize,
+    pub stage_status: String,
+    pub tasks: Vec<TaskAttemptResponse>,
+}
+
+/// A task attempt of a stage. The scheduler only keeps the latest attempt of each partition,
+/// identified by its task id.
+#[derive(Debug, serde::Serialize)]
+pub struct TaskAttemptResponse {
+    pub task_id: usize,
+    pub partition_id: usize,
+    pub executor_id: Option<String>,
+    pub status: String,
+    pub scheduled_time: u64,
+    pub launch_time: u64,
+    pub start_exec_time: u64,
+    pub end_exec_time: u64,
+    pub finish_time: u64,
+    pub exec_duration_ms: u64,
+    /// Time from scheduling until the scheduler saw the task finish
+    pub total_duration_ms: u64,
+    /// Metrics of each operator of the stage plan, in plan order
+    pub metrics: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TenantResponse {
+    pub tenant: String,
+    pub running_jobs: usize,
+    pub running_tasks: usize,
+    pub shuffle_bytes: u64,
+    pub max_running_jobs: Option<usize>,
+    pub max_task_slots: Option<u32>,
+    pub max_shuffle_bytes: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct JobHistoryResponse {
+    pub job_id: String,
+    pub session_id: String,
+    pub sql: Option<String>,
+    pub status: String,
+    pub failure_reason: Option<String>,
+    pub queued_at: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub num_stages: usize,
+}
+
+/// A SQL query submitted over the REST API
+#[derive(Debug, serde::Deserialize)]
+pub struct SqlRequest {
+    pub sql: String,
+    /// Run the query in this session instead of the session of the bearer token
+    pub session_id: Option<String>,
+    /// Settings which are applied on top of the settings of the session. Queries with
+    /// settings run in a new session.
+    #[serde(default)]
+    pub settings: HashMap<String, String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SqlResponse {
+    pub job_id: String,
+    pub session_id: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct JobResultQuery {
+    pub format: Option<ResultFormat>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
 #[derive(Debug, serde::Serialize)]
 struct CancelJobResponse {
     pub cancelled: bool,
@@ -92,22 +270,122 @@ pub(crate) async fn get_executors<T: AsLogicalPlan, U: AsExecutionPlan>(
     Ok(warp::reply::json(&executors))
 }
 
-/// Return list of jobs
+/// Return the slots, running tasks and recent heartbeats of an executor
+pub(crate) async fn get_executor<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    executor_id: String,
+) -> Result<impl warp::Reply, Rejection> {
+    let executor_manager = &data_server.state.executor_manager;
+
+    let heartbeat = executor_manager
+        .get_executor_heartbeat(&executor_id)
+        .ok_or_else(warp::reject::not_found)?;
+    let metadata = executor_manager
+        .get_executor_metadata(&executor_id)
+        .await
+        .map_err(|_| warp::reject::not_found())?;
+    let available_task_slots = executor_manager
+        .get_available_slots(&executor_id)
+        .await
+        .map_err(|_| warp::reject())?;
+
+    let mut running_tasks: Vec<RunningTaskResponse> = data_server
+        .state
+        .task_manager
+        .get_executor_running_tasks(&executor_id)
+        .await
+        .into_iter()
+        .map(|task| RunningTaskResponse {
+            job_id: task.job_id,
+            stage_id: task.stage_id,
+            partition_id: task.partition_id,
+            task_id: task.task_id,
+        })
+        .collect();
+    running_tasks.sort_by_key(|task| task.task_id);
+
+    let heartbeats = executor_manager
+        .get_heartbeat_history(&executor_id)
+        .iter()
+        .map(|heartbeat| HeartbeatResponse {
+            timestamp: heartbeat.timestamp,
+            status: heartbeat_status(heartbeat).to_string(),
+        })
+        .collect();
+
+    let mut reclaimed_shuffle_bytes = 0;
+    let mut removed_job_dirs = 0;
+    for metric in heartbeat
+        .metrics
+        .iter()
+        .filter_map(|metric| metric.metric.as_ref())
+    {
+        match metric {
+            executor_metric::Metric::ReclaimedShuffleBytes(bytes) => {
+                reclaimed_shuffle_bytes = *bytes
+            }
+            executor_metric::Metric::RemovedJobDirs(dirs) => removed_job_dirs = *dirs,
+        }
+    }
+
+    Ok(warp::reply::json(&ExecutorDetailResponse {
+        id: metadata.id,
+        host: metadata.host,
+        port: metadata.port,
+        grpc_port: metadata.grpc_port,
+        status: heartbeat_status(&heartbeat).to_string(),
+        last_seen: heartbeat.timestamp,
+        total_task_slots: metadata.specification.task_slots,
+        available_task_slots,
+        running_tasks,
+        heartbeats,
+        reclaimed_shuffle_bytes,
+        removed_job_dirs,
+    }))
+}
+
+fn heartbeat_status(heartbeat: &ExecutorHeartbeat) -> &'static str {
+    match heartbeat
+        .status
+        .as_ref()
+        .and_then(|status| status.status.as_ref())
+    {
+        Some(executor_status::Status::Active(_)) => "Active",
+        Some(executor_status::Status::Dead(_)) => "Dead",
+        Some(executor_status::Status::Terminating(_)) => "Terminating",
+        Some(executor_status::Status::Unknown(_)) | None => "Unknown",
+    }
+}
+
+/// Return list of jobs, filtered, sorted and paginated by the query. The total number of
+/// matching jobs is returned in the `X-Total-Count` header.
 pub(crate) async fn get_jobs<T: AsLogicalPlan, U: AsExecutionPlan>(
     data_server: SchedulerServer<T, U>,
+    query: JobsQuery,
 ) -> Result<impl warp::Reply, Rejection> {
     // TODO: Display last seen information in UI
-    let state = data_server.state;
+    let state = &data_server.state;
 
     let jobs = state
         .task_manager
         .get_jobs()
         .await
         .map_err(|_| warp::reject())?;
+    let users = job_users(&data_server, &jobs).await;
 
-    let jobs: Vec<JobResponse> = jobs
+    let mut jobs: Vec<JobResponse> = jobs
         .iter()
-        .map(|job| {
+        .enumerate()
+        .filter(|(i, job)| {
+            let status_matches = query.status.as_ref().is_none_or(|status| {
+                status_and_reason(&job.status)
+                    .0
+                    .eq_ignore_ascii_case(status)
+            });
+            let user_matches = query.user.as_ref().is_none_or(|user| &users[*i] == user);
+            status_matches && user_matches
+        })
+        .map(|(i, job)| {
             let status = &job.status;
             let job_status = match &status.status {
                 Some(Status::Queued(_)) => "Queued".to_string(),
@@ -145,6 +423,11 @@ pub(crate) async fn get_jobs<T: AsLogicalPlan, U: AsExecutionPlan>(
             JobResponse {
                 job_id: job.job_id.to_string(),
                 job_status,
+                session_id: job.session_id.clone(),
+                user: users[i].clone(),
+                queued_at: job.queued_at,
+                start_time: job.start_time,
+                end_time: job.end_time,
                 num_stages: job.num_stages,
                 completed_stages: job.completed_stages,
                 percent_complete,
@@ -152,9 +435,569 @@ pub(crate) async fn get_jobs<T: AsLogicalPlan, U: AsExecutionPlan>(
         })
         .collect();
 
+    let total = jobs.len();
+    let descending =
+        !matches!(query.order.as_deref(), Some(order) if order.eq_ignore_ascii_case("asc"));
+    match query.sort_by.as_deref() {
+        Some("job_id") => jobs.sort_by(|a, b| a.job_id.cmp(&b.job_id)),
+        Some("start_time") => jobs.sort_by_key(|job| job.start_time),
+        Some("end_time") => jobs.sort_by_key(|job| job.end_time),
+        _ => jobs.sort_by_key(|job| job.queued_at),
+    }
+    if descending {
+        jobs.reverse();
+    }
+    let jobs: Vec<JobResponse> = jobs
+        .into_iter()
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    Ok(warp::reply::with_header(
+        warp::reply::json(&jobs),
+        "X-Total-Count",
+        total.to_string(),
+    ))
+}
+
+/// Resolve the tenant of each job, looking up the sessions of jobs which are no longer active
+async fn job_users<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: &SchedulerServer<T, U>,
+    jobs: &[JobOverview],
+) -> Vec<String> {
+    let state = &data_server.state;
+    let mut session_tenants: HashMap<String, String> = HashMap::new();
+    let mut users = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let user = if let Some(tenant) = state.task_manager.get_job_tenant(&job.job_id) {
+            tenant
+        } else if let Some(tenant) = session_tenants.get(&job.session_id) {
+            tenant.clone()
+        } else {
+            let tenant = state
+                .session_manager
+                .get_session_tenant(&job.session_id)
+                .await
+                .unwrap_or_else(|_| DEFAULT_TENANT.to_owned());
+            session_tenants.insert(job.session_id.clone(), tenant.clone());
+            tenant
+        };
+        users.push(user);
+    }
+    users
+}
+
+/// Return the status, timings, SQL text and output locations of a job
+pub(crate) async fn get_job<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+) -> Result<impl warp::Reply, Rejection> {
+    let state = &data_server.state;
+
+    // A rejection would turn into the 405 of the route cancelling jobs on the same path
+    let Some(job_status) = state
+        .task_manager
+        .get_job_status(&job_id)
+        .await
+        .map_err(|_| warp::reject())?
+    else {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            format!("Job {job_id} not found"),
+        ));
+    };
+    let graph = state
+        .task_manager
+        .get_job_execution_graph(&job_id)
+        .await
+        .map_err(|_| warp::reject())?;
+    let sql = state
+        .job_history
+        .sql(&job_id)
+        .await
+        .map_err(|_| warp::reject())?;
+
+    let (status, failure_reason) = status_and_reason(&job_status);
+    let output_locations = match &job_status.status {
+        Some(Status::Successful(completed)) => completed
+            .partition_location
+            .iter()
+            .map(|location| {
+                let executor = location.executor_meta.clone().unwrap_or_default();
+                let stats = location.partition_stats.clone().unwrap_or_default();
+                OutputLocationResponse {
+                    partition_id: location
+                        .partition_id
+                        .as_ref()
+                        .map(|id| id.partition_id)
+                        .unwrap_or_default(),
+                    executor_id: executor.id,
+                    host: executor.host,
+                    port: executor.port,
+                    path: location.path.clone(),
+                    num_rows: stats.num_rows,
+                    num_batches: stats.num_batches,
+                    num_bytes: stats.num_bytes,
+                }
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    let response = if let Some(graph) = graph {
+        let job = JobOverview::from(graph.as_ref());
+        let user = job_users(&data_server, std::slice::from_ref(&job))
+            .await
+            .pop();
+        JobDetailResponse {
+            job_id,
+            status,
+            failure_reason,
+            session_id: Some(job.session_id),
+            user,
+            sql,
+            queued_at: job.queued_at,
+            start_time: job.start_time,
+            end_time: job.end_time,
+            num_stages: job.num_stages,
+            completed_stages: job.completed_stages,
+            output_locations,
+        }
+    } else {
+        let queued_at = match &job_status.status {
+            Some(Status::Queued(queued)) => queued.queued_at,
+            Some(Status::Failed(failed)) => failed.queued_at,
+            _ => 0,
+        };
+        JobDetailResponse {
+            job_id,
+            status,
+            failure_reason,
+            session_id: None,
+            user: None,
+            sql,
+            queued_at,
+            start_time: 0,
+            end_time: 0,
+            num_stages: 0,
+            completed_stages: 0,
+            output_locations,
+        }
+    };
+
+    Ok(warp::reply::json(&response).into_response())
+}
+
+/// Return the stage DAG of a job as a Graphviz DOT graph
+pub(crate) async fn get_job_dot_graph<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+) -> Result<impl warp::Reply, Rejection> {
+    let graph = get_execution_graph(&data_server, &job_id).await?;
+    Ok(StageGraph::new(&graph).to_dot())
+}
+
+/// Return the stage DAG of a job as JSON
+pub(crate) async fn get_job_stage_graph<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+) -> Result<impl warp::Reply, Rejection> {
+    let graph = get_execution_graph(&data_server, &job_id).await?;
+    Ok(warp::reply::json(&StageGraph::new(&graph)))
+}
+
+async fn get_execution_graph<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: &SchedulerServer<T, U>,
+    job_id: &str,
+) -> Result<Arc<ExecutionGraph>, Rejection> {
+    data_server
+        .state
+        .task_manager
+        .get_job_execution_graph(job_id)
+        .await
+        .map_err(|_| warp::reject())?
+        .ok_or_else(warp::reject::not_found)
+}
+
+/// Return the task attempts of a stage with their executor, timings and operator metrics
+pub(crate) async fn get_stage_tasks<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+    stage_id: usize,
+) -> Result<impl warp::Reply, Rejection> {
+    let graph = get_execution_graph(&data_server, &job_id).await?;
+    let stage = graph
+        .stages()
+        .get(&stage_id)
+        .ok_or_else(warp::reject::not_found)?;
+
+    let tasks = stage
+        .task_infos()
+        .into_iter()
+        .map(|(partition_id, info)| {
+            let summary = info.summary(partition_id);
+            let metrics = info
+                .metrics()
+                .map_err(|_| warp::reject())?
+                .iter()
+                .map(|metrics| metrics.aggregate_by_name().to_string())
+                .collect();
+            let total_duration_ms = if summary.finish_time > 0 {
+                summary.finish_time.saturating_sub(summary.scheduled_time)
+            } else {
+                0
+            };
+            Ok(TaskAttemptResponse {
+                exec_duration_ms: summary
+                    .end_exec_time
+                    .saturating_sub(summary.start_exec_time),
+                total_duration_ms,
+                task_id: summary.task_id,
+                partition_id: summary.partition_id,
+                executor_id: summary.executor_id,
+                status: summary.status,
+                scheduled_time: summary.scheduled_time,
+                launch_time: summary.launch_time,
+                start_exec_time: summary.start_exec_time,
+                end_exec_time: summary.end_exec_time,
+                finish_time: summary.finish_time,
+                metrics,
+            })
+        })
+        .collect::<Result<Vec<_>, Rejection>>()?;
+
+    Ok(warp::reply::json(&StageTasksResponse {
+        stage_id,
+        stage_status: stage.variant_name().to_string(),
+        tasks,
+    }))
+}
+
+/// Return the current resource usage and quota of each tenant
+pub(crate) async fn get_tenants<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+) -> Result<impl warp::Reply, Rejection> {
+    let state = data_server.state;
+
+    let mut usage = state.task_manager.get_tenant_usage().await;
+    for tenant in state.config.tenant_quotas.keys() {
+        usage.entry(tenant.clone()).or_default();
+    }
+
+    let mut tenants: Vec<TenantResponse> = usage
+        .into_iter()
+        .map(|(tenant, usage)| {
+            let quota = state.config.tenant_quota(&tenant);
+            TenantResponse {
+                running_jobs: usage.running_jobs,
+                running_tasks: usage.running_tasks,
+                shuffle_bytes: usage.shuffle_bytes,
+                max_running_jobs: quota.max_running_jobs,
+                max_task_slots: quota.max_task_slots,
+                max_shuffle_bytes: quota.max_shuffle_bytes,
+                tenant,
+            }
+        })
+        .collect();
+    tenants.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+
+    Ok(warp::reply::json(&tenants))
+}
+
+/// Return the finished jobs in the job history matching the filter, newest first
+pub(crate) async fn get_job_history<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    filter: JobHistoryFilter,
+) -> Result<impl warp::Reply, Rejection> {
+    let jobs = data_server
+        .state
+        .job_history
+        .list(&filter)
+        .await
+        .map_err(|_| warp::reject())?;
+
+    let jobs: Vec<JobHistoryResponse> = jobs
+        .into_iter()
+        .map(|job| JobHistoryResponse {
+            job_id: job.job_id,
+            session_id: job.session_id,
+            sql: job.sql,
+            status: job.status,
+            failure_reason: job.failure_reason,
+            queued_at: job.queued_at,
+            start_time: job.start_time,
+            end_time: job.end_time,
+            num_stages: job.stages.len(),
+        })
+        .collect();
+
     Ok(warp::reply::json(&jobs))
 }
 
+/// Return the archived summary of a finished job, including its stage plans and task timings
+pub(crate) async fn get_job_history_entry<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+) -> Result<impl warp::Reply, Rejection> {
+    let job = data_server
+        .state
+        .job_history
+        .get(&job_id)
+        .await
+        .map_err(|_| warp::reject())?
+        .ok_or_else(warp::reject::not_found)?;
+
+    Ok(warp::reply::json(&job))
+}
+
+fn error_response(status: StatusCode, error: impl Into<String>) -> warp::reply::Response {
+    warp::reply::with_status(
+        warp::reply::json(&ErrorResponse {
+            error: error.into(),
+        }),
+        status,
+    )
+    .into_response()
+}
+
+/// An authenticated REST client and the session of its bearer token, if it sent one
+struct Client {
+    user: String,
+    session: Option<Arc<SessionContext>>,
+}
+
+/// Authenticate a REST client the same way as a Flight SQL client, either with the bearer
+/// token handed out by the Flight SQL handshake or with `Basic` credentials
+fn authenticate<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: &SchedulerServer<T, U>,
+    authorization: Option<String>,
+) -> Result<Client, warp::reply::Response> {
+    let authorization = authorization
+        .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "No authorization header!"))?;
+    if authorization.starts_with("Bearer ") {
+        let session = data_server
+            .state
+            .session_manager
+            .get_token_session(&authorization)
+            .map_err(|e| error_response(StatusCode::UNAUTHORIZED, e.to_string()))?;
+        Ok(Client {
+            user: session_tenant(&session),
+            session: Some(session),
+        })
+    } else {
+        let user = data_server
+            .state
+            .session_manager
+            .authenticate_basic(&authorization)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, e.to_string()))?
+            .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "Invalid credentials!"))?;
+        Ok(Client {
+            user,
+            session: None,
+        })
+    }
+}
+
+/// Plan a SQL query and submit it as a job, the same way as Flight SQL does
+pub(crate) async fn submit_sql<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    authorization: Option<String>,
+    request: SqlRequest,
+) -> Result<warp::reply::Response, Rejection> {
+    let client = match authenticate(&data_server, authorization) {
+        Ok(client) => client,
+        Err(response) => return Ok(response),
+    };
+    let session_manager = &data_server.state.session_manager;
+
+    let session = match &request.session_id {
+        Some(session_id) => match session_manager.get_session(session_id).await {
+            Ok(session) if session_tenant(&session) == client.user => Some(session),
+            Ok(_) => {
+                return Ok(error_response(
+                    StatusCode::FORBIDDEN,
+                    format!("Session {session_id} belongs to another user"),
+                ))
+            }
+            Err(e) => return Ok(error_response(StatusCode::NOT_FOUND, e.to_string())),
+        },
+        None => client.session,
+    };
+
+    let ctx = match session {
+        Some(session) if request.settings.is_empty() => session,
+        session => {
+            let mut settings = session
+                .and_then(|session| {
+                    session
+                        .copied_config()
+                        .get_extension::<BallistaConfig>()
+                        .map(|config| config.settings().clone())
+                })
+                .unwrap_or_default();
+            settings.extend(request.settings);
+            settings.insert(BALLISTA_TENANT.to_owned(), client.user);
+            let config = match BallistaConfig::with_settings(settings) {
+                Ok(config) => config,
+                Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e.to_string())),
+            };
+            session_manager
+                .create_session(&config)
+                .await
+                .map_err(|_| warp::reject())?
+        }
+    };
+
+    let plan = match data_server.plan_sql(&ctx, &request.sql).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            return Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Error building plan: {e}"),
+            ))
+        }
+    };
+    let job_id = data_server
+        .submit_sql_job(ctx.clone(), &plan, &request.sql)
+        .await
+        .map_err(|_| warp::reject())?;
+
+    Ok(warp::reply::json(&SqlResponse {
+        job_id,
+        session_id: ctx.session_id(),
+    })
+    .into_response())
+}
+
+/// Stream the output of a successful job as Arrow IPC, JSON lines or CSV
+pub(crate) async fn get_job_result<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+    authorization: Option<String>,
+    query: JobResultQuery,
+) -> Result<warp::reply::Response, Rejection> {
+    let client = match authenticate(&data_server, authorization) {
+        Ok(client) => client,
+        Err(response) => return Ok(response),
+    };
+
+    let job_status = data_server
+        .state
+        .task_manager
+        .get_job_status(&job_id)
+        .await
+        .map_err(|_| warp::reject())?
+        .ok_or_else(warp::reject::not_found)?;
+    let completed = match job_status.status {
+        Some(Status::Successful(completed)) => completed,
+        status => {
+            let (status, _) = status_and_reason(&JobStatus {
+                job_id: job_id.clone(),
+                status,
+            });
+            return Ok(error_response(
+                StatusCode::CONFLICT,
+                format!("Job {job_id} has no result, it is {status}"),
+            ));
+        }
+    };
+    let graph = get_execution_graph(&data_server, &job_id).await?;
+    // Only the user who submitted the job can fetch its result
+    let owner = data_server
+        .state
+        .session_manager
+        .get_session_tenant(graph.session_id())
+        .await
+        .map_err(|_| warp::reject())?;
+    if owner != client.user {
+        return Ok(error_response(
+            StatusCode::FORBIDDEN,
+            format!("Job {job_id} belongs to another user"),
+        ));
+    }
+    let schema = graph.output_schema().ok_or_else(warp::reject::not_found)?;
+
+    let format = query.format.unwrap_or_default();
+    let body = stream_job_output(schema, completed.partition_location, format)
+        .map_err(|_| warp::reject())?;
+
+    warp::http::Response::builder()
+        .header("Content-Type", format.content_type())
+        .body(warp::hyper::Body::wrap_stream(body))
+        .map_err(|_| warp::reject())
+}
+
+/// Stream the progress of a job as server-sent events until it finishes, fails or is
+/// cancelled. The stream starts with the current state of the job.
+pub(crate) async fn get_job_events<T: AsLogicalPlan, U: AsExecutionPlan>(
+    data_server: SchedulerServer<T, U>,
+    job_id: String,
+) -> Result<impl warp::Reply, Rejection> {
+    // Subscribe before reading the current state so that no event is missed in between
+    let rx = data_server.state.job_events.subscribe();
+
+    let job_status = data_server
+        .state
+        .task_manager
+        .get_job_status(&job_id)
+        .await
+        .map_err(|_| warp::reject())?
+        .ok_or_else(warp::reject::not_found)?;
+    let mut initial_events: Vec<JobEvent> =
+        JobEvent::from_job_status(&job_status).into_iter().collect();
+    if let Some(graph) = data_server
+        .state
+        .task_manager
+        .get_active_execution_graph(&job_id)
+    {
+        initial_events.push(JobEvent::TasksFinished {
+            job_id: job_id.clone(),
+            progress: graph.read().await.progress(),
+        });
+    }
+    let finished = initial_events.iter().any(|event| event.is_terminal());
+
+    let live_events = futures::stream::unfold((!finished).then_some(rx), move |rx| {
+        let job_id = job_id.clone();
+        let data_server = data_server.clone();
+        async move {
+            let mut rx = rx?;
+            loop {
+                match rx.recv().await {
+                    Ok(event) if event.job_id() == job_id => {
+                        let rx = (!event.is_terminal()).then_some(rx);
+                        return Some((event, rx));
+                    }
+                    Ok(_) => continue,
+                    // The missed events may include the one ending the job
+                    Err(RecvError::Lagged(_)) => {
+                        let job_status =
+                            data_server.state.task_manager.get_job_status(&job_id).await;
+                        if let Some(event) = job_status
+                            .ok()
+                            .flatten()
+                            .and_then(|status| JobEvent::from_job_status(&status))
+                            .filter(JobEvent::is_terminal)
+                        {
+                            return Some((event, None));
+                        }
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    let events = futures::stream::iter(initial_events)
+        .chain(live_events)
+        .map(|event| {
+            warp::sse::Event::default()
+                .event(event.name())
+                .json_data(&event)
+        });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
 pub(crate) async fn cancel_job<T: AsLogicalPlan, U: AsExecutionPlan>(
     data_server: SchedulerServer<T, U>,
     job_id: String,
@@ -276,3 +1119,265 @@ fn get_combined_count(metrics: &[MetricsSet], name: &str) -> usize {
         })
         .sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::api::get_routes;
+    use crate::config::SchedulerConfig;
+    use crate::state::job_events::JobEvent;
+    use crate::test_utils::{default_task_runner, SchedulerTest, TaskRunner, TaskRunnerFn};
+    use ballista_core::config::{BallistaConfig, BALLISTA_TENANT};
+    use ballista_core::error::Result;
+    use ballista_core::serde::protobuf::{
+        job_status, task_status, FailedTask, MultiTaskDefinition,
+    };
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::logical_expr::{col, sum, LogicalPlan};
+    use datafusion::test_util::scan_empty;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+
+    fn test_plan() -> LogicalPlan {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("gmv", DataType::UInt64, false),
+        ]);
+
+        scan_empty(None, &schema, Some(vec![0, 1]))
+            .unwrap()
+            .aggregate(vec![col("id")], vec![sum(col("gmv"))])
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    /// Run a successful job of the default tenant, a successful job of `alice` and a job
+    /// whose tasks fail
+    async fn run_jobs() -> Result<SchedulerTest> {
+        let default_runner = default_task_runner();
+        let runner = Arc::new(TaskRunnerFn::new(
+            move |executor_id: String, task: MultiTaskDefinition| {
+                let mut statuses = default_runner.run(executor_id, task);
+                for status in statuses
+                    .iter_mut()
+                    .filter(|status| status.job_id == "job-3")
+                {
+                    status.status = Some(task_status::Status::Failed(FailedTask {
+                        error: "ERROR".to_owned(),
+                    }));
+                }
+                statuses
+            },
+        ));
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 4, 1, Some(runner)).await?;
+        test.process_task_statuses();
+        let plan = test_plan();
+
+        test.submit("job-1", &plan).await?;
+        test.await_completion("job-1").await?;
+
+        let config = BallistaConfig::builder()
+            .set(BALLISTA_TENANT, "alice")
+            .build()?;
+        let ctx = test
+            .scheduler()
+            .state
+            .session_manager
+            .create_session(&config)
+            .await?;
+        test.scheduler().submit_job("job-2", ctx, &plan).await?;
+        test.await_completion("job-2").await?;
+
+        test.submit("job-3", &plan).await?;
+        test.await_completion("job-3").await?;
+
+        Ok(test)
+    }
+
+    async fn get(test: &SchedulerTest, path: &str) -> (StatusCode, Option<String>, Value) {
+        let response = warp::test::request()
+            .path(path)
+            .reply(&get_routes(test.scheduler()))
+            .await;
+        let total = response
+            .headers()
+            .get("X-Total-Count")
+            .map(|total| total.to_str().unwrap().to_owned());
+        let body = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), total, body)
+    }
+
+    fn job_ids(jobs: &Value) -> Vec<&str> {
+        jobs.as_array()
+            .unwrap()
+            .iter()
+            .map(|job| job["job_id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn filter_sort_and_paginate_jobs() -> Result<()> {
+        let test = run_jobs().await?;
+
+        let (status, total, jobs) = get(&test, "/api/jobs?sort_by=job_id&order=asc").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(total.as_deref(), Some("3"));
+        assert_eq!(job_ids(&jobs), vec!["job-1", "job-2", "job-3"]);
+
+        let (_, total, jobs) = get(&test, "/api/jobs?status=failed").await;
+        assert_eq!(total.as_deref(), Some("1"));
+        assert_eq!(job_ids(&jobs), vec!["job-3"]);
+
+        let (_, total, jobs) = get(&test, "/api/jobs?user=alice").await;
+        assert_eq!(total.as_deref(), Some("1"));
+        assert_eq!(job_ids(&jobs), vec!["job-2"]);
+        assert_eq!(jobs[0]["user"], "alice");
+
+        let (_, _, jobs) = get(&test, "/api/jobs?status=Successful&user=alice").await;
+        assert_eq!(job_ids(&jobs), vec!["job-2"]);
+
+        // the total counts the matching jobs before pagination
+        let (_, total, jobs) = get(
+            &test,
+            "/api/jobs?sort_by=job_id&order=desc&offset=1&limit=1",
+        )
+        .await;
+        assert_eq!(total.as_deref(), Some("3"));
+        assert_eq!(job_ids(&jobs), vec!["job-2"]);
+
+        let (status, total, jobs) = get(&test, "/api/jobs?offset=5&limit=10").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(total.as_deref(), Some("3"));
+        assert!(job_ids(&jobs).is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_job_and_stage_tasks() -> Result<()> {
+        let test = run_jobs().await?;
+
+        let (status, _, job) = get(&test, "/api/job/job-1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(job["status"], "Successful");
+        assert_eq!(job["num_stages"], 2);
+        assert_eq!(job["completed_stages"], 2);
+        assert_eq!(job["output_locations"].as_array().unwrap().len(), 4);
+
+        let (_, _, job) = get(&test, "/api/job/job-3").await;
+        assert_eq!(job["status"], "Failed");
+        assert!(job["failure_reason"].as_str().unwrap().contains("ERROR"));
+
+        let (status, _, stage) = get(&test, "/api/job/job-1/stage/2/tasks").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stage["stage_status"], "Successful");
+        let tasks = stage["tasks"].as_array().unwrap();
+        assert_eq!(tasks.len(), 4);
+        assert!(tasks
+            .iter()
+            .all(|task| task["executor_id"].as_str().is_some()));
+
+        for path in [
+            "/api/job/unknown",
+            "/api/job/unknown/stage/1/tasks",
+            "/api/job/job-1/stage/9/tasks",
+        ] {
+            let (status, _, _) = get(&test, path).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{path}");
+        }
+
+        Ok(())
+    }
+
+    /// Names of the consecutive distinct events
+    fn event_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        let mut names: Vec<&str> = names.into_iter().collect();
+        names.dedup();
+        names
+    }
+
+    #[tokio::test]
+    async fn stream_job_events() -> Result<()> {
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 4, 1, None).await?;
+        let mut events = test.scheduler().state.job_events.subscribe();
+        // the job is successful as soon as its finished event is seen
+        let mut finished_events = test.scheduler().state.job_events.subscribe();
+        let scheduler = test.scheduler();
+        let status_when_finished = tokio::spawn(async move {
+            while !matches!(finished_events.recv().await, Ok(JobEvent::Finished { .. })) {}
+            scheduler.state.task_manager.get_job_status("job").await
+        });
+        test.submit("job", &test_plan()).await?;
+
+        let scheduler = test.scheduler();
+        let sse = tokio::spawn(async move {
+            warp::test::request()
+                .path("/api/job/job/events")
+                .reply(&get_routes(scheduler))
+                .await
+        });
+        test.process_task_statuses();
+        test.await_completion("job").await?;
+
+        // the events of the job are published in the order of its progress
+        let mut published = vec![];
+        while let Ok(event) = events.try_recv() {
+            published.push(event);
+        }
+        assert_eq!(
+            event_names(published.iter().map(|event| event.name())),
+            vec![
+                "queued",
+                "submitted",
+                "tasks_finished",
+                "stage_resolved",
+                "tasks_finished",
+                "finished"
+            ]
+        );
+
+        // the stream starts with the current state of the job and ends when it finishes
+        let response = sse.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = std::str::from_utf8(response.body()).unwrap();
+        let streamed = event_names(body.lines().filter_map(|line| line.strip_prefix("event:")));
+        assert!(
+            matches!(streamed.first(), Some(&"queued") | Some(&"submitted")),
+            "{streamed:?}"
+        );
+        assert_eq!(streamed.last(), Some(&"finished"));
+        assert_eq!(
+            streamed.iter().filter(|name| **name == "finished").count(),
+            1
+        );
+
+        let status = status_when_finished.await.unwrap()?.unwrap();
+        assert!(
+            matches!(status.status, Some(job_status::Status::Successful(_))),
+            "{status:?}"
+        );
+
+        let (status, _, _) = get(&test, "/api/job/unknown/events").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_executor_detail() -> Result<()> {
+        let test = SchedulerTest::new(SchedulerConfig::default(), 2, 3, None).await?;
+
+        let (status, _, executor) = get(&test, "/api/executor/virtual-executor-0").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(executor["id"], "virtual-executor-0");
+        assert_eq!(executor["total_task_slots"], 3);
+        assert_eq!(executor["status"], "Active");
+        assert!(executor["running_tasks"].as_array().unwrap().is_empty());
+
+        let (status, _, _) = get(&test, "/api/executor/unknown").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+}
@@ -11,8 +11,10 @@
 // limitations under the License.
 
 mod handlers;
+pub(crate) mod result;
 
 use crate::scheduler_server::SchedulerServer;
+use crate::state::job_history::JobHistoryFilter;
 use anyhow::Result;
 use datafusion_proto::logical_plan::AsLogicalPlan;
 use datafusion_proto::physical_plan::AsExecutionPlan;
@@ -90,9 +92,32 @@ pub fn get_routes<T: AsLogicalPlan + Clone, U: 'static + AsExecutionPlan>(
         .and(with_data_server(scheduler_server.clone()))
         .and_then(handlers::get_executors);
 
+    let route_executor = warp::path!("api" / "executor" / String)
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|executor_id, data_server| handlers::get_executor(data_server, executor_id));
+
     let route_jobs = warp::path!("api" / "jobs")
+        .and(warp::query::<handlers::JobsQuery>())
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|query, data_server| handlers::get_jobs(data_server, query));
+
+    let route_job = warp::path!("api" / "job" / String)
+        .and(warp::get())
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, data_server| handlers::get_job(data_server, job_id));
+
+    let route_tenants = warp::path!("api" / "tenants")
         .and(with_data_server(scheduler_server.clone()))
-        .and_then(|data_server| handlers::get_jobs(data_server));
+        .and_then(handlers::get_tenants);
+
+    let route_job_history = warp::path!("api" / "history")
+        .and(warp::query::<JobHistoryFilter>())
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|filter, data_server| handlers::get_job_history(data_server, filter));
+
+    let route_job_history_entry = warp::path!("api" / "history" / String)
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, data_server| handlers::get_job_history_entry(data_server, job_id));
 
     let route_cancel_job = warp::path!("api" / "job" / String)
         .and(warp::patch())
@@ -103,10 +128,58 @@ pub fn get_routes<T: AsLogicalPlan + Clone, U: 'static + AsExecutionPlan>(
         .and(with_data_server(scheduler_server.clone()))
         .and_then(|job_id, data_server| handlers::get_query_stages(data_server, job_id));
 
+    let route_job_dot = warp::path!("api" / "job" / String / "dot")
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, data_server| handlers::get_job_dot_graph(data_server, job_id));
+
+    let route_job_dag = warp::path!("api" / "job" / String / "dag")
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, data_server| handlers::get_job_stage_graph(data_server, job_id));
+
+    let route_submit_sql = warp::path!("api" / "sql")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|authorization, request, data_server| {
+            handlers::submit_sql(data_server, authorization, request)
+        });
+
+    let route_job_result = warp::path!("api" / "job" / String / "result")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<handlers::JobResultQuery>())
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, authorization, query, data_server| {
+            handlers::get_job_result(data_server, job_id, authorization, query)
+        });
+
+    let route_job_events = warp::path!("api" / "job" / String / "events")
+        .and(warp::get())
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, data_server| handlers::get_job_events(data_server, job_id));
+
+    let route_stage_tasks = warp::path!("api" / "job" / String / "stage" / usize / "tasks")
+        .and(with_data_server(scheduler_server.clone()))
+        .and_then(|job_id, stage_id, data_server| {
+            handlers::get_stage_tasks(data_server, job_id, stage_id)
+        });
+
     let routes = route_scheduler_state
         .or(route_executors)
+        .or(route_executor)
         .or(route_jobs)
+        .or(route_job)
+        .or(route_tenants)
+        .or(route_job_history)
+        .or(route_job_history_entry)
         .or(route_cancel_job)
-        .or(route_query_stages);
+        .or(route_query_stages)
+        .or(route_stage_tasks)
+        .or(route_job_dot)
+        .or(route_job_dag)
+        .or(route_submit_sql)
+        .or(route_job_result)
+        .or(route_job_events);
     routes.boxed()
 }
@@ -0,0 +1,235 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streaming of the output of a finished job over HTTP
+
+use ballista_core::client::BallistaClient;
+use ballista_core::error::{BallistaError, Result};
+use ballista_core::serde::protobuf;
+use ballista_core::serde::scheduler::PartitionId;
+use datafusion::arrow::csv;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::arrow::json::LineDelimitedWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::StreamExt;
+use log::warn;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Format in which the output of a job is returned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultFormat {
+    /// Arrow IPC stream
+    #[default]
+    Arrow,
+    /// One JSON object per row
+    Json,
+    Csv,
+}
+
+impl ResultFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ResultFormat::Arrow => "application/vnd.apache.arrow.stream",
+            ResultFormat::Json => "application/x-ndjson",
+            ResultFormat::Csv => "text/csv",
+        }
+    }
+}
+
+/// Encodes record batches into chunks of the response body
+enum ResultEncoder {
+    Arrow(StreamWriter<Vec<u8>>),
+    Json,
+    Csv { header: bool },
+}
+
+impl ResultEncoder {
+    fn try_new(format: ResultFormat, schema: &SchemaRef) -> Result<Self> {
+        Ok(match format {
+            ResultFormat::Arrow => ResultEncoder::Arrow(StreamWriter::try_new(vec![], schema)?),
+            ResultFormat::Json => ResultEncoder::Json,
+            ResultFormat::Csv => ResultEncoder::Csv { header: true },
+        })
+    }
+
+    /// Bytes written since the last call, starting with the schema for Arrow IPC
+    fn take(&mut self) -> Vec<u8> {
+        match self {
+            ResultEncoder::Arrow(writer) => std::mem::take(writer.get_mut()),
+            _ => vec![],
+        }
+    }
+
+    fn encode(&mut self, batch: &RecordBatch) -> Result<Vec<u8>> {
+        match self {
+            ResultEncoder::Arrow(writer) => {
+                writer.write(batch)?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+            ResultEncoder::Json => {
+                let mut writer = LineDelimitedWriter::new(vec![]);
+                writer.write(batch)?;
+                writer.finish()?;
+                Ok(writer.into_inner())
+            }
+            ResultEncoder::Csv { header } => {
+                let mut buf = vec![];
+                {
+                    let mut writer = csv::WriterBuilder::new()
+                        .with_header(*header)
+                        .build(&mut buf);
+                    writer.write(batch)?;
+                }
+                *header = false;
+                Ok(buf)
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>> {
+        match self {
+            ResultEncoder::Arrow(writer) => {
+                writer.finish()?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+/// Stream the output partitions of a successful job, fetched from the executors which
+/// hold them, encoded in the requested format
+pub(crate) fn stream_job_output(
+    schema: SchemaRef,
+    locations: Vec<protobuf::PartitionLocation>,
+    format: ResultFormat,
+) -> Result<ReceiverStream<Result<Vec<u8>>>> {
+    let mut encoder = ResultEncoder::try_new(format, &schema)?;
+    let (tx, rx) = channel(2);
+
+    tokio::spawn(async move {
+        let schema_bytes = encoder.take();
+        if !schema_bytes.is_empty() && tx.send(Ok(schema_bytes)).await.is_err() {
+            return;
+        }
+        for location in locations {
+            if let Err(e) = send_partition(&tx, &mut encoder, location).await {
+                warn!("Error streaming job output: {e:?}");
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        }
+        let _ = tx.send(encoder.finish()).await;
+    });
+
+    Ok(ReceiverStream::new(rx))
+}
+
+/// Fetch an output partition of a successful job from the executor which holds it
+pub(crate) async fn fetch_output_partition(
+    location: protobuf::PartitionLocation,
+) -> Result<SendableRecordBatchStream> {
+    let executor = location.executor_meta.ok_or_else(|| {
+        BallistaError::Internal("Output partition location has no executor".to_owned())
+    })?;
+    let partition_id = location.partition_id.ok_or_else(|| {
+        BallistaError::Internal("Output partition location has no partition id".to_owned())
+    })?;
+    let partition_id = PartitionId::new(
+        &partition_id.job_id,
+        partition_id.stage_id as usize,
+        partition_id.partition_id as usize,
+    );
+    let port = executor.port as u16;
+
+    let mut client = BallistaClient::try_new(&executor.host, port).await?;
+    client
+        .fetch_partition(
+            &executor.id,
+            &partition_id,
+            &location.path,
+            &executor.host,
+            port,
+        )
+        .await
+}
+
+async fn send_partition(
+    tx: &Sender<Result<Vec<u8>>>,
+    encoder: &mut ResultEncoder,
+    location: protobuf::PartitionLocation,
+) -> Result<()> {
+    let mut stream = fetch_output_partition(location).await?;
+    while let Some(batch) = stream.next().await {
+        let bytes = encoder.encode(&batch?)?;
+        if tx.send(Ok(bytes)).await.is_err() {
+            // The client went away
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Int32Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::ipc::reader::StreamReader;
+    use std::sync::Arc;
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["a", "b"])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn encode(format: ResultFormat) -> Result<Vec<u8>> {
+        let batch = batch();
+        let mut encoder = ResultEncoder::try_new(format, &batch.schema())?;
+        let mut bytes = encoder.take();
+        bytes.extend(encoder.encode(&batch)?);
+        bytes.extend(encoder.encode(&batch)?);
+        bytes.extend(encoder.finish()?);
+        Ok(bytes)
+    }
+
+    #[test]
+    fn encode_results() -> Result<()> {
+        let csv = String::from_utf8(encode(ResultFormat::Csv)?).unwrap();
+        assert_eq!(csv, "id,name\n1,a\n2,b\n1,a\n2,b\n");
+
+        let json = String::from_utf8(encode(ResultFormat::Json)?).unwrap();
+        assert_eq!(json.lines().count(), 4);
+        assert_eq!(json.lines().next(), Some(r#"{"id":1,"name":"a"}"#));
+
+        let arrow = encode(ResultFormat::Arrow)?;
+        let reader = StreamReader::try_new(arrow.as_slice(), None)?;
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 4);
+
+        Ok(())
+    }
+}
@@ -17,15 +17,23 @@
 
 //! Ballista Rust scheduler binary.
 
+use std::fmt::Display;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::{env, io};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
+use ballista_core::telemetry::{shutdown_tracing, trace_layer, TraceExporter};
 use ballista_scheduler::cluster::BallistaCluster;
-use ballista_scheduler::config::{ClusterStorageConfig, SchedulerConfig, TaskDistributionPolicy};
+use ballista_scheduler::config::{
+    ClusterStorageConfig, JobHistoryStorageConfig, SchedulerConfig, TaskDistributionPolicy,
+    TenantQuota,
+};
 use ballista_scheduler::scheduler_process::start_server;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -37,12 +45,18 @@ async fn main() -> Result<()> {
     let rust_log = env::var(EnvFilter::DEFAULT_ENV);
     let log_filter = EnvFilter::new(rust_log.unwrap_or("INFO,datafusion=INFO".to_string()));
     // Console layer
-    tracing_subscriber::fmt()
+    let console_layer = tracing_subscriber::fmt::layer()
         .with_ansi(false)
         .with_thread_names(true)
         .with_thread_ids(true)
         .with_writer(io::stdout)
-        .with_env_filter(log_filter)
+        .with_filter(log_filter);
+    tracing_subscriber::registry()
+        .with(console_layer)
+        .with(trace_layer(
+            "ballista-scheduler",
+            &TraceExporter::from_env(),
+        )?)
         .init();
 
     let addr = format!("{}:{}", "0.0.0.0", bind_port);
@@ -61,6 +75,23 @@ async fn main() -> Result<()> {
         _ => unimplemented!(),
     };
 
+    let job_history_storage = match (env::var("JOB_HISTORY_STORAGE"), env::var("JOB_HISTORY_DIR")) {
+        (Ok(storage), Ok(dir)) if storage == "dir" => JobHistoryStorageConfig::Directory(dir),
+        (Ok(storage), Ok(dir)) if storage == "sled" => JobHistoryStorageConfig::Sled(dir),
+        (Ok(storage), Err(_)) if storage == "dir" || storage == "sled" => {
+            return Err(anyhow!(
+                "JOB_HISTORY_DIR must be set for JOB_HISTORY_STORAGE {storage:?}"
+            ))
+        }
+        (Ok(storage), _) if storage == "memory" => JobHistoryStorageConfig::Memory,
+        (Ok(storage), _) => {
+            return Err(anyhow!(
+                "Invalid JOB_HISTORY_STORAGE {storage:?}, expected one of memory, dir or sled"
+            ))
+        }
+        (Err(_), _) => JobHistoryStorageConfig::Memory,
+    };
+
     let config = SchedulerConfig {
         namespace: "ballista".to_string(),
         external_host: env::var("EXTERNAL_HOST").unwrap_or("localhost".to_string()),
@@ -75,10 +106,50 @@ async fn main() -> Result<()> {
         grpc_server_max_encoding_message_size: 16777216, // 16MB
         executor_timeout_seconds: 180,
         expire_dead_executor_interval_seconds: 15,
+        default_tenant_quota: TenantQuota {
+            max_task_slots: env_var("TENANT_MAX_TASK_SLOTS")?,
+            max_running_jobs: env_var("TENANT_MAX_RUNNING_JOBS")?,
+            max_shuffle_bytes: env_var("TENANT_MAX_SHUFFLE_BYTES")?,
+        },
+        // e.g. `analytics:max_task_slots=8,max_running_jobs=2;etl:max_shuffle_bytes=1073741824`
+        tenant_quotas: match env::var("TENANT_QUOTAS") {
+            Ok(quotas) => TenantQuota::parse_tenant_quotas(&quotas)
+                .map_err(|e| anyhow!("Invalid TENANT_QUOTAS: {e}"))?,
+            Err(_) => Default::default(),
+        },
+        job_history_storage,
+        job_history_max_age_seconds: env_var("JOB_HISTORY_MAX_AGE_SECONDS")?
+            .unwrap_or(7 * 24 * 3600),
+        job_history_max_jobs: env_var("JOB_HISTORY_MAX_JOBS")?.unwrap_or(1000),
+        event_log_path: env::var("EVENT_LOG_PATH").ok(),
+        // e.g. `alice:secret1,bob:secret2`
+        users: match env::var("SCHEDULER_USERS") {
+            Ok(users) => users
+                .parse()
+                .map_err(|e| anyhow!("Invalid SCHEDULER_USERS: {e}"))?,
+            Err(_) => Default::default(),
+        },
+        session_token_ttl_seconds: env_var("SESSION_TOKEN_TTL_SECONDS")?.unwrap_or(3600),
     };
 
     let cluster = BallistaCluster::new_from_config(&config).await?;
 
     start_server(cluster, addr, Arc::new(config)).await?;
+    shutdown_tracing();
     Ok(())
 }
+
+/// Parse an optional setting from the environment, failing if it is set to an invalid value
+fn env_var<T>(key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid value {value:?} of {key}: {e}")),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,397 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Archive of finished jobs, kept after the job state itself has been cleaned up
+//! from the cluster state.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use ballista_core::error::{BallistaError, Result};
+use log::warn;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::cluster::storage::sled::sled_to_ballista_error;
+
+/// Summary of a finished job as kept in the job history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobSummary {
+    pub job_id: String,
+    pub session_id: String,
+    /// SQL text of the query, if the job was submitted as SQL
+    pub sql: Option<String>,
+    /// Ballista settings of the session the job was submitted with
+    pub settings: HashMap<String, String>,
+    /// Final status of the job, e.g. `Successful` or `Failed`
+    pub status: String,
+    pub failure_reason: Option<String>,
+    pub queued_at: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub stages: Vec<StageSummary>,
+}
+
+impl JobSummary {
+    pub fn key(&self) -> JobHistoryKey {
+        JobHistoryKey {
+            end_time: self.end_time,
+            job_id: self.job_id.clone(),
+        }
+    }
+}
+
+/// Summary of one stage of a finished job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageSummary {
+    pub stage_id: usize,
+    /// State the stage was in when the job finished
+    pub state: String,
+    pub partitions: usize,
+    /// The stage plan annotated with the operator metrics collected from its tasks
+    pub plan: String,
+    pub tasks: Vec<TaskSummary>,
+}
+
+/// Timings of one task of a stage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskSummary {
+    pub task_id: usize,
+    pub partition_id: usize,
+    pub executor_id: Option<String>,
+    pub status: String,
+    pub scheduled_time: u64,
+    pub launch_time: u64,
+    pub start_exec_time: u64,
+    pub end_exec_time: u64,
+    pub finish_time: u64,
+}
+
+/// Key of an archived job. Keys are ordered by the end time of the job, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobHistoryKey {
+    pub end_time: u64,
+    pub job_id: String,
+}
+
+impl JobHistoryKey {
+    /// Encode the key so that the lexicographic order of encoded keys is the order by end time
+    fn encode(&self) -> String {
+        format!("{:020}-{}", self.end_time, self.job_id)
+    }
+
+    fn decode(key: &str) -> Option<Self> {
+        let (end_time, job_id) = key.split_once('-')?;
+        Some(Self {
+            end_time: end_time.parse().ok()?,
+            job_id: job_id.to_owned(),
+        })
+    }
+}
+
+/// Append-only storage of the summaries of finished jobs
+#[tonic::async_trait]
+pub trait JobHistoryStore: Send + Sync + 'static {
+    /// Archive the summary of a finished job
+    async fn archive(&self, summary: &JobSummary) -> Result<()>;
+
+    /// Keys of all archived jobs, oldest first
+    async fn keys(&self) -> Result<Vec<JobHistoryKey>>;
+
+    /// Summaries of all archived jobs, oldest first
+    async fn list(&self) -> Result<Vec<JobSummary>>;
+
+    /// Summary of an archived job
+    async fn get(&self, key: &JobHistoryKey) -> Result<Option<JobSummary>>;
+
+    /// Remove archived jobs, e.g. when they have expired
+    async fn remove(&self, keys: &[JobHistoryKey]) -> Result<()>;
+}
+
+/// A [`JobHistoryStore`] which only keeps the job history in memory
+#[derive(Default)]
+pub struct InMemoryJobHistoryStore {
+    jobs: Mutex<BTreeMap<JobHistoryKey, JobSummary>>,
+}
+
+#[tonic::async_trait]
+impl JobHistoryStore for InMemoryJobHistoryStore {
+    async fn archive(&self, summary: &JobSummary) -> Result<()> {
+        self.jobs.lock().insert(summary.key(), summary.clone());
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<JobHistoryKey>> {
+        Ok(self.jobs.lock().keys().cloned().collect())
+    }
+
+    async fn list(&self) -> Result<Vec<JobSummary>> {
+        Ok(self.jobs.lock().values().cloned().collect())
+    }
+
+    async fn get(&self, key: &JobHistoryKey) -> Result<Option<JobSummary>> {
+        Ok(self.jobs.lock().get(key).cloned())
+    }
+
+    async fn remove(&self, keys: &[JobHistoryKey]) -> Result<()> {
+        let mut jobs = self.jobs.lock();
+        for key in keys {
+            jobs.remove(key);
+        }
+        Ok(())
+    }
+}
+
+/// A [`JobHistoryStore`] which writes every job summary as a JSON file into a local directory
+pub struct DirectoryJobHistoryStore {
+    dir: PathBuf,
+}
+
+impl DirectoryJobHistoryStore {
+    pub fn try_new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, key: &JobHistoryKey) -> PathBuf {
+        self.dir.join(format!("{}.json", key.encode()))
+    }
+}
+
+#[tonic::async_trait]
+impl JobHistoryStore for DirectoryJobHistoryStore {
+    async fn archive(&self, summary: &JobSummary) -> Result<()> {
+        let value = serde_json::to_vec(summary).map_err(|e| {
+            BallistaError::Internal(format!("Error serializing job summary: {e:?}"))
+        })?;
+        // Write to a temporary file first so that readers never see a partial summary
+        let path = self.path(&summary.key());
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, value).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<JobHistoryKey>> {
+        let mut keys = vec![];
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            if let Some(key) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(JobHistoryKey::decode)
+            {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn list(&self) -> Result<Vec<JobSummary>> {
+        let mut jobs = vec![];
+        for key in self.keys().await? {
+            jobs.extend(self.get(&key).await?);
+        }
+        Ok(jobs)
+    }
+
+    async fn get(&self, key: &JobHistoryKey) -> Result<Option<JobSummary>> {
+        let value = match tokio::fs::read(self.path(key)).await {
+            Ok(value) => value,
+            // The job may have been removed by retention in the meantime
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_slice(&value) {
+            Ok(summary) => Ok(Some(summary)),
+            Err(e) => {
+                warn!("Skipping unreadable job history entry {key:?}: {e:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn remove(&self, keys: &[JobHistoryKey]) -> Result<()> {
+        for key in keys {
+            match tokio::fs::remove_file(self.path(key)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [`JobHistoryStore`] which keeps the job summaries in a sled tree
+pub struct SledJobHistoryStore {
+    tree: sled::Tree,
+}
+
+impl SledJobHistoryStore {
+    const TREE_NAME: &'static str = "job_history";
+
+    pub fn try_new(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let db = sled::open(path).map_err(sled_to_ballista_error)?;
+        Self::try_new_from_db(&db)
+    }
+
+    pub fn try_new_from_db(db: &sled::Db) -> Result<Self> {
+        Ok(Self {
+            tree: db
+                .open_tree(Self::TREE_NAME)
+                .map_err(sled_to_ballista_error)?,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl JobHistoryStore for SledJobHistoryStore {
+    async fn archive(&self, summary: &JobSummary) -> Result<()> {
+        let value = serde_json::to_vec(summary).map_err(|e| {
+            BallistaError::Internal(format!("Error serializing job summary: {e:?}"))
+        })?;
+        self.tree
+            .insert(summary.key().encode(), value)
+            .map_err(sled_to_ballista_error)?;
+        self.tree
+            .flush_async()
+            .await
+            .map_err(sled_to_ballista_error)?;
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<JobHistoryKey>> {
+        let mut keys = vec![];
+        for key in self.tree.iter().keys() {
+            let key = key.map_err(sled_to_ballista_error)?;
+            if let Some(key) = std::str::from_utf8(&key)
+                .ok()
+                .and_then(JobHistoryKey::decode)
+            {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    async fn list(&self) -> Result<Vec<JobSummary>> {
+        let mut jobs = vec![];
+        for entry in self.tree.iter() {
+            let (key, value) = entry.map_err(sled_to_ballista_error)?;
+            match serde_json::from_slice(&value) {
+                Ok(summary) => jobs.push(summary),
+                Err(e) => warn!(
+                    "Skipping unreadable job history entry {}: {e:?}",
+                    String::from_utf8_lossy(&key)
+                ),
+            }
+        }
+        Ok(jobs)
+    }
+
+    async fn get(&self, key: &JobHistoryKey) -> Result<Option<JobSummary>> {
+        let Some(value) = self
+            .tree
+            .get(key.encode())
+            .map_err(sled_to_ballista_error)?
+        else {
+            return Ok(None);
+        };
+        match serde_json::from_slice(&value) {
+            Ok(summary) => Ok(Some(summary)),
+            Err(e) => {
+                warn!("Skipping unreadable job history entry {key:?}: {e:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn remove(&self, keys: &[JobHistoryKey]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for key in keys {
+            batch.remove(key.encode().as_str());
+        }
+        self.tree
+            .apply_batch(batch)
+            .map_err(sled_to_ballista_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary(job_id: &str, end_time: u64) -> JobSummary {
+        JobSummary {
+            job_id: job_id.to_owned(),
+            session_id: "session".to_owned(),
+            sql: Some("SELECT 1".to_owned()),
+            settings: HashMap::new(),
+            status: "Successful".to_owned(),
+            failure_reason: None,
+            queued_at: end_time - 2,
+            start_time: end_time - 1,
+            end_time,
+            stages: vec![],
+        }
+    }
+
+    async fn check_store(store: &dyn JobHistoryStore) -> Result<()> {
+        store.archive(&summary("job_b", 20)).await?;
+        store.archive(&summary("job_a", 10)).await?;
+        store.archive(&summary("job_c", 30)).await?;
+
+        let keys = store.keys().await?;
+        assert_eq!(
+            keys.iter().map(|k| k.job_id.as_str()).collect::<Vec<_>>(),
+            vec!["job_a", "job_b", "job_c"]
+        );
+        assert_eq!(store.list().await?[1], summary("job_b", 20));
+        assert_eq!(store.get(&keys[1]).await?, Some(summary("job_b", 20)));
+
+        store.remove(&keys[..2]).await?;
+        let jobs = store.list().await?;
+        assert_eq!(jobs, vec![summary("job_c", 30)]);
+        assert_eq!(store.get(&keys[0]).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn in_memory_store() -> Result<()> {
+        check_store(&InMemoryJobHistoryStore::default()).await
+    }
+
+    #[tokio::test]
+    async fn directory_store() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        check_store(&DirectoryJobHistoryStore::try_new(dir.path())?).await
+    }
+
+    #[tokio::test]
+    async fn sled_store() -> Result<()> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(sled_to_ballista_error)?;
+        check_store(&SledJobHistoryStore::try_new_from_db(&db)?).await
+    }
+}
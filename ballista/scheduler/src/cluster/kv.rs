@@ -20,7 +20,8 @@ use crate::cluster::{
     bind_task_bias, bind_task_round_robin, BoundTask, ClusterState, ExecutorHeartbeatStream,
     ExecutorSlot, JobState, JobStatus, TaskDistributionPolicy,
 };
-use crate::scheduler_server::{timestamp_secs, SessionBuilder};
+use crate::scheduler_server::{timestamp_millis, timestamp_secs, SessionBuilder};
+use crate::state::catalog::CatalogTable;
 use crate::state::execution_graph::ExecutionGraph;
 use crate::state::session_manager::create_datafusion_context;
 use crate::state::task_manager::JobInfoCache;
@@ -42,10 +43,11 @@ use datafusion_proto::physical_plan::AsExecutionPlan;
 use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
 use futures::StreamExt;
 use itertools::Itertools;
-use log::info;
+use log::{info, warn};
 use prost::Message;
 use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 /// State implementation based on underlying `KeyValueStore`
@@ -69,6 +71,52 @@ pub struct KeyValueState<
     queued_jobs: DashMap<String, u64>,
     //// `SessionBuilder` for constructing `SessionContext` from stored `BallistaConfig`
     session_builder: SessionBuilder,
+    /// Shared catalog tables, loaded on first use and kept up to date by watching the
+    /// catalog keyspace
+    catalog: tokio::sync::OnceCell<Arc<CatalogCache>>,
+}
+
+/// In-memory copy of the shared catalog. The version is bumped on every change so that
+/// sessions only need to sync their tables when it moved.
+#[derive(Default)]
+struct CatalogCache {
+    tables: DashMap<String, CatalogTable>,
+    version: AtomicU64,
+}
+
+impl CatalogCache {
+    fn put(&self, table: CatalogTable) {
+        let name = table.name();
+        let newer = self
+            .tables
+            .get(&name)
+            .is_none_or(|current| current.version < table.version);
+        if newer {
+            self.tables.insert(name, table);
+            self.version.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn remove(&self, name: &str) {
+        if self.tables.remove(name).is_some() {
+            self.version.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn apply(&self, event: WatchEvent) {
+        let catalog_key = format!("/{:?}/", Keyspace::Catalog);
+        match event {
+            WatchEvent::Put(_, value) => match CatalogTable::decode(&value) {
+                Ok(table) => self.put(table),
+                Err(e) => warn!("Could not decode catalog table: {e}"),
+            },
+            WatchEvent::Delete(key) => {
+                if let Some((_, name)) = key.split_once(&catalog_key) {
+                    self.remove(name);
+                }
+            }
+        }
+    }
 }
 
 impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
@@ -88,9 +136,39 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
             codec,
             queued_jobs: DashMap::new(),
             session_builder,
+            catalog: tokio::sync::OnceCell::new(),
         }
     }
 
+    /// Get the cached shared catalog, loading it and starting to watch it for changes
+    /// made by other schedulers on first use
+    async fn catalog(&self) -> Result<&Arc<CatalogCache>> {
+        self.catalog
+            .get_or_try_init(|| async {
+                // Watch before scanning so that no change is missed in between
+                let mut events = self
+                    .store
+                    .watch(Keyspace::Catalog, String::default())
+                    .await?;
+                let catalog = Arc::new(CatalogCache::default());
+                for (_, value) in self.store.scan(Keyspace::Catalog, None).await? {
+                    catalog.put(CatalogTable::decode(&value)?);
+                }
+
+                let watched = Arc::downgrade(&catalog);
+                tokio::task::spawn(async move {
+                    while let Some(event) = events.next().await {
+                        match watched.upgrade() {
+                            Some(catalog) => catalog.apply(event),
+                            None => break,
+                        }
+                    }
+                });
+                Ok(catalog)
+            })
+            .await
+    }
+
     /// Initialize the set of active executor heartbeats from storage
     async fn init_active_executor_heartbeats(&self) -> Result<()> {
         let heartbeats = self.store.scan(Keyspace::Heartbeats, None).await?;
@@ -177,6 +255,7 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
         distribution: TaskDistributionPolicy,
         active_jobs: Arc<HashMap<String, JobInfoCache>>,
         executors: Option<HashSet<String>>,
+        tenant_slots: HashMap<String, u32>,
     ) -> Result<Vec<BoundTask>> {
         let lock = self.store.lock(Keyspace::Slots, "global").await?;
 
@@ -203,9 +282,11 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
                 .collect();
 
             let bound_tasks = match distribution {
-                TaskDistributionPolicy::Bias => bind_task_bias(available_slots, active_jobs).await,
+                TaskDistributionPolicy::Bias => {
+                    bind_task_bias(available_slots, active_jobs, tenant_slots).await
+                }
                 TaskDistributionPolicy::RoundRobin => {
-                    bind_task_round_robin(available_slots, active_jobs).await
+                    bind_task_round_robin(available_slots, active_jobs, tenant_slots).await
                 }
             };
 
@@ -264,6 +345,7 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
         self.save_executor_heartbeat(ExecutorHeartbeat {
             executor_id: executor_id.clone(),
             timestamp: timestamp_secs(),
+            metrics: vec![],
             status: Some(protobuf::ExecutorStatus {
                 status: Some(protobuf::executor_status::Status::Active(String::default())),
             }),
@@ -343,6 +425,17 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
         Ok(metadata)
     }
 
+    async fn get_available_slots(&self, executor_id: &str) -> Result<Option<u32>> {
+        let resources = self.store.get(Keyspace::Slots, "all").await?;
+        let slots: ExecutorTaskSlots = decode_protobuf(resources.as_slice())?;
+
+        Ok(slots
+            .task_slots
+            .into_iter()
+            .find(|slots| slots.executor_id == executor_id)
+            .map(|slots| slots.slots))
+    }
+
     async fn save_executor_heartbeat(&self, heartbeat: ExecutorHeartbeat) -> Result<()> {
         let executor_id = heartbeat.executor_id.clone();
         self.store
@@ -360,6 +453,7 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
         let value = ExecutorHeartbeat {
             executor_id: executor_id.to_owned(),
             timestamp: timestamp_secs(),
+            metrics: vec![],
             status: Some(protobuf::ExecutorStatus {
                 status: Some(protobuf::executor_status::Status::Dead("".to_string())),
             }),
@@ -527,6 +621,12 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
     }
 
     async fn get_session(&self, session_id: &str) -> Result<Arc<SessionContext>> {
+        let config = self.get_session_config(session_id).await?;
+
+        Ok(create_datafusion_context(&config, self.session_builder))
+    }
+
+    async fn get_session_config(&self, session_id: &str) -> Result<BallistaConfig> {
         let value = self.store.get(Keyspace::Sessions, session_id).await?;
 
         let settings: protobuf::SessionSettings = decode_protobuf(&value)?;
@@ -535,9 +635,7 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
         for kv_pair in &settings.configs {
             config_builder = config_builder.set(&kv_pair.key, &kv_pair.value);
         }
-        let config = config_builder.build()?;
-
-        Ok(create_datafusion_context(&config, self.session_builder))
+        config_builder.build()
     }
 
     async fn create_session(&self, config: &BallistaConfig) -> Result<Arc<SessionContext>> {
@@ -564,6 +662,56 @@ impl<S: KeyValueStore, T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
 
         Ok(session)
     }
+
+    async fn save_catalog_table(&self, mut table: CatalogTable) -> Result<CatalogTable> {
+        let name = table.name();
+        let lock = self.store.lock(Keyspace::Catalog, &name).await?;
+
+        let table = with_lock(lock, async {
+            let value = self.store.get(Keyspace::Catalog, &name).await?;
+            let previous = if value.is_empty() {
+                0
+            } else {
+                CatalogTable::decode(&value)?.version
+            };
+            // Based on the time so that versions are not reused after a table is dropped
+            table.version = timestamp_millis().max(previous + 1);
+
+            self.store
+                .put(Keyspace::Catalog, name.clone(), table.encode()?)
+                .await?;
+            Ok::<_, BallistaError>(table)
+        })
+        .await?;
+        self.catalog().await?.put(table.clone());
+        Ok(table)
+    }
+
+    async fn remove_catalog_table(&self, name: &str) -> Result<()> {
+        self.store
+            .apply_txn(vec![(
+                Operation::Delete,
+                Keyspace::Catalog,
+                name.to_owned(),
+            )])
+            .await?;
+        self.catalog().await?.remove(name);
+        Ok(())
+    }
+
+    async fn get_catalog_tables(&self) -> Result<Vec<CatalogTable>> {
+        Ok(self
+            .catalog()
+            .await?
+            .tables
+            .iter()
+            .map(|table| table.value().clone())
+            .collect())
+    }
+
+    async fn get_catalog_version(&self) -> Result<u64> {
+        Ok(self.catalog().await?.version.load(Ordering::SeqCst))
+    }
 }
 
 async fn with_lock<Out, F: Future<Output = Out>>(mut lock: Box<dyn Lock>, op: F) -> Out {
@@ -578,8 +726,12 @@ mod test {
     use crate::cluster::kv::KeyValueState;
     use crate::cluster::storage::sled::SledClient;
     use crate::cluster::test_util::test_job_planning_failure;
-    use crate::test_utils::{test_aggregation_plan, test_join_plan, test_two_aggregations_plan};
+    use crate::cluster::ClusterState;
+    use crate::test_utils::{
+        mock_executor, test_aggregation_plan, test_join_plan, test_two_aggregations_plan,
+    };
     use ballista_core::error::Result;
+    use ballista_core::serde::scheduler::ExecutorData;
     use ballista_core::serde::BallistaCodec;
     use ballista_core::utils::default_session_builder;
 
@@ -592,6 +744,26 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_available_slots() -> Result<()> {
+        let state = make_sled_state()?;
+        state
+            .register_executor(
+                mock_executor("executor-1".to_owned()),
+                ExecutorData {
+                    executor_id: "executor-1".to_owned(),
+                    total_task_slots: 4,
+                    available_task_slots: 3,
+                },
+            )
+            .await?;
+
+        assert_eq!(state.get_available_slots("executor-1").await?, Some(3));
+        assert_eq!(state.get_available_slots("executor-2").await?, None);
+
+        Ok(())
+    }
+
     fn make_sled_state() -> Result<KeyValueState<SledClient>> {
         Ok(KeyValueState::new(
             "",
@@ -34,15 +34,22 @@ use ballista_core::serde::scheduler::{ExecutorData, ExecutorMetadata, PartitionI
 use ballista_core::serde::BallistaCodec;
 use ballista_core::utils::default_session_builder;
 
+use crate::cluster::history::{
+    DirectoryJobHistoryStore, InMemoryJobHistoryStore, JobHistoryStore, SledJobHistoryStore,
+};
 use crate::cluster::kv::KeyValueState;
 use crate::cluster::storage::etcd::EtcdClient;
 use crate::cluster::storage::sled::SledClient;
 use crate::cluster::storage::KeyValueStore;
-use crate::config::{ClusterStorageConfig, SchedulerConfig, TaskDistributionPolicy};
+use crate::config::{
+    ClusterStorageConfig, JobHistoryStorageConfig, SchedulerConfig, TaskDistributionPolicy,
+};
 use crate::scheduler_server::SessionBuilder;
+use crate::state::catalog::CatalogTable;
 use crate::state::execution_graph::{create_task_info, ExecutionGraph, TaskDescription};
 use crate::state::task_manager::JobInfoCache;
 
+pub mod history;
 pub mod kv;
 pub mod storage;
 
@@ -54,6 +61,7 @@ pub mod test_util;
 pub struct BallistaCluster {
     cluster_state: Arc<dyn ClusterState>,
     job_state: Arc<dyn JobState>,
+    job_history: Arc<dyn JobHistoryStore>,
 }
 
 impl BallistaCluster {
@@ -61,6 +69,7 @@ impl BallistaCluster {
         Self {
             cluster_state,
             job_state,
+            job_history: Arc::new(InMemoryJobHistoryStore::default()),
         }
     }
 
@@ -74,13 +83,20 @@ impl BallistaCluster {
         Self {
             cluster_state: kv_state.clone(),
             job_state: kv_state,
+            job_history: Arc::new(InMemoryJobHistoryStore::default()),
         }
     }
 
+    /// Archive finished jobs into the given store instead of in memory
+    pub fn with_job_history(mut self, job_history: Arc<dyn JobHistoryStore>) -> Self {
+        self.job_history = job_history;
+        self
+    }
+
     pub async fn new_from_config(config: &SchedulerConfig) -> Result<Self> {
         let scheduler = config.scheduler_name();
 
-        match &config.cluster_storage {
+        let cluster = match &config.cluster_storage {
             ClusterStorageConfig::Etcd(urls) => {
                 let etcd = etcd_client::Client::connect(urls.as_slice(), None)
                     .await
@@ -88,12 +104,12 @@ impl BallistaCluster {
                         BallistaError::Internal(format!("Could not connect to etcd: {err:?}"))
                     })?;
 
-                Ok(Self::new_kv(
+                Self::new_kv(
                     EtcdClient::new(config.namespace.clone(), etcd),
                     scheduler,
                     default_session_builder,
                     BallistaCodec::default(),
-                ))
+                )
             }
 
             ClusterStorageConfig::Sled(dir) => {
@@ -101,25 +117,42 @@ impl BallistaCluster {
                     info!("Initializing Sled database in directory {}", dir);
                     let sled = SledClient::try_new(dir)?;
 
-                    Ok(Self::new_kv(
+                    Self::new_kv(
                         sled,
                         scheduler,
                         default_session_builder,
                         BallistaCodec::default(),
-                    ))
+                    )
                 } else {
                     info!("Initializing Sled database in temp directory");
                     let sled = SledClient::try_new_temporary()?;
 
-                    Ok(Self::new_kv(
+                    Self::new_kv(
                         sled,
                         scheduler,
                         default_session_builder,
                         BallistaCodec::default(),
-                    ))
+                    )
                 }
             }
-        }
+        };
+
+        let job_history: Arc<dyn JobHistoryStore> = match &config.job_history_storage {
+            JobHistoryStorageConfig::Memory => Arc::new(InMemoryJobHistoryStore::default()),
+            JobHistoryStorageConfig::Directory(dir) => {
+                info!("Archiving finished jobs into directory {}", dir);
+                Arc::new(DirectoryJobHistoryStore::try_new(dir)?)
+            }
+            JobHistoryStorageConfig::Sled(dir) => {
+                info!(
+                    "Archiving finished jobs into Sled database in directory {}",
+                    dir
+                );
+                Arc::new(SledJobHistoryStore::try_new(dir)?)
+            }
+        };
+
+        Ok(cluster.with_job_history(job_history))
     }
 
     pub fn cluster_state(&self) -> Arc<dyn ClusterState> {
@@ -129,6 +162,10 @@ impl BallistaCluster {
     pub fn job_state(&self) -> Arc<dyn JobState> {
         self.job_state.clone()
     }
+
+    pub fn job_history(&self) -> Arc<dyn JobHistoryStore> {
+        self.job_history.clone()
+    }
 }
 
 /// Stream of `ExecutorHeartbeat`. This stream should contain all `ExecutorHeartbeats` received
@@ -152,12 +189,14 @@ pub trait ClusterState: Send + Sync + 'static {
 
     /// Bind the ready to running tasks from [`active_jobs`] with available executors.
     ///
-    /// If `executors` is provided, only bind slots from the specified executor IDs
+    /// If `executors` is provided, only bind slots from the specified executor IDs.
+    /// Jobs of tenants in `tenant_slots` will take no more than the given number of slots
     async fn bind_schedulable_tasks(
         &self,
         distribution: TaskDistributionPolicy,
         active_jobs: Arc<HashMap<String, JobInfoCache>>,
         executors: Option<HashSet<String>>,
+        tenant_slots: HashMap<String, u32>,
     ) -> Result<Vec<BoundTask>>;
 
     /// Unbind executor and task when a task finishes or fails. It will increase the executor
@@ -176,6 +215,9 @@ pub trait ClusterState: Send + Sync + 'static {
     /// Get executor metadata for the provided executor ID. Returns an error if the executor does not exist
     async fn get_executor_metadata(&self, executor_id: &str) -> Result<ExecutorMetadata>;
 
+    /// Get the number of free task slots of the executor. Return None if the executor does not exist
+    async fn get_available_slots(&self, executor_id: &str) -> Result<Option<u32>>;
+
     /// Save the executor heartbeat
     async fn save_executor_heartbeat(&self, heartbeat: ExecutorHeartbeat) -> Result<()>;
 
@@ -229,13 +271,31 @@ pub trait JobState: Send + Sync {
     /// session does not exist
     async fn get_session(&self, session_id: &str) -> Result<Arc<SessionContext>>;
 
+    /// Get the `BallistaConfig` of the session `session_id` without building a
+    /// `SessionContext` for it. Returns an error if the session does not exist
+    async fn get_session_config(&self, session_id: &str) -> Result<BallistaConfig>;
+
     /// Create a new saved session
     async fn create_session(&self, config: &BallistaConfig) -> Result<Arc<SessionContext>>;
+
+    /// Save a table to the shared catalog, replacing any previous definition of it.
+    /// Returns the table with the version assigned to it
+    async fn save_catalog_table(&self, table: CatalogTable) -> Result<CatalogTable>;
+
+    /// Remove a table from the shared catalog. Does nothing if the table does not exist
+    async fn remove_catalog_table(&self, name: &str) -> Result<()>;
+
+    /// Get all tables of the shared catalog
+    async fn get_catalog_tables(&self) -> Result<Vec<CatalogTable>>;
+
+    /// Version of the shared catalog, which changes whenever a table is saved or removed
+    async fn get_catalog_version(&self) -> Result<u64>;
 }
 
 pub(crate) async fn bind_task_bias(
     mut slots: Vec<&mut AvailableTaskSlots>,
     active_jobs: Arc<HashMap<String, JobInfoCache>>,
+    mut tenant_slots: HashMap<String, u32>,
 ) -> Vec<BoundTask> {
     let mut schedulable_tasks: Vec<BoundTask> = vec![];
 
@@ -258,9 +318,23 @@ pub(crate) async fn bind_task_bias(
             );
             continue;
         }
+        let mut tenant_slot = tenant_slots.get_mut(&job_info.tenant);
+        if tenant_slot.as_deref() == Some(&0) {
+            debug!(
+                "Tenant {} of job {} has no task slots left and will be skipped",
+                job_info.tenant, job_id
+            );
+            continue;
+        }
         let mut graph = job_info.execution_graph.write().await;
         let session_id = graph.session_id().to_string();
         while let Some((running_stage, task_id_gen)) = graph.fetch_running_stage() {
+            if tenant_slot.as_deref() == Some(&0) {
+                break;
+            }
+            let max_tasks = tenant_slot
+                .as_deref()
+                .map_or(total_slots, |tenant_slot| total_slots.min(*tenant_slot));
             // We are sure that it will at least bind one task by going through the following logic.
             // It will not go into a dead loop.
             let runnable_tasks = running_stage
@@ -268,7 +342,7 @@ pub(crate) async fn bind_task_bias(
                 .iter_mut()
                 .enumerate()
                 .filter(|(_partition, info)| info.is_none())
-                .take(total_slots as usize)
+                .take(max_tasks as usize)
                 .collect::<Vec<_>>();
             for (partition_id, task_info) in runnable_tasks {
                 // Assign [`slot`] with a slot available slot number larger than 0
@@ -299,6 +373,9 @@ pub(crate) async fn bind_task_bias(
                 schedulable_tasks.push((executor_id, task_desc));
 
                 slot.slots -= 1;
+                if let Some(tenant_slot) = tenant_slot.as_deref_mut() {
+                    *tenant_slot -= 1;
+                }
             }
         }
     }
@@ -309,6 +386,7 @@ pub(crate) async fn bind_task_bias(
 pub(crate) async fn bind_task_round_robin(
     mut slots: Vec<&mut AvailableTaskSlots>,
     active_jobs: Arc<HashMap<String, JobInfoCache>>,
+    mut tenant_slots: HashMap<String, u32>,
 ) -> Vec<BoundTask> {
     let mut schedulable_tasks: Vec<BoundTask> = vec![];
 
@@ -331,9 +409,23 @@ pub(crate) async fn bind_task_round_robin(
             );
             continue;
         }
+        let mut tenant_slot = tenant_slots.get_mut(&job_info.tenant);
+        if tenant_slot.as_deref() == Some(&0) {
+            debug!(
+                "Tenant {} of job {} has no task slots left and will be skipped",
+                job_info.tenant, job_id
+            );
+            continue;
+        }
         let mut graph = job_info.execution_graph.write().await;
         let session_id = graph.session_id().to_string();
         while let Some((running_stage, task_id_gen)) = graph.fetch_running_stage() {
+            if tenant_slot.as_deref() == Some(&0) {
+                break;
+            }
+            let max_tasks = tenant_slot
+                .as_deref()
+                .map_or(total_slots, |tenant_slot| total_slots.min(*tenant_slot));
             // We are sure that it will at least bind one task by going through the following logic.
             // It will not go into a dead loop.
             let runnable_tasks = running_stage
@@ -341,7 +433,7 @@ pub(crate) async fn bind_task_round_robin(
                 .iter_mut()
                 .enumerate()
                 .filter(|(_partition, info)| info.is_none())
-                .take(total_slots as usize)
+                .take(max_tasks as usize)
                 .collect::<Vec<_>>();
             for (partition_id, task_info) in runnable_tasks {
                 // Move to the index which has available slots
@@ -376,6 +468,9 @@ pub(crate) async fn bind_task_round_robin(
                 idx_slot += 1;
                 slot.slots -= 1;
                 total_slots -= 1;
+                if let Some(tenant_slot) = tenant_slot.as_deref_mut() {
+                    *tenant_slot -= 1;
+                }
                 if total_slots == 0 {
                     return schedulable_tasks;
                 }
@@ -32,6 +32,7 @@ pub enum Keyspace {
     Slots,
     Sessions,
     Heartbeats,
+    Catalog,
 }
 
 impl Keyspace {
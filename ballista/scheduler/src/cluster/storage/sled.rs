@@ -56,7 +56,7 @@ impl SledClient {
     }
 }
 
-fn sled_to_ballista_error(e: sled::Error) -> BallistaError {
+pub(crate) fn sled_to_ballista_error(e: sled::Error) -> BallistaError {
     match e {
         sled::Error::Io(io) => BallistaError::IoError(io),
         _ => BallistaError::General(format!("{e}")),
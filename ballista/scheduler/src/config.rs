@@ -18,6 +18,14 @@
 
 //! Ballista scheduler specific configuration
 
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub use ballista_core::auth::UserCredentials;
+
+/// The tenant that jobs are accounted to when their session does not specify one
+pub const DEFAULT_TENANT: &str = "default";
+
 /// Configurations for the ballista scheduler of scheduling jobs and tasks
 #[derive(Debug, Clone)]
 pub struct SchedulerConfig {
@@ -49,6 +57,23 @@ pub struct SchedulerConfig {
     pub executor_timeout_seconds: u64,
     /// The interval to check expired or dead executors
     pub expire_dead_executor_interval_seconds: u64,
+    /// The quota applied to tenants which have no quota of their own in `tenant_quotas`
+    pub default_tenant_quota: TenantQuota,
+    /// Quotas of specific tenants, keyed by the tenant name
+    pub tenant_quotas: HashMap<String, TenantQuota>,
+    /// Where to archive the summaries of finished jobs
+    pub job_history_storage: JobHistoryStorageConfig,
+    /// Archived jobs older than this are removed from the job history, 0 means no age limit
+    pub job_history_max_age_seconds: u64,
+    /// Maximum number of jobs kept in the job history, 0 means no limit
+    pub job_history_max_jobs: usize,
+    /// JSON-lines file recording every event received by the scheduler and every state
+    /// transition of jobs and stages, `None` disables the event log
+    pub event_log_path: Option<String>,
+    /// Users which Flight SQL and REST clients authenticate as
+    pub users: UserCredentials,
+    /// Bearer tokens handed out to clients expire when not used for this long
+    pub session_token_ttl_seconds: u64,
 }
 
 impl Default for SchedulerConfig {
@@ -67,6 +92,14 @@ impl Default for SchedulerConfig {
             grpc_server_max_encoding_message_size: 16777216,
             executor_timeout_seconds: 180,
             expire_dead_executor_interval_seconds: 15,
+            default_tenant_quota: TenantQuota::default(),
+            tenant_quotas: HashMap::new(),
+            job_history_storage: JobHistoryStorageConfig::Memory,
+            job_history_max_age_seconds: 7 * 24 * 3600,
+            job_history_max_jobs: 1000,
+            event_log_path: None,
+            users: UserCredentials::default(),
+            session_token_ttl_seconds: 3600,
         }
     }
 }
@@ -112,6 +145,32 @@ impl SchedulerConfig {
         self
     }
 
+    pub fn with_job_history_storage(mut self, storage: JobHistoryStorageConfig) -> Self {
+        self.job_history_storage = storage;
+        self
+    }
+
+    pub fn with_job_history_retention(mut self, max_age_seconds: u64, max_jobs: usize) -> Self {
+        self.job_history_max_age_seconds = max_age_seconds;
+        self.job_history_max_jobs = max_jobs;
+        self
+    }
+
+    pub fn with_event_log_path(mut self, path: impl Into<String>) -> Self {
+        self.event_log_path = Some(path.into());
+        self
+    }
+
+    pub fn with_users(mut self, users: UserCredentials) -> Self {
+        self.users = users;
+        self
+    }
+
+    pub fn with_session_token_ttl_seconds(mut self, ttl_seconds: u64) -> Self {
+        self.session_token_ttl_seconds = ttl_seconds;
+        self
+    }
+
     pub fn with_task_distribution(mut self, policy: TaskDistributionPolicy) -> Self {
         self.task_distribution = policy;
         self
@@ -136,6 +195,96 @@ impl SchedulerConfig {
         self.grpc_server_max_encoding_message_size = value;
         self
     }
+
+    pub fn with_default_tenant_quota(mut self, quota: TenantQuota) -> Self {
+        self.default_tenant_quota = quota;
+        self
+    }
+
+    pub fn with_tenant_quota(mut self, tenant: impl Into<String>, quota: TenantQuota) -> Self {
+        self.tenant_quotas.insert(tenant.into(), quota);
+        self
+    }
+
+    /// Get the quota of the tenant, falling back to the default tenant quota
+    pub fn tenant_quota(&self, tenant: &str) -> &TenantQuota {
+        self.tenant_quotas
+            .get(tenant)
+            .unwrap_or(&self.default_tenant_quota)
+    }
+}
+
+/// Resource limits of a tenant. A limit of `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantQuota {
+    /// The maximum number of task slots the tenant's jobs can occupy at the same time
+    pub max_task_slots: Option<u32>,
+    /// The maximum number of jobs of the tenant running at the same time.
+    /// Jobs beyond this limit stay queued until a running job finishes
+    pub max_running_jobs: Option<usize>,
+    /// The maximum number of shuffle bytes the tenant's running jobs can hold.
+    /// New jobs are not admitted while the tenant is at or above this limit
+    pub max_shuffle_bytes: Option<u64>,
+}
+
+impl TenantQuota {
+    pub fn with_max_task_slots(mut self, max_task_slots: u32) -> Self {
+        self.max_task_slots = Some(max_task_slots);
+        self
+    }
+
+    pub fn with_max_running_jobs(mut self, max_running_jobs: usize) -> Self {
+        self.max_running_jobs = Some(max_running_jobs);
+        self
+    }
+
+    pub fn with_max_shuffle_bytes(mut self, max_shuffle_bytes: u64) -> Self {
+        self.max_shuffle_bytes = Some(max_shuffle_bytes);
+        self
+    }
+
+    /// Parse the quotas of specific tenants from a list like
+    /// `analytics:max_task_slots=8,max_running_jobs=2;etl:max_shuffle_bytes=1073741824`
+    pub fn parse_tenant_quotas(quotas: &str) -> Result<HashMap<String, TenantQuota>, String> {
+        quotas
+            .split(';')
+            .map(str::trim)
+            .filter(|quota| !quota.is_empty())
+            .map(|quota| {
+                let (tenant, limits) = quota
+                    .split_once(':')
+                    .ok_or_else(|| format!("Missing tenant name in quota {quota:?}"))?;
+                Ok((tenant.trim().to_owned(), limits.parse()?))
+            })
+            .collect()
+    }
+}
+
+impl FromStr for TenantQuota {
+    type Err = String;
+
+    /// Parse limits like `max_task_slots=8,max_running_jobs=2`, omitted limits are unlimited
+    fn from_str(limits: &str) -> Result<Self, Self::Err> {
+        let mut quota = TenantQuota::default();
+        for limit in limits.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+            let (name, value) = limit
+                .split_once('=')
+                .ok_or_else(|| format!("Expected a limit like name=value but got {limit:?}"))?;
+            let value = value.trim();
+            let invalid = |e: std::num::ParseIntError| format!("Invalid {name} {value:?}: {e}");
+            match name.trim() {
+                "max_task_slots" => quota.max_task_slots = Some(value.parse().map_err(invalid)?),
+                "max_running_jobs" => {
+                    quota.max_running_jobs = Some(value.parse().map_err(invalid)?)
+                }
+                "max_shuffle_bytes" => {
+                    quota.max_shuffle_bytes = Some(value.parse().map_err(invalid)?)
+                }
+                other => return Err(format!("Unknown tenant quota limit {other:?}")),
+            }
+        }
+        Ok(quota)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -144,6 +293,16 @@ pub enum ClusterStorageConfig {
     Sled(Option<String>),
 }
 
+#[derive(Clone, Debug)]
+pub enum JobHistoryStorageConfig {
+    /// Keep the job history in memory only, it is lost when the scheduler restarts
+    Memory,
+    /// Write every finished job as a JSON file into a local directory
+    Directory(String),
+    /// Keep the job history in a sled database in the given directory
+    Sled(String),
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum TaskDistributionPolicy {
     /// Eagerly assign tasks to executor slots. This will assign as many task slots per executor
@@ -153,3 +312,35 @@ pub enum TaskDistributionPolicy {
     /// and assign one task to each executor until all tasks are assigned.
     RoundRobin,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tenant_quotas() {
+        let quotas = TenantQuota::parse_tenant_quotas(
+            "analytics: max_task_slots=8, max_running_jobs=2; etl:max_shuffle_bytes=1024;",
+        )
+        .unwrap();
+        assert_eq!(
+            quotas,
+            HashMap::from([
+                (
+                    "analytics".to_owned(),
+                    TenantQuota::default()
+                        .with_max_task_slots(8)
+                        .with_max_running_jobs(2)
+                ),
+                (
+                    "etl".to_owned(),
+                    TenantQuota::default().with_max_shuffle_bytes(1024)
+                ),
+            ])
+        );
+
+        assert!(TenantQuota::parse_tenant_quotas("max_task_slots=8").is_err());
+        assert!(TenantQuota::parse_tenant_quotas("etl:max_task_slots=many").is_err());
+        assert!(TenantQuota::parse_tenant_quotas("etl:max_memory=8").is_err());
+    }
+}
@@ -19,12 +19,14 @@
 //! [`crate::physical_plan::displayable`] for examples of how to
 //! format
 
+use ballista_core::execution_plans::ShuffleWriterExec;
 use ballista_core::utils::collect_plan_metrics;
 use datafusion::logical_expr::{StringifiedPlan, ToStringifiedPlan};
 use datafusion::physical_plan::metrics::MetricsSet;
 use datafusion::physical_plan::{accept, DisplayFormatType, ExecutionPlan, ExecutionPlanVisitor};
 use log::{error, info};
 use std::fmt;
+use std::sync::Arc;
 
 pub fn print_stage_metrics(
     job_id: &str,
@@ -32,19 +34,7 @@ pub fn print_stage_metrics(
     plan: &dyn ExecutionPlan,
     stage_metrics: &[MetricsSet],
 ) {
-    // The plan_metrics collected here is a snapshot clone from the plan metrics.
-    // They are all empty now and need to combine with the stage metrics in the ExecutionStages
-    let mut plan_metrics = collect_plan_metrics(plan);
-    if plan_metrics.len() == stage_metrics.len() {
-        plan_metrics
-            .iter_mut()
-            .zip(stage_metrics)
-            .for_each(|(plan_metric, stage_metric)| {
-                stage_metric
-                    .iter()
-                    .for_each(|s| plan_metric.push(s.clone()));
-            });
-
+    if let Some(plan_metrics) = combine_stage_metrics(plan, stage_metrics) {
         info!(
             "=== [{}/{}] Stage finished, physical plan with metrics ===\n{}\n",
             job_id,
@@ -53,22 +43,142 @@ pub fn print_stage_metrics(
         );
     } else {
         error!("Fail to combine stage metrics to plan for stage [{}/{}],  plan metrics array size {} does not equal
-                to the stage metrics array size {}", job_id, stage_id, plan_metrics.len(), stage_metrics.len());
+                to the stage metrics array size {}", job_id, stage_id, collect_plan_metrics(plan).len(), stage_metrics.len());
+    }
+}
+
+/// Combine the stage metrics with the metrics of the plan, in the order the plan is visited.
+/// Returns `None` if the stage metrics do not line up with the operators of the plan.
+fn combine_stage_metrics(
+    plan: &dyn ExecutionPlan,
+    stage_metrics: &[MetricsSet],
+) -> Option<Vec<MetricsSet>> {
+    // The plan_metrics collected here is a snapshot clone from the plan metrics.
+    // They are all empty now and need to combine with the stage metrics in the ExecutionStages
+    let mut plan_metrics = collect_plan_metrics(plan);
+    if plan_metrics.len() != stage_metrics.len() {
+        return None;
+    }
+    plan_metrics
+        .iter_mut()
+        .zip(stage_metrics)
+        .for_each(|(plan_metric, stage_metric)| {
+            stage_metric
+                .iter()
+                .for_each(|s| plan_metric.push(s.clone()));
+        });
+    Some(plan_metrics)
+}
+
+/// Summary of the tasks of a finished stage, shown by `EXPLAIN ANALYZE`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StageTaskSummary {
+    /// Number of tasks that ran for the stage
+    pub task_count: usize,
+    /// Total shuffle bytes written by the tasks
+    pub shuffle_bytes: u64,
+    /// Shortest task execution time in milliseconds
+    pub min_duration_ms: u64,
+    /// Median task execution time in milliseconds
+    pub median_duration_ms: u64,
+    /// Longest task execution time in milliseconds
+    pub max_duration_ms: u64,
+}
+
+impl StageTaskSummary {
+    pub fn new(mut durations_ms: Vec<u64>, shuffle_bytes: u64) -> Self {
+        durations_ms.sort_unstable();
+        let task_count = durations_ms.len();
+        let median_duration_ms = match task_count {
+            0 => 0,
+            n if n % 2 == 0 => (durations_ms[n / 2 - 1] + durations_ms[n / 2]) / 2,
+            n => durations_ms[n / 2],
+        };
+        Self {
+            task_count,
+            shuffle_bytes,
+            min_duration_ms: durations_ms.first().copied().unwrap_or_default(),
+            median_duration_ms,
+            max_duration_ms: durations_ms.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for StageTaskSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tasks={}, shuffle_bytes={}, task_duration=[min={}ms, median={}ms, max={}ms]",
+            self.task_count,
+            self.shuffle_bytes,
+            self.min_duration_ms,
+            self.median_duration_ms,
+            self.max_duration_ms
+        )
+    }
+}
+
+/// Render a finished stage for `EXPLAIN ANALYZE`: the task summary of the stage followed
+/// by its plan annotated with the operator metrics aggregated across all tasks.
+pub fn display_analyzed_stage(
+    plan: &dyn ExecutionPlan,
+    stage_metrics: &[MetricsSet],
+    summary: &StageTaskSummary,
+) -> String {
+    format!("{summary}\n{}", display_stage_plan(plan, stage_metrics))
+}
+
+/// Render the plan of a stage with the operator metrics aggregated across its tasks,
+/// or without metrics if they are not available (yet).
+pub fn display_stage_plan(plan: &dyn ExecutionPlan, stage_metrics: &[MetricsSet]) -> String {
+    match combine_stage_metrics(plan, stage_metrics) {
+        Some(plan_metrics) => DisplayableBallistaExecutionPlan::new(plan, &plan_metrics)
+            .indent()
+            .to_string(),
+        None => DisplayableBallistaExecutionPlan::without_metrics(plan)
+            .indent()
+            .to_string(),
     }
 }
 
+/// Render the stages produced by the [`crate::planner::DistributedPlanner`] as
+/// `(plan_type, plan)` pairs, one per stage, in the order they were planned.
+pub fn display_query_stages(stages: &[Arc<ShuffleWriterExec>]) -> Vec<(String, String)> {
+    stages
+        .iter()
+        .map(|stage| {
+            let plan = DisplayableBallistaExecutionPlan::without_metrics(stage.as_ref())
+                .indent()
+                .to_string();
+            (format!("stage_{}", stage.stage_id()), plan)
+        })
+        .collect()
+}
+
 /// Wraps an `ExecutionPlan` to display this plan with metrics collected/aggregated.
 /// The metrics must be collected in the same order as how we visit and display the plan.
 pub struct DisplayableBallistaExecutionPlan<'a> {
     inner: &'a dyn ExecutionPlan,
-    metrics: &'a Vec<MetricsSet>,
+    metrics: Option<&'a Vec<MetricsSet>>,
 }
 
 impl<'a> DisplayableBallistaExecutionPlan<'a> {
     /// Create a wrapper around an [`'ExecutionPlan'] which can be
     /// pretty printed with aggregated metrics.
     pub fn new(inner: &'a dyn ExecutionPlan, metrics: &'a Vec<MetricsSet>) -> Self {
-        Self { inner, metrics }
+        Self {
+            inner,
+            metrics: Some(metrics),
+        }
+    }
+
+    /// Create a wrapper around an [`'ExecutionPlan'] which can be
+    /// pretty printed without any metrics, e.g. for `EXPLAIN`.
+    pub fn without_metrics(inner: &'a dyn ExecutionPlan) -> Self {
+        Self {
+            inner,
+            metrics: None,
+        }
     }
 
     /// Return a `format`able structure that produces a single line
@@ -84,7 +194,7 @@ impl<'a> DisplayableBallistaExecutionPlan<'a> {
     pub fn indent(&self) -> impl fmt::Display + 'a {
         struct Wrapper<'a> {
             plan: &'a dyn ExecutionPlan,
-            metrics: &'a Vec<MetricsSet>,
+            metrics: Option<&'a Vec<MetricsSet>>,
         }
         impl<'a> fmt::Display for Wrapper<'a> {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -106,6 +216,14 @@ impl<'a> DisplayableBallistaExecutionPlan<'a> {
     }
 }
 
+/// Ratio of the in-memory size of the batches written by a shuffle writer to the size of
+/// the shuffle files, from the bytes summed across its tasks
+fn compression_ratio(metrics: &MetricsSet) -> Option<f64> {
+    let uncompressed_bytes = metrics.sum_by_name("uncompressed_bytes")?.as_usize();
+    let output_bytes = metrics.sum_by_name("output_bytes")?.as_usize();
+    (output_bytes > 0).then(|| uncompressed_bytes as f64 / output_bytes as f64)
+}
+
 /// Formats plans with a single line per node.
 struct IndentVisitor<'a, 'b> {
     /// How to format each node
@@ -114,8 +232,8 @@ struct IndentVisitor<'a, 'b> {
     f: &'a mut fmt::Formatter<'b>,
     /// Indent size
     indent: usize,
-    /// The metrics along with the plan
-    metrics: &'a Vec<MetricsSet>,
+    /// The metrics along with the plan, if any
+    metrics: Option<&'a Vec<MetricsSet>>,
     /// The metric index
     metric_index: usize,
 }
@@ -125,14 +243,20 @@ impl<'a, 'b> ExecutionPlanVisitor for IndentVisitor<'a, 'b> {
     fn pre_visit(&mut self, plan: &dyn ExecutionPlan) -> std::result::Result<bool, Self::Error> {
         write!(self.f, "{:indent$}", "", indent = self.indent * 2)?;
         plan.fmt_as(self.t, self.f)?;
-        if let Some(metrics) = self.metrics.get(self.metric_index) {
-            let metrics = metrics
-                .aggregate_by_name()
-                .sorted_for_display()
-                .timestamps_removed();
-            write!(self.f, ", metrics=[{metrics}]")?;
-        } else {
-            write!(self.f, ", metrics=[]")?;
+        if let Some(metrics) = self.metrics {
+            if let Some(metrics) = metrics.get(self.metric_index) {
+                let metrics = metrics
+                    .aggregate_by_name()
+                    .sorted_for_display()
+                    .timestamps_removed();
+                write!(self.f, ", metrics=[{metrics}")?;
+                if let Some(ratio) = compression_ratio(&metrics) {
+                    write!(self.f, ", compression_ratio={ratio:.2}")?;
+                }
+                write!(self.f, "]")?;
+            } else {
+                write!(self.f, ", metrics=[]")?;
+            }
         }
         writeln!(self.f)?;
         self.indent += 1;
@@ -151,3 +275,66 @@ impl<'a> ToStringifiedPlan for DisplayableBallistaExecutionPlan<'a> {
         StringifiedPlan::new(plan_type, self.indent().to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::Schema;
+    use datafusion::physical_plan::empty::EmptyExec;
+    use datafusion::physical_plan::metrics::{Count, Metric, MetricValue};
+
+    #[test]
+    fn stage_task_summary() {
+        let summary = StageTaskSummary::new(vec![40, 10, 30, 20], 1024);
+        assert_eq!(
+            summary,
+            StageTaskSummary {
+                task_count: 4,
+                shuffle_bytes: 1024,
+                min_duration_ms: 10,
+                median_duration_ms: 25,
+                max_duration_ms: 40,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "tasks=4, shuffle_bytes=1024, task_duration=[min=10ms, median=25ms, max=40ms]"
+        );
+
+        let summary = StageTaskSummary::new(vec![7, 3, 5], 0);
+        assert_eq!(summary.median_duration_ms, 5);
+
+        assert_eq!(
+            StageTaskSummary::new(vec![], 0),
+            StageTaskSummary::default()
+        );
+    }
+
+    #[test]
+    fn compression_ratio_of_stage() {
+        let input = Arc::new(EmptyExec::new(Arc::new(Schema::empty())));
+        let plan = ShuffleWriterExec::new("job".to_owned(), 1, input, String::new(), None);
+
+        // Two tasks which wrote 300 and 100 bytes for 600 and 400 bytes of batches
+        let mut shuffle_metrics = MetricsSet::new();
+        for (partition, uncompressed, output) in [(0, 600, 300), (1, 400, 100)] {
+            for (name, bytes) in [
+                ("uncompressed_bytes", uncompressed),
+                ("output_bytes", output),
+            ] {
+                let count = Count::new();
+                count.add(bytes);
+                let value = MetricValue::Count {
+                    name: name.into(),
+                    count,
+                };
+                shuffle_metrics.push(Arc::new(Metric::new(value, Some(partition))));
+            }
+        }
+        let rendered = display_stage_plan(&plan, &[shuffle_metrics]);
+        assert!(
+            rendered.contains("compression_ratio=2.50"),
+            "unexpected plan {rendered}"
+        );
+    }
+}
@@ -15,97 +15,578 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use arrow_flight::decode::FlightRecordBatchStream;
+use arrow_flight::error::FlightError;
 use arrow_flight::flight_descriptor::DescriptorType;
 use arrow_flight::flight_service_server::FlightService;
+use arrow_flight::sql::metadata::{
+    SqlInfoData, SqlInfoDataBuilder, XdbcTypeInfo, XdbcTypeInfoData, XdbcTypeInfoDataBuilder,
+};
 use arrow_flight::sql::server::{FlightSqlService, PeekableFlightDataStream};
 use arrow_flight::sql::{
-    ActionClosePreparedStatementRequest, ActionCreatePreparedStatementRequest,
-    ActionCreatePreparedStatementResult, CommandGetTableTypes, CommandGetTables,
-    CommandPreparedStatementQuery, CommandPreparedStatementUpdate, CommandStatementQuery, SqlInfo,
+    ActionCancelQueryRequest, ActionCancelQueryResult, ActionClosePreparedStatementRequest,
+    ActionCreatePreparedStatementRequest, ActionCreatePreparedStatementResult, CommandGetCatalogs,
+    CommandGetCrossReference, CommandGetDbSchemas, CommandGetPrimaryKeys, CommandGetSqlInfo,
+    CommandGetTableTypes, CommandGetTables, CommandGetXdbcTypeInfo, CommandPreparedStatementQuery,
+    CommandPreparedStatementUpdate, CommandStatementQuery, CommandStatementUpdate, Nullable,
+    Searchable, SqlInfo, SupportedSqlGrammar, XdbcDataType,
 };
 use arrow_flight::{
     Action, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest,
     HandshakeResponse, Location, Ticket,
 };
-use base64::Engine;
-use futures::Stream;
-use log::{debug, error, warn};
+use futures::{Stream, StreamExt, TryStreamExt};
+use log::{debug, error, info, warn};
 use std::convert::TryFrom;
 use std::pin::Pin;
-use std::str::FromStr;
 use std::string::ToString;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tonic::{Request, Response, Status, Streaming};
 
+use crate::api::result::fetch_output_partition;
+use crate::display::display_query_stages;
+use crate::planner::DistributedPlanner;
 use crate::scheduler_server::SchedulerServer;
+use crate::state::session_manager::session_tenant;
 use arrow_flight::flight_service_client::FlightServiceClient;
 use arrow_flight::sql::ProstMessageExt;
 use arrow_flight::utils::batches_to_flight_data;
 use arrow_flight::SchemaAsIpc;
-use ballista_core::config::BallistaConfig;
+use ballista_core::auth::client_authorization;
+use ballista_core::config::{BallistaConfig, BALLISTA_TENANT};
 use ballista_core::serde::protobuf;
 use ballista_core::serde::protobuf::action::ActionType::FetchPartition;
 use ballista_core::serde::protobuf::job_status;
 use ballista_core::serde::protobuf::JobStatus;
 use ballista_core::serde::protobuf::SuccessfulJob;
 use ballista_core::utils::create_grpc_client_connection;
+use ballista_core::BALLISTA_VERSION;
+use dashmap::mapref::one::RefMut;
 use dashmap::DashMap;
 use datafusion::arrow;
-use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::array::{ArrayRef, StringArray, UInt64Array};
+use datafusion::arrow::compute::sum;
 use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
 use datafusion::arrow::error::ArrowError;
 use datafusion::arrow::ipc::writer::{IpcDataGenerator, IpcWriteOptions};
 use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::CatalogProvider;
 use datafusion::common::DFSchemaRef;
-use datafusion::logical_expr::LogicalPlan;
+use datafusion::datasource::file_format::json::JsonSink;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::logical_expr::{Analyze, DmlStatement, Explain, LogicalPlan, WriteOp};
+use datafusion::physical_plan::display::DisplayableExecutionPlan;
+use datafusion::physical_plan::insert::FileSinkExec;
+use datafusion::physical_plan::{ExecutionPlan, SendableRecordBatchStream};
 use datafusion::prelude::SessionContext;
+use datafusion::scalar::ScalarValue;
 use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
 use prost::Message;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::time::sleep;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::metadata::MetadataValue;
+use tonic::metadata::{KeyAndValueRef, MetadataMap, MetadataValue};
 use uuid::Uuid;
 
 pub struct FlightSqlServiceImpl {
     server: SchedulerServer<LogicalPlanNode, PhysicalPlanNode>,
-    statements: Arc<DashMap<Uuid, LogicalPlan>>,
-    contexts: Arc<DashMap<Uuid, Arc<SessionContext>>>,
+    statements: Arc<PreparedStatements>,
+    results: Arc<LocalResults>,
+    sql_info: Arc<SqlInfoData>,
+    xdbc_type_info: Arc<XdbcTypeInfoData>,
+}
+
+/// A prepared statement along with the SQL text it was created from
+#[derive(Clone, Debug)]
+struct PreparedStatement {
+    /// The session which created the statement, the only one which may use its handle
+    session_id: String,
+    sql: String,
+    plan: LogicalPlan,
+    /// Values of the parameters `$1`, `$2`, ... last bound by the client
+    params: Vec<ScalarValue>,
+    last_used: Instant,
+}
+
+impl PreparedStatement {
+    /// The plan with the bound parameter values in place of its placeholders
+    fn bound_plan(&self) -> Result<LogicalPlan, Status> {
+        if self.params.is_empty() {
+            return Ok(self.plan.clone());
+        }
+        self.plan
+            .clone()
+            .with_param_values(self.params.clone())
+            .map_err(|e| Status::invalid_argument(format!("Error binding parameters: {e}")))
+    }
+}
+
+/// Prepared statements which have not been used for this long are dropped
+const PREPARED_STATEMENT_TTL: Duration = Duration::from_secs(3600);
+
+/// How often expired prepared statements and local results are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Prepared statements by their handles. Statements which clients forgot to close expire
+/// after [`PREPARED_STATEMENT_TTL`].
+#[derive(Default)]
+struct PreparedStatements {
+    statements: DashMap<Uuid, PreparedStatement>,
+}
+
+impl PreparedStatements {
+    fn insert(&self, session_id: &str, sql: &str, plan: LogicalPlan, now: Instant) -> Uuid {
+        self.sweep(now);
+        let handle = Uuid::new_v4();
+        self.statements.insert(
+            handle,
+            PreparedStatement {
+                session_id: session_id.to_owned(),
+                sql: sql.to_owned(),
+                plan,
+                params: vec![],
+                last_used: now,
+            },
+        );
+        handle
+    }
+
+    /// The statement of the handle, which has to have been created by the session
+    fn get_mut(
+        &self,
+        session_id: &str,
+        handle: &Uuid,
+        now: Instant,
+    ) -> Result<RefMut<'_, Uuid, PreparedStatement>, Status> {
+        let mut statement = self
+            .statements
+            .get_mut(handle)
+            .ok_or_else(|| Status::not_found(format!("Statement handle not found: {handle}")))?;
+        if statement.session_id != session_id {
+            return Err(Status::permission_denied(format!(
+                "Statement handle {handle} belongs to another session"
+            )));
+        }
+        statement.last_used = now;
+        Ok(statement)
+    }
+
+    fn remove(&self, session_id: &str, handle: &Uuid) -> Result<(), Status> {
+        // Check the owner without holding the entry while removing it
+        drop(self.get_mut(session_id, handle, Instant::now())?);
+        self.statements.remove(handle);
+        Ok(())
+    }
+
+    /// Drop the statements which have expired
+    fn sweep(&self, now: Instant) {
+        self.statements.retain(|_, statement| {
+            now.saturating_duration_since(statement.last_used) < PREPARED_STATEMENT_TTL
+        });
+    }
+}
+
+/// Results computed on the scheduler which have not been fetched for this long are dropped
+const LOCAL_RESULT_TTL: Duration = Duration::from_secs(600);
+
+/// Maximum number of results computed on the scheduler which are kept until fetched
+const MAX_LOCAL_RESULTS: usize = 1024;
+
+/// Results computed on the scheduler, such as those of `EXPLAIN`, which are kept until the
+/// client fetches them. Results of clients which never fetch them expire after
+/// [`LOCAL_RESULT_TTL`], and the oldest results are dropped to keep at most
+/// [`MAX_LOCAL_RESULTS`].
+#[derive(Default)]
+struct LocalResults {
+    results: DashMap<String, (RecordBatch, Instant)>,
+}
+
+impl LocalResults {
+    fn insert(&self, result_id: String, data: RecordBatch, now: Instant) {
+        self.sweep(now);
+        while self.results.len() >= MAX_LOCAL_RESULTS {
+            let oldest = self
+                .results
+                .iter()
+                .min_by_key(|entry| entry.value().1)
+                .map(|entry| entry.key().clone());
+            match oldest {
+                Some(result_id) => self.results.remove(&result_id),
+                None => break,
+            };
+        }
+        self.results.insert(result_id, (data, now));
+    }
+
+    fn remove(&self, result_id: &str) -> Option<RecordBatch> {
+        self.results.remove(result_id).map(|(_, (data, _))| data)
+    }
+
+    /// Drop the results which have expired
+    fn sweep(&self, now: Instant) {
+        self.results
+            .retain(|_, (_, created)| now.saturating_duration_since(*created) < LOCAL_RESULT_TTL);
+    }
 }
 
 const TABLE_TYPES: [&str; 2] = ["TABLE", "VIEW"];
 
+/// Type of the Flight `CancelFlightInfo` action, which our version of arrow-flight predates
+const CANCEL_FLIGHT_INFO: &str = "CancelFlightInfo";
+
+/// Body of the `CancelFlightInfo` action
+#[derive(Clone, PartialEq, prost::Message)]
+struct CancelFlightInfoRequest {
+    #[prost(message, optional, tag = "1")]
+    info: Option<FlightInfo>,
+}
+
+/// Result of cancelling a query, which arrow-flight does not export from its generated code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+enum CancelResult {
+    Unspecified = 0,
+    Cancelled = 1,
+    Cancelling = 2,
+    NotCancellable = 3,
+}
+
+/// Level of ANSI-92 SQL support reported in `GetSqlInfo`, which arrow-flight does not export
+/// from its generated code either
+const ANSI92_ENTRY_SQL: i32 = 0;
+
+/// Result of the `CancelFlightInfo` action, whose status has the values of [`CancelResult`]
+#[derive(Clone, PartialEq, prost::Message)]
+struct CancelFlightInfoResult {
+    #[prost(int32, tag = "1")]
+    status: i32,
+}
+
+/// Cancels the job when dropped before [`JobCancelGuard::disarm`] is called, so that a job
+/// whose client disconnected while waiting for it stops taking task slots
+struct JobCancelGuard {
+    server: SchedulerServer<LogicalPlanNode, PhysicalPlanNode>,
+    job_id: Option<String>,
+}
+
+impl JobCancelGuard {
+    fn new(server: SchedulerServer<LogicalPlanNode, PhysicalPlanNode>, job_id: &str) -> Self {
+        Self {
+            server,
+            job_id: Some(job_id.to_owned()),
+        }
+    }
+
+    fn disarm(mut self) {
+        self.job_id = None;
+    }
+}
+
+impl Drop for JobCancelGuard {
+    fn drop(&mut self) {
+        if let Some(job_id) = self.job_id.take() {
+            let server = self.server.clone();
+            tokio::spawn(async move {
+                match server.cancel_job(&job_id).await {
+                    Ok(true) => info!("Cancelled job {} after its client went away", job_id),
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to cancel job {}: {:?}", job_id, e),
+                }
+            });
+        }
+    }
+}
+
+/// Number the `?` placeholders of a query as `$1`, `$2`, ..., the only placeholders which
+/// DataFusion can bind values to. Question marks in literals, quoted identifiers and
+/// comments are left alone.
+fn number_placeholders(query: &str) -> String {
+    let mut numbered = String::with_capacity(query.len());
+    let mut num_placeholders = 0;
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        numbered.push(c);
+        match c {
+            '?' => {
+                numbered.pop();
+                num_placeholders += 1;
+                numbered.push_str(&format!("${num_placeholders}"));
+            }
+            '\'' | '"' => {
+                for quoted in chars.by_ref() {
+                    numbered.push(quoted);
+                    if quoted == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for commented in chars.by_ref() {
+                    numbered.push(commented);
+                    if commented == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                let mut prev = ' ';
+                for commented in chars.by_ref() {
+                    numbered.push(commented);
+                    if prev == '*' && commented == '/' {
+                        break;
+                    }
+                    prev = commented;
+                }
+            }
+            _ => {}
+        }
+    }
+    numbered
+}
+
+/// The ids of the jobs whose results are fetched by the endpoints of a flight
+fn flight_info_job_ids(info: &FlightInfo) -> Result<Vec<String>, Status> {
+    let mut job_ids = vec![];
+    for ticket in info.endpoint.iter().filter_map(|fiep| fiep.ticket.as_ref()) {
+        let message = arrow_flight::sql::Any::decode(ticket.ticket.clone())
+            .map_err(|e| Status::invalid_argument(format!("Error decoding ticket: {e}")))?;
+        let action: Option<protobuf::Action> = message
+            .unpack()
+            .map_err(|e| Status::invalid_argument(format!("Error decoding ticket: {e}")))?;
+        if let Some(protobuf::Action {
+            action_type: Some(FetchPartition(fp)),
+            ..
+        }) = action
+        {
+            if !job_ids.contains(&fp.job_id) {
+                job_ids.push(fp.job_id);
+            }
+        }
+    }
+    Ok(job_ids)
+}
+
 impl FlightSqlServiceImpl {
     pub fn new(server: SchedulerServer<LogicalPlanNode, PhysicalPlanNode>) -> Self {
-        Self {
+        let service = Self {
             server,
             statements: Default::default(),
-            contexts: Default::default(),
+            results: Default::default(),
+            sql_info: Arc::new(Self::sql_info().expect("valid sql info")),
+            xdbc_type_info: Arc::new(Self::xdbc_type_info().expect("valid type info")),
+        };
+        service.start_sweeper();
+        service
+    }
+
+    /// Periodically drop the prepared statements and local results which have expired,
+    /// until the service is dropped
+    fn start_sweeper(&self) {
+        let statements = Arc::downgrade(&self.statements);
+        let results = Arc::downgrade(&self.results);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let (Some(statements), Some(results)) = (statements.upgrade(), results.upgrade())
+                else {
+                    break;
+                };
+                let now = Instant::now();
+                statements.sweep(now);
+                results.sweep(now);
+            }
+        });
+    }
+
+    /// The catalogs of the session, or only the given one
+    fn catalogs(
+        ctx: &SessionContext,
+        catalog: Option<&str>,
+    ) -> Vec<(String, Arc<dyn CatalogProvider>)> {
+        ctx.catalog_names()
+            .into_iter()
+            .filter(|name| catalog.is_none_or(|catalog| catalog == name))
+            .filter_map(|name| ctx.catalog(&name).map(|provider| (name, provider)))
+            .collect()
+    }
+
+    /// The tables of the session as (catalog, schema, table), or only those in the given
+    /// catalog. Filters on the schema and table name patterns are applied by the builders
+    /// of the results.
+    async fn session_tables(
+        ctx: &SessionContext,
+        catalog: Option<&str>,
+    ) -> Vec<(String, String, String, Arc<dyn TableProvider>)> {
+        let mut tables = vec![];
+        for (catalog_name, catalog) in Self::catalogs(ctx, catalog) {
+            for schema_name in catalog.schema_names() {
+                let Some(schema) = catalog.schema(&schema_name) else {
+                    continue;
+                };
+                for table_name in schema.table_names() {
+                    if let Some(table) = schema.table(&table_name).await {
+                        tables.push((catalog_name.clone(), schema_name.clone(), table_name, table));
+                    }
+                }
+            }
         }
+        tables
     }
 
-    #[allow(deprecated)]
-    fn tables(&self, ctx: Arc<SessionContext>) -> Result<RecordBatch, ArrowError> {
-        let schema = Arc::new(Schema::new(vec![
+    fn catalogs_batch(
+        ctx: &SessionContext,
+        query: CommandGetCatalogs,
+    ) -> Result<RecordBatch, FlightError> {
+        let mut builder = query.into_builder();
+        for (catalog_name, _) in Self::catalogs(ctx, None) {
+            builder.append(catalog_name);
+        }
+        builder.build()
+    }
+
+    fn schemas_batch(
+        ctx: &SessionContext,
+        query: CommandGetDbSchemas,
+    ) -> Result<RecordBatch, FlightError> {
+        let catalog = query.catalog.clone();
+        let mut builder = query.into_builder();
+        for (catalog_name, catalog) in Self::catalogs(ctx, catalog.as_deref()) {
+            for schema_name in catalog.schema_names() {
+                builder.append(&catalog_name, schema_name);
+            }
+        }
+        builder.build()
+    }
+
+    async fn tables_batch(
+        ctx: &SessionContext,
+        query: CommandGetTables,
+    ) -> Result<RecordBatch, FlightError> {
+        let catalog = query.catalog.clone();
+        let mut builder = query.into_builder();
+        for (catalog_name, schema_name, table_name, table) in
+            Self::session_tables(ctx, catalog.as_deref()).await
+        {
+            let table_type = match table.table_type() {
+                TableType::View => "VIEW",
+                TableType::Base | TableType::Temporary => "TABLE",
+            };
+            builder.append(
+                catalog_name,
+                schema_name,
+                table_name,
+                table_type,
+                table.schema().as_ref(),
+            )?;
+        }
+        builder.build()
+    }
+
+    /// Information about the server and the SQL it supports, answered to `GetSqlInfo`
+    fn sql_info() -> Result<SqlInfoData, FlightError> {
+        let mut builder = SqlInfoDataBuilder::new();
+        builder.append(SqlInfo::FlightSqlServerName, "Ballista");
+        builder.append(SqlInfo::FlightSqlServerVersion, BALLISTA_VERSION);
+        builder.append(SqlInfo::FlightSqlServerReadOnly, false);
+        builder.append(SqlInfo::FlightSqlServerSql, true);
+        builder.append(SqlInfo::FlightSqlServerSubstrait, false);
+        builder.append(SqlInfo::FlightSqlServerTransaction, 0_i32);
+        builder.append(SqlInfo::FlightSqlServerCancel, true);
+        builder.append(SqlInfo::SqlDdlCatalog, false);
+        builder.append(SqlInfo::SqlDdlSchema, true);
+        builder.append(SqlInfo::SqlDdlTable, true);
+        builder.append(SqlInfo::SqlIdentifierQuoteChar, "\"");
+        builder.append(SqlInfo::SqlSchemaTerm, "schema");
+        builder.append(SqlInfo::SqlCatalogTerm, "catalog");
+        builder.append(
+            SqlInfo::SqlSupportedGrammar,
+            1_i32 << SupportedSqlGrammar::SqlMinimumGrammar as i32
+                | 1_i32 << SupportedSqlGrammar::SqlCoreGrammar as i32,
+        );
+        builder.append(SqlInfo::SqlAnsi92SupportedLevel, 1_i32 << ANSI92_ENTRY_SQL);
+        builder.build()
+    }
+
+    /// The SQL types which tables of the cluster can have, answered to `GetXdbcTypeInfo`
+    fn xdbc_type_info() -> Result<XdbcTypeInfoData, FlightError> {
+        let types = [
+            ("BOOLEAN", XdbcDataType::XdbcBit, None),
+            ("TINYINT", XdbcDataType::XdbcTinyint, Some(3)),
+            ("SMALLINT", XdbcDataType::XdbcSmallint, Some(5)),
+            ("INT", XdbcDataType::XdbcInteger, Some(10)),
+            ("BIGINT", XdbcDataType::XdbcBigint, Some(19)),
+            ("FLOAT", XdbcDataType::XdbcFloat, Some(24)),
+            ("DOUBLE", XdbcDataType::XdbcDouble, Some(53)),
+            ("DECIMAL", XdbcDataType::XdbcDecimal, Some(38)),
+            ("VARCHAR", XdbcDataType::XdbcVarchar, None),
+            ("BYTEA", XdbcDataType::XdbcBinary, None),
+            ("DATE", XdbcDataType::XdbcDate, None),
+            ("TIMESTAMP", XdbcDataType::XdbcTimestamp, None),
+        ];
+
+        let mut builder = XdbcTypeInfoDataBuilder::new();
+        for (type_name, data_type, column_size) in types {
+            let is_string = data_type == XdbcDataType::XdbcVarchar;
+            let is_numeric = column_size.is_some();
+            builder.append(XdbcTypeInfo {
+                type_name: type_name.to_owned(),
+                data_type,
+                column_size,
+                literal_prefix: is_string.then(|| "'".to_owned()),
+                literal_suffix: is_string.then(|| "'".to_owned()),
+                create_params: (data_type == XdbcDataType::XdbcDecimal)
+                    .then(|| vec!["precision".to_owned(), "scale".to_owned()]),
+                nullable: Nullable::NullabilityNullable,
+                case_sensitive: is_string,
+                searchable: Searchable::Full,
+                unsigned_attribute: is_numeric.then_some(false),
+                fixed_prec_scale: false,
+                auto_increment: is_numeric.then_some(false),
+                local_type_name: Some(type_name.to_owned()),
+                minimum_scale: None,
+                maximum_scale: None,
+                sql_data_type: data_type,
+                datetime_subcode: None,
+                num_prec_radix: is_numeric.then_some(10),
+                interval_precision: None,
+            });
+        }
+        builder.build()
+    }
+
+    /// Primary keys of tables, which are not tracked in our catalogs
+    fn primary_keys_batch() -> RecordBatch {
+        let schema = Schema::new(vec![
             Field::new("catalog_name", DataType::Utf8, true),
             Field::new("db_schema_name", DataType::Utf8, true),
             Field::new("table_name", DataType::Utf8, false),
-            Field::new("table_type", DataType::Utf8, false),
-        ]));
-        let tables = ctx.tables()?; // resolved in #501
-        let names: Vec<_> = tables.iter().map(|it| Some(it.as_str())).collect();
-        let types: Vec<_> = names.iter().map(|_| Some("TABLE")).collect();
-        let cats: Vec<_> = names.iter().map(|_| None).collect();
-        let schemas: Vec<_> = names.iter().map(|_| None).collect();
-        let rb = RecordBatch::try_new(
-            schema,
-            [cats, schemas, names, types]
-                .iter()
-                .map(|i| Arc::new(StringArray::from(i.clone())) as ArrayRef)
-                .collect::<Vec<_>>(),
-        )?;
-        Ok(rb)
+            Field::new("column_name", DataType::Utf8, false),
+            Field::new("key_name", DataType::Utf8, true),
+            Field::new("key_sequence", DataType::Int32, false),
+        ]);
+        RecordBatch::new_empty(Arc::new(schema))
+    }
+
+    /// Foreign keys between tables, which are not tracked in our catalogs
+    fn cross_reference_batch() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("pk_catalog_name", DataType::Utf8, true),
+            Field::new("pk_db_schema_name", DataType::Utf8, true),
+            Field::new("pk_table_name", DataType::Utf8, false),
+            Field::new("pk_column_name", DataType::Utf8, false),
+            Field::new("fk_catalog_name", DataType::Utf8, true),
+            Field::new("fk_db_schema_name", DataType::Utf8, true),
+            Field::new("fk_table_name", DataType::Utf8, false),
+            Field::new("fk_column_name", DataType::Utf8, false),
+            Field::new("key_sequence", DataType::Int32, false),
+            Field::new("fk_key_name", DataType::Utf8, true),
+            Field::new("pk_key_name", DataType::Utf8, true),
+            Field::new("update_rule", DataType::UInt8, false),
+            Field::new("delete_rule", DataType::UInt8, false),
+        ]);
+        RecordBatch::new_empty(Arc::new(schema))
     }
 
     fn table_types() -> Result<RecordBatch, ArrowError> {
@@ -123,11 +604,115 @@ impl FlightSqlServiceImpl {
         )
     }
 
-    async fn create_ctx(&self) -> Result<Uuid, Status> {
-        let config_builder = BallistaConfig::builder();
-        let config = config_builder
+    /// Plan the query into Ballista stages without running it, and render every
+    /// stage as a row of an `EXPLAIN` result set.
+    async fn explain_stages(
+        &self,
+        ctx: &SessionContext,
+        explain: &Explain,
+    ) -> Result<RecordBatch, Status> {
+        let state = ctx.state();
+        let logical_plan = state
+            .optimize(&explain.plan)
+            .map_err(|e| Status::internal(format!("Error optimizing plan: {e}")))?;
+        let physical_plan = state
+            .create_physical_plan(&logical_plan)
+            .await
+            .map_err(|e| Status::internal(format!("Error creating physical plan: {e}")))?;
+        let job_id = self.server.state.task_manager.generate_job_id();
+        let stages = DistributedPlanner::new()
+            .plan_query_stages(&job_id, physical_plan)
+            .map_err(|e| Status::internal(format!("Error planning query stages: {e}")))?;
+
+        let mut plan_types = vec!["logical_plan".to_string()];
+        let mut plans = vec![logical_plan.display_indent().to_string()];
+        for (plan_type, plan) in display_query_stages(&stages) {
+            plan_types.push(plan_type);
+            plans.push(plan);
+        }
+        Self::explain_batch(plan_types, plans)
+            .map_err(|e| Status::internal(format!("Error building explain result: {e}")))
+    }
+
+    fn explain_batch(
+        plan_types: Vec<String>,
+        plans: Vec<String>,
+    ) -> Result<RecordBatch, ArrowError> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("plan_type", DataType::Utf8, false),
+            Field::new("plan", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(plan_types)) as ArrayRef,
+                Arc::new(StringArray::from(plans)) as ArrayRef,
+            ],
+        )
+    }
+
+    /// Answer a plan with a result computed on the scheduler instead of returning
+    /// the job output, if it is one we handle here (`EXPLAIN` and `EXPLAIN ANALYZE`).
+    async fn execute_local_plan(
+        &self,
+        ctx: &Arc<SessionContext>,
+        plan: &LogicalPlan,
+        sql: &str,
+    ) -> Result<Option<Response<FlightInfo>>, Status> {
+        let data = match plan {
+            LogicalPlan::Explain(explain) => self.explain_stages(ctx, explain).await?,
+            LogicalPlan::Analyze(analyze) => self.analyze_stages(ctx.clone(), analyze, sql).await?,
+            _ => return Ok(None),
+        };
+        Ok(Some(self.local_result_resp(data)?))
+    }
+
+    /// Keep a result computed on the scheduler until the client fetches it
+    fn local_result_resp(&self, data: RecordBatch) -> Result<Response<FlightInfo>, Status> {
+        let result_id = self.server.state.task_manager.generate_job_id();
+        let resp = self.batch_to_schema_resp(&data, &result_id)?;
+        self.results.insert(result_id, data, Instant::now());
+        Ok(resp)
+    }
+
+    /// Cancel the jobs of a flight which are still queued or running
+    async fn cancel_flight(&self, info: &FlightInfo) -> Result<CancelResult, Status> {
+        let mut result = CancelResult::NotCancellable;
+        for job_id in flight_info_job_ids(info)? {
+            let cancelled =
+                self.server.cancel_job(&job_id).await.map_err(|e| {
+                    Status::internal(format!("Error cancelling job {job_id}: {e:?}"))
+                })?;
+            if cancelled {
+                // Running tasks are cancelled on the executors asynchronously
+                result = CancelResult::Cancelling;
+            }
+        }
+        Ok(result)
+    }
+
+    /// The settings of a new session of the user, with the Ballista settings sent by the
+    /// client as `ballista.*` headers of the handshake. The tenant is always the
+    /// authenticated user, clients can't account their jobs to another tenant.
+    fn session_config(user: &str, metadata: &MetadataMap) -> Result<BallistaConfig, Status> {
+        let mut config_builder = BallistaConfig::builder();
+        for (key, value) in metadata.iter().filter_map(|kv| match kv {
+            KeyAndValueRef::Ascii(key, value) => Some((key.as_str(), value.to_str().ok()?)),
+            KeyAndValueRef::Binary(_, _) => None,
+        }) {
+            if key.starts_with("ballista.") && key != BALLISTA_TENANT {
+                config_builder = config_builder.set(key, value);
+            }
+        }
+        config_builder
+            .set(BALLISTA_TENANT, user)
             .build()
-            .map_err(|e| Status::internal(format!("Error building config: {e}")))?;
+            .map_err(|e| Status::invalid_argument(format!("Error building config: {e}")))
+    }
+
+    /// Create a session for the user
+    async fn create_ctx(&self, user: &str, metadata: &MetadataMap) -> Result<Uuid, Status> {
+        let config = Self::session_config(user, metadata)?;
         let ctx = self
             .server
             .state
@@ -135,9 +720,7 @@ impl FlightSqlServiceImpl {
             .create_session(&config)
             .await
             .map_err(|e| Status::internal(format!("Failed to create SessionContext: {e:?}")))?;
-        let handle = Uuid::new_v4();
-        self.contexts.insert(handle, ctx);
-        Ok(handle)
+        Ok(self.server.state.session_manager.create_token(ctx))
     }
 
     fn get_ctx<T>(&self, req: &Request<T>) -> Result<Arc<SessionContext>, Status> {
@@ -145,37 +728,208 @@ impl FlightSqlServiceImpl {
             .metadata()
             .get("authorization")
             .ok_or_else(|| Status::internal("No authorization header!"))?;
-        let str = auth
+        let authorization = auth
             .to_str()
             .map_err(|e| Status::internal(format!("Error parsing header: {e}")))?;
-        let authorization = str.to_string();
-        let bearer = "Bearer ";
-        if !authorization.starts_with(bearer) {
-            Err(Status::internal("Invalid auth header!"))?;
-        }
-        let auth = authorization[bearer.len()..].to_string();
 
-        let handle = Uuid::from_str(auth.as_str())
-            .map_err(|e| Status::internal(format!("Error locking contexts: {e}")))?;
-        if let Some(context) = self.contexts.get(&handle) {
-            Ok(context.clone())
+        self.server
+            .state
+            .session_manager
+            .get_token_session(authorization)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+
+    /// Check that the job was submitted by the user of the session, clients can only
+    /// fetch the results of their own jobs
+    async fn check_job_owner(&self, job_id: &str, ctx: &SessionContext) -> Result<(), Status> {
+        let denied =
+            || Status::permission_denied(format!("Job {job_id} was not submitted by the user"));
+        let graph = self
+            .server
+            .state
+            .task_manager
+            .get_job_execution_graph(job_id)
+            .await
+            .map_err(|e| Status::internal(format!("Error getting job {job_id}: {e:?}")))?
+            .ok_or_else(denied)?;
+        let owner = self
+            .server
+            .state
+            .session_manager
+            .get_session_tenant(graph.session_id())
+            .await
+            .map_err(|_| denied())?;
+        if owner == session_tenant(ctx) {
+            Ok(())
         } else {
-            Err(Status::internal(format!(
-                "Context handle not found: {handle}"
-            )))?
+            Err(denied())
         }
     }
 
     async fn prepare_statement(
+        &self,
         query: &str,
         ctx: &Arc<SessionContext>,
     ) -> Result<LogicalPlan, Status> {
-        let plan = ctx
-            .sql(query)
+        self.server
+            .plan_sql(ctx, query)
             .await
-            .and_then(|df| df.into_optimized_plan())
-            .map_err(|e| Status::internal(format!("Error building plan: {e}")))?;
-        Ok(plan)
+            .map_err(|e| Status::internal(format!("Error building plan: {e}")))
+    }
+
+    /// Pick up the changes other sessions made to the shared catalog
+    async fn refresh_catalog(&self, ctx: &SessionContext) -> Result<(), Status> {
+        self.server
+            .state
+            .session_manager
+            .refresh_catalog(ctx)
+            .await
+            .map_err(|e| Status::internal(format!("Error refreshing catalog: {e}")))
+    }
+
+    /// Plan a prepared statement without applying it yet if it changes the catalog. The
+    /// plan is optimized once its parameters are bound.
+    async fn plan_statement(query: &str, ctx: &SessionContext) -> Result<LogicalPlan, Status> {
+        ctx.state()
+            .create_logical_plan(&number_placeholders(query))
+            .await
+            .map_err(|e| Status::internal(format!("Error building plan: {e}")))
+    }
+
+    /// The schema of the parameters of a plan, with a field for each of `$1`, `$2`, ...
+    fn parameter_schema(plan: &LogicalPlan) -> Result<Schema, Status> {
+        let mut params: Vec<_> = plan
+            .get_parameter_types()
+            .map_err(|e| Status::internal(format!("Error getting parameter types: {e}")))?
+            .into_iter()
+            .collect();
+        params.sort_by_key(|(id, _)| {
+            id.trim_start_matches('$')
+                .parse::<usize>()
+                .unwrap_or(usize::MAX)
+        });
+        let fields: Vec<_> = params
+            .into_iter()
+            .map(|(id, data_type)| Field::new(id, data_type.unwrap_or(DataType::Null), true))
+            .collect();
+        Ok(Schema::new(fields))
+    }
+
+    /// The parameter values sent by the client as a batch with a single row
+    async fn decode_params(stream: PeekableFlightDataStream) -> Result<Vec<ScalarValue>, Status> {
+        let batches: Vec<RecordBatch> =
+            FlightRecordBatchStream::new_from_flight_data(stream.map_err(FlightError::from))
+                .try_collect()
+                .await
+                .map_err(|e| Status::invalid_argument(format!("Error decoding parameters: {e}")))?;
+        let batch = match batches.iter().find(|batch| batch.num_rows() > 0) {
+            Some(batch) if batch.num_rows() == 1 => batch,
+            Some(_) => Err(Status::invalid_argument(
+                "Expected a single row of parameter values",
+            ))?,
+            None => return Ok(vec![]),
+        };
+        batch
+            .columns()
+            .iter()
+            .map(|column| ScalarValue::try_from_array(column, 0))
+            .collect::<datafusion::error::Result<_>>()
+            .map_err(|e| Status::invalid_argument(format!("Error decoding parameters: {e}")))
+    }
+
+    /// Apply a statement which changes the catalog or data and return the number of rows it
+    /// affected. DDL is applied to the catalog of the session and to the shared catalog,
+    /// while inserts and copies run as jobs on the cluster.
+    async fn execute_update(
+        &self,
+        ctx: Arc<SessionContext>,
+        plan: &LogicalPlan,
+        sql: &str,
+    ) -> Result<i64, Status> {
+        match plan {
+            LogicalPlan::Ddl(_) | LogicalPlan::Statement(_) => {
+                self.refresh_catalog(&ctx).await?;
+                self.server
+                    .state
+                    .session_manager
+                    .execute_logical_plan(&ctx, plan.clone())
+                    .await
+                    .map_err(|e| Status::internal(format!("Error executing statement: {e}")))?;
+                Ok(0)
+            }
+            LogicalPlan::Dml(DmlStatement {
+                op: WriteOp::InsertInto | WriteOp::InsertOverwrite,
+                ..
+            })
+            | LogicalPlan::Copy(_) => {
+                let physical_plan = ctx
+                    .state()
+                    .create_physical_plan(plan)
+                    .await
+                    .map_err(|e| Status::internal(format!("Error planning statement: {e}")))?;
+                Self::check_sinks(&physical_plan)?;
+                let timeout = Self::job_timeout(&ctx);
+                let job_id = self.enqueue_job(ctx, plan, sql).await?;
+                let guard = JobCancelGuard::new(self.server.clone(), &job_id);
+                let completed = self.wait_for_job(&job_id, timeout).await?;
+                guard.disarm();
+                Self::count_affected_rows(completed).await
+            }
+            LogicalPlan::Dml(dml) => Err(Status::unimplemented(format!(
+                "{} is not supported",
+                dml.op
+            ))),
+            _ => Err(Status::invalid_argument(
+                "Statement does not update data, it has to be run as a query",
+            )),
+        }
+    }
+
+    /// Check that the data sinks of the plan can be sent to the executors, only JSON files
+    /// can be serialized so far. Tables held in memory would be written on the executors.
+    fn check_sinks(plan: &Arc<dyn ExecutionPlan>) -> Result<(), Status> {
+        if let Some(exec) = plan.as_any().downcast_ref::<FileSinkExec>() {
+            if !exec.sink().as_any().is::<JsonSink>() {
+                return Err(Status::unimplemented(format!(
+                    "Writing to {} is not supported, only JSON files can be written",
+                    DisplayableExecutionPlan::new(plan.as_ref()).one_line()
+                )));
+            }
+        }
+        plan.children().iter().try_for_each(Self::check_sinks)
+    }
+
+    /// Sum the `count` column of the output of an insert or copy job
+    async fn count_affected_rows(completed: SuccessfulJob) -> Result<i64, Status> {
+        let mut count = 0;
+        for location in completed.partition_location {
+            let stream = fetch_output_partition(location)
+                .await
+                .map_err(|e| Status::internal(format!("Error fetching job output: {e:?}")))?;
+            count += Self::sum_affected_rows(stream).await?;
+        }
+        Ok(count)
+    }
+
+    /// Sum the `count` column of the batches of an output partition of an insert or copy job
+    async fn sum_affected_rows(mut stream: SendableRecordBatchStream) -> Result<i64, Status> {
+        let mut count = 0;
+        while let Some(batch) = stream.next().await {
+            let batch =
+                batch.map_err(|e| Status::internal(format!("Error fetching job output: {e}")))?;
+            let counts = batch
+                .columns()
+                .first()
+                .and_then(|column| column.as_any().downcast_ref::<UInt64Array>())
+                .ok_or_else(|| {
+                    Status::internal(format!(
+                        "Expected a UInt64 count of affected rows, got {}",
+                        batch.schema()
+                    ))
+                })?;
+            count += sum(counts).unwrap_or_default();
+        }
+        Ok(count as i64)
     }
 
     async fn check_job(&self, job_id: &String) -> Result<Option<SuccessfulJob>, Status> {
@@ -192,11 +946,8 @@ impl FlightSqlServiceImpl {
             })?;
         let status: JobStatus = match status {
             Some(status) => status,
-            None => {
-                let msg = format!("Error getting status for job {job_id}!");
-                error!("{}", msg);
-                Err(Status::internal(msg))?
-            }
+            // The job has no status until the scheduler has handled its submission
+            None => return Ok(None),
         };
         let status: job_status::Status = match status.status {
             Some(status) => status,
@@ -220,12 +971,38 @@ impl FlightSqlServiceImpl {
         }
     }
 
+    /// Whether the client of the session fetches results straight from the executors
+    fn direct_results(ctx: &SessionContext) -> bool {
+        ctx.copied_config()
+            .get_extension::<BallistaConfig>()
+            .map(|config| config.flight_sql_direct_results())
+            .unwrap_or_default()
+    }
+
+    /// How long requests of the session wait for their jobs
+    fn job_timeout(ctx: &SessionContext) -> Duration {
+        match ctx.copied_config().get_extension::<BallistaConfig>() {
+            Some(config) => config.flight_sql_job_timeout(),
+            None => BallistaConfig::new()
+                .expect("valid default config")
+                .flight_sql_job_timeout(),
+        }
+    }
+
+    /// Endpoints fetching the output partitions of a job. By default the tickets are
+    /// redeemed on the scheduler, which proxies the data from the executors. With
+    /// `direct_results` they point at the executors first, falling back to the scheduler
+    /// for clients which can't reach the executors.
     async fn job_to_fetch_part(
         &self,
         completed: SuccessfulJob,
+        direct_results: bool,
         num_rows: &mut i64,
         num_bytes: &mut i64,
     ) -> Result<Vec<FlightEndpoint>, Status> {
+        let scheduler_loc = Location {
+            uri: format!("grpc+tcp://{}", self.server.state.config.scheduler_name()),
+        };
         let mut fieps: Vec<_> = vec![];
         for loc in completed.partition_location.iter() {
             let (exec_host, exec_port) = if let Some(ref md) = loc.executor_meta {
@@ -259,15 +1036,21 @@ impl FlightSqlServiceImpl {
             } else {
                 Err(Status::internal("Error getting stats".to_string()))?
             }
-            let authority = format!("{}:{}", &exec_host, &exec_port);
-            let loc = Location {
-                uri: format!("grpc+tcp://{authority}"),
+            let location = if direct_results {
+                let authority = format!("{}:{}", &exec_host, &exec_port);
+                let loc = Location {
+                    uri: format!("grpc+tcp://{authority}"),
+                };
+                vec![loc, scheduler_loc.clone()]
+            } else {
+                // No location means the ticket is redeemed on this service
+                vec![]
             };
             let buf = fetch.as_any().encode_to_vec();
             let ticket = Ticket { ticket: buf.into() };
             let fiep = FlightEndpoint {
                 ticket: Some(ticket),
-                location: vec![loc],
+                location,
             };
             fieps.push(fiep);
         }
@@ -302,27 +1085,35 @@ impl FlightSqlServiceImpl {
         Ok(fieps)
     }
 
-    fn cache_plan(&self, plan: LogicalPlan) -> Result<Uuid, Status> {
-        let handle = Uuid::new_v4();
-        self.statements.insert(handle, plan);
-        Ok(handle)
+    fn cache_plan(&self, ctx: &SessionContext, sql: &str, plan: LogicalPlan) -> Uuid {
+        self.statements
+            .insert(&ctx.session_id(), sql, plan, Instant::now())
     }
 
-    fn get_plan(&self, handle: &Uuid) -> Result<LogicalPlan, Status> {
-        if let Some(plan) = self.statements.get(handle) {
-            Ok(plan.clone())
-        } else {
-            Err(Status::internal(format!(
-                "Statement handle not found: {handle}"
-            )))?
-        }
+    fn get_plan(&self, ctx: &SessionContext, handle: &Uuid) -> Result<PreparedStatement, Status> {
+        let statement = self
+            .statements
+            .get_mut(&ctx.session_id(), handle, Instant::now())?;
+        Ok(statement.clone())
     }
 
-    fn remove_plan(&self, handle: Uuid) -> Result<(), Status> {
-        self.statements.remove(&handle);
+    fn bind_params(
+        &self,
+        ctx: &SessionContext,
+        handle: &Uuid,
+        params: Vec<ScalarValue>,
+    ) -> Result<(), Status> {
+        let mut statement = self
+            .statements
+            .get_mut(&ctx.session_id(), handle, Instant::now())?;
+        statement.params = params;
         Ok(())
     }
 
+    fn remove_plan(&self, ctx: &SessionContext, handle: Uuid) -> Result<(), Status> {
+        self.statements.remove(&ctx.session_id(), &handle)
+    }
+
     fn df_schema_to_arrow(&self, schema: &DFSchemaRef) -> Result<Vec<u8>, Status> {
         let arrow_schema: Schema = (&**schema).into();
         let schema_bytes = self.schema_to_arrow(Arc::new(arrow_schema))?;
@@ -344,17 +1135,12 @@ impl FlightSqlServiceImpl {
         &self,
         ctx: Arc<SessionContext>,
         plan: &LogicalPlan,
+        sql: &str,
     ) -> Result<String, Status> {
-        let job_id = self.server.state.task_manager.generate_job_id();
         self.server
-            .submit_job(&job_id, ctx, plan)
+            .submit_sql_job(ctx, plan, sql)
             .await
-            .map_err(|e| {
-                let msg = format!("Failed to send JobQueued event for {job_id}: {e:?}");
-                error!("{}", msg);
-                Status::internal(msg)
-            })?;
-        Ok(job_id)
+            .map_err(|e| Status::internal(e.to_string()))
     }
 
     fn create_resp(
@@ -374,7 +1160,8 @@ impl FlightSqlServiceImpl {
             endpoint: fieps,
             total_records: num_rows,
             total_bytes: num_bytes,
-            ordered: false,
+            // the endpoints follow the order of the output partitions of the job
+            ordered: true,
         };
         Response::new(info)
     }
@@ -383,24 +1170,20 @@ impl FlightSqlServiceImpl {
         &self,
         ctx: Arc<SessionContext>,
         plan: &LogicalPlan,
+        sql: &str,
     ) -> Result<Response<FlightInfo>, Status> {
-        let job_id = self.enqueue_job(ctx, plan).await?;
+        let direct_results = Self::direct_results(&ctx);
+        let timeout = Self::job_timeout(&ctx);
+        let job_id = self.enqueue_job(ctx, plan, sql).await?;
+        let guard = JobCancelGuard::new(self.server.clone(), &job_id);
+        let completed = self.wait_for_job(&job_id, timeout).await?;
+        guard.disarm();
 
-        // poll for job completion
         let mut num_rows = 0;
         let mut num_bytes = 0;
-        let fieps = loop {
-            sleep(Duration::from_millis(100)).await;
-            let completed = if let Some(comp) = self.check_job(&job_id).await? {
-                comp
-            } else {
-                continue;
-            };
-            let fieps = self
-                .job_to_fetch_part(completed, &mut num_rows, &mut num_bytes)
-                .await?;
-            break fieps;
-        };
+        let fieps = self
+            .job_to_fetch_part(completed, direct_results, &mut num_rows, &mut num_bytes)
+            .await?;
 
         // Generate response
         let schema_bytes = self.df_schema_to_arrow(plan.schema())?;
@@ -408,6 +1191,69 @@ impl FlightSqlServiceImpl {
         Ok(resp)
     }
 
+    /// Wait for the job to finish, checking its status again whenever it is done, and fail
+    /// with `DEADLINE_EXCEEDED` if it takes longer than the timeout
+    async fn wait_for_job(
+        &self,
+        job_id: &String,
+        timeout: Duration,
+    ) -> Result<SuccessfulJob, Status> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Subscribe before checking the status so that no event is missed in between
+        let mut events = self.server.state.job_events.subscribe();
+        loop {
+            if let Some(completed) = self.check_job(job_id).await? {
+                return Ok(completed);
+            }
+            loop {
+                match tokio::time::timeout_at(deadline, events.recv()).await {
+                    Err(_) => {
+                        return Err(Status::deadline_exceeded(format!(
+                            "Job {job_id} did not finish within {timeout:?}"
+                        )))
+                    }
+                    Ok(Ok(event)) if event.job_id() == job_id && event.is_terminal() => break,
+                    Ok(Ok(_)) => {}
+                    // Missed events may include the one finishing the job
+                    Ok(Err(RecvError::Lagged(_))) => break,
+                    Ok(Err(RecvError::Closed)) => {
+                        return Err(Status::unavailable("Scheduler is shutting down"))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the query as a Ballista job, wait for it to finish and render every stage
+    /// with the metrics collected from its tasks as a row of an `EXPLAIN ANALYZE` result set.
+    async fn analyze_stages(
+        &self,
+        ctx: Arc<SessionContext>,
+        analyze: &Analyze,
+        sql: &str,
+    ) -> Result<RecordBatch, Status> {
+        let timeout = Self::job_timeout(&ctx);
+        let job_id = self.enqueue_job(ctx, &analyze.input, sql).await?;
+        let guard = JobCancelGuard::new(self.server.clone(), &job_id);
+        self.wait_for_job(&job_id, timeout).await?;
+        guard.disarm();
+
+        let graph = self
+            .server
+            .state
+            .task_manager
+            .get_job_execution_graph(&job_id)
+            .await
+            .map_err(|e| Status::internal(format!("Error getting job {job_id}: {e:?}")))?
+            .ok_or_else(|| {
+                Status::internal(format!("Execution graph for job {job_id} not found"))
+            })?;
+
+        let (plan_types, plans) = graph.analyze_stages().into_iter().unzip();
+        Self::explain_batch(plan_types, plans)
+            .map_err(|e| Status::internal(format!("Error building explain result: {e}")))
+    }
+
     async fn record_batch_to_resp(
         rb: RecordBatch,
     ) -> Result<Response<Pin<Box<dyn Stream<Item = Result<FlightData, Status>> + Send>>>, Status>
@@ -459,34 +1305,21 @@ impl FlightSqlService for FlightSqlServiceImpl {
             debug!("{:?}", md);
         }
 
-        let basic = "Basic ";
         let authorization = request
             .metadata()
             .get("authorization")
             .ok_or_else(|| Status::invalid_argument("authorization field not present"))?
             .to_str()
             .map_err(|_| Status::invalid_argument("authorization not parsable"))?;
-        if !authorization.starts_with(basic) {
-            Err(Status::invalid_argument(format!(
-                "Auth type not implemented: {authorization}"
-            )))?;
-        }
-        let bytes = base64::engine::general_purpose::STANDARD
-            .decode(&authorization[basic.len()..])
-            .map_err(|_| Status::invalid_argument("authorization not parsable"))?;
-        let str = String::from_utf8(bytes)
-            .map_err(|_| Status::invalid_argument("authorization not parsable"))?;
-        let parts: Vec<_> = str.split(':').collect();
-        if parts.len() != 2 {
-            Err(Status::invalid_argument("Invalid authorization header"))?;
-        }
-        let user = parts[0];
-        let pass = parts[1];
-        if user != "admin" || pass != "password" {
-            Err(Status::unauthenticated("Invalid credentials!"))?
-        }
+        let user = self
+            .server
+            .state
+            .session_manager
+            .authenticate_basic(authorization)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?
+            .ok_or_else(|| Status::unauthenticated("Invalid credentials!"))?;
 
-        let token = self.create_ctx().await?;
+        let token = self.create_ctx(&user, request.metadata()).await?;
 
         let result = HandshakeResponse {
             protocol_version: 0,
@@ -509,6 +1342,7 @@ impl FlightSqlService for FlightSqlServiceImpl {
         message: arrow_flight::sql::Any,
     ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
         debug!("do_get_fallback type_url: {}", message.type_url);
+        // Only clients with a session can fetch results
         let ctx = self.get_ctx(&request)?;
         if !message.is::<protobuf::Action>() {
             Err(Status::unimplemented(format!(
@@ -535,17 +1369,17 @@ impl FlightSqlService for FlightSqlServiceImpl {
                 let resp = Self::record_batch_to_resp(rb).await?;
                 return Ok(resp);
             }
-            "get_flight_info_tables" => {
-                debug!("Responding with tables");
-                let rb = self
-                    .tables(ctx)
-                    .map_err(|_| Status::internal("Error getting tables".to_string()))?;
-                let resp = Self::record_batch_to_resp(rb).await?;
-                return Ok(resp);
+            job_id => {
+                if let Some(rb) = self.results.remove(job_id) {
+                    debug!("Responding with local result {}", job_id);
+                    let resp = Self::record_batch_to_resp(rb).await?;
+                    return Ok(resp);
+                }
             }
-            _ => {}
         }
 
+        self.check_job_owner(&fp.job_id, &ctx).await?;
+
         // Proxy the flight
         let addr = format!("http://{}:{}", fp.host, fp.port);
         debug!("Scheduler proxying flight for to {}", addr);
@@ -558,7 +1392,15 @@ impl FlightSqlService for FlightSqlServiceImpl {
             })?;
         let mut flight_client = FlightServiceClient::new(connection);
         let buf = action.encode_to_vec();
-        let request = Request::new(Ticket { ticket: buf.into() });
+        let mut request = Request::new(Ticket { ticket: buf.into() });
+        // The executors check the credentials of the scheduler rather than of the client
+        if let Some(authorization) = client_authorization() {
+            let authorization = MetadataValue::try_from(authorization)
+                .map_err(|_| Status::internal("Invalid scheduler credentials"))?;
+            request
+                .metadata_mut()
+                .insert("authorization", authorization);
+        }
 
         let stream = flight_client
             .do_get(request)
@@ -576,8 +1418,11 @@ impl FlightSqlService for FlightSqlServiceImpl {
         debug!("get_flight_info_statement query:\n{}", query.query);
 
         let ctx = self.get_ctx(&request)?;
-        let plan = Self::prepare_statement(&query.query, &ctx).await?;
-        let resp = self.execute_plan(ctx, &plan).await?;
+        let plan = self.prepare_statement(&query.query, &ctx).await?;
+        if let Some(resp) = self.execute_local_plan(&ctx, &plan, &query.query).await? {
+            return Ok(resp);
+        }
+        let resp = self.execute_plan(ctx, &plan, &query.query).await?;
 
         debug!("Returning flight info...");
         Ok(resp)
@@ -592,25 +1437,96 @@ impl FlightSqlService for FlightSqlServiceImpl {
         let ctx = self.get_ctx(&request)?;
         let handle = Uuid::from_slice(handle.prepared_statement_handle.as_ref())
             .map_err(|e| Status::internal(format!("Error decoding handle: {e}")))?;
-        let plan = self.get_plan(&handle)?;
-        let resp = self.execute_plan(ctx, &plan).await?;
+        let statement = self.get_plan(&ctx, &handle)?;
+        let plan = statement.bound_plan()?;
+        if let Some(resp) = self.execute_local_plan(&ctx, &plan, &statement.sql).await? {
+            return Ok(resp);
+        }
+        let resp = self.execute_plan(ctx, &plan, &statement.sql).await?;
 
         debug!("Responding to query {}...", handle);
         Ok(resp)
     }
 
+    async fn get_flight_info_catalogs(
+        &self,
+        query: CommandGetCatalogs,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        debug!("get_flight_info_catalogs");
+        let ctx = self.get_ctx(&request)?;
+        let data = Self::catalogs_batch(&ctx, query)
+            .map_err(|e| Status::internal(format!("Error getting catalogs: {e}")))?;
+        self.local_result_resp(data)
+    }
+
+    async fn get_flight_info_schemas(
+        &self,
+        query: CommandGetDbSchemas,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        debug!("get_flight_info_schemas");
+        let ctx = self.get_ctx(&request)?;
+        let data = Self::schemas_batch(&ctx, query)
+            .map_err(|e| Status::internal(format!("Error getting schemas: {e}")))?;
+        self.local_result_resp(data)
+    }
+
     async fn get_flight_info_tables(
         &self,
-        _query: CommandGetTables,
+        query: CommandGetTables,
         request: Request<FlightDescriptor>,
     ) -> Result<Response<FlightInfo>, Status> {
         debug!("get_flight_info_tables");
         let ctx = self.get_ctx(&request)?;
-        let data = self
-            .tables(ctx)
+        let data = Self::tables_batch(&ctx, query)
+            .await
             .map_err(|e| Status::internal(format!("Error getting tables: {e}")))?;
-        let resp = self.batch_to_schema_resp(&data, "get_flight_info_tables")?;
-        Ok(resp)
+        self.local_result_resp(data)
+    }
+
+    async fn get_flight_info_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        debug!("get_flight_info_sql_info");
+        let data = query
+            .into_builder(&self.sql_info)
+            .build()
+            .map_err(|e| Status::internal(format!("Error getting sql info: {e}")))?;
+        self.local_result_resp(data)
+    }
+
+    async fn get_flight_info_xdbc_type_info(
+        &self,
+        query: CommandGetXdbcTypeInfo,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        debug!("get_flight_info_xdbc_type_info");
+        let data = query
+            .into_builder(&self.xdbc_type_info)
+            .build()
+            .map_err(|e| Status::internal(format!("Error getting type info: {e}")))?;
+        self.local_result_resp(data)
+    }
+
+    async fn get_flight_info_primary_keys(
+        &self,
+        _query: CommandGetPrimaryKeys,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        debug!("get_flight_info_primary_keys");
+        self.local_result_resp(Self::primary_keys_batch())
+    }
+
+    async fn get_flight_info_cross_reference(
+        &self,
+        _query: CommandGetCrossReference,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        debug!("get_flight_info_cross_reference");
+        self.local_result_resp(Self::cross_reference_batch())
     }
 
     async fn get_flight_info_table_types(
@@ -634,10 +1550,41 @@ impl FlightSqlService for FlightSqlServiceImpl {
         let ctx = self.get_ctx(&request)?;
         let handle = Uuid::from_slice(handle.prepared_statement_handle.as_ref())
             .map_err(|e| Status::internal(format!("Error decoding handle: {e}")))?;
-        let plan = self.get_plan(&handle)?;
-        let _ = self.execute_plan(ctx, &plan).await?;
-        debug!("Sending -1 rows affected");
-        Ok(-1)
+        let statement = self.get_plan(&ctx, &handle)?;
+        let rows = self
+            .execute_update(ctx, &statement.bound_plan()?, &statement.sql)
+            .await?;
+        debug!("Sending {} rows affected", rows);
+        Ok(rows)
+    }
+
+    async fn do_put_prepared_statement_query(
+        &self,
+        query: CommandPreparedStatementQuery,
+        request: Request<PeekableFlightDataStream>,
+    ) -> Result<Response<<Self as FlightService>::DoPutStream>, Status> {
+        debug!("do_put_prepared_statement_query");
+        let ctx = self.get_ctx(&request)?;
+        let handle = Uuid::from_slice(query.prepared_statement_handle.as_ref())
+            .map_err(|e| Status::internal(format!("Error decoding handle: {e}")))?;
+        let params = Self::decode_params(request.into_inner()).await?;
+        debug!("Binding {} parameters to {}", params.len(), handle);
+        self.bind_params(&ctx, &handle, params)?;
+        Ok(Response::new(Box::pin(futures::stream::empty())))
+    }
+
+    async fn do_put_statement_update(
+        &self,
+        ticket: CommandStatementUpdate,
+        request: Request<PeekableFlightDataStream>,
+    ) -> Result<i64, Status> {
+        debug!("do_put_statement_update query:\n{}", ticket.query);
+        let ctx = self.get_ctx(&request)?;
+        self.refresh_catalog(&ctx).await?;
+        let plan = Self::plan_statement(&ticket.query, &ctx).await?;
+        let rows = self.execute_update(ctx, &plan, &ticket.query).await?;
+        debug!("Sending {} rows affected", rows);
+        Ok(rows)
     }
 
     async fn do_action_create_prepared_statement(
@@ -647,14 +1594,21 @@ impl FlightSqlService for FlightSqlServiceImpl {
     ) -> Result<ActionCreatePreparedStatementResult, Status> {
         debug!("do_action_create_prepared_statement");
         let ctx = self.get_ctx(&request)?;
-        let plan = Self::prepare_statement(&query.query, &ctx).await?;
+        self.refresh_catalog(&ctx).await?;
+        let plan = Self::plan_statement(&query.query, &ctx).await?;
         let schema_bytes = self.df_schema_to_arrow(plan.schema())?;
-        let handle = self.cache_plan(plan)?;
+        let parameter_schema = Self::parameter_schema(&plan)?;
+        let parameter_schema_bytes = if parameter_schema.fields().is_empty() {
+            vec![]
+        } else {
+            self.schema_to_arrow(Arc::new(parameter_schema))?
+        };
+        let handle = self.cache_plan(&ctx, &query.query, plan);
         debug!("Prepared statement {}:\n{}", handle, query.query);
         let res = ActionCreatePreparedStatementResult {
             prepared_statement_handle: handle.as_bytes().to_vec().into(),
             dataset_schema: schema_bytes.into(),
-            parameter_schema: Vec::new().into(), // TODO: parameters
+            parameter_schema: parameter_schema_bytes.into(),
         };
         Ok(res)
     }
@@ -662,9 +1616,10 @@ impl FlightSqlService for FlightSqlServiceImpl {
     async fn do_action_close_prepared_statement(
         &self,
         handle: ActionClosePreparedStatementRequest,
-        _request: Request<Action>,
+        request: Request<Action>,
     ) -> Result<(), Status> {
         debug!("do_action_close_prepared_statement");
+        let ctx = self.get_ctx(&request)?;
         let handle = Uuid::from_slice(handle.prepared_statement_handle.as_ref())
             .map(|id| {
                 debug!("Closing {}", id);
@@ -672,9 +1627,599 @@ impl FlightSqlService for FlightSqlServiceImpl {
             })
             .map_err(|e| Status::internal(format!("Failed to parse handle: {e:?}")))?;
 
-        self.remove_plan(handle)
+        self.remove_plan(&ctx, handle)
+    }
+
+    async fn do_action_cancel_query(
+        &self,
+        query: ActionCancelQueryRequest,
+        _request: Request<Action>,
+    ) -> Result<ActionCancelQueryResult, Status> {
+        debug!("do_action_cancel_query");
+        let info = FlightInfo::decode(query.info)
+            .map_err(|e| Status::invalid_argument(format!("Error decoding FlightInfo: {e}")))?;
+        let result = self.cancel_flight(&info).await?;
+        Ok(ActionCancelQueryResult {
+            result: result.into(),
+        })
+    }
+
+    async fn do_action_fallback(
+        &self,
+        request: Request<Action>,
+    ) -> Result<Response<<Self as FlightService>::DoActionStream>, Status> {
+        let action = request.into_inner();
+        if action.r#type != CANCEL_FLIGHT_INFO {
+            Err(Status::invalid_argument(format!(
+                "do_action: The defined request is invalid: {:?}",
+                action.r#type
+            )))?
+        }
+
+        debug!("do_action_cancel_flight_info");
+        let info = CancelFlightInfoRequest::decode(action.body)
+            .map_err(|e| Status::invalid_argument(format!("Error decoding request: {e}")))?
+            .info
+            .ok_or_else(|| Status::invalid_argument("Expected a FlightInfo but got None!"))?;
+        let status = self.cancel_flight(&info).await?;
+        let result = arrow_flight::Result {
+            body: CancelFlightInfoResult {
+                status: status.into(),
+            }
+            .encode_to_vec()
+            .into(),
+        };
+        let output = futures::stream::iter(vec![Ok(result)]);
+        Ok(Response::new(Box::pin(output)))
     }
 
     /// Register a new SqlInfo result, making it available when calling GetSqlInfo.
     async fn register_sql_info(&self, _id: i32, _result: &SqlInfo) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SchedulerConfig;
+    use crate::state::executor_manager::ExecutorManager;
+    use crate::state::task_manager::TaskLauncher;
+    use crate::test_utils::SchedulerTest;
+    use arrow_flight::flight_service_server::FlightServiceServer;
+    use ballista_core::auth::{set_client_credentials, UserCredentials};
+    use ballista_core::error::BallistaError;
+    use ballista_core::execution_plans::ShuffleWriterExec;
+    use ballista_core::serde::protobuf::{
+        task_status, MultiTaskDefinition, SuccessfulTask, TaskStatus,
+    };
+    use ballista_core::serde::scheduler::from_proto::get_task_definition_vec;
+    use ballista_core::serde::scheduler::{ExecutorMetadata, ExecutorSpecification};
+    use ballista_core::serde::BallistaCodec;
+    use ballista_executor::flight_service::BallistaFlightService;
+    use datafusion::common::DFSchema;
+    use datafusion::execution::context::TaskContext;
+    use datafusion::execution::runtime_env::RuntimeEnv;
+    use datafusion::logical_expr::EmptyRelation;
+    use datafusion::test_util::scan_empty;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn fetch_endpoint(job_id: &str, partition_id: u32) -> FlightEndpoint {
+        let fetch = protobuf::Action {
+            action_type: Some(FetchPartition(protobuf::FetchPartition {
+                job_id: job_id.to_owned(),
+                stage_id: 2,
+                partition_id,
+                path: format!("/tmp/{job_id}/2/{partition_id}"),
+                host: "localhost".to_owned(),
+                port: 50051,
+            })),
+            settings: vec![],
+        };
+        FlightEndpoint {
+            ticket: Some(Ticket {
+                ticket: fetch.as_any().encode_to_vec().into(),
+            }),
+            location: vec![],
+        }
+    }
+
+    #[test]
+    fn job_ids_of_flight_info() {
+        let resp = FlightSqlServiceImpl::create_resp(
+            vec![],
+            vec![
+                fetch_endpoint("job_1", 0),
+                fetch_endpoint("job_1", 1),
+                fetch_endpoint("job_2", 0),
+            ],
+            0,
+            0,
+        );
+        let info = resp.into_inner();
+        assert_eq!(flight_info_job_ids(&info).unwrap(), vec!["job_1", "job_2"]);
+
+        // The info is sent encoded in the CancelFlightInfo action
+        let request = CancelFlightInfoRequest { info: Some(info) };
+        let decoded = CancelFlightInfoRequest::decode(request.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, request);
+
+        let invalid = FlightInfo {
+            endpoint: vec![FlightEndpoint {
+                ticket: Some(Ticket {
+                    ticket: vec![1, 2, 3].into(),
+                }),
+                location: vec![],
+            }],
+            ..Default::default()
+        };
+        assert!(flight_info_job_ids(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn metadata_commands() {
+        let ctx = SessionContext::new();
+        let batch = FlightSqlServiceImpl::primary_keys_batch();
+        ctx.register_batch("orders", batch.clone()).unwrap();
+        ctx.register_batch("lineitem", batch).unwrap();
+
+        let catalogs = FlightSqlServiceImpl::catalogs_batch(&ctx, CommandGetCatalogs {}).unwrap();
+        assert_eq!(catalogs.num_rows(), 1);
+
+        let schemas = FlightSqlServiceImpl::schemas_batch(
+            &ctx,
+            CommandGetDbSchemas {
+                catalog: Some("datafusion".to_owned()),
+                db_schema_filter_pattern: Some("pub%".to_owned()),
+            },
+        )
+        .unwrap();
+        assert_eq!(schemas.num_rows(), 1);
+
+        let tables = FlightSqlServiceImpl::tables_batch(
+            &ctx,
+            CommandGetTables {
+                catalog: Some("datafusion".to_owned()),
+                table_name_filter_pattern: Some("order%".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(tables.num_rows(), 1);
+
+        let tables = FlightSqlServiceImpl::tables_batch(
+            &ctx,
+            CommandGetTables {
+                catalog: Some("other".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(tables.num_rows(), 0);
+
+        let sql_info = FlightSqlServiceImpl::sql_info().unwrap();
+        let info = CommandGetSqlInfo {
+            info: vec![SqlInfo::FlightSqlServerVersion as u32],
+        }
+        .into_builder(&sql_info)
+        .build()
+        .unwrap();
+        assert_eq!(info.num_rows(), 1);
+
+        let type_info = FlightSqlServiceImpl::xdbc_type_info().unwrap();
+        let types = CommandGetXdbcTypeInfo { data_type: None }
+            .into_builder(&type_info)
+            .build()
+            .unwrap();
+        assert_eq!(types.num_rows(), 12);
+    }
+
+    #[tokio::test]
+    async fn prepared_ddl_is_applied_on_execution() {
+        let ctx = SessionContext::new();
+
+        let plan = FlightSqlServiceImpl::plan_statement("CREATE VIEW v AS SELECT 1 AS a", &ctx)
+            .await
+            .unwrap();
+        assert!(matches!(plan, LogicalPlan::Ddl(_)));
+        assert!(!ctx.table_exist("v").unwrap());
+
+        let plan = FlightSqlServiceImpl::plan_statement("SELECT 1 AS a", &ctx)
+            .await
+            .unwrap();
+        assert!(matches!(plan, LogicalPlan::Projection(_)));
+    }
+
+    /// Launcher which runs the tasks in process the way an executor does, writing their
+    /// output into the work dir served by the flight service of the executor
+    struct LocalTaskLauncher {
+        work_dir: String,
+        sender: Sender<(String, Vec<TaskStatus>)>,
+    }
+
+    #[tonic::async_trait]
+    impl TaskLauncher for LocalTaskLauncher {
+        async fn launch_tasks(
+            &self,
+            executor: &ExecutorMetadata,
+            tasks: Vec<MultiTaskDefinition>,
+            _executor_manager: &ExecutorManager,
+        ) -> ballista_core::error::Result<()> {
+            let mut statuses = vec![];
+            for multi_task in tasks {
+                let codec: BallistaCodec<LogicalPlanNode, PhysicalPlanNode> =
+                    BallistaCodec::default();
+                let runtime = Arc::new(RuntimeEnv::default());
+                for task in get_task_definition_vec(multi_task, runtime, codec)? {
+                    let shuffle_writer = task
+                        .plan
+                        .as_any()
+                        .downcast_ref::<ShuffleWriterExec>()
+                        .expect("Plan is not a ShuffleWriterExec")
+                        .with_work_dir(self.work_dir.clone());
+                    let result = shuffle_writer
+                        .execute_shuffle_write(task.partition_id, Arc::new(TaskContext::default()))
+                        .await;
+                    let status = match result {
+                        Ok(partitions) => task_status::Status::Successful(SuccessfulTask {
+                            executor_id: executor.id.clone(),
+                            partitions,
+                            shuffle_service_id: String::new(),
+                        }),
+                        Err(e) => task_status::Status::Failed(BallistaError::from(e).into()),
+                    };
+                    statuses.push(TaskStatus {
+                        task_id: task.task_id as u32,
+                        job_id: task.job_id,
+                        stage_id: task.stage_id as u32,
+                        partition_id: task.partition_id as u32,
+                        launch_time: task.launch_time,
+                        start_exec_time: task.launch_time,
+                        end_exec_time: task.launch_time,
+                        metrics: vec![],
+                        status: Some(status),
+                    });
+                }
+            }
+            self.sender
+                .send((executor.id.clone(), statuses))
+                .await
+                .map_err(|e| BallistaError::Internal(format!("Error sending task status: {e:?}")))
+        }
+    }
+
+    /// Create a scheduler with an executor which runs its tasks in process and serves their
+    /// output from `work_dir` with the flight service of the executor
+    async fn local_scheduler(work_dir: &Path) -> ballista_core::error::Result<SchedulerTest> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        // The scheduler authenticates at the executor the way it does when it is started
+        set_client_credentials(&UserCredentials::default());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(FlightServiceServer::new(BallistaFlightService::new(
+                    work_dir,
+                )))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let (sender, mut receiver) = channel(100);
+        let launcher = LocalTaskLauncher {
+            work_dir: work_dir.to_string_lossy().into_owned(),
+            sender,
+        };
+        let executor = ExecutorMetadata {
+            id: "local-executor".to_owned(),
+            host: "127.0.0.1".to_owned(),
+            port,
+            grpc_port: 0,
+            specification: ExecutorSpecification { task_slots: 2 },
+        };
+        let test = SchedulerTest::new_with_launcher(
+            SchedulerConfig::default(),
+            vec![executor],
+            Arc::new(launcher),
+        )
+        .await?;
+
+        let scheduler = test.scheduler();
+        tokio::spawn(async move {
+            while let Some((executor_id, statuses)) = receiver.recv().await {
+                scheduler
+                    .update_task_status(&executor_id, statuses)
+                    .await
+                    .unwrap();
+            }
+        });
+        Ok(test)
+    }
+
+    #[tokio::test]
+    async fn insert_into_json_table() -> ballista_core::error::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let table_dir = tempfile::tempdir()?;
+        let test = local_scheduler(work_dir.path()).await?;
+        let flight_sql = FlightSqlServiceImpl::new(test.scheduler());
+        let ctx = test.ctx().await?;
+
+        let update = |sql: String| {
+            let flight_sql = &flight_sql;
+            let ctx = ctx.clone();
+            async move {
+                let plan = FlightSqlServiceImpl::plan_statement(&sql, &ctx).await?;
+                flight_sql.execute_update(ctx, &plan, &sql).await
+            }
+        };
+        let create = format!(
+            "CREATE EXTERNAL TABLE t (a INT) STORED AS JSON LOCATION '{}/'",
+            table_dir.path().display()
+        );
+        assert_eq!(update(create).await.unwrap(), 0);
+        let rows =
+            update("INSERT INTO t SELECT 1 UNION ALL SELECT 2 UNION ALL SELECT 3".to_owned())
+                .await
+                .unwrap();
+        assert_eq!(rows, 3);
+        let written = ctx.sql("SELECT a FROM t").await?.count().await?;
+        assert_eq!(written, 3);
+
+        // Sinks which can't be sent to the executors are rejected before a job is submitted
+        update("CREATE TABLE m (a INT)".to_owned()).await.unwrap();
+        let err = update("INSERT INTO m VALUES (1)".to_owned())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+        let copy = format!(
+            "COPY (SELECT 1 AS a) TO '{}/out.csv'",
+            table_dir.path().display()
+        );
+        let err = update(copy).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+        assert_eq!(
+            test.scheduler().state.task_manager.get_jobs().await?.len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_only_results_of_own_jobs() -> ballista_core::error::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let test = local_scheduler(work_dir.path()).await?;
+        let flight_sql = FlightSqlServiceImpl::new(test.scheduler());
+        let mut tokens = HashMap::new();
+        for user in ["alice", "bob"] {
+            let token = flight_sql
+                .create_ctx(user, &MetadataMap::new())
+                .await
+                .unwrap();
+            tokens.insert(user, format!("Bearer {token}"));
+        }
+
+        let request = |user: &str, ticket: &Ticket| {
+            let mut request = Request::new(ticket.clone());
+            request
+                .metadata_mut()
+                .insert("authorization", tokens[user].parse().unwrap());
+            request
+        };
+        let sql = "SELECT 1 AS a UNION ALL SELECT 2";
+        let ctx = flight_sql.get_ctx(&request("alice", &Ticket::default()))?;
+        let plan = FlightSqlServiceImpl::plan_statement(sql, &ctx)
+            .await
+            .unwrap();
+        let info = flight_sql
+            .execute_plan(ctx, &plan, sql)
+            .await
+            .unwrap()
+            .into_inner();
+        let ticket = info.endpoint[0].ticket.clone().unwrap();
+        let message = arrow_flight::sql::Any::decode(ticket.ticket.clone()).unwrap();
+
+        let err = flight_sql
+            .do_get_fallback(request("bob", &ticket), message)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        let mut rows = 0;
+        for endpoint in &info.endpoint {
+            let ticket = endpoint.ticket.clone().unwrap();
+            let message = arrow_flight::sql::Any::decode(ticket.ticket.clone()).unwrap();
+            let stream = flight_sql
+                .do_get_fallback(request("alice", &ticket), message)
+                .await
+                .unwrap()
+                .into_inner();
+            let batches: Vec<RecordBatch> =
+                FlightRecordBatchStream::new_from_flight_data(stream.map_err(FlightError::from))
+                    .try_collect()
+                    .await
+                    .unwrap();
+            rows += batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        }
+        assert_eq!(rows, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_returns_affected_rows() -> ballista_core::error::Result<()> {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t (a INT)").await?.collect().await?;
+        let insert = ctx.sql("INSERT INTO t VALUES (1), (2), (3)").await?;
+        let rows = FlightSqlServiceImpl::sum_affected_rows(insert.execute_stream().await?)
+            .await
+            .unwrap();
+        assert_eq!(rows, 3);
+
+        // Output which is not a count of affected rows is rejected
+        let select = ctx.sql("SELECT a FROM t").await?;
+        let err = FlightSqlServiceImpl::sum_affected_rows(select.execute_stream().await?)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Internal);
+        Ok(())
+    }
+
+    #[test]
+    fn tenant_is_the_authenticated_user() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(BALLISTA_TENANT, "other".parse().unwrap());
+        metadata.insert("ballista.batch.size", "1024".parse().unwrap());
+
+        let config = FlightSqlServiceImpl::session_config("admin", &metadata).unwrap();
+        assert_eq!(config.tenant(), Some("admin"));
+        assert_eq!(config.default_batch_size(), 1024);
+    }
+
+    #[test]
+    fn number_question_mark_placeholders() {
+        assert_eq!(
+            number_placeholders("SELECT * FROM t WHERE a = ? AND b > ?"),
+            "SELECT * FROM t WHERE a = $1 AND b > $2"
+        );
+        assert_eq!(
+            number_placeholders("SELECT '?', \"?\" -- ?\nFROM t /* ? */ WHERE a = ?"),
+            "SELECT '?', \"?\" -- ?\nFROM t /* ? */ WHERE a = $1"
+        );
+        assert_eq!(number_placeholders("SELECT $1 + 1"), "SELECT $1 + 1");
+    }
+
+    #[tokio::test]
+    async fn bind_prepared_statement_params() {
+        let ctx = SessionContext::new();
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, false),
+            ])),
+            vec![
+                Arc::new(datafusion::arrow::array::Int64Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["a", "b"])),
+            ],
+        )
+        .unwrap();
+        ctx.register_batch("t", batch).unwrap();
+
+        let plan = FlightSqlServiceImpl::plan_statement(
+            "SELECT name FROM t WHERE id = ? AND name <> ?",
+            &ctx,
+        )
+        .await
+        .unwrap();
+        let schema = FlightSqlServiceImpl::parameter_schema(&plan).unwrap();
+        assert_eq!(schema.field(0).name(), "$1");
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(1).name(), "$2");
+        assert_eq!(schema.field(1).data_type(), &DataType::Utf8);
+
+        let statement = PreparedStatement {
+            session_id: ctx.session_id(),
+            sql: String::new(),
+            plan,
+            params: vec![
+                ScalarValue::Int64(Some(2)),
+                ScalarValue::Utf8(Some("a".to_owned())),
+            ],
+            last_used: Instant::now(),
+        };
+        let bound = statement.bound_plan().unwrap();
+        let rows = ctx
+            .execute_logical_plan(bound)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(rows.iter().map(|batch| batch.num_rows()).sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn prepared_statements_belong_to_their_session() {
+        let statements = PreparedStatements::default();
+        let plan = LogicalPlan::EmptyRelation(EmptyRelation {
+            produce_one_row: true,
+            schema: Arc::new(DFSchema::empty()),
+        });
+        let now = Instant::now();
+        let handle = statements.insert("session", "SELECT 1", plan.clone(), now);
+        let other = statements.insert("session", "SELECT 1", plan, now);
+
+        let err = statements.get_mut("other", &handle, now).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = statements.remove("other", &handle).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert_eq!(
+            statements.get_mut("session", &handle, now).unwrap().sql,
+            "SELECT 1"
+        );
+
+        // Statements expire unless they are used
+        let later = now + PREPARED_STATEMENT_TTL / 2;
+        statements.get_mut("session", &handle, later).unwrap();
+        statements.sweep(now + PREPARED_STATEMENT_TTL);
+        assert!(statements.get_mut("session", &handle, later).is_ok());
+        let err = statements.get_mut("session", &other, later).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        statements.remove("session", &handle).unwrap();
+        assert!(statements.statements.is_empty());
+    }
+
+    #[test]
+    fn local_results_are_bounded() {
+        let data = RecordBatch::new_empty(Arc::new(Schema::empty()));
+        let results = LocalResults::default();
+        let now = Instant::now();
+
+        results.insert("fetched".to_owned(), data.clone(), now);
+        results.insert("abandoned".to_owned(), data.clone(), now);
+        assert!(results.remove("fetched").is_some());
+        assert!(results.remove("fetched").is_none());
+
+        // Results which are never fetched expire
+        let later = now + LOCAL_RESULT_TTL;
+        results.insert("new".to_owned(), data.clone(), later);
+        assert!(results.remove("abandoned").is_none());
+        assert!(results.remove("new").is_some());
+
+        // The oldest results are dropped when there are too many
+        for i in 0..=MAX_LOCAL_RESULTS {
+            results.insert(
+                format!("result-{i}"),
+                data.clone(),
+                later + Duration::from_millis(i as u64),
+            );
+        }
+        assert_eq!(results.results.len(), MAX_LOCAL_RESULTS);
+        assert!(results.remove("result-0").is_none());
+        assert!(results
+            .remove(&format!("result-{MAX_LOCAL_RESULTS}"))
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn wait_for_job_until_deadline() -> ballista_core::error::Result<()> {
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 1, 1, None).await?;
+        let flight_sql = FlightSqlServiceImpl::new(test.scheduler());
+        let schema = Schema::new(vec![Field::new("id", DataType::Utf8, false)]);
+        let plan = scan_empty(None, &schema, None)?.build()?;
+        test.submit("job", &plan).await?;
+
+        // No task finishes while task statuses are not processed
+        let err = flight_sql
+            .wait_for_job(&"job".to_owned(), Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+
+        test.process_task_statuses();
+        flight_sql
+            .wait_for_job(&"job".to_owned(), Duration::from_secs(30))
+            .await
+            .unwrap();
+        Ok(())
+    }
+}
@@ -22,19 +22,27 @@ use std::sync::Arc;
 
 use ballista_core::error::{BallistaError, Result};
 use ballista_core::{
-    execution_plans::{ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec},
+    execution_plans::{
+        RangePartitioning, RangeRepartitionExec, RangeSampleExec, ShuffleReaderExec,
+        ShuffleWriterExec, UnresolvedShuffleExec,
+    },
     serde::scheduler::PartitionLocation,
 };
 use datafusion::physical_plan::coalesce_partitions::CoalescePartitionsExec;
 use datafusion::physical_plan::repartition::RepartitionExec;
+use datafusion::physical_plan::sorts::sort::SortExec;
 use datafusion::physical_plan::sorts::sort_preserving_merge::SortPreservingMergeExec;
 use datafusion::physical_plan::windows::WindowAggExec;
 use datafusion::physical_plan::{with_new_children_if_necessary, ExecutionPlan, Partitioning};
 
 use log::{debug, info};
+use tracing::info_span;
 
 type PartialQueryStageResult = (Arc<dyn ExecutionPlan>, Vec<Arc<ShuffleWriterExec>>);
 
+/// The number of sort keys sampled per range partition, across all input partitions
+const SAMPLES_PER_RANGE_PARTITION: usize = 100;
+
 pub struct DistributedPlanner {
     next_stage_id: usize,
 }
@@ -61,6 +69,7 @@ impl DistributedPlanner {
         execution_plan: Arc<dyn ExecutionPlan>,
     ) -> Result<Vec<Arc<ShuffleWriterExec>>> {
         info!("planning query stages for job {}", job_id);
+        let _span = info_span!("distributed_plan", job_id).entered();
         let (new_plan, mut stages) = self.plan_query_stages_internal(job_id, execution_plan)?;
         stages.push(create_shuffle_writer(
             job_id,
@@ -87,6 +96,12 @@ impl DistributedPlanner {
         let mut stages = vec![];
         let mut children = vec![];
         for child in execution_plan.children() {
+            // a hash shuffle redistributes its input anyway, so fanning the input out with a
+            // round-robin repartition right below it would only add a stage
+            let child = match round_robin_input(&child) {
+                Some(input) if is_hash_repartition(&execution_plan) => input,
+                _ => child,
+            };
             let (new_child, mut child_stages) =
                 self.plan_query_stages_internal(job_id, child.clone())?;
             children.push(new_child);
@@ -130,11 +145,62 @@ impl DistributedPlanner {
                     stages.push(shuffle_writer);
                     Ok((unresolved_shuffle, stages))
                 }
+                // fan the input out to more tasks than it has partitions, e.g. when a scan
+                // over a few large files has fewer partitions than the shuffle partitions
+                Partitioning::RoundRobinBatch(partition_count)
+                    if partition_count > children[0].output_partitioning().partition_count() =>
+                {
+                    let shuffle_writer = create_shuffle_writer(
+                        job_id,
+                        self.next_stage_id(),
+                        children[0].clone(),
+                        Some(repart.partitioning().to_owned()),
+                    );
+                    let unresolved_shuffle = create_unresolved_shuffle(&shuffle_writer);
+                    stages.push(shuffle_writer);
+                    Ok((unresolved_shuffle, stages))
+                }
                 _ => {
-                    // remove any non-hash repartition from the distributed plan
+                    // remove any other repartition from the distributed plan
                     Ok((children[0].clone(), stages))
                 }
             }
+        } else if let Some(range_repart) = execution_plan
+            .as_any()
+            .downcast_ref::<RangeRepartitionExec>()
+        {
+            // the split points of the range partitions are computed from samples of the sort
+            // keys, which are taken by a stage of their own
+            let input = children[0].clone();
+            let input_partition_count = input.output_partitioning().partition_count().max(1);
+            let sample_size = (SAMPLES_PER_RANGE_PARTITION * range_repart.partition_count())
+                .div_ceil(input_partition_count);
+            let sample = Arc::new(RangeSampleExec::try_new(
+                input.clone(),
+                range_repart.sort_exprs().to_vec(),
+                sample_size,
+            )?);
+            let sample_writer = create_shuffle_writer(job_id, self.next_stage_id(), sample, None);
+            let samples = create_unresolved_shuffle(&sample_writer);
+            stages.push(sample_writer);
+
+            let shuffle_writer = Arc::new(
+                ShuffleWriterExec::new(
+                    job_id.to_owned(),
+                    self.next_stage_id(),
+                    input,
+                    "".to_owned(), // executor will decide on the work_dir path
+                    None,
+                )
+                .with_range_partitioning(RangePartitioning {
+                    sort_exprs: range_repart.sort_exprs().to_vec(),
+                    partition_count: range_repart.partition_count(),
+                    samples,
+                }),
+            );
+            let unresolved_shuffle = create_unresolved_shuffle(&shuffle_writer);
+            stages.push(shuffle_writer);
+            Ok((unresolved_shuffle, stages))
         } else if let Some(window) = execution_plan.as_any().downcast_ref::<WindowAggExec>() {
             Err(BallistaError::NotImplemented(format!(
                 "WindowAggExec with window {window:?}"
@@ -154,6 +220,46 @@ impl DistributedPlanner {
     }
 }
 
+/// Rewrite a global sort at the root of the plan, which merges all sorted partitions of its
+/// input in a single task, into sorting range partitions of its input in parallel. The
+/// output partitions are then ordered among each other, so that concatenating them in
+/// order returns the sorted result.
+pub fn range_partition_sorts(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    let Some(merge) = plan.as_any().downcast_ref::<SortPreservingMergeExec>() else {
+        return Ok(plan);
+    };
+    let Some(sort) = merge.input().as_any().downcast_ref::<SortExec>() else {
+        return Ok(plan);
+    };
+    let partition_count = sort.input().output_partitioning().partition_count();
+    // a top-k sort is cheaper to merge than to range partition
+    if merge.fetch().is_some() || sort.fetch().is_some() || partition_count < 2 {
+        return Ok(plan);
+    }
+
+    let range_repart = Arc::new(RangeRepartitionExec::new(
+        sort.input().clone(),
+        sort.expr().to_vec(),
+        partition_count,
+    ));
+    Ok(Arc::new(
+        SortExec::new(sort.expr().to_vec(), range_repart).with_preserve_partitioning(true),
+    ))
+}
+
+fn is_hash_repartition(plan: &Arc<dyn ExecutionPlan>) -> bool {
+    plan.as_any()
+        .downcast_ref::<RepartitionExec>()
+        .is_some_and(|repart| matches!(repart.partitioning(), Partitioning::Hash(_, _)))
+}
+
+/// The input of a round-robin repartition
+fn round_robin_input(plan: &Arc<dyn ExecutionPlan>) -> Option<Arc<dyn ExecutionPlan>> {
+    let repart = plan.as_any().downcast_ref::<RepartitionExec>()?;
+    matches!(repart.partitioning(), Partitioning::RoundRobinBatch(_))
+        .then(|| repart.input().clone())
+}
+
 fn create_unresolved_shuffle(shuffle_writer: &ShuffleWriterExec) -> Arc<UnresolvedShuffleExec> {
     Arc::new(UnresolvedShuffleExec::new(
         shuffle_writer.stage_id(),
@@ -252,24 +358,38 @@ fn create_shuffle_writer(
 
 #[cfg(test)]
 mod test {
-    use crate::planner::DistributedPlanner;
-    use crate::test_utils::datafusion_test_context;
+    use crate::display::display_query_stages;
+    use crate::planner::{range_partition_sorts, remove_unresolved_shuffles, DistributedPlanner};
+    use crate::test_utils::{datafusion_test_context, mock_executor};
     use ballista_core::error::BallistaError;
-    use ballista_core::execution_plans::UnresolvedShuffleExec;
+    use ballista_core::execution_plans::{
+        RangeSampleExec, ShuffleReaderExec, ShuffleWriterExec, UnresolvedShuffleExec,
+    };
+    use ballista_core::serde::scheduler::{PartitionId, PartitionLocation, PartitionStats};
     use ballista_core::serde::BallistaCodec;
+    use datafusion::arrow::array::{Float64Array, UInt32Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::datasource::MemTable;
     use datafusion::physical_plan::aggregates::{AggregateExec, AggregateMode};
     use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
+    use datafusion::physical_plan::common::collect;
+    use datafusion::physical_plan::expressions::Column;
     use datafusion::physical_plan::joins::HashJoinExec;
+    use datafusion::physical_plan::memory::MemoryExec;
     use datafusion::physical_plan::projection::ProjectionExec;
+    use datafusion::physical_plan::repartition::RepartitionExec;
     use datafusion::physical_plan::sorts::sort::SortExec;
     use datafusion::physical_plan::sorts::sort_preserving_merge::SortPreservingMergeExec;
-    use datafusion::physical_plan::{displayable, ExecutionPlan};
-    use datafusion::prelude::SessionContext;
+    use datafusion::physical_plan::{displayable, ExecutionPlan, Partitioning};
+    use datafusion::prelude::{SessionConfig, SessionContext};
     use datafusion_proto::physical_plan::AsExecutionPlan;
     use datafusion_proto::protobuf::LogicalPlanNode;
     use datafusion_proto::protobuf::PhysicalPlanNode;
+    use std::collections::HashMap;
     use std::ops::Deref;
     use std::sync::Arc;
+    use tempfile::TempDir;
     use uuid::Uuid;
 
     macro_rules! downcast_exec {
@@ -567,6 +687,290 @@ order by
         Ok(())
     }
 
+    #[tokio::test]
+    async fn display_distributed_plan_stages() -> Result<(), BallistaError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql(
+                "select l_returnflag, sum(l_extendedprice * 1) as sum_disc_price
+            from lineitem
+            group by l_returnflag
+            order by l_returnflag",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+
+        let mut planner = DistributedPlanner::new();
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        let rendered = display_query_stages(&stages);
+
+        assert_eq!(3, rendered.len());
+        assert_eq!(
+            vec!["stage_1", "stage_2", "stage_3"],
+            rendered.iter().map(|(t, _)| t.as_str()).collect::<Vec<_>>()
+        );
+        assert!(rendered[0].1.starts_with("ShuffleWriterExec: Some(Hash("));
+        assert!(!rendered[0].1.contains("UnresolvedShuffleExec"));
+        assert!(rendered[1]
+            .1
+            .contains("UnresolvedShuffleExec: stage_id=1, partitions=2"));
+        assert!(rendered[2].1.contains("UnresolvedShuffleExec: stage_id=2"));
+        assert!(rendered.iter().all(|(_, plan)| !plan.contains("metrics=")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_range_partitioned_sort_plan() -> Result<(), BallistaError> {
+        let ctx = datafusion_test_context("testdata").await?;
+        let session_state = ctx.state();
+
+        let df = ctx
+            .sql(
+                "select l_orderkey, l_extendedprice
+            from lineitem
+            order by l_extendedprice desc",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+        let plan = range_partition_sorts(plan)?;
+
+        let mut planner = DistributedPlanner::new();
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        for stage in &stages {
+            println!("{}", displayable(stage.as_ref()).indent(false));
+        }
+
+        /* Expected result:
+
+        ShuffleWriterExec: None
+          RangeSampleExec: [l_extendedprice@1 DESC], sample_size=100
+            CsvExec: file_groups={2 groups: [[testdata/lineitem/partition0.tbl], [testdata/lineitem/partition1.tbl]]}, projection=[l_orderkey, l_extendedprice], has_header=false
+
+        ShuffleWriterExec: Range([l_extendedprice@1 DESC], 2)
+          CsvExec: file_groups={2 groups: [[testdata/lineitem/partition0.tbl], [testdata/lineitem/partition1.tbl]]}, projection=[l_orderkey, l_extendedprice], has_header=false
+          UnresolvedShuffleExec
+
+        ShuffleWriterExec: None
+          SortExec: expr=[l_extendedprice@1 DESC]
+            UnresolvedShuffleExec
+        */
+
+        assert_eq!(3, stages.len());
+
+        // verify stage 0
+        assert!(stages[0].shuffle_output_partitioning().is_none());
+        let sample = stages[0].children()[0].clone();
+        let sample = downcast_exec!(sample, RangeSampleExec);
+        assert_eq!(100, sample.sample_size());
+
+        // verify stage 1
+        assert_eq!(2, stages[1].children().len());
+        let range_partitioning = stages[1].range_partitioning().unwrap();
+        assert_eq!(2, range_partitioning.partition_count);
+        assert_eq!(2, stages[1].output_partitioning().partition_count());
+        let samples = downcast_exec!(range_partitioning.samples, UnresolvedShuffleExec);
+        assert_eq!(1, samples.stage_id);
+        assert_eq!(2, samples.output_partition_count);
+
+        // verify stage 2
+        assert!(stages[2].shuffle_output_partitioning().is_none());
+        let sort = stages[2].children()[0].clone();
+        let sort = downcast_exec!(sort, SortExec);
+        assert!(sort.preserve_partitioning());
+        let unresolved_shuffle = sort.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(2, unresolved_shuffle.stage_id);
+        assert_eq!(2, unresolved_shuffle.output_partition_count);
+
+        // the range partitioning survives a roundtrip through the codec
+        let shuffle_writer = roundtrip_operator(&ctx, stages[1].clone())?;
+        let shuffle_writer = downcast_exec!(shuffle_writer, ShuffleWriterExec);
+        assert_eq!(
+            range_partitioning.sort_exprs,
+            shuffle_writer.range_partitioning().unwrap().sort_exprs
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn range_partitioned_sort_returns_sorted_rows() -> Result<(), BallistaError> {
+        let ctx = SessionContext::new_with_config(SessionConfig::new().with_target_partitions(2));
+        let session_state = ctx.state();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("l_orderkey", DataType::UInt32, false),
+            Field::new("l_extendedprice", DataType::Float64, false),
+        ]));
+        let partitions = (0..2u32)
+            .map(|partition| {
+                let orderkeys = (0..10).map(|i| partition * 10 + i).collect::<Vec<_>>();
+                let prices = orderkeys
+                    .iter()
+                    .map(|key| f64::from((key * 37) % 101))
+                    .collect::<Vec<_>>();
+                let batch = RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(UInt32Array::from(orderkeys)),
+                        Arc::new(Float64Array::from(prices)),
+                    ],
+                )?;
+                Ok(vec![batch])
+            })
+            .collect::<Result<Vec<_>, BallistaError>>()?;
+        ctx.register_table("lineitem", Arc::new(MemTable::try_new(schema, partitions)?))?;
+
+        let df = ctx
+            .sql(
+                "select l_orderkey, l_extendedprice
+            from lineitem
+            order by l_extendedprice desc",
+            )
+            .await?;
+
+        let plan = df.into_optimized_plan()?;
+        let plan = session_state.optimize(&plan)?;
+        let plan = session_state.create_physical_plan(&plan).await?;
+        let plan = range_partition_sorts(plan)?;
+
+        let job_id = Uuid::new_v4().to_string();
+        let stages = DistributedPlanner::new().plan_query_stages(&job_id, plan)?;
+        assert_eq!(3, stages.len());
+        assert!(stages[1].range_partitioning().is_some());
+
+        // run the stages in order as the executors would, reading the output of the
+        // previous stages from local shuffle files
+        let work_dir = TempDir::new()?;
+        let executor = mock_executor("executor".to_owned());
+        let mut partition_locations: HashMap<usize, HashMap<usize, Vec<PartitionLocation>>> =
+            HashMap::new();
+        for stage in &stages {
+            let stage = remove_unresolved_shuffles(stage.clone(), &partition_locations)?;
+            let stage = downcast_exec!(stage, ShuffleWriterExec)
+                .with_work_dir(work_dir.path().to_str().unwrap().to_owned());
+            let mut locations: HashMap<usize, Vec<PartitionLocation>> = HashMap::new();
+            for map_partition in 0..stage.input_partition_count() {
+                let partitions = stage
+                    .execute_shuffle_write(map_partition, ctx.task_ctx())
+                    .await?;
+                for partition in partitions {
+                    let partition_id = partition.partition_id as usize;
+                    locations
+                        .entry(partition_id)
+                        .or_default()
+                        .push(PartitionLocation {
+                            map_partition_id: map_partition,
+                            partition_id: PartitionId::new(&job_id, stage.stage_id(), partition_id),
+                            executor_meta: executor.clone(),
+                            partition_stats: PartitionStats::new(
+                                Some(partition.num_rows),
+                                Some(partition.num_batches),
+                                Some(partition.num_bytes),
+                            ),
+                            path: partition.path,
+                        });
+                }
+            }
+            partition_locations.insert(stage.stage_id(), locations);
+        }
+
+        // concatenating the output partitions of the last stage in order returns the rows
+        // sorted by l_extendedprice
+        let last_stage = stages.last().unwrap();
+        let output = &partition_locations[&last_stage.stage_id()];
+        let reader = ShuffleReaderExec::new(
+            last_stage.stage_id(),
+            (0..output.len()).map(|p| output[&p].clone()).collect(),
+            last_stage.schema(),
+        );
+        let mut prices = vec![];
+        for partition in 0..reader.output_partitioning().partition_count() {
+            for batch in collect(reader.execute(partition, ctx.task_ctx())?).await? {
+                let price = batch
+                    .column(1)
+                    .as_any()
+                    .downcast_ref::<Float64Array>()
+                    .unwrap();
+                prices.extend(price.values().iter().copied());
+            }
+        }
+
+        assert_eq!(2, output.len());
+        assert_eq!(20, prices.len());
+        assert!(prices.windows(2).all(|w| w[0] >= w[1]), "{prices:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_round_robin_plan() -> Result<(), BallistaError> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::UInt32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(vec![1, 2, 3]))],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?);
+
+        // a round-robin repartition which increases the parallelism becomes a stage
+        let plan = Arc::new(RepartitionExec::try_new(
+            input.clone(),
+            Partitioning::RoundRobinBatch(4),
+        )?);
+        let mut planner = DistributedPlanner::new();
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        assert_eq!(2, stages.len());
+        assert!(matches!(
+            stages[0].shuffle_output_partitioning(),
+            Some(Partitioning::RoundRobinBatch(4))
+        ));
+        let unresolved_shuffle = stages[1].children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(4, unresolved_shuffle.output_partition_count);
+
+        // any other is removed from the plan
+        let plan = Arc::new(RepartitionExec::try_new(
+            input.clone(),
+            Partitioning::RoundRobinBatch(1),
+        )?);
+        let mut planner = DistributedPlanner::new();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        assert_eq!(1, stages.len());
+        assert_eq!(1, stages[0].input_partition_count());
+
+        // and so is one which is directly followed by a hash shuffle
+        let round_robin = Arc::new(RepartitionExec::try_new(
+            input.clone(),
+            Partitioning::RoundRobinBatch(4),
+        )?);
+        let plan = Arc::new(RepartitionExec::try_new(
+            round_robin,
+            Partitioning::Hash(vec![Arc::new(Column::new("a", 0))], 4),
+        )?);
+        let mut planner = DistributedPlanner::new();
+        let stages = planner.plan_query_stages(&job_uuid.to_string(), plan)?;
+        assert_eq!(2, stages.len());
+        assert!(matches!(
+            stages[0].shuffle_output_partitioning(),
+            Some(Partitioning::Hash(_, 4))
+        ));
+        assert_eq!(1, stages[0].input_partition_count());
+
+        Ok(())
+    }
+
     fn roundtrip_operator(
         ctx: &SessionContext,
         plan: Arc<dyn ExecutionPlan>,
@@ -28,6 +28,7 @@ use tower::Service;
 
 use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
 
+use ballista_core::auth::set_client_credentials;
 use ballista_core::serde::protobuf::scheduler_grpc_server::SchedulerGrpcServer;
 use ballista_core::serde::BallistaCodec;
 use ballista_core::utils::create_grpc_server;
@@ -49,17 +50,19 @@ pub async fn start_server(
         "Ballista v{} Scheduler listening on {:?}",
         BALLISTA_VERSION, addr
     );
-    // Should only call SchedulerServer::new() once in the process
+    // Should only call SchedulerServer::try_new() once in the process
     info!("Starting Scheduler grpc server with push task scheduling policy",);
+    // The scheduler fetches job results from the executors as one of its users
+    set_client_credentials(&config.users);
 
     let mut scheduler_server: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
-        SchedulerServer::new(
+        SchedulerServer::try_new(
             config.scheduler_name(),
             cluster,
             BallistaCodec::default(),
             config.clone(),
             Arc::new(DefaultTaskLauncher::new(config.scheduler_name())),
-        );
+        )?;
 
     scheduler_server.init().await?;
 
@@ -0,0 +1,601 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Structured log of the events received by the `QueryStageScheduler` and of the state
+//! transitions of jobs and their stages, written as JSON lines.
+//!
+//! Every line is one [`EventLogRecord`]. Task statuses and logical plans are stored as base64
+//! encoded protobuf so that a recorded log can be fed back into a scheduler to reproduce a run.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use ballista_core::error::{BallistaError, Result};
+use ballista_core::serde::protobuf::TaskStatus;
+use ballista_core::serde::BallistaCodec;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::prelude::SessionContext;
+use datafusion_proto::logical_plan::AsLogicalPlan;
+use datafusion_proto::physical_plan::AsExecutionPlan;
+use log::warn;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::scheduler_server::event::QueryStageSchedulerEvent;
+use crate::scheduler_server::timestamp_millis;
+use crate::state::execution_graph::RunningTaskInfo;
+use crate::state::session_manager::session_task_settings;
+
+/// One line of the event log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventLogRecord {
+    /// Position of the record in the log, starting at 0
+    pub seq: u64,
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub entry: EventLogEntry,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventLogEntry {
+    /// An event received by the `QueryStageScheduler`
+    Event { event: LoggedEvent },
+    /// The status of a job changed, e.g. from `Queued` to `Running`
+    JobTransition {
+        job_id: String,
+        from: String,
+        to: String,
+    },
+    /// A stage of a job changed, e.g. from `Resolved` to `Running`
+    StageTransition {
+        job_id: String,
+        stage_id: usize,
+        from: String,
+        to: String,
+    },
+}
+
+/// Serializable form of a [`QueryStageSchedulerEvent`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LoggedEvent {
+    JobQueued {
+        job_id: String,
+        session_id: String,
+        /// Ballista settings of the session, which the job is planned with
+        #[serde(default)]
+        settings: HashMap<String, String>,
+        queued_at: u64,
+        /// The logical plan as shown by `EXPLAIN`
+        plan: String,
+        /// The protobuf encoded logical plan, if the plan can be serialized
+        encoded_plan: Option<String>,
+    },
+    JobSubmitted {
+        job_id: String,
+        queued_at: u64,
+        submitted_at: u64,
+    },
+    JobPlanningFailed {
+        job_id: String,
+        fail_message: String,
+        queued_at: u64,
+        failed_at: u64,
+    },
+    JobFinished {
+        job_id: String,
+        queued_at: u64,
+        completed_at: u64,
+    },
+    JobRunningFailed {
+        job_id: String,
+        fail_message: String,
+        queued_at: u64,
+        failed_at: u64,
+    },
+    JobUpdated {
+        job_id: String,
+    },
+    JobCancel {
+        job_id: String,
+    },
+    TaskUpdating {
+        executor_id: String,
+        /// Protobuf encoded `TaskStatus`es
+        statuses: Vec<String>,
+    },
+    ReviveOffers,
+    ExecutorLost {
+        executor_id: String,
+        reason: Option<String>,
+    },
+    CancelTasks {
+        tasks: Vec<LoggedTask>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedTask {
+    pub task_id: usize,
+    pub job_id: String,
+    pub stage_id: usize,
+    pub partition_id: usize,
+    pub executor_id: String,
+}
+
+impl LoggedEvent {
+    pub fn new<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>(
+        event: &QueryStageSchedulerEvent,
+        codec: &BallistaCodec<T, U>,
+    ) -> Self {
+        match event.clone() {
+            QueryStageSchedulerEvent::JobQueued {
+                job_id,
+                session_ctx,
+                plan,
+                queued_at,
+            } => LoggedEvent::JobQueued {
+                job_id,
+                session_id: session_ctx.session_id(),
+                settings: session_task_settings(&session_ctx),
+                queued_at,
+                plan: format!("{}", plan.display_indent()),
+                encoded_plan: encode_plan(&plan, codec),
+            },
+            QueryStageSchedulerEvent::JobSubmitted {
+                job_id,
+                queued_at,
+                submitted_at,
+            } => LoggedEvent::JobSubmitted {
+                job_id,
+                queued_at,
+                submitted_at,
+            },
+            QueryStageSchedulerEvent::JobPlanningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            } => LoggedEvent::JobPlanningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            },
+            QueryStageSchedulerEvent::JobFinished {
+                job_id,
+                queued_at,
+                completed_at,
+            } => LoggedEvent::JobFinished {
+                job_id,
+                queued_at,
+                completed_at,
+            },
+            QueryStageSchedulerEvent::JobRunningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            } => LoggedEvent::JobRunningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            },
+            QueryStageSchedulerEvent::JobUpdated(job_id) => LoggedEvent::JobUpdated { job_id },
+            QueryStageSchedulerEvent::JobCancel(job_id) => LoggedEvent::JobCancel { job_id },
+            QueryStageSchedulerEvent::TaskUpdating(executor_id, statuses) => {
+                LoggedEvent::TaskUpdating {
+                    executor_id,
+                    statuses: statuses
+                        .iter()
+                        .map(|status| STANDARD.encode(status.encode_to_vec()))
+                        .collect(),
+                }
+            }
+            QueryStageSchedulerEvent::ReviveOffers => LoggedEvent::ReviveOffers,
+            QueryStageSchedulerEvent::ExecutorLost(executor_id, reason) => {
+                LoggedEvent::ExecutorLost {
+                    executor_id,
+                    reason,
+                }
+            }
+            QueryStageSchedulerEvent::CancelTasks(tasks) => LoggedEvent::CancelTasks {
+                tasks: tasks
+                    .into_iter()
+                    .map(|task| LoggedTask {
+                        task_id: task.task_id,
+                        job_id: task.job_id,
+                        stage_id: task.stage_id,
+                        partition_id: task.partition_id,
+                        executor_id: task.executor_id,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// Convert the logged event back into a scheduler event. A queued job is submitted again
+    /// with the given session and plan, the plan is usually decoded with
+    /// [`LoggedEvent::decode_plan`].
+    pub fn into_scheduler_event(
+        self,
+        queued_job: Option<(Arc<SessionContext>, LogicalPlan)>,
+    ) -> Result<QueryStageSchedulerEvent> {
+        Ok(match self {
+            LoggedEvent::JobQueued {
+                job_id, queued_at, ..
+            } => {
+                let (session_ctx, plan) = queued_job.ok_or_else(|| {
+                    BallistaError::General(format!("No logical plan to replay job {job_id}"))
+                })?;
+                QueryStageSchedulerEvent::JobQueued {
+                    job_id,
+                    session_ctx,
+                    plan: Box::new(plan),
+                    queued_at,
+                }
+            }
+            LoggedEvent::JobSubmitted {
+                job_id,
+                queued_at,
+                submitted_at,
+            } => QueryStageSchedulerEvent::JobSubmitted {
+                job_id,
+                queued_at,
+                submitted_at,
+            },
+            LoggedEvent::JobPlanningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            } => QueryStageSchedulerEvent::JobPlanningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            },
+            LoggedEvent::JobFinished {
+                job_id,
+                queued_at,
+                completed_at,
+            } => QueryStageSchedulerEvent::JobFinished {
+                job_id,
+                queued_at,
+                completed_at,
+            },
+            LoggedEvent::JobRunningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            } => QueryStageSchedulerEvent::JobRunningFailed {
+                job_id,
+                fail_message,
+                queued_at,
+                failed_at,
+            },
+            LoggedEvent::JobUpdated { job_id } => QueryStageSchedulerEvent::JobUpdated(job_id),
+            LoggedEvent::JobCancel { job_id } => QueryStageSchedulerEvent::JobCancel(job_id),
+            LoggedEvent::TaskUpdating {
+                executor_id,
+                statuses,
+            } => QueryStageSchedulerEvent::TaskUpdating(
+                executor_id,
+                statuses
+                    .iter()
+                    .map(|status| {
+                        let bytes = STANDARD.decode(status).map_err(|e| {
+                            BallistaError::General(format!("Invalid logged task status: {e}"))
+                        })?;
+                        TaskStatus::decode(bytes.as_slice()).map_err(|e| {
+                            BallistaError::General(format!("Invalid logged task status: {e}"))
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            LoggedEvent::ReviveOffers => QueryStageSchedulerEvent::ReviveOffers,
+            LoggedEvent::ExecutorLost {
+                executor_id,
+                reason,
+            } => QueryStageSchedulerEvent::ExecutorLost(executor_id, reason),
+            LoggedEvent::CancelTasks { tasks } => QueryStageSchedulerEvent::CancelTasks(
+                tasks
+                    .into_iter()
+                    .map(|task| RunningTaskInfo {
+                        task_id: task.task_id,
+                        job_id: task.job_id,
+                        stage_id: task.stage_id,
+                        partition_id: task.partition_id,
+                        executor_id: task.executor_id,
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Decode the logical plan of a queued job, if it was recorded
+    pub fn decode_plan<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>(
+        &self,
+        ctx: &SessionContext,
+        codec: &BallistaCodec<T, U>,
+    ) -> Result<Option<LogicalPlan>> {
+        let LoggedEvent::JobQueued {
+            encoded_plan: Some(encoded_plan),
+            ..
+        } = self
+        else {
+            return Ok(None);
+        };
+        let bytes = STANDARD
+            .decode(encoded_plan)
+            .map_err(|e| BallistaError::General(format!("Invalid logged plan: {e}")))?;
+        let plan =
+            T::try_decode(&bytes)?.try_into_logical_plan(ctx, codec.logical_extension_codec())?;
+        Ok(Some(plan))
+    }
+}
+
+fn encode_plan<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>(
+    plan: &LogicalPlan,
+    codec: &BallistaCodec<T, U>,
+) -> Option<String> {
+    let mut bytes = vec![];
+    match T::try_from_logical_plan(plan, codec.logical_extension_codec())
+        .and_then(|node| node.try_encode(&mut bytes))
+    {
+        Ok(()) => Some(STANDARD.encode(bytes)),
+        Err(e) => {
+            warn!("Logical plan cannot be recorded in the event log: {e}");
+            None
+        }
+    }
+}
+
+/// Requests to the thread writing the event log
+enum EventLogMessage {
+    Record {
+        timestamp: u64,
+        entry: EventLogEntry,
+    },
+    /// Reply once all records sent before have been written
+    Flush(Sender<()>),
+}
+
+/// Appends records to an event log file. Records are written by a dedicated thread so that
+/// recording never blocks the scheduler on file IO. Clones share the same file.
+#[derive(Clone)]
+pub struct EventLog {
+    sender: Arc<Sender<EventLogMessage>>,
+}
+
+impl EventLog {
+    /// Open the event log, appending to the file if it already exists. The records
+    /// appended are numbered on from the records in the file.
+    pub fn try_new(path: impl AsRef<Path>) -> Result<Self> {
+        let next_seq = match File::open(path.as_ref()) {
+            Ok(file) => next_seq(BufReader::new(file))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())?;
+        let (sender, receiver) = channel();
+        thread::Builder::new()
+            .name("event-log-writer".to_owned())
+            .spawn(move || write_records(BufWriter::new(file), next_seq, receiver))?;
+        Ok(Self {
+            sender: Arc::new(sender),
+        })
+    }
+
+    /// Wait until all records recorded so far have been written to the file
+    pub fn flush(&self) -> Result<()> {
+        let (sender, receiver) = channel();
+        self.sender
+            .send(EventLogMessage::Flush(sender))
+            .map_err(|_| BallistaError::General("Event log writer stopped".to_owned()))?;
+        receiver
+            .recv()
+            .map_err(|_| BallistaError::General("Event log writer stopped".to_owned()))
+    }
+
+    pub fn record_event<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>(
+        &self,
+        event: &QueryStageSchedulerEvent,
+        codec: &BallistaCodec<T, U>,
+    ) {
+        self.record(EventLogEntry::Event {
+            event: LoggedEvent::new(event, codec),
+        });
+    }
+
+    pub fn record_job_transition(&self, job_id: &str, from: &str, to: &str) {
+        self.record(EventLogEntry::JobTransition {
+            job_id: job_id.to_owned(),
+            from: from.to_owned(),
+            to: to.to_owned(),
+        });
+    }
+
+    pub fn record_stage_transition(&self, job_id: &str, stage_id: usize, from: &str, to: &str) {
+        self.record(EventLogEntry::StageTransition {
+            job_id: job_id.to_owned(),
+            stage_id,
+            from: from.to_owned(),
+            to: to.to_owned(),
+        });
+    }
+
+    fn record(&self, entry: EventLogEntry) {
+        let message = EventLogMessage::Record {
+            timestamp: timestamp_millis(),
+            entry,
+        };
+        if self.sender.send(message).is_err() {
+            warn!("Failed to write to the scheduler event log as its writer stopped");
+        }
+    }
+}
+
+/// Number of the record following the records of an existing log. A record torn by a crash
+/// while it was written is skipped.
+fn next_seq(reader: impl BufRead) -> Result<u64> {
+    let mut next_seq = 0;
+    for line in reader.lines() {
+        if let Ok(record) = serde_json::from_str::<EventLogRecord>(&line?) {
+            next_seq = next_seq.max(record.seq + 1);
+        }
+    }
+    Ok(next_seq)
+}
+
+/// Write the records received until all senders are dropped. Records are numbered from
+/// `seq` in the order they are received, and the file is flushed whenever no more records
+/// are waiting.
+fn write_records(mut writer: BufWriter<File>, mut seq: u64, receiver: Receiver<EventLogMessage>) {
+    let mut message = receiver.recv();
+    while let Ok(received) = message {
+        match received {
+            EventLogMessage::Record { timestamp, entry } => {
+                let record = EventLogRecord {
+                    seq,
+                    timestamp,
+                    entry,
+                };
+                seq += 1;
+                let result = serde_json::to_writer(&mut writer, &record)
+                    .map_err(|e| BallistaError::General(e.to_string()))
+                    .and_then(|_| Ok(writer.write_all(b"\n")?));
+                if let Err(e) = result {
+                    warn!("Failed to write to the scheduler event log: {e:?}");
+                }
+            }
+            EventLogMessage::Flush(reply) => {
+                if let Err(e) = writer.flush() {
+                    warn!("Failed to flush the scheduler event log: {e:?}");
+                }
+                let _ = reply.send(());
+            }
+        }
+        message = match receiver.try_recv() {
+            Ok(received) => Ok(received),
+            Err(_) => {
+                if let Err(e) = writer.flush() {
+                    warn!("Failed to flush the scheduler event log: {e:?}");
+                }
+                receiver.recv()
+            }
+        };
+    }
+}
+
+/// Read all records of an event log
+pub fn read_event_log(path: impl AsRef<Path>) -> Result<Vec<EventLogRecord>> {
+    let reader = BufReader::new(File::open(path.as_ref())?);
+    let mut records = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line).map_err(|e| {
+            BallistaError::General(format!("Invalid event log record {line}: {e}"))
+        })?);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ballista_core::serde::protobuf::{task_status, RunningTask};
+    use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
+
+    #[test]
+    fn event_log_round_trip() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("event-log");
+        let codec: BallistaCodec<LogicalPlanNode, PhysicalPlanNode> = BallistaCodec::default();
+        let status = TaskStatus {
+            task_id: 3,
+            job_id: "job".to_owned(),
+            stage_id: 1,
+            partition_id: 2,
+            status: Some(task_status::Status::Running(RunningTask {
+                executor_id: "executor".to_owned(),
+            })),
+            ..Default::default()
+        };
+
+        let log = EventLog::try_new(&path)?;
+        log.record_event(
+            &QueryStageSchedulerEvent::TaskUpdating("executor".to_owned(), vec![status.clone()]),
+            &codec,
+        );
+        log.record_stage_transition("job", 1, "Running", "Successful");
+        log.flush()?;
+
+        let records = read_event_log(&path)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].seq, 1);
+
+        // records appended after a restart are numbered on
+        drop(log);
+        let log = EventLog::try_new(&path)?;
+        log.record_job_transition("job", "Running", "Successful");
+        log.flush()?;
+        let seqs: Vec<u64> = read_event_log(&path)?
+            .iter()
+            .map(|record| record.seq)
+            .collect();
+        assert_eq!(seqs, vec![0, 1, 2]);
+        assert_eq!(
+            records[1].entry,
+            EventLogEntry::StageTransition {
+                job_id: "job".to_owned(),
+                stage_id: 1,
+                from: "Running".to_owned(),
+                to: "Successful".to_owned(),
+            }
+        );
+
+        let EventLogEntry::Event { event } = records[0].entry.clone() else {
+            panic!("Expected an event, found {:?}", records[0].entry);
+        };
+        match event.into_scheduler_event(None)? {
+            QueryStageSchedulerEvent::TaskUpdating(executor_id, statuses) => {
+                assert_eq!(executor_id, "executor");
+                assert_eq!(statuses, vec![status]);
+            }
+            event => panic!("Unexpected event {event:?}"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Replay of a recorded scheduler event log, to reproduce a run deterministically when
+//! debugging the scheduler.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ballista_core::config::{BallistaConfig, BALLISTA_DEFAULT_SHUFFLE_PARTITIONS};
+use ballista_core::error::{BallistaError, Result};
+use ballista_core::event_loop::EventAction;
+use ballista_core::serde::protobuf::MultiTaskDefinition;
+use ballista_core::serde::scheduler::{ExecutorData, ExecutorMetadata, ExecutorSpecification};
+use ballista_core::serde::BallistaCodec;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion_proto::protobuf::{LogicalPlanNode, PhysicalPlanNode};
+use tokio::sync::mpsc::channel;
+
+use crate::cluster::BallistaCluster;
+use crate::config::SchedulerConfig;
+use crate::scheduler_server::event_log::{
+    read_event_log, EventLogEntry, EventLogRecord, LoggedEvent,
+};
+use crate::scheduler_server::query_stage_scheduler::QueryStageScheduler;
+use crate::scheduler_server::SchedulerServer;
+use crate::state::executor_manager::ExecutorManager;
+use crate::state::task_manager::TaskLauncher;
+
+/// How often and how many times to check whether a replayed job has been planned
+const PLANNING_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+const PLANNING_CHECKS: usize = 500;
+
+/// Replays a recorded scheduler event log to reproduce a run deterministically.
+///
+/// Every event received by the `QueryStageScheduler` in the recorded run is fed to a fresh
+/// scheduler in the recorded order. Events which the scheduler posts itself are dropped, as they
+/// are replayed from the log as well. Tasks launched on the executors of the recorded run are
+/// never run, the task statuses are taken from the log instead.
+pub struct EventLogReplay {
+    records: Vec<EventLogRecord>,
+    plans: HashMap<String, LogicalPlan>,
+}
+
+impl EventLogReplay {
+    pub fn try_new(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            records: read_event_log(path)?,
+            plans: HashMap::new(),
+        })
+    }
+
+    /// Plan to submit for a job whose plan could not be recorded in the event log
+    pub fn with_plan(mut self, job_id: impl Into<String>, plan: LogicalPlan) -> Self {
+        self.plans.insert(job_id.into(), plan);
+        self
+    }
+
+    /// The executors which appear in the log
+    fn executor_ids(&self) -> BTreeSet<String> {
+        let mut executor_ids = BTreeSet::new();
+        for record in &self.records {
+            match &record.entry {
+                EventLogEntry::Event {
+                    event: LoggedEvent::TaskUpdating { executor_id, .. },
+                }
+                | EventLogEntry::Event {
+                    event: LoggedEvent::ExecutorLost { executor_id, .. },
+                } => {
+                    executor_ids.insert(executor_id.clone());
+                }
+                EventLogEntry::Event {
+                    event: LoggedEvent::CancelTasks { tasks },
+                } => {
+                    executor_ids.extend(tasks.iter().map(|task| task.executor_id.clone()));
+                }
+                _ => {}
+            }
+        }
+        executor_ids
+    }
+
+    /// Replay the log on a scheduler with `config`, giving every executor of the recorded
+    /// run `task_slots_per_executor` slots. Returns the scheduler in the state the recorded
+    /// run ended in.
+    pub async fn run(
+        &self,
+        config: SchedulerConfig,
+        task_slots_per_executor: usize,
+    ) -> Result<SchedulerServer<LogicalPlanNode, PhysicalPlanNode>> {
+        let config = Arc::new(config);
+        let cluster = BallistaCluster::new_from_config(&config).await?;
+        let mut server: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
+            SchedulerServer::try_new(
+                config.scheduler_name(),
+                cluster,
+                BallistaCodec::default(),
+                config.clone(),
+                Arc::new(ReplayTaskLauncher),
+            )?;
+        server.init().await?;
+
+        let executor_ids = self.executor_ids();
+        let total_task_slots = executor_ids.len() * task_slots_per_executor;
+        for executor_id in executor_ids {
+            let metadata = ExecutorMetadata {
+                id: executor_id.clone(),
+                host: String::default(),
+                port: 0,
+                grpc_port: 0,
+                specification: ExecutorSpecification {
+                    task_slots: task_slots_per_executor as u32,
+                },
+            };
+            let executor_data = ExecutorData {
+                executor_id,
+                total_task_slots: task_slots_per_executor as u32,
+                available_task_slots: task_slots_per_executor as u32,
+            };
+            server
+                .state
+                .executor_manager
+                .register_executor(metadata, executor_data)
+                .await?;
+        }
+
+        let mut default_config = BallistaConfig::builder();
+        if total_task_slots > 0 {
+            default_config = default_config.set(
+                BALLISTA_DEFAULT_SHUFFLE_PARTITIONS,
+                &total_task_slots.to_string(),
+            );
+        }
+        let default_config = default_config.build()?;
+
+        let state = server.state.clone();
+        let scheduler = QueryStageScheduler::new(state.clone(), config);
+        let (tx_event, mut rx_event) = channel(10000);
+
+        for record in &self.records {
+            let EventLogEntry::Event { event } = &record.entry else {
+                continue;
+            };
+
+            let queued_job = match event {
+                LoggedEvent::JobQueued {
+                    job_id, settings, ..
+                } => {
+                    // The job is planned with the settings of its session in the recorded
+                    // run, logs which didn't record them get the defaults of the replay
+                    let session_config = if settings.is_empty() {
+                        default_config.clone()
+                    } else {
+                        BallistaConfig::with_settings(settings.clone())?
+                    };
+                    let ctx = state
+                        .session_manager
+                        .create_session(&session_config)
+                        .await?;
+                    let plan = match self.plans.get(job_id) {
+                        Some(plan) => plan.clone(),
+                        None => event.decode_plan(&ctx, &state.codec)?.ok_or_else(|| {
+                            BallistaError::General(format!(
+                                "No logical plan to replay job {job_id}"
+                            ))
+                        })?,
+                    };
+                    Some((ctx, plan))
+                }
+                LoggedEvent::JobSubmitted { job_id, .. } => {
+                    // Jobs are planned in the background, wait for the planning to complete
+                    // as it had in the recorded run
+                    let mut checks = 0;
+                    while state
+                        .task_manager
+                        .get_active_execution_graph(job_id)
+                        .is_none()
+                    {
+                        checks += 1;
+                        if checks > PLANNING_CHECKS {
+                            return Err(BallistaError::General(format!(
+                                "Job {job_id} was not planned in time for replay"
+                            )));
+                        }
+                        tokio::time::sleep(PLANNING_CHECK_INTERVAL).await;
+                    }
+                    None
+                }
+                _ => None,
+            };
+
+            let event = event.clone().into_scheduler_event(queued_job)?;
+            scheduler.on_receive(event, &tx_event, &rx_event).await?;
+            while rx_event.try_recv().is_ok() {}
+        }
+
+        Ok(server)
+    }
+}
+
+/// Drops the tasks launched during a replay, their statuses are replayed from the log
+struct ReplayTaskLauncher;
+
+#[async_trait]
+impl TaskLauncher for ReplayTaskLauncher {
+    async fn launch_tasks(
+        &self,
+        _executor: &ExecutorMetadata,
+        _tasks: Vec<MultiTaskDefinition>,
+        _executor_manager: &ExecutorManager,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
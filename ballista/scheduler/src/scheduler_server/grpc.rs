@@ -17,8 +17,8 @@
 
 use ballista_core::serde::protobuf::scheduler_grpc_server::SchedulerGrpc;
 use ballista_core::serde::protobuf::{
-    ExecutorHeartbeat, HeartBeatParams, HeartBeatResult, RegisterExecutorParams,
-    RegisterExecutorResult, UpdateTaskStatusParams, UpdateTaskStatusResult,
+    ExecutorHeartbeat, GetActiveJobsParams, GetActiveJobsResult, HeartBeatParams, HeartBeatResult,
+    RegisterExecutorParams, RegisterExecutorResult, UpdateTaskStatusParams, UpdateTaskStatusResult,
 };
 use ballista_core::serde::scheduler::ExecutorMetadata;
 
@@ -28,7 +28,7 @@ use log::{debug, error, info, warn};
 
 use tonic::{Request, Response, Status};
 
-use crate::scheduler_server::{timestamp_secs, SchedulerServer};
+use crate::scheduler_server::{timestamp_millis, timestamp_secs, SchedulerServer};
 
 #[tonic::async_trait]
 impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
@@ -74,6 +74,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
             executor_id,
             status,
             metadata,
+            metrics,
         } = request.into_inner();
         debug!("Received heart beat request for {:?}", executor_id);
 
@@ -109,6 +110,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
         let executor_heartbeat = ExecutorHeartbeat {
             executor_id,
             timestamp: timestamp_secs(),
+            metrics,
             status,
         };
 
@@ -151,6 +153,23 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerGrpc
 
         Ok(Response::new(UpdateTaskStatusResult { success: true }))
     }
+
+    async fn get_active_jobs(
+        &self,
+        _request: Request<GetActiveJobsParams>,
+    ) -> Result<Response<GetActiveJobsResult>, Status> {
+        let job_id = self
+            .state
+            .get_active_jobs(timestamp_millis())
+            .await
+            .map_err(|e| {
+                let msg = format!("Fail to get active jobs due to {e:?}");
+                error!("{}", msg);
+                Status::internal(msg)
+            })?;
+
+        Ok(Response::new(GetActiveJobsResult { job_id }))
+    }
 }
 
 #[cfg(test)]
@@ -183,13 +202,13 @@ mod test {
         let config = SchedulerConfig::default();
         let scheduler_name = "localhost:50050".to_owned();
         let mut scheduler: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
-            SchedulerServer::new(
+            SchedulerServer::try_new(
                 scheduler_name.clone(),
                 cluster,
                 BallistaCodec::default(),
                 Arc::new(config),
                 Arc::new(DefaultTaskLauncher::new(scheduler_name)),
-            );
+            )?;
         scheduler.init().await?;
 
         let exec_meta = ExecutorRegistration {
@@ -205,6 +224,7 @@ mod test {
                 status: Some(executor_status::Status::Active("".to_string())),
             }),
             metadata: Some(exec_meta.clone()),
+            metrics: vec![],
         });
         scheduler
             .heart_beat_from_executor(request)
@@ -235,13 +255,13 @@ mod test {
         let config = SchedulerConfig::default();
         let scheduler_name = "localhost:50050".to_owned();
         let mut scheduler: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
-            SchedulerServer::new(
+            SchedulerServer::try_new(
                 scheduler_name.clone(),
                 cluster.clone(),
                 BallistaCodec::default(),
                 Arc::new(config),
                 Arc::new(DefaultTaskLauncher::new(scheduler_name)),
-            );
+            )?;
         scheduler.init().await?;
 
         let exec_meta = ExecutorRegistration {
@@ -283,6 +303,7 @@ mod test {
                 status: Some(executor_status::Status::Active("".to_string())),
             }),
             metadata: Some(exec_meta.clone()),
+            metrics: vec![],
         });
 
         let _response = scheduler
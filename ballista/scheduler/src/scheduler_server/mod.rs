@@ -18,9 +18,9 @@
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use ballista_core::error::Result;
+use ballista_core::error::{BallistaError, Result};
 use ballista_core::event_loop::{EventLoop, EventSender};
-use ballista_core::serde::protobuf::TaskStatus;
+use ballista_core::serde::protobuf::{job_status, TaskStatus};
 use ballista_core::serde::BallistaCodec;
 
 use datafusion::execution::context::SessionState;
@@ -43,6 +43,8 @@ use crate::state::task_manager::TaskLauncher;
 use crate::state::SchedulerState;
 
 pub mod event;
+pub mod event_log;
+pub mod event_log_replay;
 mod grpc;
 pub(crate) mod query_stage_scheduler;
 
@@ -59,20 +61,20 @@ pub struct SchedulerServer<T: 'static + AsLogicalPlan, U: 'static + AsExecutionP
 }
 
 impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T, U> {
-    pub fn new(
+    pub fn try_new(
         scheduler_name: String,
         cluster: BallistaCluster,
         codec: BallistaCodec<T, U>,
         config: Arc<SchedulerConfig>,
         task_launcher: Arc<dyn TaskLauncher>,
-    ) -> Self {
-        let state = Arc::new(SchedulerState::new(
+    ) -> Result<Self> {
+        let state = Arc::new(SchedulerState::try_new(
             cluster,
             codec,
             scheduler_name.clone(),
             config.clone(),
             task_launcher,
-        ));
+        )?);
         let query_stage_scheduler =
             Arc::new(QueryStageScheduler::new(state.clone(), config.clone()));
         let query_stage_event_loop = EventLoop::new(
@@ -81,13 +83,13 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T
             query_stage_scheduler.clone(),
         );
 
-        Self {
+        Ok(Self {
             scheduler_name,
             start_time: timestamp_millis() as u128,
             state,
             query_stage_event_loop,
             config,
-        }
+        })
     }
 
     pub async fn init(&mut self) -> Result<()> {
@@ -108,15 +110,71 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerServer<T
         ctx: Arc<SessionContext>,
         plan: &LogicalPlan,
     ) -> Result<()> {
-        self.query_stage_event_loop
-            .get_sender()?
-            .post_event(QueryStageSchedulerEvent::JobQueued {
-                job_id: job_id.to_owned(),
-                session_ctx: ctx,
-                plan: Box::new(plan.clone()),
-                queued_at: timestamp_millis(),
-            })
-            .await
+        let queued_at = timestamp_millis();
+        self.state
+            .job_history
+            .record_queued(job_id, &ctx, queued_at);
+        let event = QueryStageSchedulerEvent::JobQueued {
+            job_id: job_id.to_owned(),
+            session_ctx: ctx,
+            plan: Box::new(plan.clone()),
+            queued_at,
+        };
+        let result = match self.query_stage_event_loop.get_sender() {
+            Ok(sender) => sender.post_event(event).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            // The job is never archived if it could not be queued
+            self.state.job_history.forget(job_id);
+        }
+        result
+    }
+
+    /// Plan a SQL query in the session the way it is submitted as a job. DDL is applied
+    /// to the session and to the shared catalog.
+    pub(crate) async fn plan_sql(&self, ctx: &SessionContext, sql: &str) -> Result<LogicalPlan> {
+        let session_manager = &self.state.session_manager;
+        session_manager.refresh_catalog(ctx).await?;
+        let plan = ctx.state().create_logical_plan(sql).await?;
+        let plan = session_manager
+            .execute_logical_plan(ctx, plan)
+            .await?
+            .into_optimized_plan()?;
+        Ok(plan)
+    }
+
+    /// Submit the plan of a SQL query as a new job and return its job id
+    pub(crate) async fn submit_sql_job(
+        &self,
+        ctx: Arc<SessionContext>,
+        plan: &LogicalPlan,
+        sql: &str,
+    ) -> Result<String> {
+        let job_id = self.state.task_manager.generate_job_id();
+        self.state.job_history.record_sql(&job_id, sql);
+        self.submit_job(&job_id, ctx, plan).await.map_err(|e| {
+            let msg = format!("Failed to send JobQueued event for {job_id}: {e:?}");
+            error!("{}", msg);
+            BallistaError::Internal(msg)
+        })?;
+        Ok(job_id)
+    }
+
+    /// Cancel a job if it is still queued or running, freeing the task slots of its running
+    /// tasks. Returns whether the job was cancelled.
+    pub(crate) async fn cancel_job(&self, job_id: &str) -> Result<bool> {
+        let status = self.state.task_manager.get_job_status(job_id).await?;
+        match status.and_then(|status| status.status) {
+            Some(job_status::Status::Queued(_)) | Some(job_status::Status::Running(_)) => {
+                self.query_stage_event_loop
+                    .get_sender()?
+                    .post_event(QueryStageSchedulerEvent::JobCancel(job_id.to_owned()))
+                    .await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
     }
 
     /// It just send task status update event to the channel,
@@ -269,7 +327,9 @@ pub fn timestamp_millis() -> u64 {
 
 #[cfg(test)]
 mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
+    use std::time::Duration;
 
     use datafusion::arrow::datatypes::{DataType, Field, Schema};
     use datafusion::logical_expr::{col, sum, LogicalPlan};
@@ -277,8 +337,9 @@ mod test {
     use datafusion::test_util::scan_empty;
 
     use ballista_core::error::Result;
+    use ballista_core::serde::scheduler::{ExecutorData, ExecutorMetadata, ExecutorSpecification};
 
-    use crate::config::SchedulerConfig;
+    use crate::config::{SchedulerConfig, TenantQuota};
 
     use ballista_core::serde::protobuf::{
         job_status, task_status, FailedTask, JobStatus, MultiTaskDefinition, SuccessfulJob, TaskId,
@@ -286,8 +347,12 @@ mod test {
     };
 
     use crate::scheduler_server::timestamp_millis;
+    use crate::state::execution_graph::ExecutionStage;
 
-    use crate::test_utils::{ExplodingTableProvider, SchedulerTest, TaskRunnerFn};
+    use crate::test_utils::{
+        await_condition, default_task_runner, ExplodingTableProvider, SchedulerTest, TaskRunner,
+        TaskRunnerFn,
+    };
 
     #[tokio::test]
     async fn test_push_scheduling() -> Result<()> {
@@ -311,6 +376,246 @@ mod test {
         Ok(())
     }
 
+    // Tasks of a tenant should never take more slots than the tenant's quota allows
+    #[tokio::test]
+    async fn test_tenant_task_slot_quota() -> Result<()> {
+        let plan = test_plan();
+
+        let max_launched = Arc::new(AtomicUsize::new(0));
+        let max_launched_clone = max_launched.clone();
+        let default_runner = default_task_runner();
+        let runner = Arc::new(TaskRunnerFn::new(
+            move |executor_id: String, task: MultiTaskDefinition| {
+                max_launched_clone.fetch_max(task.task_ids.len(), Ordering::SeqCst);
+                default_runner.run(executor_id, task)
+            },
+        ));
+
+        let config = SchedulerConfig::default()
+            .with_default_tenant_quota(TenantQuota::default().with_max_task_slots(1));
+        let mut test = SchedulerTest::new(config, 1, 4, Some(runner)).await?;
+
+        let status = test.run("job", &plan).await.expect("running plan");
+
+        assert!(
+            matches!(
+                status,
+                JobStatus {
+                    status: Some(job_status::Status::Successful(_)),
+                    ..
+                }
+            ),
+            "{}",
+            "Expected job status to be successful but it was {status:?}"
+        );
+        assert_eq!(max_launched.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    // Cancelling a job which already finished neither fails it nor publishes an event
+    #[tokio::test]
+    async fn test_cancel_finished_job() -> Result<()> {
+        let plan = test_plan();
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 4, 1, None).await?;
+        let status = test.run("job", &plan).await?;
+        assert!(matches!(
+            status.status,
+            Some(job_status::Status::Successful(_))
+        ));
+
+        let mut events = test.scheduler().state.job_events.subscribe();
+        test.cancel("job").await?;
+        // Scheduler events are handled in order, so the event of the next job comes first
+        test.submit("job-2", &plan).await?;
+        let event = events.recv().await.expect("job event");
+        assert_eq!(event.job_id(), "job-2", "Unexpected event {event:?}");
+
+        let status = test.job_status("job").await?.expect("job status");
+        assert!(matches!(
+            status.status,
+            Some(job_status::Status::Successful(_))
+        ));
+        Ok(())
+    }
+
+    // Jobs over a tenant's running job limit stay queued until a running job finishes
+    #[tokio::test]
+    async fn test_tenant_running_job_quota() -> Result<()> {
+        let plan = test_plan();
+
+        let config = SchedulerConfig::default()
+            .with_default_tenant_quota(TenantQuota::default().with_max_running_jobs(1));
+        let mut test = SchedulerTest::new(config, 4, 1, None).await?;
+
+        test.submit("job-1", &plan).await?;
+        test.submit("job-2", &plan).await?;
+        let throttled = await_condition(Duration::from_millis(10), 100, || async {
+            let queued = matches!(
+                test.job_status("job-2").await?,
+                Some(JobStatus {
+                    status: Some(job_status::Status::Queued(_)),
+                    ..
+                })
+            );
+            Ok(queued && test.running_job_number() == 1)
+        })
+        .await?;
+        assert!(throttled, "Expected job-2 to be queued while job-1 runs");
+
+        test.process_task_statuses();
+        for job_id in ["job-1", "job-2"] {
+            let status = test.await_completion(job_id).await?;
+            assert!(
+                matches!(status.status, Some(job_status::Status::Successful(_))),
+                "Expected {job_id} to be successful but it was {status:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_analyze_stages() -> Result<()> {
+        let plan = test_plan();
+
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 4, 1, None).await?;
+
+        let status = test.run("job", &plan).await.expect("running plan");
+        assert!(matches!(
+            status.status,
+            Some(job_status::Status::Successful(_))
+        ));
+
+        let stages = test.analyze_stages("job").await?;
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[0].0, "stage_1");
+        assert_eq!(stages[1].0, "stage_2");
+        for (_, plan) in &stages {
+            assert!(plan.starts_with("tasks="));
+            assert!(plan.contains("ShuffleWriterExec"));
+        }
+        assert!(stages[1].1.contains("ShuffleReaderExec"));
+
+        Ok(())
+    }
+
+    // The shuffle data of a successful job is kept until the scheduler cleans it up
+    #[tokio::test]
+    async fn test_active_jobs() -> Result<()> {
+        let plan = test_plan();
+
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 4, 1, None).await?;
+        let status = test.run("job", &plan).await.expect("running plan");
+        assert!(matches!(
+            status.status,
+            Some(job_status::Status::Successful(_))
+        ));
+        let now = timestamp_millis();
+        assert_eq!(test.active_jobs(now).await?, vec!["job".to_owned()]);
+
+        // the data is cleaned up once the clean up interval has passed since the job ended
+        let clean_up_interval_ms =
+            SchedulerConfig::default().finished_job_data_clean_up_interval_seconds * 1000;
+        assert!(test
+            .active_jobs(now + clean_up_interval_ms)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    // Partitions written into the work dir of a shuffle service are served by it, so they
+    // outlive the executors which wrote them
+    #[tokio::test]
+    async fn test_shuffle_service_locations() -> Result<()> {
+        let plan = test_plan();
+
+        let default_runner = default_task_runner();
+        let runner = Arc::new(TaskRunnerFn::new(
+            move |executor_id: String, task: MultiTaskDefinition| {
+                let mut statuses = default_runner.run(executor_id, task);
+                for status in &mut statuses {
+                    if let Some(task_status::Status::Successful(task)) = &mut status.status {
+                        task.shuffle_service_id = "shuffle-service".to_owned();
+                    }
+                }
+                statuses
+            },
+        ));
+
+        let mut test = SchedulerTest::new(SchedulerConfig::default(), 4, 1, Some(runner)).await?;
+        let shuffle_service = ExecutorMetadata {
+            id: "shuffle-service".to_owned(),
+            host: "shuffle-host".to_owned(),
+            port: 50052,
+            grpc_port: 50053,
+            specification: ExecutorSpecification { task_slots: 0 },
+        };
+        test.scheduler()
+            .state
+            .executor_manager
+            .register_executor(
+                shuffle_service.clone(),
+                ExecutorData {
+                    executor_id: shuffle_service.id.clone(),
+                    total_task_slots: 0,
+                    available_task_slots: 0,
+                },
+            )
+            .await?;
+
+        let status = test.run("job", &plan).await.expect("running plan");
+        let Some(job_status::Status::Successful(SuccessfulJob {
+            partition_location, ..
+        })) = status.status
+        else {
+            panic!("Expected success status but found {status:?}");
+        };
+        assert_eq!(partition_location.len(), 4);
+        for location in &partition_location {
+            let executor_meta = location.executor_meta.as_ref().unwrap();
+            assert_eq!(executor_meta.id, shuffle_service.id);
+            assert_eq!(executor_meta.host, shuffle_service.host);
+        }
+
+        // losing the executors keeps the completed map stage, whose output is still served
+        for executor_id in 0..4 {
+            test.scheduler()
+                .state
+                .remove_executor(&format!("virtual-executor-{executor_id}"), None)
+                .await;
+        }
+        let graph = test
+            .scheduler()
+            .state
+            .task_manager
+            .get_job_execution_graph("job")
+            .await?
+            .expect("execution graph");
+        assert!(matches!(graph.stages()[&1], ExecutionStage::Successful(_)));
+        let ExecutionStage::Successful(final_stage) = &graph.stages()[&2] else {
+            panic!("Expected the final stage to be successful");
+        };
+        let map_locations = final_stage.inputs[&1]
+            .partition_locations
+            .values()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert!(!map_locations.is_empty());
+        for location in map_locations {
+            assert_eq!(location.executor_meta, shuffle_service);
+        }
+        assert!(matches!(
+            test.job_status("job")
+                .await?
+                .and_then(|status| status.status),
+            Some(job_status::Status::Successful(_))
+        ));
+
+        Ok(())
+    }
+
     // Simulate a task failure and ensure the job status is updated correctly
     #[tokio::test]
     async fn test_job_failure() -> Result<()> {
@@ -15,33 +15,154 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
+use tracing::{info_span, Instrument};
 
 use ballista_core::error::{BallistaError, Result};
 use ballista_core::event_loop::{EventAction, EventSender};
+use ballista_core::serde::protobuf::{job_status, JobStatus};
 
 use crate::config::SchedulerConfig;
 use crate::scheduler_server::timestamp_millis;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::prelude::SessionContext;
 use datafusion_proto::logical_plan::AsLogicalPlan;
 use datafusion_proto::physical_plan::AsExecutionPlan;
+use parking_lot::Mutex;
 use tokio::sync::mpsc;
 
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
 
+use crate::state::job_events::JobEvent;
+use crate::state::session_manager::session_tenant;
 use crate::state::SchedulerState;
 
+/// A queued job which its tenant's quota doesn't admit yet
+struct ThrottledJob {
+    job_id: String,
+    session_ctx: Arc<SessionContext>,
+    plan: Box<LogicalPlan>,
+    queued_at: u64,
+}
+
 pub(crate) struct QueryStageScheduler<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> {
     state: Arc<SchedulerState<T, U>>,
     #[allow(dead_code)]
     config: Arc<SchedulerConfig>,
+    // Jobs waiting for their tenant's quota in the order they were queued. They are
+    // admitted again once a job leaves the active jobs and frees part of a quota.
+    throttled_jobs: Mutex<VecDeque<ThrottledJob>>,
 }
 
 impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> QueryStageScheduler<T, U> {
     pub(crate) fn new(state: Arc<SchedulerState<T, U>>, config: Arc<SchedulerConfig>) -> Self {
-        Self { state, config }
+        Self {
+            state,
+            config,
+            throttled_jobs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Plan a queued job if its tenant's quota admits it, otherwise keep it throttled
+    async fn admit_job(
+        &self,
+        job: ThrottledJob,
+        event_sender: &EventSender<QueryStageSchedulerEvent>,
+    ) {
+        let ThrottledJob {
+            job_id,
+            session_ctx,
+            plan,
+            queued_at,
+        } = job;
+
+        let tenant = session_tenant(&session_ctx);
+        if let Some(reason) = self.state.check_tenant_admission(&tenant).await {
+            info!(
+                "Job {} of tenant {} stays queued: {}",
+                job_id, tenant, reason
+            );
+            self.throttled_jobs.lock().push_back(ThrottledJob {
+                job_id,
+                session_ctx,
+                plan,
+                queued_at,
+            });
+            return;
+        }
+        self.state.task_manager.admit_job(&job_id, &tenant);
+
+        let state = self.state.clone();
+        let event_sender = event_sender.clone();
+        let plan_span = info_span!(
+            parent: &state.task_manager.job_span(&job_id),
+            "plan_job",
+            job_id = job_id.as_str()
+        );
+        tokio::spawn(async move {
+            let result = state
+                .submit_job(&job_id, session_ctx, &plan, queued_at)
+                .instrument(plan_span)
+                .await;
+            state.task_manager.complete_admission(&job_id);
+            let event = if let Err(e) = result {
+                let fail_message = format!("Error planning job {job_id}: {e:?}");
+                error!("{}", &fail_message);
+                QueryStageSchedulerEvent::JobPlanningFailed {
+                    job_id,
+                    fail_message,
+                    queued_at,
+                    failed_at: timestamp_millis(),
+                }
+            } else {
+                QueryStageSchedulerEvent::JobSubmitted {
+                    job_id,
+                    queued_at,
+                    submitted_at: timestamp_millis(),
+                }
+            };
+            if let Err(e) = event_sender.post_event(event).await {
+                error!("Fail to send event due to {}", e);
+            }
+        });
+    }
+
+    /// Try to admit the throttled jobs again after a job left the active jobs. Jobs which
+    /// are still over their tenant's quota are throttled again in the same order.
+    async fn admit_throttled_jobs(&self, event_sender: &EventSender<QueryStageSchedulerEvent>) {
+        let throttled_jobs = std::mem::take(&mut *self.throttled_jobs.lock());
+        for job in throttled_jobs {
+            self.admit_job(job, event_sender).await;
+        }
+    }
+
+    /// Remove a job from the throttled jobs, returning whether it was throttled
+    fn remove_throttled_job(&self, job_id: &str) -> bool {
+        let mut throttled_jobs = self.throttled_jobs.lock();
+        let len = throttled_jobs.len();
+        throttled_jobs.retain(|job| job.job_id != job_id);
+        throttled_jobs.len() != len
+    }
+
+    /// Whether the job is still queued or running, so that it can be cancelled
+    async fn is_unfinished(&self, job_id: &str) -> bool {
+        matches!(
+            self.state.task_manager.get_job_status(job_id).await,
+            Ok(Some(JobStatus {
+                status: Some(job_status::Status::Queued(_) | job_status::Status::Running(_)),
+                ..
+            }))
+        )
+    }
+
+    fn publish(&self, job_event: Option<JobEvent>) {
+        if let Some(job_event) = job_event {
+            self.state.job_events.publish(job_event);
+        }
     }
 }
 
@@ -63,7 +184,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
         tx_event: &mpsc::Sender<QueryStageSchedulerEvent>,
         _rx_event: &mpsc::Receiver<QueryStageSchedulerEvent>,
     ) -> Result<()> {
+        if let Some(event_log) = &self.state.event_log {
+            event_log.record_event(&event, &self.state.codec);
+        }
         let event_sender = EventSender::new(tx_event.clone());
+        let job_event = JobEvent::from_scheduler_event(&event);
         match event {
             QueryStageSchedulerEvent::JobQueued {
                 job_id,
@@ -72,40 +197,23 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                 queued_at,
             } => {
                 info!("Job {} queued", job_id);
-
                 if let Err(e) = self.state.task_manager.queue_job(&job_id, queued_at) {
                     error!("Fail to queue job {} due to {:?}", job_id, e);
                     return Ok(());
                 }
+                self.publish(job_event);
 
-                let state = self.state.clone();
-                tokio::spawn(async move {
-                    let event = if let Err(e) = state
-                        .submit_job(&job_id, session_ctx, &plan, queued_at)
-                        .await
-                    {
-                        let fail_message = format!("Error planning job {job_id}: {e:?}");
-                        error!("{}", &fail_message);
-                        QueryStageSchedulerEvent::JobPlanningFailed {
-                            job_id,
-                            fail_message,
-                            queued_at,
-                            failed_at: timestamp_millis(),
-                        }
-                    } else {
-                        QueryStageSchedulerEvent::JobSubmitted {
-                            job_id,
-                            queued_at,
-                            submitted_at: timestamp_millis(),
-                        }
-                    };
-                    if let Err(e) = event_sender.post_event(event).await {
-                        error!("Fail to send event due to {}", e);
-                    }
-                });
+                let job = ThrottledJob {
+                    job_id,
+                    session_ctx,
+                    plan,
+                    queued_at,
+                };
+                self.admit_job(job, &event_sender).await;
             }
             QueryStageSchedulerEvent::JobSubmitted { job_id, .. } => {
                 info!("Job {} submitted", job_id);
+                self.publish(job_event);
 
                 event_sender
                     .post_event(QueryStageSchedulerEvent::ReviveOffers)
@@ -128,6 +236,9 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                         job_id, e
                     );
                 }
+                self.publish(job_event);
+                self.state.archive_job(job_id);
+                self.admit_throttled_jobs(&event_sender).await;
             }
             QueryStageSchedulerEvent::JobFinished { job_id, .. } => {
                 info!("Job {} success", job_id);
@@ -137,7 +248,11 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                         job_id, e
                     );
                 }
+                // Subscribers may look up the job status as soon as they see the event
+                self.publish(job_event);
+                self.state.archive_job(job_id.clone());
                 self.state.clean_up_successful_job(job_id);
+                self.admit_throttled_jobs(&event_sender).await;
             }
             QueryStageSchedulerEvent::JobRunningFailed {
                 job_id,
@@ -162,7 +277,10 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                         error!("Fail to invoke abort_job for job {} due to {:?}", job_id, e);
                     }
                 }
+                self.publish(job_event);
+                self.state.archive_job(job_id.clone());
                 self.state.clean_up_failed_job(job_id);
+                self.admit_throttled_jobs(&event_sender).await;
             }
             QueryStageSchedulerEvent::JobUpdated(job_id) => {
                 info!("Job {} Updated", job_id);
@@ -172,23 +290,46 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                         job_id, e
                     );
                 }
+                self.state.publish_job_progress(&job_id, true).await;
             }
             QueryStageSchedulerEvent::JobCancel(job_id) => {
+                if !self.is_unfinished(&job_id).await {
+                    info!("Job {} is not queued or running, not cancelling it", job_id);
+                    return Ok(());
+                }
                 info!("Job {} Cancelled", job_id);
-                match self.state.task_manager.cancel_job(&job_id).await {
-                    Ok((running_tasks, _pending_tasks)) => {
-                        event_sender
-                            .post_event(QueryStageSchedulerEvent::CancelTasks(running_tasks))
-                            .await?;
-                    }
-                    Err(e) => {
+                if self.remove_throttled_job(&job_id) {
+                    // A throttled job has not been planned yet, so it has no tasks to cancel
+                    if let Err(e) = self
+                        .state
+                        .task_manager
+                        .fail_unscheduled_job(&job_id, "Cancelled".to_owned())
+                        .await
+                    {
                         error!(
-                            "Fail to invoke cancel_job for job {} due to {:?}",
+                            "Fail to invoke fail_unscheduled_job for job {} due to {:?}",
                             job_id, e
                         );
                     }
+                } else {
+                    match self.state.task_manager.cancel_job(&job_id).await {
+                        Ok((running_tasks, _pending_tasks)) => {
+                            event_sender
+                                .post_event(QueryStageSchedulerEvent::CancelTasks(running_tasks))
+                                .await?;
+                        }
+                        Err(e) => {
+                            error!(
+                                "Fail to invoke cancel_job for job {} due to {:?}",
+                                job_id, e
+                            );
+                        }
+                    }
                 }
+                self.publish(job_event);
+                self.state.archive_job(job_id.clone());
                 self.state.clean_up_failed_job(job_id);
+                self.admit_throttled_jobs(&event_sender).await;
             }
             QueryStageSchedulerEvent::TaskUpdating(executor_id, tasks_status) => {
                 debug!(
@@ -197,6 +338,10 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                 );
 
                 let num_status = tasks_status.len();
+                let job_ids: HashSet<String> = tasks_status
+                    .iter()
+                    .map(|status| status.job_id.clone())
+                    .collect();
                 self.state
                     .executor_manager
                     .unbind_tasks(vec![(executor_id.clone(), num_status as u32)])
@@ -211,6 +356,10 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
                             .post_event(QueryStageSchedulerEvent::ReviveOffers)
                             .await?;
 
+                        for job_id in job_ids {
+                            self.state.publish_job_progress(&job_id, false).await;
+                        }
+
                         for stage_event in stage_events {
                             event_sender.post_event(stage_event).await?;
                         }
@@ -270,8 +419,14 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> EventAction<Query
 #[cfg(test)]
 mod tests {
     use crate::config::SchedulerConfig;
+    use crate::scheduler_server::event_log::{read_event_log, EventLogEntry, LoggedEvent};
+    use crate::scheduler_server::event_log_replay::EventLogReplay;
     use crate::test_utils::{await_condition, SchedulerTest};
+    use ballista_core::config::{
+        BallistaConfig, BALLISTA_DEFAULT_SHUFFLE_PARTITIONS, BALLISTA_SHUFFLE_SORT_BASED,
+    };
     use ballista_core::error::Result;
+    use ballista_core::serde::protobuf::job_status::Status;
     use datafusion::arrow::datatypes::{DataType, Field, Schema};
     use datafusion::logical_expr::{col, sum, LogicalPlan};
     use datafusion::test_util::scan_empty_with_partitions;
@@ -322,6 +477,137 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_replay_event_log() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let recorded_log = dir.path().join("recorded-event-log");
+        let replayed_log = dir.path().join("replayed-event-log");
+        let plan = test_plan(4);
+
+        let config = SchedulerConfig::default().with_event_log_path(recorded_log.to_string_lossy());
+        let mut test = SchedulerTest::new(config, 2, 2, None).await?;
+        let status = test.run("job-1", &plan).await?;
+        assert!(matches!(status.status, Some(Status::Successful(_))));
+
+        // The job succeeds before the scheduler has received the JobFinished event
+        let finished = await_condition(Duration::from_millis(10), 100, || {
+            let records = read_event_log(&recorded_log);
+            futures::future::ready(records.map(|records| {
+                records.iter().any(|record| {
+                    matches!(
+                        record.entry,
+                        EventLogEntry::Event {
+                            event: LoggedEvent::JobFinished { .. }
+                        }
+                    )
+                })
+            }))
+        })
+        .await?;
+        assert!(finished);
+
+        let config = SchedulerConfig::default().with_event_log_path(replayed_log.to_string_lossy());
+        let replay = EventLogReplay::try_new(&recorded_log)?.with_plan("job-1", plan);
+        let replayed = SchedulerTest::with_scheduler(replay.run(config, 2).await?)?;
+        let status = replayed.await_completion_timeout("job-1", 5000).await?;
+        assert!(matches!(status.status, Some(Status::Successful(_))));
+        for test in [&test, &replayed] {
+            if let Some(event_log) = &test.scheduler().state.event_log {
+                event_log.flush()?;
+            }
+        }
+
+        let transitions = |path: &std::path::PathBuf| -> Result<Vec<EventLogEntry>> {
+            Ok(read_event_log(path)?
+                .into_iter()
+                .map(|record| record.entry)
+                .filter(|entry| !matches!(entry, EventLogEntry::Event { .. }))
+                .collect())
+        };
+        let recorded_transitions = transitions(&recorded_log)?;
+        assert!(
+            recorded_transitions.contains(&EventLogEntry::StageTransition {
+                job_id: "job-1".to_owned(),
+                stage_id: 1,
+                from: "Running".to_owned(),
+                to: "Successful".to_owned(),
+            })
+        );
+        assert_eq!(recorded_transitions, transitions(&replayed_log)?);
+
+        Ok(())
+    }
+
+    // Jobs are replayed with the settings of their sessions in the recorded run rather than
+    // with the defaults of the replaying scheduler
+    #[tokio::test]
+    async fn test_replay_event_log_with_session_settings() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let recorded_log = dir.path().join("recorded-event-log");
+        let plan = test_plan(4);
+
+        let config = SchedulerConfig::default().with_event_log_path(recorded_log.to_string_lossy());
+        let mut test = SchedulerTest::new(config, 2, 2, None).await?;
+        let session_config = BallistaConfig::builder()
+            .set(BALLISTA_DEFAULT_SHUFFLE_PARTITIONS, "3")
+            .set(BALLISTA_SHUFFLE_SORT_BASED, "true")
+            .build()?;
+        let ctx = test
+            .scheduler()
+            .state
+            .session_manager
+            .create_session(&session_config)
+            .await?;
+        test.scheduler().submit_job("job-1", ctx, &plan).await?;
+        test.process_task_statuses();
+        let status = test.await_completion("job-1").await?;
+        let Some(Status::Successful(recorded)) = status.status else {
+            panic!("Expected the job to succeed, found {status:?}");
+        };
+        assert_eq!(recorded.partition_location.len(), 3);
+
+        let finished = await_condition(Duration::from_millis(10), 100, || {
+            let records = read_event_log(&recorded_log);
+            futures::future::ready(records.map(|records| {
+                records.iter().any(|record| {
+                    matches!(
+                        record.entry,
+                        EventLogEntry::Event {
+                            event: LoggedEvent::JobFinished { .. }
+                        }
+                    )
+                })
+            }))
+        })
+        .await?;
+        assert!(finished);
+        let queued_settings = read_event_log(&recorded_log)?
+            .into_iter()
+            .find_map(|record| match record.entry {
+                EventLogEntry::Event {
+                    event: LoggedEvent::JobQueued { settings, .. },
+                } => Some(settings),
+                _ => None,
+            })
+            .expect("queued job");
+        assert_eq!(
+            queued_settings.get(BALLISTA_SHUFFLE_SORT_BASED),
+            Some(&"true".to_owned())
+        );
+
+        // the replaying scheduler defaults to 4 shuffle partitions, one per task slot
+        let replay = EventLogReplay::try_new(&recorded_log)?.with_plan("job-1", plan);
+        let replayed =
+            SchedulerTest::with_scheduler(replay.run(SchedulerConfig::default(), 2).await?)?;
+        let status = replayed.await_completion_timeout("job-1", 5000).await?;
+        let Some(Status::Successful(replayed_job)) = status.status else {
+            panic!("Expected the replayed job to succeed, found {status:?}");
+        };
+        assert_eq!(replayed_job.partition_location.len(), 3);
+
+        Ok(())
+    }
+
     fn test_plan(partitions: usize) -> LogicalPlan {
         let schema = Schema::new(vec![
             Field::new("id", DataType::Utf8, false),
@@ -0,0 +1,262 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The catalog of external tables shared by all sessions of the schedulers in a namespace
+
+use ballista_core::error::{BallistaError, Result};
+use base64::Engine;
+use datafusion::common::OwnedTableReference;
+use datafusion::logical_expr::{CreateExternalTable, DdlStatement, LogicalPlan};
+use datafusion::prelude::SessionContext;
+use datafusion_proto::bytes::{logical_plan_from_bytes, logical_plan_to_bytes};
+use log::warn;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// An external table of the shared catalog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogTable {
+    pub catalog: String,
+    pub schema: String,
+    pub table: String,
+    /// Assigned when the table is saved, it increases with every change of the table and
+    /// is not reused when the table is dropped and created again
+    pub version: u64,
+    pub location: String,
+    pub file_type: String,
+    pub partition_cols: Vec<String>,
+    pub options: HashMap<String, String>,
+    pub columns: Vec<CatalogColumn>,
+    /// The base64 encoded `CREATE EXTERNAL TABLE` plan registering the table in a session
+    definition: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogColumn {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+impl CatalogTable {
+    /// Describe a table which was just created in the session by `cmd`
+    pub async fn try_new(ctx: &SessionContext, cmd: &CreateExternalTable) -> Result<Self> {
+        let reference = resolve_table(ctx, &cmd.name);
+        let provider = ctx.table_provider(reference.clone()).await?;
+        let columns = provider
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| CatalogColumn {
+                name: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                nullable: field.is_nullable(),
+            })
+            .collect();
+
+        let plan = LogicalPlan::Ddl(DdlStatement::CreateExternalTable(cmd.clone()));
+        let definition =
+            base64::engine::general_purpose::STANDARD.encode(logical_plan_to_bytes(&plan)?);
+
+        Ok(Self {
+            catalog: reference.catalog().unwrap_or_default().to_owned(),
+            schema: reference.schema().unwrap_or_default().to_owned(),
+            table: reference.table().to_owned(),
+            version: 0,
+            location: cmd.location.clone(),
+            file_type: cmd.file_type.clone(),
+            partition_cols: cmd.table_partition_cols.clone(),
+            options: cmd.options.clone(),
+            columns,
+            definition,
+        })
+    }
+
+    /// The fully qualified name of the table, which it is stored under
+    pub fn name(&self) -> String {
+        self.reference().to_string()
+    }
+
+    pub fn reference(&self) -> OwnedTableReference {
+        OwnedTableReference::full(
+            self.catalog.clone(),
+            self.schema.clone(),
+            self.table.clone(),
+        )
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self)
+            .map_err(|e| BallistaError::Internal(format!("Could not encode catalog table: {e}")))
+    }
+
+    pub fn decode(value: &[u8]) -> Result<Self> {
+        serde_json::from_slice(value)
+            .map_err(|e| BallistaError::Internal(format!("Could not decode catalog table: {e}")))
+    }
+
+    /// Register the table in a session, replacing any table of the same name
+    async fn register(&self, ctx: &SessionContext) -> Result<()> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&self.definition)
+            .map_err(|e| {
+                BallistaError::Internal(format!("Could not decode table definition: {e}"))
+            })?;
+        let plan = logical_plan_from_bytes(&bytes, ctx)?;
+        ctx.deregister_table(self.reference())?;
+        ctx.execute_logical_plan(plan).await?;
+        Ok(())
+    }
+}
+
+/// Qualify a table reference with the default catalog and schema of the session
+pub fn resolve_table(ctx: &SessionContext, reference: &OwnedTableReference) -> OwnedTableReference {
+    let config = ctx.copied_config();
+    let defaults = &config.options().catalog;
+    let resolved = reference
+        .clone()
+        .resolve(&defaults.default_catalog, &defaults.default_schema);
+    OwnedTableReference::full(
+        resolved.catalog.into_owned(),
+        resolved.schema.into_owned(),
+        resolved.table.into_owned(),
+    )
+}
+
+/// The versions of the shared catalog tables registered in a session, kept as an
+/// extension of its config
+#[derive(Debug, Default)]
+pub struct SessionCatalog {
+    versions: Mutex<HashMap<OwnedTableReference, u64>>,
+    /// Version of the shared catalog which the session was last synced with
+    catalog_version: Mutex<Option<u64>>,
+}
+
+impl SessionCatalog {
+    pub fn set_version(&self, reference: OwnedTableReference, version: u64) {
+        self.versions.lock().insert(reference, version);
+    }
+
+    pub fn remove(&self, reference: &OwnedTableReference) {
+        self.versions.lock().remove(reference);
+    }
+
+    fn version(&self, reference: &OwnedTableReference) -> Option<u64> {
+        self.versions.lock().get(reference).copied()
+    }
+
+    /// Whether the session was already synced with this version of the shared catalog
+    pub fn is_synced(&self, catalog_version: u64) -> bool {
+        *self.catalog_version.lock() == Some(catalog_version)
+    }
+
+    pub fn set_synced(&self, catalog_version: u64) {
+        *self.catalog_version.lock() = Some(catalog_version);
+    }
+
+    /// Bring the tables of a session up to date with the shared catalog. Tables which
+    /// can't be registered in the session, e.g. because their schema does not exist in
+    /// it, are left out.
+    pub async fn sync(&self, ctx: &SessionContext, tables: &[CatalogTable]) -> Result<()> {
+        let current: HashSet<_> = tables.iter().map(|table| table.reference()).collect();
+        let dropped: Vec<_> = self
+            .versions
+            .lock()
+            .keys()
+            .filter(|reference| !current.contains(*reference))
+            .cloned()
+            .collect();
+        for reference in dropped {
+            ctx.deregister_table(reference.clone())?;
+            self.remove(&reference);
+        }
+
+        for table in tables {
+            let reference = table.reference();
+            if self.version(&reference) == Some(table.version)
+                && ctx.table_exist(reference.clone())?
+            {
+                continue;
+            }
+            match table.register(ctx).await {
+                Ok(()) => self.set_version(reference, table.version),
+                Err(e) => warn!("Could not register catalog table {}: {e}", table.name()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cluster::kv::KeyValueState;
+    use crate::cluster::storage::sled::SledClient;
+    use crate::cluster::JobState;
+    use crate::state::session_manager::SessionManager;
+    use ballista_core::config::BallistaConfig;
+    use ballista_core::error::Result;
+    use ballista_core::serde::BallistaCodec;
+    use ballista_core::utils::default_session_builder;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn tables_are_shared_between_sessions() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("data.csv"), "a,b\n1,x\n2,y\n")?;
+
+        let state: Arc<dyn JobState> = Arc::new(KeyValueState::new(
+            "",
+            SledClient::try_new_temporary()?,
+            BallistaCodec::default(),
+            default_session_builder,
+        ));
+        let session_manager = SessionManager::new(state.clone());
+        let config = BallistaConfig::new()?;
+
+        let first = session_manager.create_session(&config).await?;
+        let sql = format!(
+            "CREATE EXTERNAL TABLE t STORED AS CSV WITH HEADER ROW LOCATION '{}'",
+            dir.path().display()
+        );
+        let plan = first.state().create_logical_plan(&sql).await?;
+        session_manager.execute_logical_plan(&first, plan).await?;
+
+        let tables = state.get_catalog_tables().await?;
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].name(), "datafusion.public.t");
+        assert_eq!(tables[0].file_type, "CSV");
+        assert_eq!(tables[0].columns.len(), 2);
+        assert!(tables[0].version > 0);
+        let catalog_version = state.get_catalog_version().await?;
+
+        let second = session_manager.create_session(&config).await?;
+        let rows = second.sql("SELECT * FROM t").await?.collect().await?;
+        assert_eq!(rows.iter().map(|batch| batch.num_rows()).sum::<usize>(), 2);
+
+        let plan = second.state().create_logical_plan("DROP TABLE t").await?;
+        session_manager.execute_logical_plan(&second, plan).await?;
+        assert!(state.get_catalog_tables().await?.is_empty());
+        assert_ne!(state.get_catalog_version().await?, catalog_version);
+
+        assert!(first.table_exist("t")?);
+        session_manager.refresh_catalog(&first).await?;
+        assert!(!first.table_exist("t")?);
+
+        Ok(())
+    }
+}
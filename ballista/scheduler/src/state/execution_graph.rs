@@ -21,11 +21,13 @@ use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::physical_plan::display::DisplayableExecutionPlan;
 use datafusion::physical_plan::{accept, ExecutionPlan, ExecutionPlanVisitor};
 use datafusion::prelude::SessionContext;
 use datafusion_proto::logical_plan::AsLogicalPlan;
 use log::{debug, info, warn};
+use tracing::info_span;
 
 use ballista_core::error::{BallistaError, Result};
 use ballista_core::execution_plans::{ShuffleWriterExec, UnresolvedShuffleExec};
@@ -41,14 +43,17 @@ use ballista_core::serde::scheduler::{
 use ballista_core::serde::BallistaCodec;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 
-use crate::display::print_stage_metrics;
+use crate::display::{display_analyzed_stage, print_stage_metrics};
 use crate::planner::DistributedPlanner;
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
+use crate::scheduler_server::event_log::EventLog;
 use crate::scheduler_server::timestamp_millis;
 use crate::state::execution_graph::execution_stage::RunningStage;
 pub(crate) use crate::state::execution_graph::execution_stage::{
-    ExecutionStage, ResolvedStage, SuccessfulStage, TaskInfo, UnresolvedStage,
+    ExecutionStage, ResolvedStage, StageOutput, SuccessfulStage, TaskInfo, UnresolvedStage,
 };
+use crate::state::job_events::JobProgress;
+use crate::state::job_history::status_and_reason;
 use crate::state::task_manager::UpdatedStages;
 
 mod execution_stage;
@@ -120,6 +125,8 @@ pub struct ExecutionGraph {
     output_locations: Vec<PartitionLocation>,
     /// Task ID generator, generate unique TID in the execution graph
     task_id_gen: usize,
+    /// Where to record the state transitions of the job and its stages, not persisted
+    event_log: Option<EventLog>,
 }
 
 #[derive(Clone, Debug)]
@@ -179,9 +186,17 @@ impl ExecutionGraph {
             output_partitions,
             output_locations: vec![],
             task_id_gen: 0,
+            event_log: None,
         })
     }
 
+    /// Record the state transitions of the job and its stages from now on
+    pub(crate) fn set_event_log(&mut self, event_log: EventLog) {
+        let (status, _) = status_and_reason(&self.status);
+        event_log.record_job_transition(&self.job_id, "Queued", &status);
+        self.event_log = Some(event_log);
+    }
+
     pub fn job_id(&self) -> &str {
         self.job_id.as_str()
     }
@@ -194,6 +209,10 @@ impl ExecutionGraph {
         &self.status
     }
 
+    pub fn queued_at(&self) -> u64 {
+        self.queued_at
+    }
+
     pub fn start_time(&self) -> u64 {
         self.start_time
     }
@@ -242,6 +261,7 @@ impl ExecutionGraph {
             false
         } else {
             for running_stage in running_stages {
+                self.record_stage_transition(running_stage.stage_id, "Resolved", "Running");
                 self.stages.insert(
                     running_stage.stage_id,
                     ExecutionStage::Running(running_stage),
@@ -491,6 +511,57 @@ impl ExecutionGraph {
             .collect::<Vec<RunningTaskInfo>>()
     }
 
+    /// Task counts of the stages of this job
+    pub fn progress(&self) -> JobProgress {
+        JobProgress::new(self.stages.values().map(|stage| stage.progress()).collect())
+    }
+
+    /// Total number of shuffle bytes written by the finished tasks of this job
+    pub fn shuffle_bytes(&self) -> u64 {
+        self.stages
+            .values()
+            .map(|stage| match stage {
+                ExecutionStage::Running(stage) => stage
+                    .task_infos
+                    .iter()
+                    .flatten()
+                    .map(|info| info.shuffle_bytes())
+                    .sum(),
+                ExecutionStage::Successful(stage) => stage
+                    .task_infos
+                    .iter()
+                    .map(|info| info.shuffle_bytes())
+                    .sum(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Render every successful stage of this job with its aggregated operator metrics
+    /// and task summary, as `(plan_type, plan)` pairs ordered by stage id.
+    pub fn analyze_stages(&self) -> Vec<(String, String)> {
+        let mut stages = self
+            .stages
+            .values()
+            .filter_map(|stage| match stage {
+                ExecutionStage::Successful(stage) => Some(stage),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        stages.sort_by_key(|stage| stage.stage_id);
+        stages
+            .into_iter()
+            .map(|stage| {
+                let plan = display_analyzed_stage(
+                    stage.plan.as_ref(),
+                    &stage.stage_metrics,
+                    &stage.task_summary(),
+                );
+                (format!("stage_{}", stage.stage_id), plan)
+            })
+            .collect()
+    }
+
     /// Total number of tasks in this plan that are ready for scheduling
     pub fn available_tasks(&self) -> usize {
         self.stages
@@ -562,11 +633,21 @@ impl ExecutionGraph {
         self.output_locations.clone()
     }
 
+    /// Schema of the output of the job, which is written by the final stage
+    pub fn output_schema(&self) -> Option<SchemaRef> {
+        self.stages
+            .values()
+            .find(|stage| stage.output_links().is_empty())
+            .map(|stage| stage.plan().schema())
+    }
+
     /// Convert unresolved stage to be resolved
     pub fn resolve_stage(&mut self, stage_id: usize) -> Result<bool> {
+        let _span = info_span!("resolve_stage", stage_id).entered();
         if let Some(ExecutionStage::UnResolved(stage)) = self.stages.remove(&stage_id) {
             self.stages
                 .insert(stage_id, ExecutionStage::Resolved(stage.to_resolved()?));
+            self.record_stage_transition(stage_id, "Unresolved", "Resolved");
             Ok(true)
         } else {
             warn!(
@@ -583,6 +664,7 @@ impl ExecutionGraph {
         if let Some(ExecutionStage::Running(stage)) = self.stages.remove(&stage_id) {
             self.stages
                 .insert(stage_id, ExecutionStage::Successful(stage.to_successful()));
+            self.record_stage_transition(stage_id, "Running", "Successful");
             true
         } else {
             warn!(
@@ -596,6 +678,7 @@ impl ExecutionGraph {
 
     /// fail job with error message
     pub fn fail_job(&mut self, error: String) {
+        let (from, _) = status_and_reason(&self.status);
         self.status = JobStatus {
             job_id: self.job_id.clone(),
             status: Some(Status::Failed(FailedJob {
@@ -605,6 +688,7 @@ impl ExecutionGraph {
                 ended_at: self.end_time,
             })),
         };
+        self.record_job_transition(&from);
     }
 
     /// Mark the job success
@@ -616,8 +700,11 @@ impl ExecutionGraph {
             )));
         }
 
-        let partition_location = self
-            .output_locations()
+        // The locations are reported in the order the tasks completed in, while the output
+        // of a sort is ordered across the output partitions
+        let mut output_locations = self.output_locations();
+        output_locations.sort_by_key(|l| (l.partition_id.partition_id, l.map_partition_id));
+        let partition_location = output_locations
             .into_iter()
             .map(|l| l.try_into())
             .collect::<Result<Vec<_>>>()?;
@@ -636,10 +723,24 @@ impl ExecutionGraph {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis() as u64;
+        self.record_job_transition("Running");
 
         Ok(())
     }
 
+    fn record_job_transition(&self, from: &str) {
+        if let Some(event_log) = &self.event_log {
+            let (to, _) = status_and_reason(&self.status);
+            event_log.record_job_transition(&self.job_id, from, &to);
+        }
+    }
+
+    fn record_stage_transition(&self, stage_id: usize, from: &str, to: &str) {
+        if let Some(event_log) = &self.event_log {
+            event_log.record_stage_transition(&self.job_id, stage_id, from, to);
+        }
+    }
+
     pub(crate) async fn decode_execution_graph<
         T: 'static + AsLogicalPlan,
         U: 'static + AsExecutionPlan,
@@ -692,6 +793,7 @@ impl ExecutionGraph {
             output_partitions: proto.output_partitions as usize,
             output_locations,
             task_id_gen: proto.task_id_gen as usize,
+            event_log: None,
         })
     }
 
@@ -785,6 +887,7 @@ pub(crate) fn create_task_info(executor_id: String, task_id: usize) -> TaskInfo
         end_exec_time: 0,
         finish_time: 0,
         task_status: task_status::Status::Running(RunningTask { executor_id }),
+        metrics: vec![],
     }
 }
 
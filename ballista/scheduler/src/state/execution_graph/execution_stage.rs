@@ -41,7 +41,9 @@ use ballista_core::serde::scheduler::PartitionLocation;
 use ballista_core::serde::BallistaCodec;
 use datafusion_proto::physical_plan::AsExecutionPlan;
 
-use crate::display::DisplayableBallistaExecutionPlan;
+use crate::cluster::history::{StageSummary, TaskSummary};
+use crate::display::{display_stage_plan, DisplayableBallistaExecutionPlan, StageTaskSummary};
+use crate::state::job_events::StageProgress;
 
 /// A stage in the ExecutionGraph,
 /// represents a set of tasks (one per each `partition`) which can be executed concurrently.
@@ -81,6 +83,107 @@ impl ExecutionStage {
             ExecutionStage::Successful(_) => "Successful",
         }
     }
+
+    /// `ExecutionPlan` of the stage
+    pub(crate) fn plan(&self) -> &Arc<dyn ExecutionPlan> {
+        match self {
+            ExecutionStage::UnResolved(stage) => &stage.plan,
+            ExecutionStage::Resolved(stage) => &stage.plan,
+            ExecutionStage::Running(stage) => &stage.plan,
+            ExecutionStage::Successful(stage) => &stage.plan,
+        }
+    }
+
+    /// Stage IDs of the stages which take the output of this stage as input
+    pub(crate) fn output_links(&self) -> &[usize] {
+        match self {
+            ExecutionStage::UnResolved(stage) => &stage.output_links,
+            ExecutionStage::Resolved(stage) => &stage.output_links,
+            ExecutionStage::Running(stage) => &stage.output_links,
+            ExecutionStage::Successful(stage) => &stage.output_links,
+        }
+    }
+
+    /// Summarize the stage for the job history
+    pub(crate) fn summary(&self) -> StageSummary {
+        let (stage_id, partitions, plan, stage_metrics) = match self {
+            ExecutionStage::UnResolved(stage) => (
+                stage.stage_id,
+                stage.plan.output_partitioning().partition_count(),
+                &stage.plan,
+                &[][..],
+            ),
+            ExecutionStage::Resolved(stage) => {
+                (stage.stage_id, stage.partitions, &stage.plan, &[][..])
+            }
+            ExecutionStage::Running(stage) => (
+                stage.stage_id,
+                stage.partitions,
+                &stage.plan,
+                stage.stage_metrics.as_deref().unwrap_or_default(),
+            ),
+            ExecutionStage::Successful(stage) => (
+                stage.stage_id,
+                stage.partitions,
+                &stage.plan,
+                &stage.stage_metrics[..],
+            ),
+        };
+        StageSummary {
+            stage_id,
+            state: self.variant_name().to_owned(),
+            partitions,
+            plan: display_stage_plan(plan.as_ref(), stage_metrics),
+            tasks: self
+                .task_infos()
+                .into_iter()
+                .map(|(partition_id, info)| info.summary(partition_id))
+                .collect(),
+        }
+    }
+
+    /// Task counts of the stage for clients following the progress of the job
+    pub(crate) fn progress(&self) -> StageProgress {
+        let (stage_id, partitions, running_tasks, completed_tasks) = match self {
+            ExecutionStage::UnResolved(stage) => (
+                stage.stage_id,
+                stage.plan.output_partitioning().partition_count(),
+                0,
+                0,
+            ),
+            ExecutionStage::Resolved(stage) => (stage.stage_id, stage.partitions, 0, 0),
+            ExecutionStage::Running(stage) => (
+                stage.stage_id,
+                stage.partitions,
+                stage.running_tasks().len(),
+                stage.successful_tasks(),
+            ),
+            ExecutionStage::Successful(stage) => {
+                (stage.stage_id, stage.partitions, 0, stage.partitions)
+            }
+        };
+        StageProgress {
+            stage_id,
+            state: self.variant_name().to_owned(),
+            partitions,
+            running_tasks,
+            completed_tasks,
+        }
+    }
+
+    /// The scheduled tasks of the stage along with their partition id
+    pub(crate) fn task_infos(&self) -> Vec<(usize, &TaskInfo)> {
+        match self {
+            ExecutionStage::UnResolved(_) | ExecutionStage::Resolved(_) => vec![],
+            ExecutionStage::Running(stage) => stage
+                .task_infos
+                .iter()
+                .enumerate()
+                .filter_map(|(partition_id, info)| info.as_ref().map(|info| (partition_id, info)))
+                .collect(),
+            ExecutionStage::Successful(stage) => stage.task_infos.iter().enumerate().collect(),
+        }
+    }
 }
 
 /// For a stage whose input stages are not all completed, we say it's a unresolved stage
@@ -180,6 +283,53 @@ pub(crate) struct TaskInfo {
     pub(super) finish_time: u128,
     /// Task Status
     pub(super) task_status: task_status::Status,
+    /// Operator metrics reported by the executor when the task finished
+    pub(super) metrics: Vec<OperatorMetricsSet>,
+}
+
+impl TaskInfo {
+    /// Number of shuffle bytes written by the task if it has finished successfully
+    pub(super) fn shuffle_bytes(&self) -> u64 {
+        if let task_status::Status::Successful(task) = &self.task_status {
+            task.partitions.iter().map(|p| p.num_bytes).sum()
+        } else {
+            0
+        }
+    }
+
+    /// Execution time of the task in milliseconds
+    pub(super) fn exec_duration_ms(&self) -> u64 {
+        self.end_exec_time.saturating_sub(self.start_exec_time) as u64
+    }
+
+    /// Operator metrics reported by the executor for the task, empty while it is running
+    pub(crate) fn metrics(&self) -> Result<Vec<MetricsSet>> {
+        self.metrics
+            .iter()
+            .cloned()
+            .map(|metrics| metrics.try_into())
+            .collect()
+    }
+
+    /// Summarize the task timings for the job history
+    pub(crate) fn summary(&self, partition_id: usize) -> TaskSummary {
+        let (status, executor_id) = match &self.task_status {
+            task_status::Status::Running(task) => ("Running", Some(task.executor_id.clone())),
+            task_status::Status::Failed(_) => ("Failed", None),
+            task_status::Status::Successful(task) => ("Successful", Some(task.executor_id.clone())),
+        };
+        TaskSummary {
+            task_id: self.task_id,
+            partition_id,
+            executor_id,
+            status: status.to_owned(),
+            scheduled_time: self.scheduled_time as u64,
+            launch_time: self.launch_time as u64,
+            start_exec_time: self.start_exec_time as u64,
+            end_exec_time: self.end_exec_time as u64,
+            finish_time: self.finish_time as u64,
+        }
+    }
 }
 
 impl UnresolvedStage {
@@ -537,6 +687,7 @@ impl RunningStage {
                 .unwrap()
                 .as_millis(),
             task_status: task_status.clone(),
+            metrics: status.metrics,
         };
         self.task_infos[partition_id] = Some(updated_task_info);
 
@@ -617,6 +768,20 @@ impl Debug for RunningStage {
 }
 
 impl SuccessfulStage {
+    /// Summarize the task count, shuffle output and task duration skew of this stage
+    pub(crate) fn task_summary(&self) -> StageTaskSummary {
+        StageTaskSummary::new(
+            self.task_infos
+                .iter()
+                .map(|info| info.exec_duration_ms())
+                .collect(),
+            self.task_infos
+                .iter()
+                .map(|info| info.shuffle_bytes())
+                .sum(),
+        )
+    }
+
     pub(super) fn decode<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>(
         stage: protobuf::SuccessfulStage,
         codec: &BallistaCodec<T, U>,
@@ -825,6 +990,7 @@ fn decode_taskinfo(task_info: protobuf::TaskInfo) -> TaskInfo {
         end_exec_time: task_info.end_exec_time as u128,
         finish_time: task_info.finish_time as u128,
         task_status: task_info_status,
+        metrics: task_info.metrics,
     }
 }
 
@@ -843,5 +1009,6 @@ fn encode_taskinfo(task_info: TaskInfo, partition_id: usize) -> protobuf::TaskIn
         end_exec_time: task_info.end_exec_time as u64,
         finish_time: task_info.finish_time as u64,
         status: Some(task_info_status),
+        metrics: task_info.metrics,
     }
 }
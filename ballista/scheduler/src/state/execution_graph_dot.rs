@@ -0,0 +1,302 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Export of the stage DAG of an [`ExecutionGraph`] as Graphviz DOT and as JSON
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use ballista_core::utils::collect_plan_metrics;
+use datafusion::physical_plan::display::DisplayableExecutionPlan;
+use datafusion::physical_plan::metrics::MetricsSet;
+use datafusion::physical_plan::ExecutionPlan;
+use serde::Serialize;
+
+use crate::state::execution_graph::{ExecutionGraph, ExecutionStage, StageOutput};
+
+/// The stages of a job and the shuffle dependencies between them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageGraph {
+    pub job_id: String,
+    /// Stages ordered by stage id
+    pub stages: Vec<StageNode>,
+    /// Shuffle dependencies, from the stage writing the shuffle to the stage reading it
+    pub edges: Vec<StageEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageNode {
+    pub stage_id: usize,
+    /// One of `Unresolved`, `Resolved`, `Running` or `Successful`
+    pub state: String,
+    pub partitions: usize,
+    /// Rows read and written by the shuffle writer of the stage, once tasks have finished
+    pub input_rows: Option<usize>,
+    pub output_rows: Option<usize>,
+    /// Operators of the stage plan in pre-order, starting with the shuffle writer
+    pub operators: Vec<OperatorNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OperatorNode {
+    /// Depth of the operator in the stage plan, the shuffle writer has depth 0
+    pub depth: usize,
+    pub name: String,
+    /// One line description of the operator, as shown by `EXPLAIN`
+    pub description: String,
+    pub output_rows: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StageEdge {
+    pub from: usize,
+    pub to: usize,
+    /// Whether all tasks of the input stage have completed
+    pub complete: bool,
+}
+
+impl StageGraph {
+    pub fn new(graph: &ExecutionGraph) -> Self {
+        let mut stages = vec![];
+        let mut edges = vec![];
+        for stage in graph.stages().values() {
+            let (stage_id, partitions, plan, output_links, inputs, stage_metrics) = match stage {
+                ExecutionStage::UnResolved(stage) => (
+                    stage.stage_id,
+                    stage.plan.output_partitioning().partition_count(),
+                    &stage.plan,
+                    &stage.output_links,
+                    &stage.inputs,
+                    &[][..],
+                ),
+                ExecutionStage::Resolved(stage) => (
+                    stage.stage_id,
+                    stage.partitions,
+                    &stage.plan,
+                    &stage.output_links,
+                    &stage.inputs,
+                    &[][..],
+                ),
+                ExecutionStage::Running(stage) => (
+                    stage.stage_id,
+                    stage.partitions,
+                    &stage.plan,
+                    &stage.output_links,
+                    &stage.inputs,
+                    stage.stage_metrics.as_deref().unwrap_or_default(),
+                ),
+                ExecutionStage::Successful(stage) => (
+                    stage.stage_id,
+                    stage.partitions,
+                    &stage.plan,
+                    &stage.output_links,
+                    &stage.inputs,
+                    &stage.stage_metrics[..],
+                ),
+            };
+
+            // The stage metrics only line up with the operators once tasks have reported them
+            let stage_metrics = if collect_plan_metrics(plan.as_ref()).len() == stage_metrics.len()
+            {
+                stage_metrics
+            } else {
+                &[][..]
+            };
+            let mut operators = vec![];
+            collect_operators(plan.as_ref(), 0, &mut stage_metrics.iter(), &mut operators);
+            let writer_metrics = stage_metrics.first();
+
+            stages.push(StageNode {
+                stage_id,
+                state: stage.variant_name().to_owned(),
+                partitions,
+                input_rows: writer_metrics.and_then(|m| metric_count(m, "input_rows")),
+                output_rows: writer_metrics.and_then(|m| metric_count(m, "output_rows")),
+                operators,
+            });
+            edges.extend(input_edges(stage_id, inputs));
+            edges.extend(output_links.iter().map(|link| StageEdge {
+                from: stage_id,
+                to: *link,
+                complete: false,
+            }));
+        }
+        stages.sort_by_key(|stage| stage.stage_id);
+
+        // Edges are seen from both ends, the consuming stage knows whether the input is complete
+        let mut deduplicated: HashMap<(usize, usize), StageEdge> = HashMap::new();
+        for edge in edges {
+            let entry = deduplicated
+                .entry((edge.from, edge.to))
+                .or_insert_with(|| edge.clone());
+            entry.complete |= edge.complete;
+        }
+        let mut edges = deduplicated.into_values().collect::<Vec<_>>();
+        edges.sort_by_key(|edge| (edge.from, edge.to));
+
+        Self {
+            job_id: graph.job_id().to_owned(),
+            stages,
+            edges,
+        }
+    }
+
+    /// Render the stage graph as a Graphviz DOT digraph
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        // Writing to a String cannot fail
+        let _ = self.write_dot(&mut dot);
+        dot
+    }
+
+    fn write_dot(&self, f: &mut String) -> std::fmt::Result {
+        writeln!(f, "digraph G {{")?;
+        writeln!(f, "  label=\"Job {}\";", escape(&self.job_id))?;
+        writeln!(f, "  node [shape=box, fontname=\"monospace\"];")?;
+        for stage in &self.stages {
+            let mut label = format!(
+                "Stage {} [{}]\\lpartitions={}",
+                stage.stage_id, stage.state, stage.partitions
+            );
+            if let Some(rows) = stage.input_rows {
+                write!(label, ", input_rows={rows}")?;
+            }
+            if let Some(rows) = stage.output_rows {
+                write!(label, ", output_rows={rows}")?;
+            }
+            label.push_str("\\l");
+            for op in &stage.operators {
+                write!(label, "{}{}", "  ".repeat(op.depth), escape(&op.name))?;
+                if let Some(rows) = op.output_rows {
+                    write!(label, " rows={rows}")?;
+                }
+                label.push_str("\\l");
+            }
+            writeln!(
+                f,
+                "  stage_{} [label=\"{}\", style=filled, fillcolor=\"{}\"];",
+                stage.stage_id,
+                label,
+                state_color(&stage.state)
+            )?;
+        }
+        for edge in &self.edges {
+            let style = if edge.complete { "solid" } else { "dashed" };
+            writeln!(
+                f,
+                "  stage_{} -> stage_{} [style={}];",
+                edge.from, edge.to, style
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn input_edges(
+    stage_id: usize,
+    inputs: &HashMap<usize, StageOutput>,
+) -> impl Iterator<Item = StageEdge> + '_ {
+    inputs
+        .iter()
+        .map(move |(input_stage_id, output)| StageEdge {
+            from: *input_stage_id,
+            to: stage_id,
+            complete: output.complete,
+        })
+}
+
+/// Visit the plan in the order in which `collect_plan_metrics` collects the stage metrics
+fn collect_operators<'a>(
+    plan: &dyn ExecutionPlan,
+    depth: usize,
+    metrics: &mut impl Iterator<Item = &'a MetricsSet>,
+    operators: &mut Vec<OperatorNode>,
+) {
+    let description = DisplayableExecutionPlan::new(plan)
+        .one_line()
+        .to_string()
+        .trim_end()
+        .to_owned();
+    let name = description
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_owned();
+    let output_rows = if plan.metrics().is_some() {
+        metrics.next().and_then(|m| m.output_rows())
+    } else {
+        None
+    };
+    operators.push(OperatorNode {
+        depth,
+        name,
+        description,
+        output_rows,
+    });
+    for child in plan.children() {
+        collect_operators(child.as_ref(), depth + 1, metrics, operators);
+    }
+}
+
+fn metric_count(metrics: &MetricsSet, name: &str) -> Option<usize> {
+    metrics.sum_by_name(name).map(|value| value.as_usize())
+}
+
+fn state_color(state: &str) -> &'static str {
+    match state {
+        "Running" => "lightyellow",
+        "Successful" => "palegreen",
+        "Resolved" => "lightblue",
+        _ => "white",
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_aggregation_plan;
+
+    #[tokio::test]
+    async fn stage_graph() {
+        let graph = test_aggregation_plan(4).await;
+        let stage_graph = StageGraph::new(&graph);
+
+        assert_eq!(stage_graph.stages.len(), 2);
+        assert_eq!(stage_graph.stages[0].stage_id, 1);
+        assert_eq!(stage_graph.stages[0].state, "Resolved");
+        assert_eq!(stage_graph.stages[1].state, "Unresolved");
+        assert_eq!(stage_graph.stages[0].operators[0].name, "ShuffleWriterExec");
+        assert_eq!(
+            stage_graph.edges,
+            vec![StageEdge {
+                from: 1,
+                to: 2,
+                complete: false
+            }]
+        );
+
+        let dot = stage_graph.to_dot();
+        assert!(dot.starts_with("digraph G {"));
+        assert!(dot.contains("stage_1 -> stage_2 [style=dashed];"));
+        assert!(dot.contains("Stage 2 [Unresolved]"));
+    }
+}
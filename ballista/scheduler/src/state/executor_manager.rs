@@ -31,20 +31,27 @@ use ballista_core::serde::protobuf::{
     executor_status, CancelTasksParams, ExecutorHeartbeat, MultiTaskDefinition, RemoveJobDataParams,
 };
 use ballista_core::serde::scheduler::{ExecutorData, ExecutorMetadata};
+use ballista_core::telemetry::{inject_trace_props, set_parent_from_props};
 use ballista_core::utils::{create_grpc_client_connection, get_time_before};
 use dashmap::DashMap;
 use log::{debug, error, info, warn};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use tonic::transport::Channel;
+use tracing::{info_span, Span};
 
 type ExecutorClients = Arc<DashMap<String, ExecutorGrpcClient<Channel>>>;
 
+/// Number of recent heartbeats kept for each executor
+const HEARTBEAT_HISTORY_SIZE: usize = 20;
+
 #[derive(Clone)]
 pub struct ExecutorManager {
     cluster_state: Arc<dyn ClusterState>,
     config: Arc<SchedulerConfig>,
     clients: ExecutorClients,
+    // Most recent heartbeats received by this scheduler from each executor, oldest first
+    heartbeat_history: Arc<DashMap<String, VecDeque<ExecutorHeartbeat>>>,
 }
 
 impl ExecutorManager {
@@ -53,6 +60,7 @@ impl ExecutorManager {
             cluster_state,
             config,
             clients: Default::default(),
+            heartbeat_history: Default::default(),
         }
     }
 
@@ -64,10 +72,11 @@ impl ExecutorManager {
 
     /// Bind the ready to running tasks from [`active_jobs`] with available executors.
     ///
-    /// If `executors` is provided, only bind slots from the specified executor IDs
+    /// Jobs of tenants in `tenant_slots` will take no more than the given number of slots
     pub async fn bind_schedulable_tasks(
         &self,
         active_jobs: Arc<HashMap<String, JobInfoCache>>,
+        tenant_slots: HashMap<String, u32>,
     ) -> Result<Vec<BoundTask>> {
         if active_jobs.is_empty() {
             warn!("There's no active jobs for binding tasks");
@@ -83,6 +92,7 @@ impl ExecutorManager {
                 self.config.task_distribution,
                 active_jobs,
                 Some(alive_executors),
+                tenant_slots,
             )
             .await
     }
@@ -209,6 +219,24 @@ impl ExecutorManager {
         self.cluster_state.get_executor_metadata(executor_id).await
     }
 
+    /// Get the number of free task slots of the executor, if it is registered
+    pub async fn get_available_slots(&self, executor_id: &str) -> Result<Option<u32>> {
+        self.cluster_state.get_available_slots(executor_id).await
+    }
+
+    /// Get the last heartbeat of the executor. Return None if the executor does not exist
+    pub fn get_executor_heartbeat(&self, executor_id: &str) -> Option<ExecutorHeartbeat> {
+        self.cluster_state.get_executor_heartbeat(executor_id)
+    }
+
+    /// Get the most recent heartbeats received from the executor, oldest first
+    pub fn get_heartbeat_history(&self, executor_id: &str) -> Vec<ExecutorHeartbeat> {
+        self.heartbeat_history
+            .get(executor_id)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Register the executor with the scheduler.
     ///
     /// This will save the executor metadata and the executor data to persistent state.
@@ -236,28 +264,52 @@ impl ExecutorManager {
     /// Remove the executor from the cluster
     pub async fn remove_executor(&self, executor_id: &str, reason: Option<String>) -> Result<()> {
         info!("Removing executor {}: {:?}", executor_id, reason);
+        self.heartbeat_history.remove(executor_id);
         self.cluster_state.remove_executor(executor_id).await
     }
 
     pub async fn launch_multi_task(
         &self,
         executor_id: &str,
-        multi_tasks: Vec<MultiTaskDefinition>,
+        mut multi_tasks: Vec<MultiTaskDefinition>,
         scheduler_id: String,
     ) -> Result<()> {
+        // Trace the launch as part of each job and make it the parent of the launched tasks
+        let spans: Vec<Span> = multi_tasks
+            .iter_mut()
+            .map(|multi_task| {
+                let span = info_span!(
+                    "launch_multi_task",
+                    executor_id,
+                    job_id = multi_task.job_id.as_str(),
+                    stage_id = multi_task.stage_id,
+                    num_tasks = multi_task.task_ids.len(),
+                );
+                let props: HashMap<String, String> = multi_task
+                    .props
+                    .iter()
+                    .map(|kv| (kv.key.clone(), kv.value.clone()))
+                    .collect();
+                set_parent_from_props(&span, &props);
+                inject_trace_props(&span, &mut multi_task.props);
+                span
+            })
+            .collect();
+
         let mut client = self.get_client(executor_id).await?;
-        client
+        let result = client
             .launch_multi_task(protobuf::LaunchMultiTaskParams {
                 multi_tasks,
                 scheduler_id,
             })
-            .await
-            .map_err(|e| {
-                BallistaError::Internal(format!(
-                    "Failed to connect to executor {}: {:?}",
-                    executor_id, e
-                ))
-            })?;
+            .await;
+        drop(spans);
+        result.map_err(|e| {
+            BallistaError::Internal(format!(
+                "Failed to connect to executor {}: {:?}",
+                executor_id, e
+            ))
+        })?;
 
         Ok(())
     }
@@ -267,6 +319,15 @@ impl ExecutorManager {
             .save_executor_heartbeat(heartbeat.clone())
             .await?;
 
+        let mut history = self
+            .heartbeat_history
+            .entry(heartbeat.executor_id.clone())
+            .or_default();
+        if history.len() == HEARTBEAT_HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(heartbeat);
+
         Ok(())
     }
 
@@ -0,0 +1,278 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Progress events of jobs, published for clients following a job as it runs
+
+use ballista_core::serde::protobuf::{job_status, JobStatus};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::scheduler_server::event::QueryStageSchedulerEvent;
+
+/// Number of events buffered for slow subscribers before they miss events
+const JOB_EVENT_CAPACITY: usize = 1024;
+
+/// A change in the progress of a job
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    Queued {
+        job_id: String,
+        queued_at: u64,
+    },
+    Submitted {
+        job_id: String,
+        submitted_at: u64,
+    },
+    /// Input stages completed and new stages became runnable
+    StageResolved {
+        job_id: String,
+        progress: JobProgress,
+    },
+    /// Tasks of the job finished
+    TasksFinished {
+        job_id: String,
+        progress: JobProgress,
+    },
+    Finished {
+        job_id: String,
+        completed_at: u64,
+    },
+    Failed {
+        job_id: String,
+        error: String,
+        failed_at: u64,
+    },
+    Cancelled {
+        job_id: String,
+    },
+}
+
+impl JobEvent {
+    pub fn job_id(&self) -> &str {
+        match self {
+            JobEvent::Queued { job_id, .. }
+            | JobEvent::Submitted { job_id, .. }
+            | JobEvent::StageResolved { job_id, .. }
+            | JobEvent::TasksFinished { job_id, .. }
+            | JobEvent::Finished { job_id, .. }
+            | JobEvent::Failed { job_id, .. }
+            | JobEvent::Cancelled { job_id } => job_id,
+        }
+    }
+
+    /// Name of the event, as sent in the `event` field of server-sent events
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::Queued { .. } => "queued",
+            JobEvent::Submitted { .. } => "submitted",
+            JobEvent::StageResolved { .. } => "stage_resolved",
+            JobEvent::TasksFinished { .. } => "tasks_finished",
+            JobEvent::Finished { .. } => "finished",
+            JobEvent::Failed { .. } => "failed",
+            JobEvent::Cancelled { .. } => "cancelled",
+        }
+    }
+
+    /// Whether no more events follow for the job
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobEvent::Finished { .. } | JobEvent::Failed { .. } | JobEvent::Cancelled { .. }
+        )
+    }
+
+    /// The event for a scheduler event which changes the state of a job on its own. Progress
+    /// events are published after the scheduler has applied task updates to the job.
+    pub(crate) fn from_scheduler_event(event: &QueryStageSchedulerEvent) -> Option<Self> {
+        match event {
+            QueryStageSchedulerEvent::JobQueued {
+                job_id, queued_at, ..
+            } => Some(JobEvent::Queued {
+                job_id: job_id.clone(),
+                queued_at: *queued_at,
+            }),
+            QueryStageSchedulerEvent::JobSubmitted {
+                job_id,
+                submitted_at,
+                ..
+            } => Some(JobEvent::Submitted {
+                job_id: job_id.clone(),
+                submitted_at: *submitted_at,
+            }),
+            QueryStageSchedulerEvent::JobFinished {
+                job_id,
+                completed_at,
+                ..
+            } => Some(JobEvent::Finished {
+                job_id: job_id.clone(),
+                completed_at: *completed_at,
+            }),
+            QueryStageSchedulerEvent::JobPlanningFailed {
+                job_id,
+                fail_message,
+                failed_at,
+                ..
+            }
+            | QueryStageSchedulerEvent::JobRunningFailed {
+                job_id,
+                fail_message,
+                failed_at,
+                ..
+            } => Some(JobEvent::Failed {
+                job_id: job_id.clone(),
+                error: fail_message.clone(),
+                failed_at: *failed_at,
+            }),
+            QueryStageSchedulerEvent::JobCancel(job_id) => Some(JobEvent::Cancelled {
+                job_id: job_id.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The event describing the current status of a job, sent first to new subscribers
+    pub fn from_job_status(status: &JobStatus) -> Option<Self> {
+        let job_id = status.job_id.clone();
+        match status.status.as_ref()? {
+            job_status::Status::Queued(queued) => Some(JobEvent::Queued {
+                job_id,
+                queued_at: queued.queued_at,
+            }),
+            job_status::Status::Running(running) => Some(JobEvent::Submitted {
+                job_id,
+                submitted_at: running.started_at,
+            }),
+            job_status::Status::Failed(failed) => Some(JobEvent::Failed {
+                job_id,
+                error: failed.error.clone(),
+                failed_at: failed.ended_at,
+            }),
+            job_status::Status::Successful(successful) => Some(JobEvent::Finished {
+                job_id,
+                completed_at: successful.ended_at,
+            }),
+        }
+    }
+}
+
+/// Task counts of a running job
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct JobProgress {
+    pub num_stages: usize,
+    pub completed_stages: usize,
+    pub total_tasks: usize,
+    pub completed_tasks: usize,
+    pub stages: Vec<StageProgress>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StageProgress {
+    pub stage_id: usize,
+    pub state: String,
+    pub partitions: usize,
+    pub running_tasks: usize,
+    pub completed_tasks: usize,
+}
+
+impl JobProgress {
+    pub fn new(mut stages: Vec<StageProgress>) -> Self {
+        stages.sort_by_key(|stage| stage.stage_id);
+        Self {
+            num_stages: stages.len(),
+            completed_stages: stages
+                .iter()
+                .filter(|stage| stage.state == "Successful")
+                .count(),
+            total_tasks: stages.iter().map(|stage| stage.partitions).sum(),
+            completed_tasks: stages.iter().map(|stage| stage.completed_tasks).sum(),
+            stages,
+        }
+    }
+}
+
+/// Publishes job events to all subscribers, e.g. clients following a job over server-sent
+/// events. Events are dropped when nobody is subscribed.
+#[derive(Clone)]
+pub struct JobEventBus {
+    sender: broadcast::Sender<JobEvent>,
+}
+
+impl Default for JobEventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(JOB_EVENT_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl JobEventBus {
+    pub fn publish(&self, event: JobEvent) {
+        // Sending only fails if there is no subscriber
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_aggregation_plan;
+
+    #[tokio::test]
+    async fn publish_job_events() {
+        let bus = JobEventBus::default();
+        // Nobody is subscribed yet
+        bus.publish(JobEvent::Cancelled {
+            job_id: "job_0".to_owned(),
+        });
+
+        let mut rx = bus.subscribe();
+        let event = JobEvent::from_scheduler_event(&QueryStageSchedulerEvent::JobFinished {
+            job_id: "job_1".to_owned(),
+            queued_at: 1,
+            completed_at: 2,
+        })
+        .unwrap();
+        bus.publish(event.clone());
+
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received, event);
+        assert_eq!(received.job_id(), "job_1");
+        assert!(received.is_terminal());
+        assert_eq!(
+            serde_json::to_string(&received).unwrap(),
+            r#"{"event":"finished","job_id":"job_1","completed_at":2}"#
+        );
+        assert!(JobEvent::from_scheduler_event(&QueryStageSchedulerEvent::ReviveOffers).is_none());
+    }
+
+    #[tokio::test]
+    async fn job_progress() {
+        let graph = test_aggregation_plan(4).await;
+        let progress = graph.progress();
+
+        assert_eq!(progress.num_stages, 2);
+        assert_eq!(progress.completed_stages, 0);
+        assert_eq!(progress.completed_tasks, 0);
+        assert_eq!(progress.stages[0].stage_id, 1);
+        assert_eq!(progress.stages[0].state, "Resolved");
+        assert_eq!(progress.stages[1].state, "Unresolved");
+    }
+}
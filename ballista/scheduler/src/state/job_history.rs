@@ -0,0 +1,374 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ballista_core::config::BallistaConfig;
+use ballista_core::error::Result;
+use ballista_core::serde::protobuf::{job_status, JobStatus};
+use dashmap::mapref::one::RefMut;
+use dashmap::DashMap;
+use datafusion::prelude::SessionContext;
+use log::info;
+
+use crate::cluster::history::{JobHistoryStore, JobSummary};
+use crate::scheduler_server::timestamp_millis;
+use crate::state::execution_graph::ExecutionGraph;
+
+/// Filter for listing the job history. All fields are optional, and archived jobs must match
+/// every field which is set.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct JobHistoryFilter {
+    /// Final status of the job, e.g. `Successful` or `Failed`
+    pub status: Option<String>,
+    pub session_id: Option<String>,
+    /// Only jobs which ended at or after this timestamp in milliseconds
+    pub since: Option<u64>,
+    /// Only jobs which ended before this timestamp in milliseconds
+    pub until: Option<u64>,
+    /// Return at most this many jobs, newest first
+    pub limit: Option<usize>,
+}
+
+impl JobHistoryFilter {
+    fn matches(&self, job: &JobSummary) -> bool {
+        self.status
+            .as_ref()
+            .is_none_or(|status| job.status.eq_ignore_ascii_case(status))
+            && self
+                .session_id
+                .as_ref()
+                .is_none_or(|session_id| &job.session_id == session_id)
+            && self.since.is_none_or(|since| job.end_time >= since)
+            && self.until.is_none_or(|until| job.end_time < until)
+    }
+}
+
+/// Submissions of jobs which have not been archived for this long are dropped, e.g. of jobs
+/// which failed to be queued. Jobs running longer are archived without their SQL text and
+/// settings.
+const MAX_SUBMISSION_AGE_MS: u64 = 24 * 3600 * 1000;
+
+/// What we know about a job when it is submitted, but which is not kept in its `ExecutionGraph`
+#[derive(Debug, Clone, Default)]
+struct JobSubmission {
+    session_id: String,
+    sql: Option<String>,
+    settings: HashMap<String, String>,
+    queued_at: u64,
+    /// When the submission was first recorded
+    recorded_at: u64,
+}
+
+/// Archives the summaries of finished jobs into a [`JobHistoryStore`] and applies the retention
+/// by age and count.
+#[derive(Clone)]
+pub struct JobHistory {
+    store: Arc<dyn JobHistoryStore>,
+    submissions: Arc<DashMap<String, JobSubmission>>,
+    max_age_seconds: u64,
+    max_jobs: usize,
+}
+
+impl JobHistory {
+    pub fn new(store: Arc<dyn JobHistoryStore>, max_age_seconds: u64, max_jobs: usize) -> Self {
+        Self {
+            store,
+            submissions: Default::default(),
+            max_age_seconds,
+            max_jobs,
+        }
+    }
+
+    /// Remember the session of a job when it is queued
+    pub fn record_queued(&self, job_id: &str, session_ctx: &SessionContext, queued_at: u64) {
+        let settings = session_ctx
+            .copied_config()
+            .get_extension::<BallistaConfig>()
+            .map(|config| config.settings().clone())
+            .unwrap_or_default();
+        self.prune_submissions(timestamp_millis());
+        let mut submission = self.submission(job_id);
+        submission.session_id = session_ctx.session_id();
+        submission.settings = settings;
+        submission.queued_at = queued_at;
+    }
+
+    /// Remember the SQL text a job was submitted with
+    pub fn record_sql(&self, job_id: &str, sql: &str) {
+        self.submission(job_id).sql = Some(sql.to_owned());
+    }
+
+    /// Forget the submission of a job which will never be archived, e.g. because it could
+    /// not be queued
+    pub fn forget(&self, job_id: &str) {
+        self.submissions.remove(job_id);
+    }
+
+    fn submission(&self, job_id: &str) -> RefMut<'_, String, JobSubmission> {
+        self.submissions
+            .entry(job_id.to_owned())
+            .or_insert_with(|| JobSubmission {
+                recorded_at: timestamp_millis(),
+                ..Default::default()
+            })
+    }
+
+    /// Drop the submissions of jobs which have not been archived long after they were
+    /// submitted
+    fn prune_submissions(&self, now: u64) {
+        self.submissions.retain(|_, submission| {
+            now.saturating_sub(submission.recorded_at) < MAX_SUBMISSION_AGE_MS
+        });
+    }
+
+    /// Get the SQL text a job was submitted with, whether it is still running or archived
+    pub async fn sql(&self, job_id: &str) -> Result<Option<String>> {
+        if let Some(submission) = self.submissions.get(job_id) {
+            return Ok(submission.sql.clone());
+        }
+        Ok(self.get(job_id).await?.and_then(|job| job.sql))
+    }
+
+    /// Archive a job which finished after it was planned
+    pub async fn archive_graph(&self, graph: &ExecutionGraph) -> Result<()> {
+        let submission = self
+            .submissions
+            .remove(graph.job_id())
+            .map(|(_, submission)| submission)
+            .unwrap_or_default();
+        let (status, failure_reason) = status_and_reason(graph.status());
+        let end_time = if graph.end_time() > 0 {
+            graph.end_time()
+        } else {
+            timestamp_millis()
+        };
+        let mut stages = graph
+            .stages()
+            .values()
+            .map(|stage| stage.summary())
+            .collect::<Vec<_>>();
+        stages.sort_by_key(|stage| stage.stage_id);
+
+        self.archive(JobSummary {
+            job_id: graph.job_id().to_owned(),
+            session_id: graph.session_id().to_owned(),
+            sql: submission.sql,
+            settings: submission.settings,
+            status,
+            failure_reason,
+            queued_at: graph.queued_at(),
+            start_time: graph.start_time(),
+            end_time,
+            stages,
+        })
+        .await
+    }
+
+    /// Archive a job which failed before it had an `ExecutionGraph`, e.g. during planning
+    pub async fn archive_status(&self, job_id: &str, job_status: &JobStatus) -> Result<()> {
+        let submission = self
+            .submissions
+            .remove(job_id)
+            .map(|(_, submission)| submission)
+            .unwrap_or_default();
+        let (status, failure_reason) = status_and_reason(job_status);
+        let now = timestamp_millis();
+
+        self.archive(JobSummary {
+            job_id: job_id.to_owned(),
+            session_id: submission.session_id,
+            sql: submission.sql,
+            settings: submission.settings,
+            status,
+            failure_reason,
+            queued_at: submission.queued_at,
+            start_time: now,
+            end_time: now,
+            stages: vec![],
+        })
+        .await
+    }
+
+    /// List the archived jobs matching the filter, newest first
+    pub async fn list(&self, filter: &JobHistoryFilter) -> Result<Vec<JobSummary>> {
+        let jobs = self.store.list().await?;
+        Ok(jobs
+            .into_iter()
+            .rev()
+            .filter(|job| filter.matches(job))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// Get the archived summary of a job
+    pub async fn get(&self, job_id: &str) -> Result<Option<JobSummary>> {
+        let keys = self.store.keys().await?;
+        match keys.into_iter().find(|key| key.job_id == job_id) {
+            Some(key) => self.store.get(&key).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn archive(&self, summary: JobSummary) -> Result<()> {
+        self.store.archive(&summary).await?;
+        self.apply_retention(timestamp_millis()).await
+    }
+
+    /// Remove archived jobs which are older than the maximum age, or exceed the maximum count
+    async fn apply_retention(&self, now: u64) -> Result<()> {
+        let keys = self.store.keys().await?;
+        let min_end_time = if self.max_age_seconds > 0 {
+            now.saturating_sub(self.max_age_seconds * 1000)
+        } else {
+            0
+        };
+        let excess = if self.max_jobs > 0 {
+            keys.len().saturating_sub(self.max_jobs)
+        } else {
+            0
+        };
+
+        // Keys are ordered oldest first
+        let expired = keys
+            .into_iter()
+            .enumerate()
+            .take_while(|(i, key)| *i < excess || key.end_time < min_end_time)
+            .map(|(_, key)| key)
+            .collect::<Vec<_>>();
+        if !expired.is_empty() {
+            info!(
+                "Removing {} expired jobs from the job history",
+                expired.len()
+            );
+            self.store.remove(&expired).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the job status and the failure reason if the job failed
+pub(crate) fn status_and_reason(status: &JobStatus) -> (String, Option<String>) {
+    match &status.status {
+        Some(job_status::Status::Queued(_)) => ("Queued".to_owned(), None),
+        Some(job_status::Status::Running(_)) => ("Running".to_owned(), None),
+        Some(job_status::Status::Failed(failed)) => {
+            ("Failed".to_owned(), Some(failed.error.clone()))
+        }
+        Some(job_status::Status::Successful(_)) => ("Successful".to_owned(), None),
+        None => ("Unknown".to_owned(), None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cluster::history::InMemoryJobHistoryStore;
+    use ballista_core::serde::protobuf::FailedJob;
+
+    fn failed(error: &str) -> JobStatus {
+        JobStatus {
+            job_id: "job".to_owned(),
+            status: Some(job_status::Status::Failed(FailedJob {
+                error: error.to_owned(),
+                queued_at: 0,
+                started_at: 0,
+                ended_at: 0,
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_history_retention_by_count() -> Result<()> {
+        let history = JobHistory::new(Arc::new(InMemoryJobHistoryStore::default()), 0, 2);
+
+        history.record_sql("job_1", "SELECT 1");
+        history.archive_status("job_1", &failed("error 1")).await?;
+        history.archive_status("job_2", &failed("error 2")).await?;
+        history.archive_status("job_3", &failed("error 3")).await?;
+
+        let jobs = history.list(&JobHistoryFilter::default()).await?;
+        assert_eq!(
+            jobs.iter()
+                .map(|job| job.job_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["job_3", "job_2"]
+        );
+        assert_eq!(jobs[0].failure_reason.as_deref(), Some("error 3"));
+        assert!(history.get("job_1").await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_job_history_filter() -> Result<()> {
+        let history = JobHistory::new(Arc::new(InMemoryJobHistoryStore::default()), 3600, 0);
+
+        history.record_sql("job_1", "SELECT 1");
+        history.archive_status("job_1", &failed("error")).await?;
+
+        let job = history.get("job_1").await?.expect("archived job");
+        assert_eq!(job.sql.as_deref(), Some("SELECT 1"));
+        assert_eq!(history.sql("job_1").await?.as_deref(), Some("SELECT 1"));
+        assert_eq!(job.status, "Failed");
+
+        let filter = JobHistoryFilter {
+            status: Some("failed".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(history.list(&filter).await?.len(), 1);
+
+        let filter = JobHistoryFilter {
+            status: Some("successful".to_owned()),
+            ..Default::default()
+        };
+        assert!(history.list(&filter).await?.is_empty());
+
+        let filter = JobHistoryFilter {
+            since: Some(job.end_time + 1),
+            ..Default::default()
+        };
+        assert!(history.list(&filter).await?.is_empty());
+
+        // Nothing is older than the maximum age yet
+        history.apply_retention(job.end_time).await?;
+        assert!(history.get("job_1").await?.is_some());
+        history.apply_retention(job.end_time + 3601 * 1000).await?;
+        assert!(history.get("job_1").await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_job_history_prunes_submissions() -> Result<()> {
+        let history = JobHistory::new(Arc::new(InMemoryJobHistoryStore::default()), 0, 0);
+
+        history.record_sql("job_1", "SELECT 1");
+        history.record_sql("job_2", "SELECT 2");
+        history.forget("job_2");
+        assert!(history.sql("job_2").await?.is_none());
+
+        // Submissions of jobs which are never archived expire
+        history.prune_submissions(timestamp_millis());
+        assert_eq!(history.sql("job_1").await?.as_deref(), Some("SELECT 1"));
+        history.prune_submissions(timestamp_millis() + MAX_SUBMISSION_AGE_MS);
+        assert!(history.sql("job_1").await?.is_none());
+
+        Ok(())
+    }
+}
@@ -22,12 +22,18 @@ use datafusion::error::DataFusionError;
 use std::any::type_name;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
+use crate::scheduler_server::event_log::EventLog;
 
+use crate::planner::range_partition_sorts;
 use crate::state::executor_manager::ExecutorManager;
-use crate::state::session_manager::SessionManager;
+use crate::state::job_events::{JobEvent, JobEventBus};
+use crate::state::job_history::JobHistory;
+use crate::state::session_manager::{
+    session_range_partitioned_sort, session_task_settings, session_tenant, SessionManager,
+};
 use crate::state::task_manager::{TaskLauncher, TaskManager};
 
 use crate::cluster::{BallistaCluster, BoundTask, ExecutorSlot};
@@ -35,7 +41,7 @@ use crate::config::SchedulerConfig;
 use crate::state::execution_graph::TaskDescription;
 use ballista_core::error::{BallistaError, Result};
 use ballista_core::event_loop::EventSender;
-use ballista_core::serde::protobuf::TaskStatus;
+use ballista_core::serde::protobuf::{job_status, task_status, TaskStatus};
 use ballista_core::serde::BallistaCodec;
 use datafusion::logical_expr::LogicalPlan;
 use datafusion::physical_plan::display::DisplayableExecutionPlan;
@@ -45,8 +51,12 @@ use datafusion_proto::physical_plan::AsExecutionPlan;
 use log::{debug, error, info, warn};
 use prost::Message;
 
+pub mod catalog;
 pub mod execution_graph;
+pub mod execution_graph_dot;
 pub mod executor_manager;
+pub mod job_events;
+pub mod job_history;
 pub mod session_manager;
 pub mod task_manager;
 
@@ -77,30 +87,54 @@ pub struct SchedulerState<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPl
     pub executor_manager: ExecutorManager,
     pub task_manager: TaskManager<T, U>,
     pub session_manager: SessionManager,
+    pub job_history: JobHistory,
+    pub job_events: JobEventBus,
+    pub(crate) event_log: Option<EventLog>,
     pub codec: BallistaCodec<T, U>,
     pub config: Arc<SchedulerConfig>,
 }
 
 impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T, U> {
-    pub fn new(
+    pub fn try_new(
         cluster: BallistaCluster,
         codec: BallistaCodec<T, U>,
         scheduler_name: String,
         config: Arc<SchedulerConfig>,
         launcher: Arc<dyn TaskLauncher>,
-    ) -> Self {
-        Self {
+    ) -> Result<Self> {
+        let event_log = config
+            .event_log_path
+            .as_ref()
+            .map(|path| {
+                EventLog::try_new(path).map_err(|e| {
+                    BallistaError::General(format!(
+                        "Failed to open the scheduler event log {path}: {e:?}"
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(Self {
             executor_manager: ExecutorManager::new(cluster.cluster_state(), config.clone()),
             task_manager: TaskManager::new(
                 cluster.job_state(),
                 codec.clone(),
                 scheduler_name,
                 launcher,
+            )
+            .with_event_log(event_log.clone()),
+            session_manager: SessionManager::new(cluster.job_state())
+                .with_users(config.users.clone())
+                .with_token_ttl(Duration::from_secs(config.session_token_ttl_seconds)),
+            job_history: JobHistory::new(
+                cluster.job_history(),
+                config.job_history_max_age_seconds,
+                config.job_history_max_jobs,
             ),
-            session_manager: SessionManager::new(cluster.job_state()),
+            job_events: JobEventBus::default(),
+            event_log,
             codec,
             config,
-        }
+        })
     }
 
     pub async fn init(&self) -> Result<()> {
@@ -113,7 +147,10 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
     ) -> Result<()> {
         let schedulable_tasks = self
             .executor_manager
-            .bind_schedulable_tasks(self.task_manager.get_running_job_cache())
+            .bind_schedulable_tasks(
+                self.task_manager.get_running_job_cache(),
+                self.available_tenant_slots().await,
+            )
             .await?;
         if schedulable_tasks.is_empty() {
             warn!("No schedulable tasks found to be launched");
@@ -282,9 +319,53 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
             .get_executor_metadata(executor_id)
             .await?;
 
-        self.task_manager
-            .update_task_statuses(&executor, tasks_status)
-            .await
+        // Partitions written into the work dir of a shuffle service are served by it rather
+        // than by the executor, so their locations point at the shuffle service
+        let mut statuses_by_server: HashMap<String, Vec<TaskStatus>> = HashMap::new();
+        for status in tasks_status {
+            let server_id = match &status.status {
+                Some(task_status::Status::Successful(task))
+                    if !task.shuffle_service_id.is_empty() =>
+                {
+                    task.shuffle_service_id.clone()
+                }
+                _ => executor_id.to_owned(),
+            };
+            statuses_by_server
+                .entry(server_id)
+                .or_default()
+                .push(status);
+        }
+
+        let mut events = vec![];
+        for (server_id, statuses) in statuses_by_server {
+            let server = if server_id == executor_id {
+                executor.clone()
+            } else {
+                match self
+                    .executor_manager
+                    .get_executor_metadata(&server_id)
+                    .await
+                {
+                    Ok(shuffle_service) => shuffle_service,
+                    Err(e) => {
+                        // The shuffle files are on the host of the executor, which can
+                        // serve them as well as long as it is alive
+                        warn!(
+                            "Fail to find shuffle service {} of executor {}, its partitions are served by the executor: {}",
+                            server_id, executor_id, e
+                        );
+                        executor.clone()
+                    }
+                }
+            };
+            events.extend(
+                self.task_manager
+                    .update_task_statuses(&server, statuses)
+                    .await?,
+            );
+        }
+        Ok(events)
     }
 
     pub(crate) async fn submit_job(
@@ -340,14 +421,24 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
             Ok(VisitRecursion::Continue)
         })?;
 
-        let plan = session_ctx.state().create_physical_plan(plan).await?;
+        let mut plan = session_ctx.state().create_physical_plan(plan).await?;
+        if session_range_partitioned_sort(&session_ctx) {
+            plan = range_partition_sorts(plan)?;
+        }
         debug!(
             "Physical plan: {}",
             DisplayableExecutionPlan::new(plan.as_ref()).indent(false)
         );
 
         self.task_manager
-            .submit_job(job_id, &session_ctx.session_id(), plan, queued_at)
+            .submit_job(
+                job_id,
+                &session_tenant(&session_ctx),
+                &session_ctx.session_id(),
+                session_task_settings(&session_ctx),
+                plan,
+                queued_at,
+            )
             .await?;
 
         let elapsed = start.elapsed();
@@ -357,6 +448,124 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> SchedulerState<T,
         Ok(())
     }
 
+    /// Check whether a new job of the tenant can be admitted under the tenant's quota.
+    /// Returns the reason why the job has to wait if it can't.
+    pub(crate) async fn check_tenant_admission(&self, tenant: &str) -> Option<String> {
+        let quota = self.config.tenant_quota(tenant);
+        if quota.max_running_jobs.is_none() && quota.max_shuffle_bytes.is_none() {
+            return None;
+        }
+
+        let usage = self
+            .task_manager
+            .get_tenant_usage()
+            .await
+            .remove(tenant)
+            .unwrap_or_default();
+        if let Some(max_running_jobs) = quota.max_running_jobs {
+            if usage.running_jobs >= max_running_jobs {
+                return Some(format!(
+                    "{} running jobs reached the limit of {max_running_jobs}",
+                    usage.running_jobs
+                ));
+            }
+        }
+        if let Some(max_shuffle_bytes) = quota.max_shuffle_bytes {
+            if usage.shuffle_bytes >= max_shuffle_bytes {
+                return Some(format!(
+                    "{} shuffle bytes reached the limit of {max_shuffle_bytes}",
+                    usage.shuffle_bytes
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Get the number of task slots which each tenant with a task slot quota can still occupy.
+    /// Tenants without a task slot quota are not included.
+    async fn available_tenant_slots(&self) -> HashMap<String, u32> {
+        if self.config.default_tenant_quota.max_task_slots.is_none()
+            && self
+                .config
+                .tenant_quotas
+                .values()
+                .all(|quota| quota.max_task_slots.is_none())
+        {
+            return HashMap::new();
+        }
+
+        self.task_manager
+            .get_tenant_usage()
+            .await
+            .into_iter()
+            .filter_map(|(tenant, usage)| {
+                let max_task_slots = self.config.tenant_quota(&tenant).max_task_slots?;
+                Some((
+                    tenant,
+                    max_task_slots.saturating_sub(usage.running_tasks as u32),
+                ))
+            })
+            .collect()
+    }
+
+    /// Publish the task counts of an active job to the subscribers of its events
+    pub(crate) async fn publish_job_progress(&self, job_id: &str, stage_resolved: bool) {
+        if let Some(graph) = self.task_manager.get_active_execution_graph(job_id) {
+            let job_id = job_id.to_owned();
+            let progress = graph.read().await.progress();
+            self.job_events.publish(if stage_resolved {
+                JobEvent::StageResolved { job_id, progress }
+            } else {
+                JobEvent::TasksFinished { job_id, progress }
+            });
+        }
+    }
+
+    /// Spawn a future to archive a finished job into the job history
+    pub(crate) fn archive_job(&self, job_id: String) {
+        let task_manager = self.task_manager.clone();
+        let job_history = self.job_history.clone();
+        tokio::spawn(async move {
+            let result = match task_manager.get_job_execution_graph(&job_id).await {
+                Ok(Some(graph)) => job_history.archive_graph(&graph).await,
+                Ok(None) => match task_manager.get_job_status(&job_id).await {
+                    Ok(Some(status)) => job_history.archive_status(&job_id, &status).await,
+                    Ok(None) => Err(BallistaError::Internal(format!(
+                        "No job status found for job {job_id}"
+                    ))),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Failed to archive job {job_id} into the job history: {e:?}");
+            }
+        });
+    }
+
+    /// Return the ids of the jobs whose shuffle data may still be read, which are the
+    /// unfinished jobs and the successful jobs whose data has not been cleaned up yet at
+    /// the time `now` in milliseconds
+    pub(crate) async fn get_active_jobs(&self, now: u64) -> Result<Vec<String>> {
+        let data_clean_up_interval_ms =
+            self.config.finished_job_data_clean_up_interval_seconds * 1000;
+        Ok(self
+            .task_manager
+            .get_jobs()
+            .await?
+            .into_iter()
+            .filter(|job| match &job.status.status {
+                Some(job_status::Status::Successful(_)) => {
+                    data_clean_up_interval_ms == 0 || job.end_time + data_clean_up_interval_ms > now
+                }
+                Some(job_status::Status::Failed(_)) => false,
+                _ => true,
+            })
+            .map(|job| job.job_id)
+            .collect())
+    }
+
     /// Spawn a delayed future to clean up job data on both Scheduler and Executors
     pub(crate) fn clean_up_successful_job(&self, job_id: String) {
         self.executor_manager.clean_up_job_data_delayed(
@@ -15,30 +15,187 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use crate::config::{UserCredentials, DEFAULT_TENANT};
 use crate::scheduler_server::SessionBuilder;
 use ballista_core::config::BallistaConfig;
-use ballista_core::error::Result;
-use datafusion::prelude::{SessionConfig, SessionContext};
+use ballista_core::error::{BallistaError, Result};
+use dashmap::DashMap;
+use datafusion::logical_expr::{DdlStatement, LogicalPlan};
+use datafusion::prelude::{DataFrame, SessionConfig, SessionContext};
+use uuid::Uuid;
 
 use crate::cluster::JobState;
+use crate::state::catalog::{resolve_table, CatalogTable, SessionCatalog};
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The session of a bearer token and when the token was last used
+struct SessionToken {
+    session_ctx: Arc<SessionContext>,
+    last_used: Instant,
+}
 
 #[derive(Clone)]
 pub struct SessionManager {
     state: Arc<dyn JobState>,
+    // Sessions of authenticated clients, by the bearer token handed out to them
+    tokens: Arc<DashMap<Uuid, SessionToken>>,
+    users: UserCredentials,
+    token_ttl: Duration,
 }
 
 impl SessionManager {
     pub fn new(state: Arc<dyn JobState>) -> Self {
-        Self { state }
+        Self {
+            state,
+            tokens: Default::default(),
+            users: UserCredentials::default(),
+            token_ttl: Duration::from_secs(3600),
+        }
+    }
+
+    pub fn with_users(mut self, users: UserCredentials) -> Self {
+        self.users = users;
+        self
+    }
+
+    /// Tokens which have not been used for this long expire
+    pub fn with_token_ttl(mut self, token_ttl: Duration) -> Self {
+        self.token_ttl = token_ttl;
+        self
+    }
+
+    /// Hand out a bearer token for the session of an authenticated client
+    pub fn create_token(&self, session_ctx: Arc<SessionContext>) -> Uuid {
+        // Drop the tokens of clients which went away
+        self.tokens
+            .retain(|_, token| token.last_used.elapsed() < self.token_ttl);
+
+        let token = Uuid::new_v4();
+        self.tokens.insert(
+            token,
+            SessionToken {
+                session_ctx,
+                last_used: Instant::now(),
+            },
+        );
+        token
+    }
+
+    /// Get the session of a client from its `Bearer` authorization header
+    pub fn get_token_session(&self, authorization: &str) -> Result<Arc<SessionContext>> {
+        let token = authorization
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| BallistaError::General("Invalid auth header!".to_owned()))?;
+        let token = Uuid::from_str(token)
+            .map_err(|e| BallistaError::General(format!("Error parsing token: {e}")))?;
+        match self.tokens.get_mut(&token) {
+            Some(mut session_token) if session_token.last_used.elapsed() < self.token_ttl => {
+                session_token.last_used = Instant::now();
+                Ok(session_token.session_ctx.clone())
+            }
+            Some(session_token) => {
+                drop(session_token);
+                self.tokens.remove(&token);
+                Err(BallistaError::General(format!("Token expired: {token}")))
+            }
+            None => Err(BallistaError::General(format!(
+                "Context handle not found: {token}"
+            ))),
+        }
+    }
+
+    /// Check the credentials of a `Basic` authorization header. Returns the user if the
+    /// credentials are valid, `None` if they are not, and an error if the header is
+    /// malformed.
+    pub fn authenticate_basic(&self, authorization: &str) -> Result<Option<String>> {
+        self.users.authenticate_basic(authorization)
     }
 
     pub async fn create_session(&self, config: &BallistaConfig) -> Result<Arc<SessionContext>> {
-        self.state.create_session(config).await
+        let session = self.state.create_session(config).await?;
+        self.refresh_catalog(&session).await?;
+        Ok(session)
     }
 
     pub async fn get_session(&self, session_id: &str) -> Result<Arc<SessionContext>> {
-        self.state.get_session(session_id).await
+        let session = self.state.get_session(session_id).await?;
+        self.refresh_catalog(&session).await?;
+        Ok(session)
+    }
+
+    /// Get the tenant which the jobs of a session are accounted to, without building a
+    /// `SessionContext` for it
+    pub async fn get_session_tenant(&self, session_id: &str) -> Result<String> {
+        let config = self.state.get_session_config(session_id).await?;
+        Ok(config
+            .tenant()
+            .map(|tenant| tenant.to_owned())
+            .unwrap_or_else(|| DEFAULT_TENANT.to_owned()))
+    }
+
+    /// Bring the tables of a session up to date with the shared catalog, which may have
+    /// been changed by other sessions since the session was created. Does nothing if
+    /// the shared catalog did not change since the last refresh.
+    pub async fn refresh_catalog(&self, session_ctx: &SessionContext) -> Result<()> {
+        if let Some(session_catalog) = session_ctx
+            .copied_config()
+            .get_extension::<SessionCatalog>()
+        {
+            let version = self.state.get_catalog_version().await?;
+            if session_catalog.is_synced(version) {
+                return Ok(());
+            }
+            let tables = self.state.get_catalog_tables().await?;
+            session_catalog.sync(session_ctx, &tables).await?;
+            session_catalog.set_synced(version);
+        }
+        Ok(())
+    }
+
+    /// Execute a plan in a session. External tables created or dropped by it are also
+    /// saved to or removed from the shared catalog.
+    pub async fn execute_logical_plan(
+        &self,
+        session_ctx: &SessionContext,
+        plan: LogicalPlan,
+    ) -> Result<DataFrame> {
+        let session_catalog = session_ctx
+            .copied_config()
+            .get_extension::<SessionCatalog>();
+        match (&plan, session_catalog) {
+            (LogicalPlan::Ddl(DdlStatement::CreateExternalTable(cmd)), Some(session_catalog))
+                if !(cmd.if_not_exists && session_ctx.table_exist(cmd.name.clone())?) =>
+            {
+                let df = session_ctx.execute_logical_plan(plan.clone()).await?;
+                let saved = async {
+                    let table = CatalogTable::try_new(session_ctx, cmd).await?;
+                    self.state.save_catalog_table(table).await
+                };
+                match saved.await {
+                    Ok(table) => {
+                        session_catalog.set_version(table.reference(), table.version);
+                        Ok(df)
+                    }
+                    Err(e) => {
+                        session_ctx.deregister_table(cmd.name.clone())?;
+                        Err(e)
+                    }
+                }
+            }
+            (LogicalPlan::Ddl(DdlStatement::DropTable(cmd)), Some(session_catalog)) => {
+                let reference = resolve_table(session_ctx, &cmd.name);
+                let df = session_ctx.execute_logical_plan(plan.clone()).await?;
+                self.state
+                    .remove_catalog_table(&reference.to_string())
+                    .await?;
+                session_catalog.remove(&reference);
+                Ok(df)
+            }
+            _ => Ok(session_ctx.execute_logical_plan(plan).await?),
+        }
     }
 }
 
@@ -60,7 +217,99 @@ pub fn create_datafusion_context(
             "datafusion.optimizer.hash_join_single_partition_threshold",
             ballista_config.hash_join_single_partition_threshold(),
         )
-        .set_bool("datafusion.optimizer.enable_round_robin_repartition", false);
+        .set_bool("datafusion.optimizer.enable_round_robin_repartition", false)
+        .with_extension(Arc::new(ballista_config.clone()))
+        .with_extension(Arc::new(SessionCatalog::default()));
     let session_state = session_builder(config);
     Arc::new(SessionContext::new_with_state(session_state))
 }
+
+/// Get the tenant which the jobs of a session created by [`create_datafusion_context`]
+/// are accounted to
+pub fn session_tenant(session_ctx: &SessionContext) -> String {
+    session_ctx
+        .copied_config()
+        .get_extension::<BallistaConfig>()
+        .and_then(|config| config.tenant().map(|tenant| tenant.to_owned()))
+        .unwrap_or_else(|| DEFAULT_TENANT.to_owned())
+}
+
+/// Whether global sorts of the session's jobs are executed by range partitioning
+pub fn session_range_partitioned_sort(session_ctx: &SessionContext) -> bool {
+    session_ctx
+        .copied_config()
+        .get_extension::<BallistaConfig>()
+        .map(|config| config.range_partitioned_sort())
+        .unwrap_or_default()
+}
+
+/// Get the Ballista settings of a session which the executors run its tasks with, e.g. the
+/// object stores table data is read from
+pub fn session_task_settings(session_ctx: &SessionContext) -> HashMap<String, String> {
+    session_ctx
+        .copied_config()
+        .get_extension::<BallistaConfig>()
+        .map(|config| config.settings().clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::kv::KeyValueState;
+    use crate::cluster::storage::sled::SledClient;
+    use ballista_core::serde::BallistaCodec;
+    use ballista_core::utils::default_session_builder;
+    use base64::Engine;
+
+    fn session_manager() -> Result<SessionManager> {
+        let state: Arc<dyn JobState> = Arc::new(KeyValueState::new(
+            "",
+            SledClient::try_new_temporary()?,
+            BallistaCodec::default(),
+            default_session_builder,
+        ));
+        Ok(SessionManager::new(state))
+    }
+
+    #[test]
+    fn authenticate_configured_users() -> Result<()> {
+        let basic = |credentials: &str| {
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        };
+        let session_manager =
+            session_manager()?.with_users(UserCredentials::new().with_user("alice", "secret"));
+
+        assert_eq!(
+            session_manager.authenticate_basic(&basic("alice:secret"))?,
+            Some("alice".to_owned())
+        );
+        assert_eq!(
+            session_manager.authenticate_basic(&basic("admin:password"))?,
+            None
+        );
+        assert!(session_manager.authenticate_basic("Bearer token").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tokens_expire() -> Result<()> {
+        let session_manager = session_manager()?.with_token_ttl(Duration::from_millis(50));
+        let session = session_manager
+            .create_session(&BallistaConfig::new()?)
+            .await?;
+
+        let token = session_manager.create_token(session.clone());
+        let bearer = format!("Bearer {token}");
+        let found = session_manager.get_token_session(&bearer)?;
+        assert_eq!(found.session_id(), session.session_id());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(session_manager.get_token_session(&bearer).is_err());
+        assert!(session_manager.tokens.is_empty());
+        Ok(())
+    }
+}
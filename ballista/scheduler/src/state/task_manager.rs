@@ -16,6 +16,7 @@
 // under the License.
 
 use crate::scheduler_server::event::QueryStageSchedulerEvent;
+use crate::scheduler_server::event_log::EventLog;
 
 use crate::state::execution_graph::{
     ExecutionGraph, ExecutionStage, RunningTaskInfo, TaskDescription,
@@ -27,10 +28,11 @@ use ballista_core::error::Result;
 
 use crate::cluster::JobState;
 use ballista_core::serde::protobuf::{
-    job_status, JobStatus, MultiTaskDefinition, TaskId, TaskStatus,
+    job_status, JobStatus, KeyValuePair, MultiTaskDefinition, TaskId, TaskStatus,
 };
 use ballista_core::serde::scheduler::ExecutorMetadata;
 use ballista_core::serde::BallistaCodec;
+use ballista_core::telemetry::inject_trace_props;
 use dashmap::DashMap;
 
 use datafusion::physical_plan::ExecutionPlan;
@@ -46,7 +48,7 @@ use std::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
-use tracing::trace;
+use tracing::{info_span, trace, Span};
 
 type ActiveJobCache = Arc<DashMap<String, JobInfoCache>>;
 
@@ -107,7 +109,13 @@ pub struct TaskManager<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan>
     scheduler_id: String,
     // Cache for active jobs curated by this scheduler
     active_job_cache: ActiveJobCache,
+    // Jobs admitted for planning but not yet submitted, mapped to their tenant
+    pending_jobs: Arc<DashMap<String, String>>,
     launcher: Arc<dyn TaskLauncher>,
+    // Where the state transitions of active jobs are recorded, if enabled
+    event_log: Option<EventLog>,
+    // Root spans of the traces of queued and active jobs
+    job_spans: Arc<DashMap<String, Span>>,
 }
 
 #[derive(Clone)]
@@ -116,19 +124,41 @@ pub struct JobInfoCache {
     pub execution_graph: Arc<RwLock<ExecutionGraph>>,
     // Cache for job status
     pub status: Option<job_status::Status>,
+    // The tenant which the job is accounted to
+    pub tenant: String,
+    // Settings of the job's session which are passed on to the executors with its tasks
+    session_props: Vec<KeyValuePair>,
     // Cache for encoded execution stage plan to avoid duplicated encoding for multiple tasks
     encoded_stage_plans: HashMap<usize, Vec<u8>>,
 }
 
 impl JobInfoCache {
-    pub fn new(graph: ExecutionGraph) -> Self {
+    pub fn new(graph: ExecutionGraph, tenant: String) -> Self {
         let status = graph.status().status.clone();
         Self {
             execution_graph: Arc::new(RwLock::new(graph)),
             status,
+            tenant,
+            session_props: vec![],
             encoded_stage_plans: HashMap::new(),
         }
     }
+
+    pub fn with_session_props(mut self, session_props: HashMap<String, String>) -> Self {
+        self.session_props = session_props
+            .into_iter()
+            .map(|(key, value)| KeyValuePair { key, value })
+            .collect();
+        self
+    }
+}
+
+/// Resources currently held by the active jobs of a tenant
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantUsage {
+    pub running_jobs: usize,
+    pub running_tasks: usize,
+    pub shuffle_bytes: u64,
 }
 
 #[derive(Clone)]
@@ -150,15 +180,35 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             codec,
             scheduler_id,
             active_job_cache: Arc::new(DashMap::new()),
+            pending_jobs: Arc::new(DashMap::new()),
             launcher,
+            event_log: None,
+            job_spans: Arc::new(DashMap::new()),
         }
     }
 
+    pub fn with_event_log(mut self, event_log: Option<EventLog>) -> Self {
+        self.event_log = event_log;
+        self
+    }
+
     /// Enqueue a job for scheduling
     pub fn queue_job(&self, job_id: &str, queued_at: u64) -> Result<()> {
+        self.job_spans
+            .entry(job_id.to_owned())
+            .or_insert_with(|| info_span!(parent: None, "job", job_id));
         self.state.accept_job(job_id, queued_at)
     }
 
+    /// The root span of the trace of a queued or active job, or a disabled span if the job
+    /// is unknown
+    pub fn job_span(&self, job_id: &str) -> Span {
+        self.job_spans
+            .get(job_id)
+            .map(|span| span.clone())
+            .unwrap_or_else(Span::none)
+    }
+
     /// Get the number of running jobs.
     pub fn running_job_number(&self) -> usize {
         self.active_job_cache.len()
@@ -170,23 +220,94 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
     pub async fn submit_job(
         &self,
         job_id: &str,
+        tenant: &str,
         session_id: &str,
+        session_props: HashMap<String, String>,
         plan: Arc<dyn ExecutionPlan>,
         queued_at: u64,
     ) -> Result<()> {
         let mut graph =
             ExecutionGraph::try_new(&self.scheduler_id, job_id, session_id, plan, queued_at)?;
         info!("Submitting execution graph: {:?}", graph);
+        if let Some(event_log) = &self.event_log {
+            graph.set_event_log(event_log.clone());
+        }
 
         self.state.submit_job(job_id.to_string(), &graph).await?;
 
         graph.revive();
-        self.active_job_cache
-            .insert(job_id.to_owned(), JobInfoCache::new(graph));
+        self.active_job_cache.insert(
+            job_id.to_owned(),
+            JobInfoCache::new(graph, tenant.to_owned()).with_session_props(session_props),
+        );
 
         Ok(())
     }
 
+    /// Account a job which is admitted for planning to its tenant until
+    /// [`TaskManager::complete_admission`] is called
+    pub(crate) fn admit_job(&self, job_id: &str, tenant: &str) {
+        self.pending_jobs
+            .insert(job_id.to_owned(), tenant.to_owned());
+    }
+
+    /// Stop accounting a job once its planning has either succeeded or failed
+    pub(crate) fn complete_admission(&self, job_id: &str) {
+        self.pending_jobs.remove(job_id);
+    }
+
+    /// Get the resources held by the active and admitted jobs of each tenant
+    pub async fn get_tenant_usage(&self) -> HashMap<String, TenantUsage> {
+        let jobs: Vec<(String, Arc<RwLock<ExecutionGraph>>)> = self
+            .active_job_cache
+            .iter()
+            .map(|job| (job.tenant.clone(), job.execution_graph.clone()))
+            .collect();
+
+        let mut usage: HashMap<String, TenantUsage> = HashMap::new();
+        for (tenant, graph) in jobs {
+            let graph = graph.read().await;
+            let tenant_usage = usage.entry(tenant).or_default();
+            tenant_usage.running_jobs += 1;
+            tenant_usage.running_tasks += graph.running_tasks().len();
+            tenant_usage.shuffle_bytes += graph.shuffle_bytes();
+        }
+        for job in self.pending_jobs.iter() {
+            usage.entry(job.value().clone()).or_default().running_jobs += 1;
+        }
+
+        usage
+    }
+
+    /// Get the tasks of active jobs which are currently running on the executor
+    pub async fn get_executor_running_tasks(&self, executor_id: &str) -> Vec<RunningTaskInfo> {
+        let graphs: Vec<Arc<RwLock<ExecutionGraph>>> = self
+            .active_job_cache
+            .iter()
+            .map(|job| job.execution_graph.clone())
+            .collect();
+
+        let mut tasks = vec![];
+        for graph in graphs {
+            let graph = graph.read().await;
+            tasks.extend(
+                graph
+                    .running_tasks()
+                    .into_iter()
+                    .filter(|task| task.executor_id == executor_id),
+            );
+        }
+        tasks
+    }
+
+    /// Get the tenant an active or admitted job is accounted to
+    pub fn get_job_tenant(&self, job_id: &str) -> Option<String> {
+        self.active_job_cache
+            .get(job_id)
+            .map(|job| job.tenant.clone())
+            .or_else(|| self.pending_jobs.get(job_id).map(|tenant| tenant.clone()))
+    }
+
     pub fn get_running_job_cache(&self) -> Arc<HashMap<String, JobInfoCache>> {
         let ret = self
             .active_job_cache
@@ -281,6 +402,12 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
             // let graph = self.get_active_execution_graph(&job_id).await;
             let job_events = if let Some(cached) = self.get_active_execution_graph(&job_id) {
                 let mut graph = cached.write().await;
+                let _span = info_span!(
+                    parent: &self.job_span(&job_id),
+                    "update_task_statuses",
+                    num_tasks
+                )
+                .entered();
                 graph.update_task_status(executor, statuses)?
             } else {
                 // TODO Deal with curator changed case
@@ -300,6 +427,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
     /// and remove the job from ActiveJobs
     pub(crate) async fn succeed_job(&self, job_id: &str) -> Result<()> {
         debug!("Moving job {} from Active to Success", job_id);
+        self.job_spans.remove(job_id);
 
         if let Some(graph) = self.remove_active_execution_graph(job_id) {
             let graph = graph.read().await.clone();
@@ -327,6 +455,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
         job_id: &str,
         failure_reason: String,
     ) -> Result<(Vec<RunningTaskInfo>, usize)> {
+        self.job_spans.remove(job_id);
         let (tasks_to_cancel, pending_tasks) = if let Some(graph) =
             self.remove_active_execution_graph(job_id)
         {
@@ -358,6 +487,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
     /// Mark a unscheduled job as failed. This will create a key under the FailedJobs keyspace
     /// and remove the job from ActiveJobs or QueuedJobs
     pub async fn fail_unscheduled_job(&self, job_id: &str, failure_reason: String) -> Result<()> {
+        self.job_spans.remove(job_id);
         self.state
             .fail_unscheduled_job(job_id, failure_reason)
             .await
@@ -476,6 +606,10 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
                 let (tasks_with_data_cache, tasks_without_data_cache): (Vec<_>, Vec<_>) =
                     tasks.into_iter().partition(|task| task.data_cache);
 
+                // Tasks of the job are traced as children of the job's span
+                let mut props = job_info.session_props.clone();
+                inject_trace_props(&self.job_span(&job_id), &mut props);
+
                 let mut multi_tasks = vec![];
                 if !tasks_with_data_cache.is_empty() {
                     let task_ids = tasks_with_data_cache
@@ -492,7 +626,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
                         plan: plan.clone(),
                         session_id: session_id.clone(),
                         launch_time,
-                        props: vec![],
+                        props: props.clone(),
                     });
                 }
                 if !tasks_without_data_cache.is_empty() {
@@ -510,7 +644,7 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
                         plan,
                         session_id,
                         launch_time,
-                        props: vec![],
+                        props,
                     });
                 }
 
@@ -578,7 +712,9 @@ impl<T: 'static + AsLogicalPlan, U: 'static + AsExecutionPlan> TaskManager<T, U>
 
 pub struct JobOverview {
     pub job_id: String,
+    pub session_id: String,
     pub status: JobStatus,
+    pub queued_at: u64,
     pub start_time: u64,
     pub end_time: u64,
     pub num_stages: usize,
@@ -596,7 +732,9 @@ impl From<&ExecutionGraph> for JobOverview {
 
         Self {
             job_id: value.job_id().to_string(),
+            session_id: value.session_id().to_string(),
             status: value.status().clone(),
+            queued_at: value.queued_at(),
             start_time: value.start_time(),
             end_time: value.end_time(),
             num_stages: value.stage_count(),
@@ -300,6 +300,7 @@ pub fn default_task_runner() -> impl TaskRunner {
                 status: Some(task_status::Status::Successful(SuccessfulTask {
                     executor_id: executor_id.clone(),
                     partitions: partitions.clone(),
+                    shuffle_service_id: String::new(),
                 })),
             });
         }
@@ -379,19 +380,6 @@ impl SchedulerTest {
         task_slots_per_executor: usize,
         runner: Option<Arc<dyn TaskRunner>>,
     ) -> Result<Self> {
-        let cluster = BallistaCluster::new_from_config(&config).await?;
-
-        let ballista_config = if num_executors > 0 && task_slots_per_executor > 0 {
-            BallistaConfig::builder()
-                .set(
-                    BALLISTA_DEFAULT_SHUFFLE_PARTITIONS,
-                    format!("{}", num_executors * task_slots_per_executor).as_str(),
-                )
-                .build()?
-        } else {
-            BallistaConfig::builder().build()?
-        };
-
         let runner = runner.unwrap_or_else(|| Arc::new(default_task_runner()));
 
         let executors: HashMap<String, VirtualExecutor> = (0..num_executors)
@@ -413,31 +401,65 @@ impl SchedulerTest {
             executors: executors.clone(),
         };
 
+        let executors = executors
+            .into_iter()
+            .map(
+                |(executor_id, VirtualExecutor { task_slots, .. })| ExecutorMetadata {
+                    id: executor_id,
+                    host: String::default(),
+                    port: 0,
+                    grpc_port: 0,
+                    specification: ExecutorSpecification {
+                        task_slots: task_slots as u32,
+                    },
+                },
+            )
+            .collect();
+        let mut test = Self::new_with_launcher(config, executors, Arc::new(launcher)).await?;
+        test.status_receiver = Some(status_receiver);
+
+        Ok(test)
+    }
+
+    /// Create a scheduler with the given executors, whose tasks are launched by `launcher`
+    pub async fn new_with_launcher(
+        config: SchedulerConfig,
+        executors: Vec<ExecutorMetadata>,
+        launcher: Arc<dyn TaskLauncher>,
+    ) -> Result<Self> {
+        let cluster = BallistaCluster::new_from_config(&config).await?;
+
+        let total_task_slots: u32 = executors
+            .iter()
+            .map(|executor| executor.specification.task_slots)
+            .sum();
+        let ballista_config = if total_task_slots > 0 {
+            BallistaConfig::builder()
+                .set(
+                    BALLISTA_DEFAULT_SHUFFLE_PARTITIONS,
+                    format!("{total_task_slots}").as_str(),
+                )
+                .build()?
+        } else {
+            BallistaConfig::builder().build()?
+        };
+
         let mut scheduler: SchedulerServer<LogicalPlanNode, PhysicalPlanNode> =
-            SchedulerServer::new(
+            SchedulerServer::try_new(
                 "localhost:50050".to_owned(),
                 cluster,
                 BallistaCodec::default(),
                 Arc::new(config),
-                Arc::new(launcher),
-            );
+                launcher,
+            )?;
         scheduler.init().await?;
 
-        for (executor_id, VirtualExecutor { task_slots, .. }) in executors {
-            let metadata = ExecutorMetadata {
-                id: executor_id.clone(),
-                host: String::default(),
-                port: 0,
-                grpc_port: 0,
-                specification: ExecutorSpecification {
-                    task_slots: task_slots as u32,
-                },
-            };
-
+        for metadata in executors {
+            let task_slots = metadata.specification.task_slots;
             let executor_data = ExecutorData {
-                executor_id,
-                total_task_slots: task_slots as u32,
-                available_task_slots: task_slots as u32,
+                executor_id: metadata.id.clone(),
+                total_task_slots: task_slots,
+                available_task_slots: task_slots,
             };
 
             scheduler
@@ -450,10 +472,27 @@ impl SchedulerTest {
         Ok(Self {
             scheduler,
             ballista_config,
-            status_receiver: Some(status_receiver),
+            status_receiver: None,
         })
     }
 
+    /// Test a scheduler created elsewhere, e.g. by an [`EventLogReplay`]
+    ///
+    /// [`EventLogReplay`]: crate::scheduler_server::event_log_replay::EventLogReplay
+    pub fn with_scheduler(
+        scheduler: SchedulerServer<LogicalPlanNode, PhysicalPlanNode>,
+    ) -> Result<Self> {
+        Ok(Self {
+            scheduler,
+            ballista_config: BallistaConfig::builder().build()?,
+            status_receiver: None,
+        })
+    }
+
+    pub fn scheduler(&self) -> SchedulerServer<LogicalPlanNode, PhysicalPlanNode> {
+        self.scheduler.clone()
+    }
+
     pub fn running_job_number(&self) -> usize {
         self.scheduler.running_job_number()
     }
@@ -535,13 +574,8 @@ impl SchedulerTest {
             {
                 match inner {
                     Status::Failed(_) | Status::Successful(_) => break Ok(status.unwrap()),
-                    _ => {
-                        if time >= timeout_ms {
-                            break Ok(status.unwrap());
-                        } else {
-                            continue;
-                        }
-                    }
+                    _ if time >= timeout_ms => break Ok(status.unwrap()),
+                    _ => {}
                 }
             }
 
@@ -578,16 +612,34 @@ impl SchedulerTest {
         final_status
     }
 
-    pub async fn run(&mut self, job_id: &str, plan: &LogicalPlan) -> Result<JobStatus> {
-        let ctx = self
+    /// Render the stages of a finished job the way `EXPLAIN ANALYZE` does
+    pub async fn analyze_stages(&self, job_id: &str) -> Result<Vec<(String, String)>> {
+        let graph = self
             .scheduler
             .state
-            .session_manager
-            .create_session(&self.ballista_config)
-            .await?;
+            .task_manager
+            .get_job_execution_graph(job_id)
+            .await?
+            .ok_or_else(|| BallistaError::Internal(format!("Job {job_id} not found")))?;
+        Ok(graph.analyze_stages())
+    }
 
-        self.scheduler.submit_job(job_id, ctx, plan).await?;
+    /// Jobs whose shuffle data executors have to keep at the time `now` in milliseconds
+    pub async fn active_jobs(&self, now: u64) -> Result<Vec<String>> {
+        self.scheduler.state.get_active_jobs(now).await
+    }
 
+    pub async fn job_status(&self, job_id: &str) -> Result<Option<JobStatus>> {
+        self.scheduler
+            .state
+            .task_manager
+            .get_job_status(job_id)
+            .await
+    }
+
+    /// Update the scheduler with the statuses of the tasks run by the virtual executors in
+    /// the background, instead of one at a time with [`SchedulerTest::tick`]
+    pub fn process_task_statuses(&mut self) {
         let mut receiver = self.status_receiver.take().unwrap();
 
         let scheduler_clone = self.scheduler.clone();
@@ -599,6 +651,19 @@ impl SchedulerTest {
                     .unwrap();
             }
         });
+    }
+
+    pub async fn run(&mut self, job_id: &str, plan: &LogicalPlan) -> Result<JobStatus> {
+        let ctx = self
+            .scheduler
+            .state
+            .session_manager
+            .create_session(&self.ballista_config)
+            .await?;
+
+        self.scheduler.submit_job(job_id, ctx, plan).await?;
+
+        self.process_task_statuses();
 
         let final_status: Result<JobStatus> = loop {
             let status = self
@@ -879,6 +944,7 @@ pub fn mock_completed_task(task: TaskDescription, executor_id: &str) -> TaskStat
         status: Some(task_status::Status::Successful(protobuf::SuccessfulTask {
             executor_id: executor_id.to_owned(),
             partitions,
+            shuffle_service_id: String::new(),
         })),
     }
 }